@@ -0,0 +1,117 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use csv::StringRecord;
+use payment_engine::amount::Amount;
+use payment_engine::engine::Engine;
+use payment_engine::rules::ClientStats;
+use payment_engine::transactions::{
+    transact, Client, ClientFunds, RowRecord, TransactionRecord, Tx, TxRecords, TxType,
+};
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+const CLIENTS: u32 = 1000;
+
+// A repeatable mix of mostly deposits and withdrawals with a sprinkling of
+// disputes, resolves and chargebacks against earlier deposits.
+fn synthetic_records(rows: u32) -> Vec<TransactionRecord> {
+    (1..=rows)
+        .map(|tx| {
+            let client = Client(tx % CLIENTS);
+            let (r#type, tx, amount) = match tx % 50 {
+                0 => (TxType::Dispute, tx.saturating_sub(40), None),
+                10 => (TxType::Resolve, tx.saturating_sub(50), None),
+                20 => (TxType::Chargeback, tx.saturating_sub(60), None),
+                n if n % 3 == 0 => (TxType::Withdrawal, tx, Some(Amount::new(5000))),
+                _ => (TxType::Deposit, tx, Some(Amount::new(12345))),
+            };
+            TransactionRecord {
+                r#type,
+                client,
+                tx: Tx(u64::from(tx)),
+                amount,
+            }
+        })
+        .collect()
+}
+
+fn amount_parsing(c: &mut Criterion) {
+    let inputs = ["1", "2.5", "1234.5678", "0.0001", "98765432.1"];
+    c.bench_function("amount_from_str", |b| {
+        b.iter(|| {
+            for input in inputs.iter() {
+                black_box(Amount::from_str(black_box(input)).unwrap());
+            }
+        })
+    });
+}
+
+fn record_deserialization(c: &mut Criterion) {
+    let headers = StringRecord::from(vec!["type", "client", "tx", "amount"]);
+    let rows = [
+        StringRecord::from(vec!["deposit", "1", "1", "1.5"]),
+        StringRecord::from(vec!["withdrawal", "2", "2", "0.25"]),
+        StringRecord::from(vec!["dispute", "1", "1", ""]),
+    ];
+    c.bench_function("row_record_deserialize", |b| {
+        b.iter(|| {
+            for row in rows.iter() {
+                let record: RowRecord = row.deserialize(Some(&headers)).unwrap();
+                black_box(TransactionRecord::try_from(record).unwrap());
+            }
+        })
+    });
+}
+
+// The bare `transact` loop over a client map and tx log, without any of
+// the engine around it.
+fn transact_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transact");
+    group.sample_size(10);
+    for rows in [1_000_000, 10_000_000].iter() {
+        let records = synthetic_records(*rows);
+        group.throughput(Throughput::Elements(*rows as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(rows), &records, |b, records| {
+            b.iter(|| {
+                let mut funds = ClientFunds::default();
+                let mut log = TxRecords::default();
+                for record in records {
+                    black_box(transact(
+                        &mut funds,
+                        &mut log,
+                        record,
+                        &[],
+                        ClientStats::default(),
+                    ));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+// The same records through `Engine::process`, with its counters, ledger
+// and invariant bookkeeping.
+fn engine_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engine_process");
+    group.sample_size(10);
+    for rows in [1_000_000, 10_000_000].iter() {
+        let records = synthetic_records(*rows);
+        group.throughput(Throughput::Elements(*rows as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(rows), &records, |b, records| {
+            b.iter(|| {
+                let mut engine = Engine::new();
+                black_box(engine.process_counted(records))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    amount_parsing,
+    record_deserialization,
+    transact_throughput,
+    engine_throughput
+);
+criterion_main!(benches);
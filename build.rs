@@ -0,0 +1,38 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        // only src/ffi.rs, so public constants elsewhere stay out of it
+        let config = cbindgen::Config::from_file("cbindgen.toml").expect("bad cbindgen.toml");
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/ffi.rs")
+            .generate()
+            .expect("failed to generate the C header")
+            .write_to_file("include/payment_engine.h");
+    }
+    #[cfg(feature = "protobuf")]
+    {
+        println!("cargo:rerun-if-changed=proto");
+        #[cfg(not(feature = "grpc"))]
+        {
+            let descriptors = protox::compile(["proto/transaction.proto"], ["proto"])
+                .expect("failed to parse proto/transaction.proto");
+            prost_build::Config::new()
+                .compile_fds(descriptors)
+                .expect("failed to generate protobuf types");
+        }
+        // the service shares its package with `Transaction`, so the server
+        // code lands in the same generated module
+        #[cfg(feature = "grpc")]
+        {
+            let descriptors = protox::compile(["proto/engine.proto"], ["proto"])
+                .expect("failed to parse proto/engine.proto");
+            tonic_prost_build::configure()
+                .build_client(false)
+                .compile_fds(descriptors)
+                .expect("failed to generate gRPC service");
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::error::{PaymentResult, PaymentsError};
+use crate::transactions::RejectReason;
+use alloc::string::String;
+use core::fmt;
+use core::ops;
+use core::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Debug, Default, PartialEq, Copy, Clone, PartialOrd, Serialize, Deserialize, Eq)]
+pub struct Amount(pub u64);
+
+#[derive(Debug, PartialEq, Copy, Clone, PartialOrd, Serialize, Deserialize, Eq)]
+pub struct ProcessedAmount(pub u64);
+
+const PRECISION: usize = 4;
+pub const SCALE: u64 = 10000;
+
+fn parse_digits(digits: &str) -> Option<u64> {
+    if digits.is_empty() {
+        return None;
+    }
+    digits.bytes().try_fold(0u64, |acc, b| {
+        if b.is_ascii_digit() {
+            acc.checked_mul(10)?.checked_add(u64::from(b - b'0'))
+        } else {
+            None
+        }
+    })
+}
+
+impl FromStr for Amount {
+    type Err = PaymentsError;
+
+    fn from_str(s: &str) -> PaymentResult<Amount> {
+        let bad_input = PaymentsError::Parse("Bad input for amount");
+        if let Some(index) = s.find('.') {
+            let (left, right) = (&s[..index], &s[index + 1..]);
+            if right.len() > PRECISION {
+                return Err(PaymentsError::Parse(
+                    "A valid amount is up to 4 digits precision",
+                ));
+            }
+            let fraction = if right.is_empty() {
+                Some(0)
+            } else {
+                parse_digits(right)
+            };
+            let padding = 10u64.pow((PRECISION - right.len()) as u32);
+            match (parse_digits(left), fraction) {
+                (Some(left), Some(right)) => left
+                    .checked_mul(SCALE)
+                    .and_then(|left| left.checked_add(right * padding))
+                    .map(Amount)
+                    .ok_or(PaymentsError::Overflow),
+                _ => Err(bad_input),
+            }
+        } else {
+            let val = parse_digits(s).ok_or(bad_input)?;
+            val.checked_mul(SCALE)
+                .map(Amount)
+                .ok_or(PaymentsError::Overflow)
+        }
+    }
+}
+
+// Reads an optional config threshold written as a decimal string, e.g.
+// `large_withdrawal = "10000.00"`.
+pub fn threshold<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Amount>, D::Error> {
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
+    s.as_deref()
+        .map(Amount::from_str)
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+impl Amount {
+    pub fn new(n: u64) -> Amount {
+        Amount(n)
+    }
+
+    pub fn checked_add(self, other: Amount) -> PaymentResult<Amount> {
+        self.0
+            .checked_add(other.0)
+            .map(Amount)
+            .ok_or(PaymentsError::Overflow)
+    }
+
+    // Unlike `-`, which stops at zero, fails when `other` is more than there
+    // is.
+    pub fn checked_sub(self, other: Amount) -> PaymentResult<Amount> {
+        self.0
+            .checked_sub(other.0)
+            .map(Amount)
+            .ok_or(PaymentsError::Validation(RejectReason::InsufficientFunds))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:04}", self.0 / SCALE, self.0 % SCALE)
+    }
+}
+
+impl ops::Add<Amount> for Amount {
+    type Output = Self;
+
+    fn add(self, _rhs: Amount) -> Amount {
+        Amount(self.0 + _rhs.0)
+    }
+}
+
+impl ops::Sub<Amount> for Amount {
+    type Output = Self;
+
+    fn sub(self, _rhs: Amount) -> Amount {
+        if self >= _rhs {
+            return Amount(self.0 - _rhs.0);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Amount;
+    use crate::error::PaymentsError;
+    use alloc::string::ToString;
+    use core::str::FromStr;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(Amount::from_str("1"), Ok(Amount::new(10000)));
+        assert_eq!(Amount::from_str("2.0"), Ok(Amount::new(20000)));
+        assert_eq!(Amount::from_str("0.1"), Ok(Amount::new(1000)));
+        assert_eq!(Amount::from_str("12.3456"), Ok(Amount::new(123456)));
+        assert_eq!(Amount::from_str("3."), Ok(Amount::new(30000)));
+        assert!(Amount::from_str("1.23456").is_err());
+        assert!(Amount::from_str(".5").is_err());
+        assert!(Amount::from_str("-1").is_err());
+        assert!(Amount::from_str("1.2.3").is_err());
+        assert!(Amount::from_str("abc").is_err());
+        assert!(Amount::from_str("").is_err());
+        assert_eq!(
+            Amount::from_str("18446744073709551615"),
+            Err(PaymentsError::Overflow)
+        );
+        assert_eq!(
+            Amount::from_str("1.23456"),
+            Err(PaymentsError::Parse(
+                "A valid amount is up to 4 digits precision"
+            ))
+        );
+        assert_eq!(
+            Amount::new(u64::MAX).checked_add(Amount::new(1)),
+            Err(PaymentsError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Amount::new(15000).to_string(), "1.5000");
+        assert_eq!(Amount::new(1).to_string(), "0.0001");
+        assert_eq!(Amount::new(0).to_string(), "0.0000");
+    }
+}
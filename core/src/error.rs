@@ -0,0 +1,62 @@
+use crate::transactions::RejectReason;
+#[cfg(feature = "std")]
+use std::io;
+use thiserror::Error;
+
+pub type PaymentResult<T> = Result<T, PaymentsError>;
+
+// Errors from the ledger's own types (amounts, transaction types, funds),
+// typed so callers can act on the kind of failure rather than its message.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Error)]
+pub enum PaymentsError {
+    // text that doesn't hold a value of the type it should
+    #[error("{0}")]
+    Parse(&'static str),
+    // an operation the account's state doesn't allow
+    #[error("Transaction rejected: {}", .0.as_str())]
+    Validation(RejectReason),
+    // an amount or balance beyond what an `Amount` can hold
+    #[error("Amount is too large")]
+    Overflow,
+    #[error("Account is frozen")]
+    AccountFrozen,
+    #[error("Unknown transaction")]
+    UnknownTx,
+    // the WAL or a snapshot couldn't be read or written
+    #[cfg(feature = "std")]
+    #[error("Storage error: {0}")]
+    Storage(io::ErrorKind),
+}
+
+impl PaymentsError {
+    // The code a transaction failing with this error is rejected with.
+    pub fn reject_reason(self) -> Option<RejectReason> {
+        match self {
+            PaymentsError::Validation(reason) => Some(reason),
+            PaymentsError::Overflow => Some(RejectReason::AmountTooLarge),
+            PaymentsError::AccountFrozen => Some(RejectReason::AccountFrozen),
+            PaymentsError::UnknownTx => Some(RejectReason::UnknownTx),
+            PaymentsError::Parse(_) => None,
+            #[cfg(feature = "std")]
+            PaymentsError::Storage(_) => None,
+        }
+    }
+}
+
+impl From<RejectReason> for PaymentsError {
+    fn from(reason: RejectReason) -> PaymentsError {
+        match reason {
+            RejectReason::AmountTooLarge => PaymentsError::Overflow,
+            RejectReason::AccountFrozen => PaymentsError::AccountFrozen,
+            RejectReason::UnknownTx => PaymentsError::UnknownTx,
+            reason => PaymentsError::Validation(reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for PaymentsError {
+    fn from(err: io::Error) -> PaymentsError {
+        PaymentsError::Storage(err.kind())
+    }
+}
@@ -0,0 +1,479 @@
+use crate::amount::Amount;
+use crate::error::{PaymentResult, PaymentsError};
+use crate::transactions::{Client, RejectReason};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FundingStates {
+    Valid,
+    Disputed,
+    Frozen,
+}
+
+impl FundingStates {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FundingStates::Valid => "valid",
+            FundingStates::Disputed => "disputed",
+            FundingStates::Frozen => "frozen",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Funds {
+    pub held: Amount,
+    pub available: Amount,
+    pub client: Client,
+    pub state: FundingStates,
+    // the part of `available` granted by credits, which can't be withdrawn
+    // until `turnover` more has been deposited or withdrawn (see
+    // `turn_over`)
+    #[serde(default)]
+    pub bonus: Amount,
+    #[serde(default)]
+    pub turnover: Amount,
+    // what a chargeback left the client owing beyond its available funds,
+    // when the engine tracks debt
+    #[serde(default)]
+    pub debt: Amount,
+    // what the engine lent the account to hold the part of its open disputes
+    // its available funds didn't cover, when it tracks debt; part of
+    // `available` until the disputes resolve or charge back
+    #[serde(default)]
+    pub uncovered: Amount,
+    // deposits that haven't settled yet, when the engine settles them (see
+    // `settle`); part of the total but not available
+    #[serde(default)]
+    pub pending: Amount,
+}
+
+impl Funds {
+    pub fn new(client: Client) -> Funds {
+        Funds {
+            held: Amount::new(0),
+            available: Amount::new(0),
+            client,
+            state: FundingStates::Valid,
+            bonus: Amount::new(0),
+            turnover: Amount::new(0),
+            debt: Amount::new(0),
+            uncovered: Amount::new(0),
+            pending: Amount::new(0),
+        }
+    }
+
+    pub fn total(&self) -> Amount {
+        self.available + self.held + self.pending
+    }
+
+    // What a withdrawal can take: the available funds that aren't bonus.
+    pub fn withdrawable(&self) -> Amount {
+        Amount(self.available.0.saturating_sub(self.bonus.0))
+    }
+
+    fn check_not_frozen(&self) -> PaymentResult<()> {
+        if not_frozen(self) {
+            Ok(())
+        } else {
+            Err(PaymentsError::AccountFrozen)
+        }
+    }
+
+    // Each operation either applies in full or leaves the account untouched.
+    pub fn deposit(&mut self, amount: Amount) -> PaymentResult<()> {
+        self.check_not_frozen()?;
+        self.available = self.available.checked_add(amount)?;
+        Ok(())
+    }
+
+    pub fn withdraw(&mut self, amount: Amount) -> PaymentResult<()> {
+        self.check_not_frozen()?;
+        if self.withdrawable() < amount {
+            return Err(PaymentsError::Validation(RejectReason::InsufficientFunds));
+        }
+        self.available = self.available.checked_sub(amount)?;
+        Ok(())
+    }
+
+    // Holds `amount` of the available funds, which have to cover it.
+    pub fn dispute(&mut self, amount: Amount) -> PaymentResult<()> {
+        self.check_not_frozen()?;
+        let available = self.available.checked_sub(amount)?;
+        self.held = self.held.checked_add(amount)?;
+        self.available = available;
+        // a disputed deposit may have been spent down to the bonus
+        if self.bonus > self.available {
+            self.bonus = self.available;
+        }
+        self.update_dispute();
+        Ok(())
+    }
+
+    // Adds a bonus: available, but locked in `bonus`.
+    pub fn credit(&mut self, amount: Amount) -> PaymentResult<()> {
+        self.check_not_frozen()?;
+        self.available = self.available.checked_add(amount)?;
+        self.bonus = self.bonus.checked_add(amount)?;
+        Ok(())
+    }
+
+    // Counts `amount` deposited or withdrawn towards the turnover the bonus
+    // is locked behind, releasing it once there is none left. Returns
+    // whether it was released.
+    pub fn turn_over(&mut self, amount: Amount) -> bool {
+        if self.bonus.0 == 0 {
+            return false;
+        }
+        self.turnover = Amount(self.turnover.0.saturating_sub(amount.0));
+        if self.turnover.0 > 0 {
+            return false;
+        }
+        self.bonus = Amount::new(0);
+        true
+    }
+
+    // Moves `amount` just deposited from available to pending.
+    pub fn hold_pending(&mut self, amount: Amount) -> PaymentResult<()> {
+        let available = self.available.checked_sub(amount)?;
+        self.pending = self.pending.checked_add(amount)?;
+        self.available = available;
+        Ok(())
+    }
+
+    // Makes `amount` of a pending deposit available.
+    pub fn settle(&mut self, amount: Amount) -> PaymentResult<()> {
+        self.check_not_frozen()?;
+        let pending = self
+            .pending
+            .checked_sub(amount)
+            .map_err(|_| PaymentsError::Validation(RejectReason::NotPending))?;
+        self.available = self.available.checked_add(amount)?;
+        self.pending = pending;
+        Ok(())
+    }
+
+    // Releases `amount` of the held funds, which have to hold it.
+    pub fn resolve(&mut self, amount: Amount) -> PaymentResult<()> {
+        self.check_not_frozen()?;
+        let held = self.release(amount)?;
+        self.available = self.available.checked_add(amount)?;
+        self.held = held;
+        self.update_dispute();
+        Ok(())
+    }
+
+    fn release(&self, amount: Amount) -> PaymentResult<Amount> {
+        self.held
+            .checked_sub(amount)
+            .map_err(|_| PaymentsError::Validation(RejectReason::NotDisputed))
+    }
+
+    fn update_dispute(&mut self) -> bool {
+        if self.held.0 > 0 {
+            self.state = FundingStates::Disputed;
+            true
+        } else {
+            self.state = FundingStates::Valid;
+            false
+        }
+    }
+
+    // Lifts a chargeback freeze; funds still held by other disputes keep the
+    // account disputed. Returns false when the account wasn't frozen.
+    pub fn unfreeze(&mut self) -> bool {
+        if not_frozen(self) {
+            return false;
+        }
+        self.update_dispute();
+        true
+    }
+
+    // Freezes the account as a chargeback would. Returns false when it was
+    // already frozen.
+    pub fn freeze(&mut self) -> bool {
+        if !not_frozen(self) {
+            return false;
+        }
+        self.state = FundingStates::Frozen;
+        true
+    }
+
+    pub fn chargeback(&mut self, amount: Amount) -> PaymentResult<()> {
+        self.check_not_frozen()?;
+        self.held = self.release(amount)?;
+        self.state = FundingStates::Frozen;
+        Ok(())
+    }
+}
+
+pub fn not_frozen(fund: &Funds) -> bool {
+    fund.state != FundingStates::Frozen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Amount, Client, FundingStates, Funds, PaymentsError, RejectReason};
+    #[test]
+    fn test_fund_total() {
+        let mut fund = Funds {
+            state: FundingStates::Disputed,
+            available: Amount::new(1000),
+            held: Amount::new(1000),
+            client: Client(1),
+            bonus: Amount::new(0),
+            turnover: Amount::new(0),
+            debt: Amount::new(0),
+            uncovered: Amount::new(0),
+            pending: Amount::new(0),
+        };
+        assert_eq!(fund.total(), Amount::new(2000));
+        fund.available = Amount::new(555);
+        fund.held = Amount::new(0);
+        assert_eq!(fund.total(), Amount::new(555));
+    }
+    #[test]
+    fn test_deposit() {
+        let mut fund = Funds {
+            state: FundingStates::Disputed,
+            available: Amount::new(0),
+            held: Amount::new(0),
+            client: Client(1),
+            bonus: Amount::new(0),
+            turnover: Amount::new(0),
+            debt: Amount::new(0),
+            uncovered: Amount::new(0),
+            pending: Amount::new(0),
+        };
+        assert_eq!(fund.total(), Amount::new(0));
+        fund.deposit(Amount::new(100)).unwrap();
+        assert_eq!(fund.total(), Amount::new(100));
+    }
+    #[test]
+    fn test_withdrawal() {
+        let mut fund = Funds {
+            state: FundingStates::Disputed,
+            available: Amount::new(0),
+            held: Amount::new(0),
+            client: Client(1),
+            bonus: Amount::new(0),
+            turnover: Amount::new(0),
+            debt: Amount::new(0),
+            uncovered: Amount::new(0),
+            pending: Amount::new(0),
+        };
+        assert_eq!(fund.total(), Amount::new(0));
+        // withdraw some money thats beyond our 0 balance
+        assert_eq!(
+            fund.withdraw(Amount::new(100)),
+            Err(PaymentsError::Validation(RejectReason::InsufficientFunds))
+        );
+        assert_eq!(fund.total(), Amount::new(0));
+
+        fund.deposit(Amount::new(250)).unwrap();
+        fund.withdraw(Amount::new(25)).unwrap();
+        assert_eq!(fund.total(), Amount::new(225));
+        assert_eq!(fund.available, Amount::new(225));
+        assert_eq!(fund.held, Amount::new(0));
+        // withdraw again beyond our limit
+        assert_eq!(
+            fund.withdraw(Amount::new(300)),
+            Err(PaymentsError::Validation(RejectReason::InsufficientFunds))
+        );
+        assert_eq!(fund.total(), Amount::new(225));
+    }
+    #[test]
+    fn test_dispute() {
+        let mut fund = Funds {
+            state: FundingStates::Disputed,
+            available: Amount::new(0),
+            held: Amount::new(0),
+            client: Client(1),
+            bonus: Amount::new(0),
+            turnover: Amount::new(0),
+            debt: Amount::new(0),
+            uncovered: Amount::new(0),
+            pending: Amount::new(0),
+        };
+        assert_eq!(fund.total(), Amount::new(0));
+        // withdraw some money thats beyond our 0 balance
+        assert_eq!(
+            fund.withdraw(Amount::new(100)),
+            Err(PaymentsError::Validation(RejectReason::InsufficientFunds))
+        );
+        assert_eq!(fund.total(), Amount::new(0));
+
+        fund.deposit(Amount::new(250)).unwrap();
+        fund.withdraw(Amount::new(25)).unwrap();
+        assert_eq!(fund.total(), Amount::new(225));
+        assert_eq!(fund.available, Amount::new(225));
+        assert_eq!(fund.held, Amount::new(0));
+        // withdraw again beyond our limit
+        assert_eq!(
+            fund.withdraw(Amount::new(300)),
+            Err(PaymentsError::Validation(RejectReason::InsufficientFunds))
+        );
+        assert_eq!(fund.total(), Amount::new(225));
+        // a dispute can only hold what is available
+        assert_eq!(
+            fund.dispute(Amount::new(250)),
+            Err(PaymentsError::Validation(RejectReason::InsufficientFunds))
+        );
+        assert_eq!(
+            (fund.available, fund.held),
+            (Amount::new(225), Amount::new(0))
+        );
+        fund.dispute(Amount::new(200)).unwrap();
+        assert_eq!(
+            (fund.available, fund.held),
+            (Amount::new(25), Amount::new(200))
+        );
+        assert_eq!(fund.total(), Amount::new(225));
+    }
+    #[test]
+    fn test_resolve() {
+        let mut fund = Funds {
+            state: FundingStates::Disputed,
+            available: Amount::new(100),
+            held: Amount::new(20),
+            client: Client(1),
+            bonus: Amount::new(0),
+            turnover: Amount::new(0),
+            debt: Amount::new(0),
+            uncovered: Amount::new(0),
+            pending: Amount::new(0),
+        };
+        fund.resolve(Amount::new(19)).unwrap();
+        assert_eq!(fund.available, Amount::new(119));
+        assert_eq!(fund.total(), Amount::new(120));
+        assert_eq!(fund.state, FundingStates::Disputed);
+        fund.resolve(Amount::new(1)).unwrap();
+        assert_eq!(fund.available, Amount::new(120));
+        assert_eq!(fund.state, FundingStates::Valid);
+    }
+
+    #[test]
+    fn test_resolve_on_valid() {
+        let mut fund = Funds {
+            state: FundingStates::Valid,
+            available: Amount::new(100),
+            held: Amount::new(0),
+            client: Client(1),
+            bonus: Amount::new(0),
+            turnover: Amount::new(0),
+            debt: Amount::new(0),
+            uncovered: Amount::new(0),
+            pending: Amount::new(0),
+        };
+        assert_eq!(
+            fund.resolve(Amount::new(20)),
+            Err(PaymentsError::Validation(RejectReason::NotDisputed))
+        );
+        assert_eq!(fund.available, Amount::new(100));
+        assert_eq!(fund.held, Amount::new(0));
+        assert_eq!(fund.state, FundingStates::Valid);
+        // the state doesn't make up for funds that aren't held
+        fund.state = FundingStates::Disputed;
+        fund.held = Amount::new(10);
+        assert_eq!(
+            fund.resolve(Amount::new(20)),
+            Err(PaymentsError::Validation(RejectReason::NotDisputed))
+        );
+        assert_eq!(
+            fund.chargeback(Amount::new(20)),
+            Err(PaymentsError::Validation(RejectReason::NotDisputed))
+        );
+        assert_eq!(fund.available, Amount::new(100));
+        assert_eq!(fund.held, Amount::new(10));
+        assert_eq!(fund.state, FundingStates::Disputed);
+    }
+
+    #[test]
+    fn test_chargeback() {
+        let mut fund = Funds {
+            state: FundingStates::Disputed,
+            available: Amount::new(100),
+            held: Amount::new(20),
+            client: Client(1),
+            bonus: Amount::new(0),
+            turnover: Amount::new(0),
+            debt: Amount::new(0),
+            uncovered: Amount::new(0),
+            pending: Amount::new(0),
+        };
+        fund.chargeback(Amount::new(5)).unwrap();
+        assert_eq!(fund.state, FundingStates::Frozen);
+        assert_eq!(fund.held, Amount::new(15));
+        assert_eq!(fund.total(), Amount::new(115));
+        // run it again
+        assert_eq!(
+            fund.chargeback(Amount::new(1)),
+            Err(PaymentsError::AccountFrozen)
+        );
+        assert_eq!(fund.total(), Amount::new(115));
+        assert_eq!(fund.held, Amount::new(15));
+    }
+
+    #[test]
+    fn test_unfreeze() {
+        let mut fund = Funds {
+            state: FundingStates::Disputed,
+            available: Amount::new(100),
+            held: Amount::new(20),
+            client: Client(1),
+            bonus: Amount::new(0),
+            turnover: Amount::new(0),
+            debt: Amount::new(0),
+            uncovered: Amount::new(0),
+            pending: Amount::new(0),
+        };
+        assert!(!fund.unfreeze());
+        fund.chargeback(Amount::new(5)).unwrap();
+        assert!(fund.unfreeze());
+        // the rest of the held funds are still under dispute
+        assert_eq!(fund.state, FundingStates::Disputed);
+        fund.deposit(Amount::new(1)).unwrap();
+        assert_eq!(fund.available, Amount::new(101));
+    }
+
+    #[test]
+    fn test_bonus() {
+        let mut fund = Funds::new(Client(1));
+        fund.deposit(Amount::new(100)).unwrap();
+        fund.credit(Amount::new(50)).unwrap();
+        fund.turnover = Amount::new(80);
+        assert_eq!(fund.withdrawable(), Amount::new(100));
+        assert_eq!(
+            fund.withdraw(Amount::new(101)),
+            Err(PaymentsError::Validation(RejectReason::InsufficientFunds))
+        );
+        fund.withdraw(Amount::new(60)).unwrap();
+        assert!(!fund.turn_over(Amount::new(60)));
+        // the dispute takes more than is left besides the bonus
+        fund.dispute(Amount::new(70)).unwrap();
+        assert_eq!(fund.bonus, Amount::new(20));
+        assert!(fund.turn_over(Amount::new(20)));
+        assert_eq!(fund.withdrawable(), Amount::new(20));
+    }
+
+    #[test]
+    fn test_pending() {
+        let mut fund = Funds::new(Client(1));
+        fund.deposit(Amount::new(100)).unwrap();
+        fund.hold_pending(Amount::new(100)).unwrap();
+        assert_eq!(fund.available, Amount::new(0));
+        assert_eq!(fund.total(), Amount::new(100));
+        assert_eq!(
+            fund.withdraw(Amount::new(1)),
+            Err(PaymentsError::Validation(RejectReason::InsufficientFunds))
+        );
+        assert_eq!(
+            fund.settle(Amount::new(101)),
+            Err(PaymentsError::Validation(RejectReason::NotPending))
+        );
+        fund.settle(Amount::new(100)).unwrap();
+        assert_eq!(fund.available, Amount::new(100));
+        assert_eq!(fund.pending, Amount::new(0));
+    }
+}
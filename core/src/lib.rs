@@ -0,0 +1,20 @@
+// The engine's state machine: amounts, accounts, the validation rules and
+// applying a record, with no IO. It only needs `alloc`, so it also runs
+// where std doesn't, e.g. in a sandbox or on a microcontroller; the `std`
+// feature (on by default) adds the std-only bits, such as storage errors
+// and std's `HashMap`.
+#![no_std]
+
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+pub mod amount;
+pub mod error;
+pub mod funds;
+pub mod postings;
+pub mod rules;
+pub mod state;
+#[cfg(feature = "arbitrary")]
+mod testkit;
+pub mod transactions;
@@ -0,0 +1,132 @@
+use crate::amount::Amount;
+use crate::transactions::{Client, TxType};
+
+// The accounts of the engine's books. Each client has an `Available`, a
+// `Held` and a `Pending` account, holding what the engine owes it, and a
+// `Debt` account for what it owes the engine after a chargeback (see
+// `Funds::debt`); `Suspense` is money on
+// its way in or out, which deposits, withdrawals and chargebacks settle
+// against, and `Promotion` funds credits. Postings follow from the amounts
+// records move alone, so an account that moved by anything else doesn't
+// match the books.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum LedgerAccount {
+    Available(Client),
+    Held(Client),
+    Pending(Client),
+    Debt(Client),
+    Suspense,
+    Promotion,
+}
+
+impl LedgerAccount {
+    // The kind of account, without the client.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LedgerAccount::Available(_) => "available",
+            LedgerAccount::Held(_) => "held",
+            LedgerAccount::Pending(_) => "pending",
+            LedgerAccount::Debt(_) => "debt",
+            LedgerAccount::Suspense => "suspense",
+            LedgerAccount::Promotion => "promotion",
+        }
+    }
+
+    pub fn client(&self) -> Option<Client> {
+        match self {
+            LedgerAccount::Available(client)
+            | LedgerAccount::Held(client)
+            | LedgerAccount::Pending(client)
+            | LedgerAccount::Debt(client) => Some(*client),
+            LedgerAccount::Suspense | LedgerAccount::Promotion => None,
+        }
+    }
+}
+
+// `amount` moved from `debit` to `credit`. An account's balance is what was
+// credited to it less what was debited, so a client's `Available` and
+// `Held` balances are its account's, and the balances of all accounts
+// always add up to zero.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Posting {
+    pub debit: LedgerAccount,
+    pub credit: LedgerAccount,
+    pub amount: Amount,
+}
+
+// The postings of one applied record; none for a record that moved nothing.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Postings([Option<Posting>; 2]);
+
+impl Postings {
+    // What a record of `type` for `client` moving `amount` posts.
+    pub fn of(r#type: TxType, client: Client, amount: Amount) -> Postings {
+        let available = LedgerAccount::Available(client);
+        let held = LedgerAccount::Held(client);
+        let post = |debit, credit, amount: Amount| {
+            (amount.0 > 0).then_some(Posting {
+                debit,
+                credit,
+                amount,
+            })
+        };
+        Postings(match r#type {
+            TxType::Deposit => [post(LedgerAccount::Suspense, available, amount), None],
+            TxType::Withdrawal => [post(available, LedgerAccount::Suspense, amount), None],
+            TxType::Dispute => [post(available, held, amount), None],
+            TxType::Resolve => [post(held, available, amount), None],
+            TxType::Chargeback => [post(held, LedgerAccount::Suspense, amount), None],
+            TxType::Credit => [post(LedgerAccount::Promotion, available, amount), None],
+            TxType::Settle => [
+                post(LedgerAccount::Pending(client), available, amount),
+                None,
+            ],
+        })
+    }
+
+    pub fn single(posting: Posting) -> Postings {
+        Postings([Some(posting), None])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Posting> {
+        self.0.iter().flatten()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LedgerAccount, Postings};
+    use crate::amount::Amount;
+    use crate::transactions::{Client, TxType};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_postings() {
+        let client = Client(1);
+        let deposit = Postings::of(TxType::Deposit, client, Amount::new(10));
+        let posted: Vec<_> = deposit.iter().collect();
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0].debit, LedgerAccount::Suspense);
+        assert_eq!(posted[0].credit, LedgerAccount::Available(client));
+
+        let dispute = Postings::of(TxType::Dispute, client, Amount::new(10));
+        let posted: Vec<_> = dispute
+            .iter()
+            .map(|posting| (posting.debit, posting.credit, posting.amount))
+            .collect();
+        assert_eq!(
+            posted,
+            [(
+                LedgerAccount::Available(client),
+                LedgerAccount::Held(client),
+                Amount::new(10)
+            )]
+        );
+        let nothing = Postings::of(TxType::Resolve, client, Amount::new(0));
+        assert!(nothing.is_empty());
+    }
+}
@@ -0,0 +1,638 @@
+use crate::amount::Amount;
+use crate::error::{PaymentResult, PaymentsError};
+use crate::funds::{not_frozen, Funds};
+use crate::transactions::{
+    DisputeStatus, ProcessedRecord, RejectReason, TransactionRecord, TxType,
+};
+use core::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+// How many records of each type have been applied to a client.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct AppliedCounts([u32; 7]);
+
+fn type_index(r#type: TxType) -> usize {
+    match r#type {
+        TxType::Deposit => 0,
+        TxType::Withdrawal => 1,
+        TxType::Dispute => 2,
+        TxType::Resolve => 3,
+        TxType::Chargeback => 4,
+        TxType::Credit => 5,
+        TxType::Settle => 6,
+    }
+}
+
+impl AppliedCounts {
+    pub fn get(&self, r#type: TxType) -> u32 {
+        self.0[type_index(r#type)]
+    }
+
+    pub fn record(&mut self, r#type: TxType) {
+        let count = &mut self.0[type_index(r#type)];
+        *count = count.saturating_add(1);
+    }
+}
+
+// Where a client stands with know-your-customer checks.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KycStatus {
+    Pending,
+    Verified,
+    Rejected,
+}
+
+impl KycStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KycStatus::Pending => "pending",
+            KycStatus::Verified => "verified",
+            KycStatus::Rejected => "rejected",
+        }
+    }
+}
+
+impl FromStr for KycStatus {
+    type Err = PaymentsError;
+
+    fn from_str(s: &str) -> PaymentResult<KycStatus> {
+        [KycStatus::Pending, KycStatus::Verified, KycStatus::Rejected]
+            .iter()
+            .copied()
+            .find(|status| status.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or(PaymentsError::Parse("Unknown KYC status"))
+    }
+}
+
+// A client's account tier, lowest first.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tier {
+    Basic,
+    Verified,
+    Premium,
+}
+
+impl Tier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tier::Basic => "basic",
+            Tier::Verified => "verified",
+            Tier::Premium => "premium",
+        }
+    }
+}
+
+impl FromStr for Tier {
+    type Err = PaymentsError;
+
+    fn from_str(s: &str) -> PaymentResult<Tier> {
+        [Tier::Basic, Tier::Verified, Tier::Premium]
+            .iter()
+            .copied()
+            .find(|tier| tier.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or(PaymentsError::Parse("Unknown tier"))
+    }
+}
+
+// What an engine knows about a client beyond its balances. Engines only
+// count and score when they have custom rules or score clients; otherwise
+// those are all zero. The KYC status and tier come from a client registry,
+// and are None without one or for clients it doesn't list.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct ClientStats {
+    pub applied: AppliedCounts,
+    // see `score::risk_score`
+    pub risk_score: u8,
+    // what the deposits and withdrawals applied to the client add up to
+    pub deposited: Amount,
+    pub withdrawn: Amount,
+    pub kyc: Option<KycStatus>,
+    pub tier: Option<Tier>,
+}
+
+// What a rule gets to look at: the record, its client's account (None
+// before the client's first deposit), when the record's tx is already
+// logged, the deposit or withdrawal logged under it, and the client's
+// stats.
+pub struct RuleContext<'a> {
+    pub record: &'a TransactionRecord,
+    pub funds: Option<&'a Funds>,
+    pub previous: Option<&'a ProcessedRecord>,
+    pub stats: ClientStats,
+}
+
+impl RuleContext<'_> {
+    fn refers_to_logged_tx(&self) -> bool {
+        matches!(
+            self.record.r#type,
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback | TxType::Settle
+        )
+    }
+
+    // The amount the record moves: its own for deposits, withdrawals and
+    // credits, the logged tx's for the rest.
+    pub fn amount(&self) -> Option<Amount> {
+        if self.refers_to_logged_tx() {
+            self.previous.map(|previous| previous.amount)
+        } else {
+            self.record.amount
+        }
+    }
+}
+
+// A check a record has to pass to be applied. The engine runs its built-in
+// rules first, in the order of `check_built_in`, then those added with
+// `EngineBuilder::rule` in the order they were added; the first to fail
+// decides the rejection reason. Custom rules can reject with their own
+// code through `RejectReason::Rule`.
+pub trait ValidationRule: Send + Sync {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason>;
+
+    // Asked once a record has been applied, with the account and stats as
+    // they are afterwards; true freezes the client's account.
+    fn freezes(&self, _ctx: &RuleContext<'_>) -> bool {
+        false
+    }
+}
+
+impl<F> ValidationRule for F
+where
+    F: Fn(&RuleContext<'_>) -> Result<(), RejectReason> + Send + Sync,
+{
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        self(ctx)
+    }
+}
+
+fn require(ok: bool, reason: RejectReason) -> Result<(), RejectReason> {
+    if ok {
+        Ok(())
+    } else {
+        Err(reason)
+    }
+}
+
+// Nothing applies to an account frozen by a chargeback.
+pub struct NotFrozen;
+
+impl ValidationRule for NotFrozen {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        require(
+            ctx.funds.is_none_or(not_frozen),
+            RejectReason::AccountFrozen,
+        )
+    }
+}
+
+pub struct HasAmount;
+
+impl ValidationRule for HasAmount {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        require(
+            ctx.refers_to_logged_tx() || ctx.record.amount.is_some(),
+            RejectReason::MissingAmount,
+        )
+    }
+}
+
+// Deposits, withdrawals and credits log their tx, which can't be reused.
+pub struct NewTx;
+
+impl ValidationRule for NewTx {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        require(
+            ctx.refers_to_logged_tx() || ctx.previous.is_none(),
+            RejectReason::DuplicateTx,
+        )
+    }
+}
+
+// Only a deposit opens an account.
+pub struct KnownClient;
+
+impl ValidationRule for KnownClient {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        require(
+            ctx.record.r#type == TxType::Deposit || ctx.funds.is_some(),
+            RejectReason::UnknownClient,
+        )
+    }
+}
+
+// A withdrawal can't take more than is withdrawable, nor a dispute hold
+// more than is available.
+pub struct SufficientFunds;
+
+impl ValidationRule for SufficientFunds {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        let covered = match (ctx.record.r#type, ctx.funds, ctx.amount()) {
+            (TxType::Withdrawal, Some(funds), Some(amount)) => funds.withdrawable() >= amount,
+            (TxType::Dispute, Some(funds), Some(amount)) => funds.available >= amount,
+            _ => true,
+        };
+        require(covered, RejectReason::InsufficientFunds)
+    }
+}
+
+pub struct KnownTx;
+
+impl ValidationRule for KnownTx {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        require(
+            !ctx.refers_to_logged_tx() || ctx.previous.is_some(),
+            RejectReason::UnknownTx,
+        )
+    }
+}
+
+// A client can only dispute its own transactions.
+pub struct SameClient;
+
+impl ValidationRule for SameClient {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        let same = match ctx.previous {
+            Some(previous) if ctx.refers_to_logged_tx() => previous.client == ctx.record.client,
+            _ => true,
+        };
+        require(same, RejectReason::ClientMismatch)
+    }
+}
+
+// Credits are the operator's own, so there is nothing to dispute, a
+// deposit can't be disputed before it has settled, and a charged back tx
+// is done with. A tx under dispute can't be disputed again.
+pub struct Disputable;
+
+impl ValidationRule for Disputable {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        let disputes = matches!(
+            ctx.record.r#type,
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback
+        );
+        let previous = match ctx.previous {
+            Some(previous) if disputes => previous,
+            _ => return Ok(()),
+        };
+        let undisputable = previous.r#type == TxType::Credit
+            || previous.pending
+            || previous.dispute == DisputeStatus::ChargedBack;
+        require(!undisputable, RejectReason::NotDisputable)?;
+        require(
+            ctx.record.r#type != TxType::Dispute || previous.dispute != DisputeStatus::Disputed,
+            RejectReason::AlreadyDisputed,
+        )
+    }
+}
+
+// Only a pending deposit settles, and only once.
+pub struct Settleable;
+
+impl ValidationRule for Settleable {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        let settles = ctx.record.r#type == TxType::Settle;
+        let pending = ctx.previous.is_some_and(|previous| previous.pending);
+        require(!settles || pending, RejectReason::NotPending)
+    }
+}
+
+// Resolves and chargebacks settle a dispute, so their tx must be under one.
+pub struct Disputed;
+
+impl ValidationRule for Disputed {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        let settles = matches!(ctx.record.r#type, TxType::Resolve | TxType::Chargeback);
+        let disputed = ctx
+            .previous
+            .is_some_and(|previous| previous.dispute == DisputeStatus::Disputed);
+        require(!settles || disputed, RejectReason::NotDisputed)
+    }
+}
+
+// The built-in rules, called directly rather than through `dyn` so the
+// per-record hot path doesn't pay for the extension point.
+pub fn check_built_in(ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+    NotFrozen.check(ctx)?;
+    HasAmount.check(ctx)?;
+    NewTx.check(ctx)?;
+    KnownClient.check(ctx)?;
+    KnownTx.check(ctx)?;
+    SameClient.check(ctx)?;
+    Disputable.check(ctx)?;
+    Settleable.check(ctx)?;
+    Disputed.check(ctx)?;
+    // last, so a dispute's funds are only counted once it is one that applies
+    SufficientFunds.check(ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_built_in, ClientStats, RuleContext};
+    use crate::amount::Amount;
+    use crate::funds::{FundingStates, Funds};
+    use crate::transactions::{
+        Client, DisputeStatus, ProcessedRecord, RejectReason, TransactionRecord, Tx, TxType,
+    };
+
+    fn check(
+        funds: Option<&Funds>,
+        record: &TransactionRecord,
+        previous: Option<&ProcessedRecord>,
+    ) -> Result<(), RejectReason> {
+        check_built_in(&RuleContext {
+            record,
+            funds,
+            previous,
+            stats: ClientStats::default(),
+        })
+    }
+
+    #[test]
+    fn test_built_in_rules() {
+        let deposit = TransactionRecord {
+            client: Client(1),
+            tx: Tx(3),
+            amount: Some(Amount::new(1000)),
+            r#type: TxType::Deposit,
+        };
+        let mut fund = Funds {
+            state: FundingStates::Valid,
+            available: Amount::new(1000),
+            held: Amount::new(0),
+            client: Client(1),
+            bonus: Amount::new(0),
+            turnover: Amount::new(0),
+            debt: Amount::new(0),
+            uncovered: Amount::new(0),
+            pending: Amount::new(0),
+        };
+        assert_eq!(check(None, &deposit, None), Ok(()));
+        assert_eq!(check(Some(&fund), &deposit, None), Ok(()));
+        fund.state = FundingStates::Disputed;
+        assert_eq!(check(Some(&fund), &deposit, None), Ok(()));
+        fund.state = FundingStates::Frozen;
+        assert_eq!(
+            check(Some(&fund), &deposit, None),
+            Err(RejectReason::AccountFrozen)
+        );
+
+        let mut record = TransactionRecord {
+            client: Client(1),
+            tx: Tx(3),
+            amount: None,
+            r#type: TxType::Dispute,
+        };
+        let prev = ProcessedRecord {
+            client: Client(1),
+            tx: Tx(3),
+            amount: Amount(5),
+            r#type: TxType::Deposit,
+            pending: false,
+            dispute: DisputeStatus::Undisputed,
+        };
+        let disputed = ProcessedRecord {
+            dispute: DisputeStatus::Disputed,
+            ..prev
+        };
+        fund.state = FundingStates::Valid;
+        assert_eq!(check(Some(&fund), &record, Some(&prev)), Ok(()));
+        assert_eq!(
+            check(Some(&fund), &record, None),
+            Err(RejectReason::UnknownTx)
+        );
+        assert_eq!(
+            check(None, &record, Some(&prev)),
+            Err(RejectReason::UnknownClient)
+        );
+        assert_eq!(
+            check(Some(&fund), &record, Some(&disputed)),
+            Err(RejectReason::AlreadyDisputed)
+        );
+        // resolves and chargebacks need their own tx under dispute, whatever
+        // the account's state
+        for r#type in [TxType::Resolve, TxType::Chargeback] {
+            record.r#type = r#type;
+            fund.state = FundingStates::Disputed;
+            assert_eq!(
+                check(Some(&fund), &record, Some(&prev)),
+                Err(RejectReason::NotDisputed)
+            );
+            assert_eq!(check(Some(&fund), &record, Some(&disputed)), Ok(()));
+            fund.state = FundingStates::Frozen;
+            assert_eq!(
+                check(Some(&fund), &record, Some(&disputed)),
+                Err(RejectReason::AccountFrozen)
+            );
+        }
+        record.client = Client(2);
+        fund.state = FundingStates::Disputed;
+        assert_eq!(
+            check(Some(&fund), &record, Some(&disputed)),
+            Err(RejectReason::ClientMismatch)
+        );
+        record.client = Client(1);
+        let credit = ProcessedRecord {
+            r#type: TxType::Credit,
+            ..prev
+        };
+        assert_eq!(
+            check(Some(&fund), &record, Some(&credit)),
+            Err(RejectReason::NotDisputable)
+        );
+        let pending = ProcessedRecord {
+            pending: true,
+            ..prev
+        };
+        assert_eq!(
+            check(Some(&fund), &record, Some(&pending)),
+            Err(RejectReason::NotDisputable)
+        );
+        let charged_back = ProcessedRecord {
+            dispute: DisputeStatus::ChargedBack,
+            ..prev
+        };
+        record.r#type = TxType::Dispute;
+        fund.state = FundingStates::Valid;
+        assert_eq!(
+            check(Some(&fund), &record, Some(&charged_back)),
+            Err(RejectReason::NotDisputable)
+        );
+        // only a pending deposit settles
+        record.r#type = TxType::Settle;
+        fund.state = FundingStates::Valid;
+        assert_eq!(
+            check(Some(&fund), &record, Some(&prev)),
+            Err(RejectReason::NotPending)
+        );
+        assert_eq!(check(Some(&fund), &record, Some(&pending)), Ok(()));
+    }
+
+    #[test]
+    fn test_valid_deposit() {
+        let record = TransactionRecord {
+            client: Client(1),
+            tx: Tx(3),
+            amount: Some(Amount::new(1000)),
+            r#type: TxType::Dispute,
+        };
+        assert_eq!(check(None, &record, None), Err(RejectReason::UnknownClient));
+        let deposit = TransactionRecord {
+            client: Client(1),
+            tx: Tx(3),
+            amount: Some(Amount::new(1000)),
+            r#type: TxType::Deposit,
+        };
+        assert_eq!(check(None, &deposit, None), Ok(()));
+        let mut fund = Funds {
+            available: Amount::new(1000),
+            ..Funds::new(Client(5))
+        };
+        assert_eq!(check(Some(&fund), &deposit, None), Ok(()));
+        fund.state = FundingStates::Frozen;
+        assert_eq!(
+            check(Some(&fund), &deposit, None),
+            Err(RejectReason::AccountFrozen)
+        );
+        fund.state = FundingStates::Disputed;
+        assert_eq!(check(Some(&fund), &deposit, None), Ok(()));
+    }
+
+    #[test]
+    fn test_valid_dispute() {
+        let record = TransactionRecord {
+            client: Client(1),
+            tx: Tx(3),
+            amount: None,
+            r#type: TxType::Dispute,
+        };
+        let mut fund = Funds {
+            available: Amount::new(1000),
+            ..Funds::new(Client(1))
+        };
+        let prev = ProcessedRecord {
+            client: Client(1),
+            tx: Tx(3),
+            amount: Amount(5),
+            r#type: TxType::Deposit,
+            pending: false,
+            dispute: DisputeStatus::Undisputed,
+        };
+        assert_eq!(check(Some(&fund), &record, Some(&prev)), Ok(()));
+        assert_eq!(
+            check(Some(&fund), &record, None),
+            Err(RejectReason::UnknownTx)
+        );
+
+        fund.state = FundingStates::Frozen;
+        assert_eq!(
+            check(Some(&fund), &record, Some(&prev)),
+            Err(RejectReason::AccountFrozen)
+        );
+        assert_eq!(
+            check(Some(&fund), &record, None),
+            Err(RejectReason::AccountFrozen)
+        );
+
+        fund.state = FundingStates::Disputed;
+        assert_eq!(check(Some(&fund), &record, Some(&prev)), Ok(()));
+        assert_eq!(
+            check(Some(&fund), &record, None),
+            Err(RejectReason::UnknownTx)
+        );
+
+        assert_eq!(
+            check(None, &record, Some(&prev)),
+            Err(RejectReason::UnknownClient)
+        );
+        assert_eq!(check(None, &record, None), Err(RejectReason::UnknownClient));
+    }
+
+    #[test]
+    fn test_valid_resolve() {
+        let mut record = TransactionRecord {
+            client: Client(1),
+            tx: Tx(5),
+            amount: None,
+            r#type: TxType::Resolve,
+        };
+        let prev = ProcessedRecord {
+            client: Client(1),
+            tx: Tx(5),
+            amount: Amount(5),
+            r#type: TxType::Deposit,
+            pending: false,
+            dispute: DisputeStatus::Disputed,
+        };
+        let mut fund = Funds {
+            state: FundingStates::Disputed,
+            available: Amount::new(1000),
+            held: Amount::new(20),
+            ..Funds::new(Client(1))
+        };
+        assert_eq!(check(Some(&fund), &record, Some(&prev)), Ok(()));
+        assert_eq!(
+            check(None, &record, Some(&prev)),
+            Err(RejectReason::UnknownClient)
+        );
+        record.r#type = TxType::Dispute;
+        assert_eq!(
+            check(None, &record, Some(&prev)),
+            Err(RejectReason::UnknownClient)
+        );
+
+        record.r#type = TxType::Resolve;
+        fund.state = FundingStates::Valid;
+        assert_eq!(
+            check(Some(&fund), &record, None),
+            Err(RejectReason::UnknownTx)
+        );
+        assert_eq!(check(None, &record, None), Err(RejectReason::UnknownClient));
+    }
+
+    #[test]
+    fn test_valid_chargeback() {
+        let record = TransactionRecord {
+            client: Client(1),
+            tx: Tx(3),
+            amount: None,
+            r#type: TxType::Chargeback,
+        };
+        let mut fund = Funds {
+            state: FundingStates::Disputed,
+            available: Amount::new(1000),
+            held: Amount::new(1000),
+            ..Funds::new(Client(1))
+        };
+        let prev = ProcessedRecord {
+            client: Client(1),
+            tx: Tx(3),
+            amount: Amount(5),
+            r#type: TxType::Deposit,
+            pending: false,
+            dispute: DisputeStatus::Disputed,
+        };
+        assert_eq!(check(Some(&fund), &record, Some(&prev)), Ok(()));
+        assert_eq!(check(None, &record, None), Err(RejectReason::UnknownClient));
+        assert_eq!(
+            check(None, &record, Some(&prev)),
+            Err(RejectReason::UnknownClient)
+        );
+        // the account's state doesn't matter, the tx's own dispute does
+        fund.state = FundingStates::Valid;
+        assert_eq!(check(Some(&fund), &record, Some(&prev)), Ok(()));
+        let undisputed = ProcessedRecord {
+            dispute: DisputeStatus::Undisputed,
+            ..prev
+        };
+        assert_eq!(
+            check(Some(&fund), &record, Some(&undisputed)),
+            Err(RejectReason::NotDisputed)
+        );
+        fund.state = FundingStates::Frozen;
+        assert_eq!(
+            check(Some(&fund), &record, None),
+            Err(RejectReason::AccountFrozen)
+        );
+    }
+}
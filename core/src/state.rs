@@ -0,0 +1,197 @@
+use crate::funds::{FundingStates, Funds};
+use crate::rules::ClientStats;
+use crate::transactions::{
+    transact, Client, ClientFunds, Effect, Outcome, PackedRecord, RejectReason, TransactionRecord,
+    Tx, TxRecords, TxType,
+};
+use alloc::vec::Vec;
+use hashbrown::DefaultHashBuilder;
+use rpds::HashTrieMapSync;
+
+// A client's logged deposits, withdrawals and credits. Persistent: states
+// derived from one another share all entries but the ones they changed, so
+// keeping every state of a replay around costs no more than the log itself.
+pub type AccountLog = HashTrieMapSync<Tx, PackedRecord, DefaultHashBuilder>;
+
+// One client's account and the deposits and withdrawals logged for it, as a
+// value: `apply` returns the next state rather than changing this one, so
+// replaying the same records always gives the same states and events.
+//
+// Tx ids are only checked against this account's own log; a deposit reusing
+// another client's tx, or a dispute of one, is the engine's to catch.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AccountState {
+    pub client: Client,
+    // None until the first deposit opens the account
+    pub funds: Option<Funds>,
+    pub records: AccountLog,
+}
+
+// What applying a record to an `AccountState` did. A record that changes
+// nothing (see `Outcome::Ignored`) has no events.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Event {
+    // the record's deposit opened the account
+    Opened {
+        client: Client,
+    },
+    Applied {
+        r#type: TxType,
+        tx: Tx,
+        effect: Effect,
+    },
+    Rejected {
+        r#type: TxType,
+        tx: Tx,
+        reason: RejectReason,
+    },
+    // e.g. a dispute holding funds, or a chargeback freezing the account
+    StateChanged {
+        from: FundingStates,
+        to: FundingStates,
+    },
+}
+
+impl AccountState {
+    pub fn new(client: Client) -> AccountState {
+        AccountState {
+            client,
+            funds: None,
+            records: AccountLog::default(),
+        }
+    }
+}
+
+// Applies `record` to a copy of `state` with the built-in rules, as the
+// engine would, and returns the copy with the events describing the change.
+// A record for another client is rejected as `ClientMismatch`.
+pub fn apply(state: &AccountState, record: &TransactionRecord) -> (AccountState, Vec<Event>) {
+    let rejected = |reason| Event::Rejected {
+        r#type: record.r#type,
+        tx: record.tx,
+        reason,
+    };
+    if record.client != state.client {
+        return (
+            state.clone(),
+            alloc::vec![rejected(RejectReason::ClientMismatch)],
+        );
+    }
+    let mut funds = ClientFunds::default();
+    if let Some(account) = &state.funds {
+        funds.insert(state.client, account.clone());
+    }
+    // `transact` only looks up and logs the record's own tx, so it gets that
+    // entry alone and the log is copied only where it changes
+    let logged = state.records.get(&record.tx).copied();
+    let mut records: TxRecords = logged
+        .map(|packed| (record.tx, packed))
+        .into_iter()
+        .collect();
+    let outcome = transact(
+        &mut funds,
+        &mut records,
+        record,
+        &[],
+        ClientStats::default(),
+    );
+    let mut events = Vec::new();
+    match outcome {
+        Outcome::Applied { effect } => {
+            let before = state.funds.as_ref().map(|funds| funds.state);
+            if before.is_none() {
+                events.push(Event::Opened {
+                    client: state.client,
+                });
+            }
+            events.push(Event::Applied {
+                r#type: record.r#type,
+                tx: record.tx,
+                effect,
+            });
+            let from = before.unwrap_or(FundingStates::Valid);
+            if from != effect.state {
+                events.push(Event::StateChanged {
+                    from,
+                    to: effect.state,
+                });
+            }
+        }
+        Outcome::Rejected { reason } => events.push(rejected(reason)),
+        Outcome::Ignored => {}
+    }
+    let records = match records.remove(&record.tx) {
+        Some(packed) if logged != Some(packed) => state.records.insert(record.tx, packed),
+        _ => state.records.clone(),
+    };
+    let next = AccountState {
+        client: state.client,
+        funds: funds.remove(&state.client),
+        records,
+    };
+    (next, events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, AccountState, Event};
+    use crate::amount::Amount;
+    use crate::funds::FundingStates;
+    use crate::transactions::{Client, RejectReason, TransactionRecord, Tx, TxType};
+
+    fn record(r#type: TxType, client: u32, tx: u64, amount: Option<u64>) -> TransactionRecord {
+        TransactionRecord {
+            r#type,
+            client: Client(client),
+            tx: Tx(tx),
+            amount: amount.map(Amount::new),
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let empty = AccountState::new(Client(1));
+        let (opened, events) = apply(&empty, &record(TxType::Deposit, 1, 1, Some(10)));
+        assert_eq!(empty, AccountState::new(Client(1)));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], Event::Opened { client: Client(1) });
+        assert_eq!(opened.funds.as_ref().unwrap().available, Amount::new(10));
+        assert!(opened.records.contains_key(&Tx(1)));
+
+        let (disputed, events) = apply(&opened, &record(TxType::Dispute, 1, 1, None));
+        assert_eq!(
+            events.last(),
+            Some(&Event::StateChanged {
+                from: FundingStates::Valid,
+                to: FundingStates::Disputed
+            })
+        );
+        // the dispute is logged in the new state only
+        assert_ne!(disputed.records, opened.records);
+        assert_eq!(disputed.records.size(), 1);
+        // the same record applied to the same state gives the same result
+        assert_eq!(
+            apply(&opened, &record(TxType::Dispute, 1, 1, None)),
+            (disputed.clone(), events)
+        );
+
+        let (unchanged, events) = apply(&disputed, &record(TxType::Withdrawal, 1, 2, Some(5)));
+        assert_eq!(unchanged, disputed);
+        assert_eq!(
+            events,
+            [Event::Rejected {
+                r#type: TxType::Withdrawal,
+                tx: Tx(2),
+                reason: RejectReason::InsufficientFunds
+            }]
+        );
+        let (_, events) = apply(&disputed, &record(TxType::Deposit, 2, 3, Some(5)));
+        assert!(matches!(
+            events[..],
+            [Event::Rejected {
+                reason: RejectReason::ClientMismatch,
+                ..
+            }]
+        ));
+    }
+}
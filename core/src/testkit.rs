@@ -0,0 +1,68 @@
+use crate::amount::{Amount, SCALE};
+use crate::transactions::{Client, RowRecord, TransactionRecord, Tx, TxType};
+use arbitrary::{Arbitrary, Unstructured};
+
+// `arbitrary::Arbitrary` impls, so that property tests and fuzz targets
+// downstream can draw the crate's types from raw bytes the same way its
+// own do. `payment_engine`'s `testkit` feature turns them on.
+
+// Largest amount drawn, 1,000,000,000.0000: sums over long sequences stay
+// far from overflowing.
+const MAX_AMOUNT: u64 = 1_000_000_000 * SCALE;
+
+impl<'a> Arbitrary<'a> for Amount {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Amount> {
+        u.int_in_range(0..=MAX_AMOUNT).map(Amount)
+    }
+}
+
+impl<'a> Arbitrary<'a> for TxType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<TxType> {
+        u.choose(&[
+            TxType::Deposit,
+            TxType::Withdrawal,
+            TxType::Dispute,
+            TxType::Resolve,
+            TxType::Chargeback,
+            TxType::Credit,
+            TxType::Settle,
+        ])
+        .copied()
+    }
+}
+
+impl<'a> Arbitrary<'a> for Client {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Client> {
+        u.arbitrary().map(Client)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Tx {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Tx> {
+        u.arbitrary().map(Tx)
+    }
+}
+
+// The amount is borrowed from the input as is, so most don't parse: this is
+// for testing decoders, `TransactionRecord` for testing what gets applied.
+impl<'a> Arbitrary<'a> for RowRecord<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<RowRecord<'a>> {
+        Ok(RowRecord::new(
+            u.arbitrary()?,
+            u.arbitrary()?,
+            u.arbitrary()?,
+            u.arbitrary()?,
+        ))
+    }
+}
+
+impl<'a> Arbitrary<'a> for TransactionRecord {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<TransactionRecord> {
+        Ok(TransactionRecord {
+            r#type: u.arbitrary()?,
+            client: u.arbitrary()?,
+            tx: u.arbitrary()?,
+            amount: u.arbitrary()?,
+        })
+    }
+}
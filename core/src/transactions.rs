@@ -0,0 +1,1194 @@
+use crate::amount::Amount;
+use crate::error::{PaymentResult, PaymentsError};
+use crate::funds::{FundingStates, Funds};
+use crate::postings::Postings;
+use crate::rules::{check_built_in, ClientStats, RuleContext, ValidationRule};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
+use serde::de::{Error, Unexpected, Visitor};
+use serde::Deserializer;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize, PartialEq, Hash, Eq, PartialOrd, Ord, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum TxType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    // an operator-granted bonus, see `Funds::bonus`
+    Credit,
+    // makes a pending deposit available, see `Funds::pending`
+    Settle,
+}
+
+impl TxType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TxType::Deposit => "deposit",
+            TxType::Withdrawal => "withdrawal",
+            TxType::Dispute => "dispute",
+            TxType::Resolve => "resolve",
+            TxType::Chargeback => "chargeback",
+            TxType::Credit => "credit",
+            TxType::Settle => "settle",
+        }
+    }
+}
+
+// Spellings upstream systems use besides the engine's own names. Names and
+// aliases alike match ignoring ASCII case and `_`, `-` or space separators,
+// so `DEPOSIT` and `charge_back` parse too.
+const BUILT_IN_ALIASES: [(&str, TxType); 1] = [("withdraw", TxType::Withdrawal)];
+
+const TX_TYPES: [TxType; 7] = [
+    TxType::Deposit,
+    TxType::Withdrawal,
+    TxType::Dispute,
+    TxType::Resolve,
+    TxType::Chargeback,
+    TxType::Credit,
+    TxType::Settle,
+];
+
+// Whether `input` spells `name` (which is lowercase), as described above.
+pub fn loosely_matches(input: &str, name: &str) -> bool {
+    input
+        .bytes()
+        .filter(|b| !matches!(b, b'_' | b'-' | b' '))
+        .map(|b| b.to_ascii_lowercase())
+        .eq(name.bytes())
+}
+
+impl FromStr for TxType {
+    type Err = PaymentsError;
+
+    fn from_str(s: &str) -> PaymentResult<TxType> {
+        let s = s.trim();
+        let names = TX_TYPES.iter().map(|r#type| (r#type.as_str(), *r#type));
+        names
+            .chain(BUILT_IN_ALIASES.iter().copied())
+            .find(|(name, _)| loosely_matches(s, name))
+            .map(|(_, r#type)| r#type)
+            .ok_or(PaymentsError::Parse("Unknown transaction type"))
+    }
+}
+
+// A type as read, for callers that handle types the engine doesn't know
+// instead of failing on them.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TxTypeOrUnknown {
+    Known(TxType),
+    Unknown(String),
+}
+
+impl TxTypeOrUnknown {
+    pub fn parse(s: &str) -> TxTypeOrUnknown {
+        match TxType::from_str(s) {
+            Ok(r#type) => TxTypeOrUnknown::Known(r#type),
+            Err(_) => TxTypeOrUnknown::Unknown(s.trim().to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            TxTypeOrUnknown::Known(r#type) => r#type.as_str(),
+            TxTypeOrUnknown::Unknown(name) => name,
+        }
+    }
+}
+
+impl From<TxType> for TxTypeOrUnknown {
+    fn from(r#type: TxType) -> TxTypeOrUnknown {
+        TxTypeOrUnknown::Known(r#type)
+    }
+}
+
+impl<'de> Deserialize<'de> for TxType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TxType, D::Error> {
+        struct TxTypeVisitor;
+
+        impl Visitor<'_> for TxTypeVisitor {
+            type Value = TxType;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a transaction type")
+            }
+
+            fn visit_str<E: Error>(self, s: &str) -> Result<TxType, E> {
+                TxType::from_str(s).map_err(|_| E::invalid_value(Unexpected::Str(s), &self))
+            }
+        }
+
+        deserializer.deserialize_str(TxTypeVisitor)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Hash, Eq, Serialize, Deserialize)]
+pub struct Tx(pub u64);
+#[derive(Debug, PartialEq, Hash, Eq, Copy, Clone, Serialize, Deserialize)]
+pub struct Client(pub u32);
+
+// Surrounding whitespace is dropped, for readers that don't trim fields
+// themselves.
+pub fn amount_field(s: &str) -> PaymentResult<Option<&str>> {
+    let s = s.trim();
+    if s.is_empty() || s.eq_ignore_ascii_case("null") {
+        return Ok(None);
+    }
+    Amount::from_str(s)?;
+    Ok(Some(s))
+}
+
+fn possible_null_amount<'de, D>(deserializer: D) -> Result<Option<&'de str>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<&str> = Deserialize::deserialize(deserializer)?;
+    amount_field(s.unwrap_or("")).map_err(D::Error::custom)
+}
+
+// The client column: a client id, or `client:name` for one of the client's
+// sub-accounts. Numbers are read as ids too, for formats that have them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct AccountRef<'a> {
+    client: Client,
+    subaccount: Option<&'a str>,
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for AccountRef<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<AccountRef<'a>, D::Error> {
+        struct AccountRefVisitor;
+
+        impl<'de> Visitor<'de> for AccountRefVisitor {
+            type Value = AccountRef<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a client id, or `client:sub-account`")
+            }
+
+            fn visit_u64<E: Error>(self, id: u64) -> Result<AccountRef<'de>, E> {
+                let client = u32::try_from(id).map_err(E::custom)?;
+                Ok(AccountRef {
+                    client: Client(client),
+                    subaccount: None,
+                })
+            }
+
+            fn visit_borrowed_str<E: Error>(self, s: &'de str) -> Result<AccountRef<'de>, E> {
+                let (client, subaccount) = match s.split_once(':') {
+                    Some((client, name)) if !name.is_empty() => (client, Some(name)),
+                    Some(_) => return Err(E::custom("Empty sub-account name")),
+                    None => (s, None),
+                };
+                let client = client.parse().map_err(E::custom)?;
+                Ok(AccountRef {
+                    client: Client(client),
+                    subaccount,
+                })
+            }
+
+            // sub-account names borrow from the input, which this can't
+            fn visit_str<E: Error>(self, s: &str) -> Result<AccountRef<'de>, E> {
+                let client = s.parse().map_err(E::custom)?;
+                Ok(AccountRef {
+                    client: Client(client),
+                    subaccount: None,
+                })
+            }
+        }
+
+        deserializer.deserialize_str(AccountRefVisitor)
+    }
+}
+
+impl Serialize for AccountRef<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.subaccount {
+            Some(name) => serializer.collect_str(&format_args!("{}:{}", self.client.0, name)),
+            None => self.client.serialize(serializer),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, PartialEq, Deserialize)]
+pub struct RowRecord<'a> {
+    r#type: TxType,
+    #[serde(borrow)]
+    client: AccountRef<'a>,
+    tx: Tx,
+    #[serde(borrow, deserialize_with = "possible_null_amount")]
+    amount: Option<&'a str>,
+    // schema v2 columns, see `SchemaVersion`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    currency: Option<&'a str>,
+}
+
+impl<'a> RowRecord<'a> {
+    pub fn new(r#type: TxType, client: Client, tx: Tx, amount: Option<&'a str>) -> RowRecord<'a> {
+        RowRecord {
+            r#type,
+            client: AccountRef {
+                client,
+                subaccount: None,
+            },
+            tx,
+            amount,
+            timestamp: None,
+            currency: None,
+        }
+    }
+
+    // Addresses the row to the client's sub-account `name` (see
+    // `Engine::process_row`), or back to the client itself.
+    pub fn with_subaccount(mut self, name: Option<&'a str>) -> RowRecord<'a> {
+        self.client.subaccount = name;
+        self
+    }
+
+    pub fn r#type(&self) -> TxType {
+        self.r#type
+    }
+
+    pub fn client(&self) -> Client {
+        self.client.client
+    }
+
+    pub fn subaccount(&self) -> Option<&'a str> {
+        self.client.subaccount
+    }
+
+    pub fn tx(&self) -> Tx {
+        self.tx
+    }
+
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    pub fn currency(&self) -> Option<&'a str> {
+        self.currency
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TransactionRecord {
+    pub r#type: TxType,
+    pub amount: Option<Amount>,
+    pub tx: Tx,
+    pub client: Client,
+}
+
+// Deposits, withdrawals and credits carry their amount; disputes, resolves,
+// chargebacks and settles refer to a logged tx and never do. Building records through
+// these keeps the two apart.
+impl TransactionRecord {
+    pub fn deposit(client: Client, tx: Tx, amount: Amount) -> TransactionRecord {
+        TransactionRecord::moving(TxType::Deposit, client, tx, amount)
+    }
+
+    pub fn withdrawal(client: Client, tx: Tx, amount: Amount) -> TransactionRecord {
+        TransactionRecord::moving(TxType::Withdrawal, client, tx, amount)
+    }
+
+    pub fn credit(client: Client, tx: Tx, amount: Amount) -> TransactionRecord {
+        TransactionRecord::moving(TxType::Credit, client, tx, amount)
+    }
+
+    pub fn dispute(client: Client, tx: Tx) -> TransactionRecord {
+        TransactionRecord::referring(TxType::Dispute, client, tx)
+    }
+
+    pub fn resolve(client: Client, tx: Tx) -> TransactionRecord {
+        TransactionRecord::referring(TxType::Resolve, client, tx)
+    }
+
+    pub fn chargeback(client: Client, tx: Tx) -> TransactionRecord {
+        TransactionRecord::referring(TxType::Chargeback, client, tx)
+    }
+
+    pub fn settle(client: Client, tx: Tx) -> TransactionRecord {
+        TransactionRecord::referring(TxType::Settle, client, tx)
+    }
+
+    fn moving(r#type: TxType, client: Client, tx: Tx, amount: Amount) -> TransactionRecord {
+        TransactionRecord {
+            r#type,
+            amount: Some(amount),
+            tx,
+            client,
+        }
+    }
+
+    fn referring(r#type: TxType, client: Client, tx: Tx) -> TransactionRecord {
+        TransactionRecord {
+            r#type,
+            amount: None,
+            tx,
+            client,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProcessedRecord {
+    pub r#type: TxType,
+    pub amount: Amount,
+    pub tx: Tx,
+    pub client: Client,
+    // a deposit that hasn't settled yet
+    #[serde(default)]
+    pub pending: bool,
+    #[serde(default)]
+    pub dispute: DisputeStatus,
+}
+
+// Where a logged tx stands with disputes. Each tx is disputed on its own,
+// so an account can have several open at once.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeStatus {
+    #[default]
+    Undisputed,
+    Disputed,
+    ChargedBack,
+}
+
+// The built-in readers only pass valid amounts, but rows built by hand or
+// by a custom `RecordDecoder` may carry anything.
+impl TryFrom<RowRecord<'_>> for TransactionRecord {
+    type Error = PaymentsError;
+
+    fn try_from(val: RowRecord) -> PaymentResult<TransactionRecord> {
+        if val.subaccount().is_some() {
+            return Err(PaymentsError::Parse(
+                "Sub-accounts are only resolved by an engine",
+            ));
+        }
+        Ok(TransactionRecord {
+            client: val.client(),
+            tx: val.tx,
+            amount: val.amount.map(Amount::from_str).transpose()?,
+            r#type: val.r#type,
+        })
+    }
+}
+
+pub type ClientFunds = HashMap<Client, Funds>;
+
+// Log entries are keyed by `Tx`, so the packed form only keeps the amount,
+// a 2-bit type tag (a deposit's tells whether it is pending), a 2-bit
+// dispute status and the owning client: 12 bytes instead of the 24 a
+// `ProcessedRecord` takes, or 24 instead of 32 per map entry with the key.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(into = "LoggedRecord", try_from = "LoggedRecord")]
+#[repr(C, packed(4))]
+pub struct PackedRecord {
+    amount_and_type: u64,
+    client: Client,
+}
+
+const TYPE_BITS: u32 = 2;
+const TYPE_MASK: u64 = (1 << TYPE_BITS) - 1;
+const PENDING: u64 = 3;
+const DISPUTE_MASK: u64 = 3 << TYPE_BITS;
+const FLAG_BITS: u32 = TYPE_BITS + 2;
+
+impl PackedRecord {
+    pub fn pack(r#type: TxType, amount: Amount, client: Client) -> Option<PackedRecord> {
+        let tag = match r#type {
+            TxType::Deposit => 0,
+            TxType::Withdrawal => 1,
+            TxType::Credit => 2,
+            _ => return None,
+        };
+        if amount.0 >> (u64::BITS - FLAG_BITS) != 0 {
+            return None;
+        }
+        Some(PackedRecord {
+            amount_and_type: amount.0 << FLAG_BITS | tag,
+            client,
+        })
+    }
+
+    pub fn r#type(&self) -> TxType {
+        match self.amount_and_type & TYPE_MASK {
+            0 | PENDING => TxType::Deposit,
+            1 => TxType::Withdrawal,
+            _ => TxType::Credit,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.amount_and_type & TYPE_MASK == PENDING
+    }
+
+    // The record as a pending deposit, or unchanged when it isn't a
+    // deposit.
+    pub fn into_pending(self) -> PackedRecord {
+        if self.r#type() != TxType::Deposit {
+            return self;
+        }
+        PackedRecord {
+            amount_and_type: self.amount_and_type | PENDING,
+            client: self.client,
+        }
+    }
+
+    pub fn dispute(&self) -> DisputeStatus {
+        match (self.amount_and_type & DISPUTE_MASK) >> TYPE_BITS {
+            0 => DisputeStatus::Undisputed,
+            1 => DisputeStatus::Disputed,
+            _ => DisputeStatus::ChargedBack,
+        }
+    }
+
+    pub fn with_dispute(self, status: DisputeStatus) -> PackedRecord {
+        let bits = match status {
+            DisputeStatus::Undisputed => 0,
+            DisputeStatus::Disputed => 1,
+            DisputeStatus::ChargedBack => 2,
+        };
+        PackedRecord {
+            amount_and_type: self.amount_and_type & !DISPUTE_MASK | bits << TYPE_BITS,
+            client: self.client,
+        }
+    }
+
+    pub fn amount(&self) -> Amount {
+        Amount(self.amount_and_type >> FLAG_BITS)
+    }
+
+    pub fn client(&self) -> Client {
+        self.client
+    }
+
+    pub fn unpack(&self, tx: Tx) -> ProcessedRecord {
+        ProcessedRecord {
+            r#type: self.r#type(),
+            amount: self.amount(),
+            tx,
+            client: self.client(),
+            pending: self.is_pending(),
+            dispute: self.dispute(),
+        }
+    }
+}
+
+// `PackedRecord` as it is serialized, so formats don't depend on the
+// packing.
+#[derive(Serialize, Deserialize)]
+struct LoggedRecord {
+    r#type: TxType,
+    amount: Amount,
+    client: Client,
+    #[serde(default)]
+    pending: bool,
+    #[serde(default)]
+    dispute: DisputeStatus,
+}
+
+impl From<PackedRecord> for LoggedRecord {
+    fn from(record: PackedRecord) -> LoggedRecord {
+        LoggedRecord {
+            r#type: record.r#type(),
+            amount: record.amount(),
+            client: record.client(),
+            pending: record.is_pending(),
+            dispute: record.dispute(),
+        }
+    }
+}
+
+impl TryFrom<LoggedRecord> for PackedRecord {
+    type Error = &'static str;
+
+    fn try_from(record: LoggedRecord) -> Result<PackedRecord, &'static str> {
+        let packed = PackedRecord::pack(record.r#type, record.amount, record.client)
+            .ok_or("only deposits, withdrawals and credits up to 2^60 units are logged")?;
+        let packed = if record.pending {
+            packed.into_pending()
+        } else {
+            packed
+        };
+        Ok(packed.with_dispute(record.dispute))
+    }
+}
+
+pub type TxRecords = HashMap<Tx, PackedRecord>;
+
+// The state balances depend on: the accounts, and the log of deposits,
+// withdrawals and credits that disputes refer to. It serializes in any serde format,
+// for dumps, test fixtures and snapshots.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Ledger {
+    pub accounts: ClientFunds,
+    pub records: TxRecords,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Copy, Clone)]
+pub enum RejectReason {
+    AccountFrozen,
+    UnknownClient,
+    MissingAmount,
+    AmountTooLarge,
+    InsufficientFunds,
+    DuplicateTx,
+    UnknownTx,
+    ClientMismatch,
+    NotDisputed,
+    // the row's amount isn't a decimal of up to 4 places
+    InvalidAmount,
+    // the row's type isn't one the engine knows, see `TxTypeOrUnknown`
+    UnknownType,
+    // the client or tx id is on the denylist
+    Denied,
+    // the client carries an AML flag and the engine is set to block them
+    RiskFlagged,
+    // a withdrawal from a dormant account held until it is reactivated
+    Dormant,
+    // a dispute, resolve or chargeback of a credit or of a deposit that
+    // hasn't settled
+    NotDisputable,
+    // a settle of a tx that isn't a pending deposit
+    NotPending,
+    // a dispute of a tx already under dispute
+    AlreadyDisputed,
+    // a row for a client id that is a sub-account's, see `SubAccounts`
+    ReservedClient,
+    // a custom `ValidationRule`, with the code it reports
+    Rule(&'static str),
+}
+
+impl RejectReason {
+    // Stable codes for reporting a rejection to callers outside the process.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RejectReason::AccountFrozen => "account_frozen",
+            RejectReason::UnknownClient => "unknown_client",
+            RejectReason::MissingAmount => "missing_amount",
+            RejectReason::AmountTooLarge => "amount_too_large",
+            RejectReason::InsufficientFunds => "insufficient_funds",
+            RejectReason::DuplicateTx => "duplicate_tx",
+            RejectReason::UnknownTx => "unknown_tx",
+            RejectReason::ClientMismatch => "client_mismatch",
+            RejectReason::NotDisputed => "not_disputed",
+            RejectReason::InvalidAmount => "invalid_amount",
+            RejectReason::UnknownType => "unknown_type",
+            RejectReason::Denied => "denied",
+            RejectReason::RiskFlagged => "risk_flagged",
+            RejectReason::Dormant => "dormant",
+            RejectReason::NotDisputable => "not_disputable",
+            RejectReason::NotPending => "not_pending",
+            RejectReason::AlreadyDisputed => "already_disputed",
+            RejectReason::ReservedClient => "reserved_client",
+            RejectReason::Rule(code) => code,
+        }
+    }
+}
+
+// A record `transact` turned away, with the code of the reason why.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Rejection {
+    pub r#type: TxTypeOrUnknown,
+    pub client: Client,
+    pub tx: Tx,
+    pub amount: Option<Amount>,
+    pub reason: RejectReason,
+}
+
+// What applying a record changed: the amount it moved, its client's
+// account as it is afterwards, and the double-entry postings of the move.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Effect {
+    pub amount: Amount,
+    pub available: Amount,
+    pub held: Amount,
+    pub state: FundingStates,
+    pub postings: Postings,
+}
+
+// What `transact` did with a record. `Ignored` is a record that passed every
+// check but left the account and the log as they were, e.g. a dispute of a
+// zero deposit.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Outcome {
+    Applied { effect: Effect },
+    Rejected { reason: RejectReason },
+    Ignored,
+}
+
+impl Outcome {
+    pub fn effect(self) -> Option<Effect> {
+        match self {
+            Outcome::Applied { effect } => Some(effect),
+            _ => None,
+        }
+    }
+
+    // For callers that only care whether the record was turned away.
+    pub fn into_result(self) -> Result<(), RejectReason> {
+        match self {
+            Outcome::Rejected { reason } => Err(reason),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Rejection {
+    pub fn new(record: &TransactionRecord, reason: RejectReason) -> Rejection {
+        Rejection {
+            r#type: record.r#type.into(),
+            client: record.client,
+            tx: record.tx,
+            amount: record.amount,
+            reason,
+        }
+    }
+}
+
+// Traced at `trace` level: a span per record is only worth its cost when
+// chasing a single slow transaction.
+#[tracing::instrument(
+    level = "trace",
+    skip_all,
+    fields(client = initial_record.client.0, tx = initial_record.tx.0, r#type = initial_record.r#type.as_str())
+)]
+pub fn transact(
+    client_funds: &mut ClientFunds,
+    records: &mut TxRecords,
+    initial_record: &TransactionRecord,
+    rules: &[Arc<dyn ValidationRule>],
+    stats: ClientStats,
+) -> Outcome {
+    match apply(client_funds, records, initial_record, rules, stats) {
+        Ok(Some(effect)) => Outcome::Applied { effect },
+        Ok(None) => Outcome::Ignored,
+        Err(reason) => Outcome::Rejected { reason },
+    }
+}
+
+fn apply(
+    client_funds: &mut ClientFunds,
+    records: &mut TxRecords,
+    initial_record: &TransactionRecord,
+    rules: &[Arc<dyn ValidationRule>],
+    stats: ClientStats,
+) -> Result<Option<Effect>, RejectReason> {
+    let previous_record = records
+        .get(&initial_record.tx)
+        .map(|packed| packed.unpack(initial_record.tx));
+    let ctx = RuleContext {
+        record: initial_record,
+        funds: client_funds.get(&initial_record.client),
+        previous: previous_record.as_ref(),
+        stats,
+    };
+    check_built_in(&ctx)?;
+    for rule in rules {
+        rule.check(&ctx)?;
+    }
+    // the built-in rules guarantee there is one
+    let amount = ctx.amount().ok_or(RejectReason::MissingAmount)?;
+    let logged = match initial_record.r#type {
+        TxType::Deposit | TxType::Withdrawal | TxType::Credit => {
+            match PackedRecord::pack(initial_record.r#type, amount, initial_record.client) {
+                Some(packed) => Some(packed),
+                None => return Err(RejectReason::AmountTooLarge),
+            }
+        }
+        // a settled deposit is logged as one again
+        TxType::Settle => PackedRecord::pack(TxType::Deposit, amount, initial_record.client),
+        // disputes move the logged tx along; one of nothing changes nothing
+        TxType::Dispute | TxType::Resolve | TxType::Chargeback if amount.0 > 0 => {
+            let status = match initial_record.r#type {
+                TxType::Dispute => DisputeStatus::Disputed,
+                TxType::Resolve => DisputeStatus::Undisputed,
+                _ => DisputeStatus::ChargedBack,
+            };
+            records
+                .get(&initial_record.tx)
+                .map(|packed| packed.with_dispute(status))
+        }
+        _ => None,
+    };
+
+    let client = client_funds
+        .entry(initial_record.client)
+        .or_insert_with(|| Funds::new(initial_record.client));
+    let before = (client.available, client.held, client.state);
+    let applied = match initial_record.r#type {
+        TxType::Deposit => client.deposit(amount),
+        TxType::Withdrawal => client.withdraw(amount),
+        TxType::Dispute => client.dispute(amount),
+        TxType::Resolve => client.resolve(amount),
+        TxType::Chargeback => client.chargeback(amount),
+        TxType::Credit => client.credit(amount),
+        TxType::Settle => client.settle(amount),
+    };
+    // past validation only a balance overflowing can still fail
+    if let Err(err) = applied {
+        return Err(err.reject_reason().unwrap_or(RejectReason::AmountTooLarge));
+    }
+    let effect = Effect {
+        amount,
+        available: client.available,
+        held: client.held,
+        state: client.state,
+        postings: Postings::of(initial_record.r#type, initial_record.client, amount),
+    };
+    match logged {
+        Some(packed) => {
+            records.insert(initial_record.tx, packed);
+        }
+        None if before == (effect.available, effect.held, effect.state) => return Ok(None),
+        None => {}
+    }
+    Ok(Some(effect))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        transact, AccountRef, Amount, Client, ClientFunds, DisputeStatus, Effect, FundingStates,
+        Outcome, PackedRecord, Postings, ProcessedRecord, RejectReason, RowRecord,
+        TransactionRecord, Tx, TxRecords, TxType,
+    };
+    use crate::error::PaymentsError;
+    use crate::rules::ClientStats;
+    use alloc::vec::Vec;
+    use core::convert::TryFrom;
+    use csv::StringRecord;
+    use std::io::BufReader;
+
+    #[test]
+    fn it_serializes() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,1\ndeposit,2,2,2.0\ndeposit,1,3,2.0\ndispute,1,3,null\n";
+        let buf_reader = BufReader::new(csvfile.as_bytes());
+        let mut rdr = csv::Reader::from_reader(buf_reader);
+        let raw: Vec<StringRecord> = rdr.records().map(Result::unwrap).collect();
+        let mut rows: Vec<RowRecord> = Vec::new();
+        for record in raw.iter() {
+            // RowRecord borrows its amount from the raw record, so the raw
+            // records have to outlive the deserialized rows.
+            rows.push(record.deserialize(None).unwrap());
+        }
+        assert_eq!(4, rows.len());
+        assert_eq!(
+            rows[2],
+            RowRecord {
+                client: AccountRef {
+                    client: Client(1),
+                    subaccount: None,
+                },
+                tx: Tx(3),
+                amount: Some("2.0"),
+                r#type: TxType::Deposit,
+                timestamp: None,
+                currency: None,
+            }
+        );
+        assert_eq!(
+            rows[1],
+            RowRecord {
+                client: AccountRef {
+                    client: Client(2),
+                    subaccount: None,
+                },
+                tx: Tx(2),
+                amount: Some("2.0"),
+                r#type: TxType::Deposit,
+                timestamp: None,
+                currency: None,
+            }
+        );
+        assert_eq!(
+            rows[3],
+            RowRecord {
+                client: AccountRef {
+                    client: Client(1),
+                    subaccount: None,
+                },
+                tx: Tx(3),
+                amount: None,
+                r#type: TxType::Dispute,
+                timestamp: None,
+                currency: None,
+            }
+        )
+    }
+    #[test]
+    fn it_rejects_bad_amounts() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,abc\ndeposit,1,2,1.23456\n";
+        let mut rdr = csv::Reader::from_reader(csvfile.as_bytes());
+        for record in rdr.records() {
+            assert!(record.unwrap().deserialize::<RowRecord>(None).is_err());
+        }
+    }
+    #[test]
+    fn from_str_transactionrecord() {
+        let record = RowRecord {
+            client: AccountRef {
+                client: Client(1),
+                subaccount: None,
+            },
+            tx: Tx(3),
+            amount: None,
+            r#type: TxType::Dispute,
+            timestamp: None,
+            currency: None,
+        };
+        assert_eq!(
+            TransactionRecord {
+                client: Client(1),
+                tx: Tx(3),
+                amount: None,
+                r#type: TxType::Dispute
+            },
+            TransactionRecord::try_from(record).unwrap()
+        );
+
+        let other_record = RowRecord {
+            client: AccountRef {
+                client: Client(1),
+                subaccount: None,
+            },
+            tx: Tx(3),
+            amount: Some("0.1"),
+            r#type: TxType::Dispute,
+            timestamp: None,
+            currency: None,
+        };
+        assert_eq!(
+            TransactionRecord {
+                client: Client(1),
+                tx: Tx(3),
+                amount: Some(Amount::new(1000)),
+                r#type: TxType::Dispute
+            },
+            TransactionRecord::try_from(other_record).unwrap()
+        );
+        let bad_amount = RowRecord::new(TxType::Deposit, Client(1), Tx(4), Some("1.23456"));
+        assert!(matches!(
+            TransactionRecord::try_from(bad_amount),
+            Err(PaymentsError::Parse(_))
+        ));
+        let huge = RowRecord::new(TxType::Deposit, Client(1), Tx(5), Some("1e400"));
+        assert!(TransactionRecord::try_from(huge).is_err());
+    }
+    #[test]
+    fn test_tx_type_parsing() {
+        for (input, r#type) in [
+            ("deposit", TxType::Deposit),
+            ("DEPOSIT", TxType::Deposit),
+            (" Withdrawal", TxType::Withdrawal),
+            ("withdraw", TxType::Withdrawal),
+            ("charge_back", TxType::Chargeback),
+            ("Charge-Back", TxType::Chargeback),
+        ] {
+            assert_eq!(input.parse::<TxType>(), Ok(r#type));
+        }
+        assert!("transferx".parse::<TxType>().is_err());
+        let row = StringRecord::from(alloc::vec!["Resolve"]);
+        assert_eq!(
+            row.deserialize::<(TxType,)>(None).unwrap().0,
+            TxType::Resolve
+        );
+        let row = StringRecord::from(alloc::vec!["refund"]);
+        assert!(row.deserialize::<(TxType,)>(None).is_err());
+    }
+
+    #[test]
+    fn test_record_constructors() {
+        let deposit = TransactionRecord::deposit(Client(1), Tx(1), Amount::new(10));
+        assert_eq!(deposit.r#type, TxType::Deposit);
+        assert_eq!(deposit.amount, Some(Amount::new(10)));
+        let withdrawal = TransactionRecord::withdrawal(Client(1), Tx(2), Amount::new(5));
+        assert_eq!(withdrawal.r#type, TxType::Withdrawal);
+        for (record, r#type) in [
+            (
+                TransactionRecord::dispute(Client(1), Tx(1)),
+                TxType::Dispute,
+            ),
+            (
+                TransactionRecord::resolve(Client(1), Tx(1)),
+                TxType::Resolve,
+            ),
+            (
+                TransactionRecord::chargeback(Client(1), Tx(1)),
+                TxType::Chargeback,
+            ),
+        ] {
+            assert_eq!((record.r#type, record.amount), (r#type, None));
+            assert_eq!((record.client, record.tx), (Client(1), Tx(1)));
+        }
+    }
+    #[test]
+    fn test_packed_record() {
+        use core::mem::size_of;
+        assert_eq!(size_of::<PackedRecord>(), 12);
+        assert!(size_of::<(Tx, PackedRecord)>() < size_of::<(Tx, ProcessedRecord)>());
+
+        let packed = PackedRecord::pack(TxType::Withdrawal, Amount(123456), Client(7)).unwrap();
+        assert_eq!(
+            packed.unpack(Tx(9)),
+            ProcessedRecord {
+                r#type: TxType::Withdrawal,
+                amount: Amount(123456),
+                tx: Tx(9),
+                client: Client(7),
+                pending: false,
+                dispute: DisputeStatus::Undisputed,
+            }
+        );
+        let max = Amount(u64::MAX >> 4);
+        let packed = PackedRecord::pack(TxType::Deposit, max, Client(1)).unwrap();
+        assert_eq!((packed.r#type(), packed.amount()), (TxType::Deposit, max));
+        // the dispute status leaves the rest of the record alone
+        let disputed = packed
+            .into_pending()
+            .with_dispute(DisputeStatus::ChargedBack);
+        assert_eq!(disputed.dispute(), DisputeStatus::ChargedBack);
+        assert_eq!(disputed.amount(), max);
+        assert!(disputed.is_pending());
+        assert_eq!(
+            disputed.with_dispute(DisputeStatus::Undisputed),
+            packed.into_pending()
+        );
+        assert_eq!(
+            PackedRecord::pack(TxType::Deposit, Amount(u64::MAX), Client(1)),
+            None
+        );
+        assert_eq!(
+            PackedRecord::pack(TxType::Dispute, Amount(1), Client(1)),
+            None
+        );
+    }
+    #[test]
+    fn test_transact_reject_reasons() {
+        let mut client_funds = ClientFunds::new();
+        let mut records = TxRecords::new();
+        let mut run = |r#type, client, tx, amount: Option<u64>| {
+            let record = TransactionRecord {
+                r#type,
+                client: Client(client),
+                tx: Tx(tx),
+                amount: amount.map(Amount::new),
+            };
+            transact(
+                &mut client_funds,
+                &mut records,
+                &record,
+                &[],
+                ClientStats::default(),
+            )
+            .into_result()
+        };
+        assert_eq!(
+            run(TxType::Withdrawal, 1, 1, Some(5)),
+            Err(RejectReason::UnknownClient)
+        );
+        assert_eq!(
+            run(TxType::Deposit, 1, 1, None),
+            Err(RejectReason::MissingAmount)
+        );
+        assert_eq!(run(TxType::Deposit, 1, 1, Some(10)), Ok(()));
+        assert_eq!(
+            run(TxType::Deposit, 1, 1, Some(10)),
+            Err(RejectReason::DuplicateTx)
+        );
+        assert_eq!(
+            run(TxType::Withdrawal, 1, 2, Some(50)),
+            Err(RejectReason::InsufficientFunds)
+        );
+        assert_eq!(
+            run(TxType::Dispute, 1, 9, None),
+            Err(RejectReason::UnknownTx)
+        );
+        assert_eq!(
+            run(TxType::Resolve, 1, 1, None),
+            Err(RejectReason::NotDisputed)
+        );
+        assert_eq!(run(TxType::Deposit, 2, 2, Some(10)), Ok(()));
+        assert_eq!(
+            run(TxType::Dispute, 2, 1, None),
+            Err(RejectReason::ClientMismatch)
+        );
+        assert_eq!(run(TxType::Dispute, 1, 1, None), Ok(()));
+        assert_eq!(
+            run(TxType::Dispute, 1, 1, None),
+            Err(RejectReason::AlreadyDisputed)
+        );
+        assert_eq!(run(TxType::Chargeback, 1, 1, None), Ok(()));
+        assert_eq!(
+            run(TxType::Deposit, 1, 3, Some(10)),
+            Err(RejectReason::AccountFrozen)
+        );
+        assert_eq!(
+            run(TxType::Deposit, 2, 4, Some(u64::MAX)),
+            Err(RejectReason::AmountTooLarge)
+        );
+        // each deposit fits the log, but together they overflow the balance
+        let largest = u64::MAX >> 4;
+        for tx in 5..21 {
+            assert_eq!(run(TxType::Deposit, 3, tx, Some(largest)), Ok(()));
+        }
+        assert_eq!(
+            run(TxType::Deposit, 3, 21, Some(largest)),
+            Err(RejectReason::AmountTooLarge)
+        );
+        assert!(!records.contains_key(&Tx(21)));
+    }
+
+    #[test]
+    fn test_transact_outcome() {
+        let mut client_funds = ClientFunds::new();
+        let mut records = TxRecords::new();
+        let mut run = |r#type, tx, amount: Option<u64>| {
+            let record = TransactionRecord {
+                r#type,
+                client: Client(1),
+                tx: Tx(tx),
+                amount: amount.map(Amount::new),
+            };
+            transact(
+                &mut client_funds,
+                &mut records,
+                &record,
+                &[],
+                ClientStats::default(),
+            )
+        };
+        assert_eq!(
+            run(TxType::Deposit, 1, Some(10)),
+            Outcome::Applied {
+                effect: Effect {
+                    amount: Amount::new(10),
+                    available: Amount::new(10),
+                    held: Amount::new(0),
+                    state: FundingStates::Valid,
+                    postings: Postings::of(TxType::Deposit, Client(1), Amount::new(10)),
+                }
+            }
+        );
+        let disputed = run(TxType::Dispute, 1, None).effect().unwrap();
+        assert_eq!(disputed.amount, Amount::new(10));
+        assert_eq!(
+            (disputed.available, disputed.held),
+            (Amount::new(0), Amount::new(10))
+        );
+        assert_eq!(disputed.state, FundingStates::Disputed);
+        assert_eq!(
+            run(TxType::Withdrawal, 2, Some(5)),
+            Outcome::Rejected {
+                reason: RejectReason::InsufficientFunds
+            }
+        );
+        // a zero deposit still logs its tx, but disputing it changes nothing
+        assert!(run(TxType::Deposit, 3, Some(0)).effect().is_some());
+        assert_eq!(run(TxType::Resolve, 1, None).into_result(), Ok(()));
+        assert_eq!(run(TxType::Dispute, 3, None), Outcome::Ignored);
+        assert_eq!(
+            run(TxType::Resolve, 3, None),
+            Outcome::Rejected {
+                reason: RejectReason::NotDisputed
+            }
+        );
+    }
+
+    #[test]
+    fn test_disputes_per_tx() {
+        let mut client_funds = ClientFunds::new();
+        let mut records = TxRecords::new();
+        let mut run = |r#type, tx, amount: Option<u64>| {
+            let record = TransactionRecord {
+                r#type,
+                client: Client(1),
+                tx: Tx(tx),
+                amount: amount.map(Amount::new),
+            };
+            transact(
+                &mut client_funds,
+                &mut records,
+                &record,
+                &[],
+                ClientStats::default(),
+            )
+            .into_result()
+        };
+        assert_eq!(run(TxType::Deposit, 1, Some(1)), Ok(()));
+        assert_eq!(run(TxType::Deposit, 2, Some(100)), Ok(()));
+        assert_eq!(run(TxType::Dispute, 1, None), Ok(()));
+        // another tx's dispute doesn't make this one disputed
+        for r#type in [TxType::Resolve, TxType::Chargeback] {
+            assert_eq!(run(r#type, 2, None), Err(RejectReason::NotDisputed));
+        }
+        assert_eq!(
+            run(TxType::Dispute, 1, None),
+            Err(RejectReason::AlreadyDisputed)
+        );
+        assert_eq!(run(TxType::Resolve, 1, None), Ok(()));
+        assert_eq!(
+            run(TxType::Resolve, 1, None),
+            Err(RejectReason::NotDisputed)
+        );
+        // a resolved tx can be disputed again
+        assert_eq!(run(TxType::Dispute, 1, None), Ok(()));
+        assert_eq!(run(TxType::Chargeback, 1, None), Ok(()));
+        assert_eq!(records[&Tx(1)].dispute(), DisputeStatus::ChargedBack);
+        let funds = &client_funds[&Client(1)];
+        assert_eq!(
+            (funds.available, funds.held, funds.state),
+            (Amount::new(100), Amount::new(0), FundingStates::Frozen)
+        );
+    }
+
+    #[test]
+    fn test_dispute_of_spent_funds() {
+        let mut client_funds = ClientFunds::new();
+        let mut records = TxRecords::new();
+        let mut run = |r#type, tx, amount: Option<u64>| {
+            let record = TransactionRecord {
+                r#type,
+                client: Client(1),
+                tx: Tx(tx),
+                amount: amount.map(Amount::new),
+            };
+            transact(
+                &mut client_funds,
+                &mut records,
+                &record,
+                &[],
+                ClientStats::default(),
+            )
+            .into_result()
+        };
+        assert_eq!(run(TxType::Deposit, 1, Some(10)), Ok(()));
+        assert_eq!(run(TxType::Withdrawal, 2, Some(8)), Ok(()));
+        // only 2 are left to hold, so the dispute can't apply, and nothing
+        // can be resolved into the account
+        assert_eq!(
+            run(TxType::Dispute, 1, None),
+            Err(RejectReason::InsufficientFunds)
+        );
+        assert_eq!(
+            run(TxType::Resolve, 1, None),
+            Err(RejectReason::NotDisputed)
+        );
+        let funds = &client_funds[&Client(1)];
+        assert_eq!(
+            (funds.available, funds.held, funds.state),
+            (Amount::new(2), Amount::new(0), FundingStates::Valid)
+        );
+    }
+}
@@ -0,0 +1,26 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use payment_engine::amount::Amount;
+use payment_engine::csv_dialect::CsvDialect;
+use payment_engine::engine::Engine;
+use std::str::FromStr;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        // whatever parses prints back as the same amount
+        if let Ok(amount) = Amount::from_str(text) {
+            assert_eq!(Amount::from_str(&amount.to_string()).ok(), Some(amount));
+        }
+    }
+
+    // bad rows are errors, or skipped when permissive, but never panics
+    let mut input = b"type,client,tx,amount\n".to_vec();
+    input.extend_from_slice(data);
+    let _ = Engine::new().process_csv(input.as_slice());
+    let permissive = CsvDialect {
+        permissive: true,
+        ..CsvDialect::default()
+    };
+    let _ = Engine::new().process_csv_with(input.as_slice(), &permissive);
+});
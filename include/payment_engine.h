@@ -0,0 +1,109 @@
+#ifndef PAYMENT_ENGINE_H
+#define PAYMENT_ENGINE_H
+
+/* Generated by cbindgen from src/ffi.rs; don't edit by hand. */
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/**
+ * Transaction types for `payment_engine_apply`.
+ */
+#define PAYMENT_ENGINE_DEPOSIT 0
+
+#define PAYMENT_ENGINE_WITHDRAWAL 1
+
+#define PAYMENT_ENGINE_DISPUTE 2
+
+#define PAYMENT_ENGINE_RESOLVE 3
+
+#define PAYMENT_ENGINE_CHARGEBACK 4
+
+#define PAYMENT_ENGINE_CREDIT 5
+
+#define PAYMENT_ENGINE_SETTLE 6
+
+/**
+ * What `payment_engine_apply` returns.
+ */
+#define PAYMENT_ENGINE_APPLIED 0
+
+#define PAYMENT_ENGINE_REJECTED 1
+
+#define PAYMENT_ENGINE_INVALID_ARGUMENT -1
+
+/**
+ * An engine; opaque to C, only ever handled through a pointer.
+ */
+typedef struct PaymentEngine PaymentEngine;
+
+/**
+ * A client's balances, in ten-thousandths.
+ */
+typedef struct PaymentEngineAccount {
+  uint32_t client;
+  uint64_t available;
+  uint64_t held;
+  uint64_t total;
+  bool locked;
+} PaymentEngineAccount;
+
+/**
+ * A new engine with the default rules, to be released with
+ * `payment_engine_free`.
+ */
+struct PaymentEngine *payment_engine_new(void);
+
+/**
+ * Releases an engine; NULL is ignored.
+ *
+ * # Safety
+ *
+ * `engine` must be NULL or come from `payment_engine_new`, and isn't
+ * valid afterwards.
+ */
+void payment_engine_free(struct PaymentEngine *engine);
+
+/**
+ * Applies a transaction. `amount` is ignored unless `has_amount` is set,
+ * which disputes, resolves, chargebacks and settles don't need. Returns
+ * PAYMENT_ENGINE_APPLIED, PAYMENT_ENGINE_REJECTED (see
+ * `payment_engine_last_rejection` for why) or, for a NULL engine or unknown
+ * type, PAYMENT_ENGINE_INVALID_ARGUMENT.
+ *
+ * # Safety
+ *
+ * `engine` must be NULL or come from `payment_engine_new`.
+ */
+int32_t payment_engine_apply(struct PaymentEngine *engine,
+                             uint8_t type,
+                             uint32_t client,
+                             uint64_t tx,
+                             uint64_t amount,
+                             bool has_amount);
+
+/**
+ * The reason code of the last rejected transaction, e.g.
+ * "insufficient_funds", or an empty string before any. The string belongs
+ * to the engine and changes with the next rejection.
+ *
+ * # Safety
+ *
+ * `engine` must be NULL or come from `payment_engine_new`.
+ */
+const char *payment_engine_last_rejection(const struct PaymentEngine *engine);
+
+/**
+ * Fills `account` with the client's balances and returns true, or returns
+ * false when the client has no account.
+ *
+ * # Safety
+ *
+ * `engine` must be NULL or come from `payment_engine_new`, and `account`
+ * must be NULL or point to a writable `PaymentEngineAccount`.
+ */
+bool payment_engine_account(const struct PaymentEngine *engine,
+                            uint32_t client,
+                            struct PaymentEngineAccount *account);
+
+#endif  /* PAYMENT_ENGINE_H */
@@ -0,0 +1,185 @@
+use napi::bindgen_prelude::AsyncTask;
+use napi::{Env, Error, Result, Status, Task};
+use napi_derive::napi;
+use payment_engine::amount::Amount;
+use payment_engine::config::Config;
+use payment_engine::engine::Engine;
+use payment_engine::funds::{FundingStates, Funds};
+use payment_engine::input::read_input;
+use payment_engine::transactions::{Client, TransactionRecord, Tx, TxType};
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+// Node.js bindings through N-API, so JS services can run the engine
+// in-process instead of shelling out to the CLI. `napi build` (see
+// package.json) compiles them and writes the TypeScript definitions.
+
+// A client's balances, as the CLI prints them.
+#[napi(object)]
+#[derive(Debug, PartialEq, Clone)]
+pub struct Account {
+    pub client: u32,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+}
+
+impl From<&Funds> for Account {
+    fn from(funds: &Funds) -> Account {
+        Account {
+            client: funds.client.0,
+            available: funds.available.to_string(),
+            held: funds.held.to_string(),
+            total: funds.total().to_string(),
+            locked: funds.state == FundingStates::Frozen,
+        }
+    }
+}
+
+fn invalid(reason: impl ToString) -> Error {
+    Error::new(Status::InvalidArg, reason.to_string())
+}
+
+#[napi(js_name = "Engine")]
+pub struct NodeEngine {
+    engine: Engine,
+}
+
+impl Default for NodeEngine {
+    fn default() -> NodeEngine {
+        NodeEngine {
+            engine: Engine::new(),
+        }
+    }
+}
+
+#[napi]
+impl NodeEngine {
+    #[napi(constructor)]
+    pub fn new() -> NodeEngine {
+        NodeEngine::default()
+    }
+
+    // Applies a transaction, with the amount written as in CSV input and
+    // left out for disputes, resolves and chargebacks. Returns "applied" or
+    // the rejection code, and throws on an unknown type, a negative tx or a
+    // bad amount. Tx ids are JS numbers, so exact up to 2^53.
+    #[napi]
+    pub fn apply(
+        &mut self,
+        kind: String,
+        client: u32,
+        tx: i64,
+        amount: Option<String>,
+    ) -> Result<String> {
+        let r#type = TxType::from_str(&kind).map_err(invalid)?;
+        let amount = amount
+            .as_deref()
+            .map(Amount::from_str)
+            .transpose()
+            .map_err(invalid)?;
+        let tx = u64::try_from(tx).map_err(invalid)?;
+        let record = TransactionRecord {
+            r#type,
+            client: Client(client),
+            tx: Tx(tx),
+            amount,
+        };
+        Ok(match self.engine.try_process(&record) {
+            Ok(()) => "applied".to_string(),
+            Err(reason) => reason.as_str().to_string(),
+        })
+    }
+
+    #[napi]
+    pub fn get_account(&self, client: u32) -> Option<Account> {
+        self.engine.account(Client(client)).map(Account::from)
+    }
+}
+
+// Runs a whole file through a fresh engine on libuv's thread pool.
+pub struct ProcessFile {
+    path: String,
+}
+
+fn process_file_sync(path: &str) -> Result<Vec<Account>> {
+    let mut engine = Engine::new();
+    read_input(&mut engine, path, None, &Config::default())
+        .map_err(|err| Error::from_reason(err.to_string()))?;
+    let mut accounts: Vec<Account> = engine.accounts().map(Account::from).collect();
+    accounts.sort_by_key(|account| account.client);
+    Ok(accounts)
+}
+
+impl Task for ProcessFile {
+    type Output = Vec<Account>;
+    type JsValue = Vec<Account>;
+
+    fn compute(&mut self) -> Result<Vec<Account>> {
+        process_file_sync(&self.path)
+    }
+
+    fn resolve(&mut self, _env: Env, accounts: Vec<Account>) -> Result<Vec<Account>> {
+        Ok(accounts)
+    }
+}
+
+// Processes a file as the CLI would, in the format its extension names,
+// resolving to the final accounts ordered by client.
+#[napi(ts_return_type = "Promise<Account[]>")]
+pub fn process_file(path: String) -> AsyncTask<ProcessFile> {
+    AsyncTask::new(ProcessFile { path })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{process_file_sync, Account, NodeEngine};
+    use std::fs;
+
+    #[test]
+    fn test_node_engine() {
+        let mut engine = NodeEngine::new();
+        let apply = |engine: &mut NodeEngine, kind: &str, tx, amount: Option<&str>| {
+            engine.apply(kind.to_string(), 1, tx, amount.map(str::to_string))
+        };
+        assert_eq!(
+            apply(&mut engine, "deposit", 1, Some("2.5")).unwrap(),
+            "applied"
+        );
+        assert_eq!(
+            apply(&mut engine, "withdrawal", 2, Some("3")).unwrap(),
+            "insufficient_funds"
+        );
+        assert!(apply(&mut engine, "refund", 3, None).is_err());
+        assert!(apply(&mut engine, "deposit", -3, Some("1")).is_err());
+        assert!(apply(&mut engine, "deposit", 3, Some("1.23456")).is_err());
+        assert_eq!(apply(&mut engine, "dispute", 1, None).unwrap(), "applied");
+        assert_eq!(
+            engine.get_account(1),
+            Some(Account {
+                client: 1,
+                available: "0.0000".to_string(),
+                held: "2.5000".to_string(),
+                total: "2.5000".to_string(),
+                locked: false,
+            })
+        );
+        assert_eq!(engine.get_account(2), None);
+
+        let path = std::env::temp_dir().join("payment_engine_node.csv");
+        fs::write(
+            &path,
+            "type,client,tx,amount\ndeposit,2,1,1.0\ndeposit,1,2,2.0\nwithdrawal,2,3,0.5\n",
+        )
+        .unwrap();
+        let accounts = process_file_sync(path.to_str().unwrap()).unwrap();
+        let balances: Vec<(u32, &str)> = accounts
+            .iter()
+            .map(|account| (account.client, account.available.as_str()))
+            .collect();
+        assert_eq!(balances, vec![(1, "2.0000"), (2, "0.5000")]);
+        fs::remove_file(&path).unwrap();
+        assert!(process_file_sync("payment_engine_missing.csv").is_err());
+    }
+}
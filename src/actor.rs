@@ -0,0 +1,358 @@
+use crate::csv_dialect::CsvDialect;
+use crate::decoder::{CsvDecoder, RecordDecoder};
+use crate::engine::{Engine, EngineBuilder};
+use crate::error::PaymentResult;
+use crate::transactions::{Client, Ledger, PackedRecord, TransactionRecord, Tx, TxType};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::{self, Read};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread::{self, JoinHandle};
+
+const MAILBOX_SIZE: usize = 1024;
+
+enum Message {
+    // a record, converted by the router as `Engine::process_row` would
+    Record {
+        r#type: TxType,
+        client: Client,
+        tx: Tx,
+        converted: PaymentResult<TransactionRecord>,
+    },
+    Advance(u64),
+    // another worker's log entry, so this worker's engine turns away a
+    // reuse of its id as the one engine would
+    Mirror(Tx, PackedRecord),
+    // answered once everything sent before it was applied
+    Logged(Tx, SyncSender<Option<PackedRecord>>),
+}
+
+fn run_worker(mut engine: Engine, inbox: std::sync::mpsc::Receiver<Message>) -> Engine {
+    for message in inbox {
+        match message {
+            Message::Record {
+                r#type,
+                client,
+                tx,
+                converted,
+            } => {
+                let _ = engine.process_converted(r#type, client, None, tx, None, converted);
+            }
+            Message::Advance(timestamp) => engine.advance_to(timestamp),
+            Message::Mirror(tx, packed) => {
+                engine.ledger_mut().1.insert(tx, packed);
+            }
+            Message::Logged(tx, reply) => {
+                let _ = reply.send(engine.ledger().1.get(&tx).copied());
+            }
+        }
+    }
+    engine
+}
+
+// Each client is owned by one of a fixed set of worker threads, each
+// running its own `Engine` over the clients routed to it, so a client's
+// records keep their input order while different clients are applied in
+// parallel.
+//
+// Tx ids are unique across clients, which the workers can't see on their
+// own, so the router keeps, in input order, which worker's engine may have
+// logged each id. A record reusing an id claimed on another worker waits
+// for that worker to catch up: if the id was logged there, the entry is
+// mirrored into this worker's engine first, which then rejects the reuse
+// exactly as a single engine would; if it wasn't (the claim was rejected),
+// the id is free and the claim moves over. The accounts come out the same
+// as one engine applying the records in order.
+pub struct Router {
+    mailboxes: Vec<SyncSender<Message>>,
+    workers: Vec<JoinHandle<Engine>>,
+    claims: HashMap<Tx, usize>,
+    mirrored: HashSet<(Tx, usize)>,
+}
+
+fn worker_of(client: Client, workers: usize) -> usize {
+    client.0 as usize % workers
+}
+
+impl Router {
+    pub fn new(builder: &EngineBuilder, workers: usize) -> Router {
+        let (mailboxes, workers) = (0..workers.max(1))
+            .map(|_| {
+                let (sender, inbox) = sync_channel(MAILBOX_SIZE);
+                let engine = builder.clone().build();
+                (sender, thread::spawn(move || run_worker(engine, inbox)))
+            })
+            .unzip();
+        Router {
+            mailboxes,
+            workers,
+            claims: HashMap::new(),
+            mirrored: HashSet::new(),
+        }
+    }
+
+    fn send(&self, worker: usize, message: Message) {
+        self.mailboxes[worker]
+            .send(message)
+            .expect("client worker stopped unexpectedly");
+    }
+
+    fn logged(&self, worker: usize, tx: Tx) -> Option<PackedRecord> {
+        let (reply, answer) = sync_channel(1);
+        self.send(worker, Message::Logged(tx, reply));
+        answer.recv().expect("client worker stopped unexpectedly")
+    }
+
+    // Settles which worker owns `tx` before a record of `worker` uses it.
+    fn claim(&mut self, worker: usize, tx: Tx, opens: bool) {
+        let owner = match self.claims.get(&tx) {
+            Some(&owner) if owner != worker => owner,
+            Some(_) => return,
+            None => {
+                if opens {
+                    self.claims.insert(tx, worker);
+                }
+                return;
+            }
+        };
+        if self.mirrored.contains(&(tx, worker)) {
+            return;
+        }
+        match self.logged(owner, tx) {
+            Some(packed) => {
+                self.send(worker, Message::Mirror(tx, packed));
+                self.mirrored.insert((tx, worker));
+            }
+            None if opens => {
+                self.claims.insert(tx, worker);
+            }
+            None => {}
+        }
+    }
+
+    pub fn dispatch(&mut self, record: TransactionRecord) {
+        self.dispatch_converted(record.r#type, record.client, record.tx, Ok(record));
+    }
+
+    fn dispatch_converted(
+        &mut self,
+        r#type: TxType,
+        client: Client,
+        tx: Tx,
+        converted: PaymentResult<TransactionRecord>,
+    ) {
+        let worker = worker_of(client, self.mailboxes.len());
+        let opens = matches!(
+            r#type,
+            TxType::Deposit | TxType::Withdrawal | TxType::Credit
+        );
+        self.claim(worker, tx, opens);
+        let message = Message::Record {
+            r#type,
+            client,
+            tx,
+            converted,
+        };
+        self.send(worker, message);
+    }
+
+    // Moves every worker's clock, see `Engine::advance_to`.
+    pub fn advance_to(&self, timestamp: u64) {
+        for worker in 0..self.mailboxes.len() {
+            self.send(worker, Message::Advance(timestamp));
+        }
+    }
+
+    // The workers' engines, once everything dispatched was applied.
+    pub fn finish(self) -> Vec<Engine> {
+        drop(self.mailboxes);
+        self.workers
+            .into_iter()
+            .map(|worker| worker.join().expect("client worker panicked"))
+            .collect()
+    }
+}
+
+// Reads CSV input as `Engine::process_csv_with` does and dispatches the rows
+// to the router. Bad rows are handled by `engine` as they would be there,
+// and captured columns are kept on it. Rows naming a sub-account are an
+// error: the router can't resolve them without the parent's worker.
+pub fn route_csv<R: Read>(
+    router: &mut Router,
+    engine: &mut Engine,
+    input: R,
+    dialect: &CsvDialect,
+) -> csv::Result<()> {
+    let mut decoder = CsvDecoder::new(input, dialect)?;
+    let capture = decoder.captures_columns();
+    while let Some(row) = decoder.next() {
+        let row = match row {
+            Ok(row) => row,
+            Err(err) => {
+                engine.skip_bad_row(err, dialect)?;
+                continue;
+            }
+        };
+        if row.subaccount().is_some() {
+            let err = io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sub-accounts can't be applied by client workers",
+            );
+            return Err(err.into());
+        }
+        if let Some(timestamp) = row.timestamp() {
+            router.advance_to(timestamp);
+        }
+        let (r#type, client, tx) = (row.r#type(), row.client(), row.tx());
+        router.dispatch_converted(r#type, client, tx, TransactionRecord::try_from(row));
+        if capture {
+            let columns = decoder
+                .extra_columns()
+                .map(|(name, value)| (name.to_string(), value.to_string()));
+            engine.add_metadata(tx, columns);
+        }
+    }
+    Ok(())
+}
+
+// Gathers the workers' accounts and transaction logs into `engine`, leaving
+// out the entries mirrored from other workers, along with any invariant
+// violations they found. Counters, history and the other per-engine reports
+// stay with the workers.
+pub fn merge(engine: &mut Engine, workers: Vec<Engine>) {
+    let count = workers.len();
+    let mut ledger = Ledger::default();
+    for (index, worker) in workers.iter().enumerate() {
+        let (accounts, records) = worker.ledger();
+        ledger.accounts.extend(
+            accounts
+                .iter()
+                .map(|(client, funds)| (*client, funds.clone())),
+        );
+        let owned = records
+            .iter()
+            .filter(|(_, packed)| worker_of(packed.client(), count) == index);
+        ledger
+            .records
+            .extend(owned.map(|(tx, packed)| (*tx, *packed)));
+        engine.note_violations(worker.violations());
+    }
+    engine.import_ledger(ledger);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, route_csv, Router};
+    use crate::amount::Amount;
+    use crate::csv_dialect::CsvDialect;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::transactions::{Client, TransactionRecord, Tx, TxType};
+
+    fn record(r#type: TxType, client: u32, tx: u64, amount: Option<u64>) -> TransactionRecord {
+        TransactionRecord {
+            r#type,
+            client: Client(client),
+            tx: Tx(tx),
+            amount: amount.map(Amount::new),
+        }
+    }
+
+    fn route(records: &[TransactionRecord], workers: usize) -> Engine {
+        let mut router = Router::new(&Engine::builder(), workers);
+        for record in records {
+            router.dispatch(record.clone());
+        }
+        let mut engine = Engine::new();
+        merge(&mut engine, router.finish());
+        engine
+    }
+
+    fn assert_same_accounts(routed: &Engine, expected: &Engine) {
+        let mut accounts: Vec<_> = routed.accounts().collect();
+        let mut want: Vec<_> = expected.accounts().collect();
+        accounts.sort_by_key(|funds| funds.client.0);
+        want.sort_by_key(|funds| funds.client.0);
+        assert_eq!(accounts, want);
+    }
+
+    #[test]
+    fn test_router_keeps_client_order() {
+        let mut records = Vec::new();
+        for client in 1..=20 {
+            let tx = u64::from(client) * 10;
+            records.push(record(TxType::Deposit, client, tx, Some(500)));
+            records.push(record(TxType::Deposit, client, tx + 1, Some(200)));
+            records.push(record(TxType::Dispute, client, tx, None));
+        }
+        let engine = route(&records, 4);
+        assert_eq!(engine.accounts().count(), 20);
+        for fund in engine.accounts() {
+            assert_eq!(fund.available, Amount::new(200));
+            assert_eq!(fund.held, Amount::new(500));
+            assert_eq!(fund.state, FundingStates::Disputed);
+        }
+    }
+
+    // Tx ids reused across clients on different workers are decided in
+    // input order, as one engine decides them.
+    #[test]
+    fn test_router_matches_engine() {
+        let records = [
+            record(TxType::Deposit, 1, 1, Some(100)),
+            // tx 1 is client 1's: a duplicate, and not client 2's to dispute
+            record(TxType::Deposit, 2, 1, Some(50)),
+            record(TxType::Deposit, 2, 2, Some(10)),
+            record(TxType::Dispute, 2, 1, None),
+            // a rejected withdrawal leaves tx 3 free for client 2
+            record(TxType::Withdrawal, 1, 3, Some(1000)),
+            record(TxType::Deposit, 2, 3, Some(20)),
+            record(TxType::Deposit, 1, 3, Some(5)),
+            record(TxType::Dispute, 1, 1, None),
+            record(TxType::Chargeback, 1, 1, None),
+            record(TxType::Deposit, 3, 1, Some(7)),
+        ];
+        let mut expected = Engine::new();
+        records.iter().for_each(|record| expected.process(record));
+        for workers in 1..=4 {
+            let engine = route(&records, workers);
+            assert_same_accounts(&engine, &expected);
+            assert_eq!(engine.export_ledger(), expected.export_ledger());
+        }
+
+        // of eight clients reusing one id only the first gets it
+        let records: Vec<_> = (1..=8)
+            .map(|client| record(TxType::Deposit, client, 7, Some(100)))
+            .collect();
+        let engine = route(&records, 4);
+        assert_eq!(engine.accounts().count(), 1);
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(100)
+        );
+    }
+
+    #[test]
+    fn test_route_csv() {
+        let input = "type,client,tx,amount\ndeposit,1,1,5\ndeposit,2,1,3\ndeposit,2,2,x\ndeposit,2,3,2\nwithdrawal,1,4,1\ndispute,1,1,\n";
+        let dialect = CsvDialect {
+            permissive: true,
+            ..CsvDialect::default()
+        };
+        let mut expected = Engine::new();
+        expected
+            .process_csv_with(input.as_bytes(), &dialect)
+            .unwrap();
+
+        let mut router = Router::new(&Engine::builder(), 2);
+        let mut engine = Engine::new();
+        route_csv(&mut router, &mut engine, input.as_bytes(), &dialect).unwrap();
+        merge(&mut engine, router.finish());
+        assert_same_accounts(&engine, &expected);
+        assert_eq!(engine.metrics().rows_malformed, 1);
+
+        let mut router = Router::new(&Engine::builder(), 2);
+        let subaccount = "type,client,tx,amount\ndeposit,1:savings,1,5\n";
+        assert!(route_csv(&mut router, &mut engine, subaccount.as_bytes(), &dialect).is_err());
+    }
+}
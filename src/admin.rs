@@ -0,0 +1,230 @@
+use crate::audit::AuditRow;
+use crate::engine::Engine;
+use crate::funds::Funds;
+use crate::output::{write_accounts, AccountRow};
+use crate::serve::{SharedTenants, WeakTenants};
+use crate::shared::SharedEngine;
+use crate::transactions::Client;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+fn error(message: impl ToString) -> Value {
+    json!({ "error": message.to_string() })
+}
+
+fn client(arg: Option<&str>) -> Result<Client, Value> {
+    let arg = arg.ok_or_else(|| error("missing client"))?;
+    arg.parse()
+        .map(Client)
+        .map_err(|_| error(format!("invalid client {:?}", arg)))
+}
+
+fn account(funds: Option<Funds>, client: Client) -> Value {
+    match funds {
+        Some(funds) => json!(AccountRow::from(&funds)),
+        None => error(format!("no account for client {}", client.0)),
+    }
+}
+
+fn metrics(engine: &Engine) -> Value {
+    let metrics = engine.metrics();
+    let rejected: serde_json::Map<_, _> = metrics
+        .rejected
+        .iter()
+        .map(|(reason, count)| (reason.as_str().to_string(), json!(count)))
+        .collect();
+    json!({
+        "records_read": metrics.records_read,
+        "records_applied": metrics.records_applied,
+        "records_rejected": metrics.records_rejected(),
+        "rejected": rejected,
+        "rows_malformed": metrics.rows_malformed,
+        "clients": metrics.clients,
+        "log_size": metrics.log_size,
+        "records_per_sec": metrics.records_per_sec,
+    })
+}
+
+// Writes the accounts as CSV next to `path` first, so a reader never sees
+// a half-written snapshot. The accounts are one consistent cut, taken
+// without holding up writes.
+fn snapshot(engine: &SharedEngine, path: &str) -> Value {
+    let accounts = engine.accounts();
+    let partial = format!("{}.partial", path);
+    let written = File::create(&partial)
+        .map_err(csv::Error::from)
+        .and_then(|file| write_accounts(accounts.iter(), file))
+        .map_err(|err| err.to_string())
+        .and_then(|()| fs::rename(&partial, path).map_err(|err| err.to_string()));
+    match written {
+        Ok(()) => json!({ "snapshot": path, "accounts": accounts.len() }),
+        Err(err) => error(err),
+    }
+}
+
+fn audit(engine: &Engine, client: Client) -> Value {
+    match engine.audit() {
+        Some(log) => json!(log.client(client).map(AuditRow::from).collect::<Vec<_>>()),
+        None => error("no audit log is kept"),
+    }
+}
+
+// Rereads the denylist file; every tenant shares the list.
+fn reload_denylist(engine: &Engine) -> Value {
+    match engine.denylist().map(|list| list.reload()) {
+        Some(Ok(entries)) => json!({ "denylist_entries": entries }),
+        Some(Err(err)) => error(err),
+        None => error("no denylist is configured"),
+    }
+}
+
+// Runs one admin command and returns its JSON reply:
+// `balance <client>`, `unfreeze <client>`, `audit <client>`, `metrics`,
+// `snapshot <path>` or `reload-denylist`, optionally followed by the tenant to run it against.
+pub fn command(tenants: &SharedTenants, line: &str) -> Value {
+    let mut args = line.split_whitespace();
+    let name = args.next();
+    let arg = match name {
+        Some("metrics") | Some("reload-denylist") | None => None,
+        Some(_) => args.next(),
+    };
+    let tenant = match tenants.resolve(args.next()) {
+        Ok(tenant) => tenant,
+        Err(err) => return error(err),
+    };
+    let engine = match tenants.get(&tenant) {
+        Some(engine) => engine,
+        None => return error(format!("no tenant {:?}", tenant)),
+    };
+    let result = match name {
+        Some("balance") => client(arg).map(|client| account(engine.account(client), client)),
+        Some("unfreeze") => client(arg).map(|client| {
+            engine.with_engine(|engine| match engine.unfreeze(client) {
+                Some(true) => account(engine.account(client).cloned(), client),
+                Some(false) => error("account is not frozen"),
+                None => error(format!("no account for client {}", client.0)),
+            })
+        }),
+        Some("audit") => client(arg).map(|client| engine.read(|engine| audit(engine, client))),
+        Some("metrics") => Ok(engine.read(metrics)),
+        Some("reload-denylist") => Ok(engine.read(reload_denylist)),
+        Some("snapshot") => match arg {
+            Some(path) => Ok(snapshot(&engine, path)),
+            None => Err(error("missing snapshot path")),
+        },
+        Some(other) => Err(error(format!("unknown command {:?}", other))),
+        None => Err(error("empty command")),
+    };
+    result.unwrap_or_else(|err| err)
+}
+
+fn handle(tenants: &WeakTenants, stream: UnixStream) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // the server has shut down once the engines can't be upgraded
+        let tenants = match tenants.upgrade() {
+            Some(tenants) => tenants,
+            None => return Ok(()),
+        };
+        let reply = command(&tenants, &line);
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+// A bound admin socket. Connections are served one at a time on a
+// background thread; the socket file is removed when this is dropped.
+pub struct AdminSocket {
+    path: PathBuf,
+}
+
+impl AdminSocket {
+    // Only holds a weak reference, so the server can still take the engines
+    // back when it stops.
+    pub fn bind(path: &Path, tenants: &SharedTenants) -> io::Result<AdminSocket> {
+        // a socket file left behind by a previous run would fail the bind
+        if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        let tenants = tenants.downgrade();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|stream| handle(&tenants, stream));
+                if let Err(err) = result {
+                    tracing::warn!(error = %err, "admin connection failed");
+                }
+            }
+        });
+        Ok(AdminSocket {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for AdminSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::command;
+    use crate::engine::Engine;
+    use crate::serve::SharedTenants;
+    use crate::tenant::{Tenants, DEFAULT_TENANT};
+    use crate::transactions::{Client, TransactionRecord, Tx};
+    use serde_json::json;
+
+    #[test]
+    fn test_commands() {
+        let mut tenants = Tenants::single(Engine::builder());
+        let deposit = TransactionRecord::deposit(Client(1), Tx(1), "2.5".parse().unwrap());
+        tenants.engine(DEFAULT_TENANT).process(&deposit);
+        let tenants = SharedTenants::new(tenants);
+        assert_eq!(
+            command(&tenants, "balance 1"),
+            json!({"client": 1, "available": "2.5000", "held": "0.0000", "total": "2.5000", "locked": false})
+        );
+        assert_eq!(
+            command(&tenants, "unfreeze 1"),
+            json!({ "error": "account is not frozen" })
+        );
+        assert_eq!(command(&tenants, "metrics")["records_applied"], 1);
+        assert_eq!(
+            command(&tenants, "reload-denylist"),
+            json!({ "error": "no denylist is configured" })
+        );
+        assert_eq!(
+            command(&tenants, "audit 1"),
+            json!({ "error": "no audit log is kept" })
+        );
+        assert_eq!(
+            command(&tenants, "balance x"),
+            json!({ "error": "invalid client \"x\"" })
+        );
+        assert_eq!(
+            command(&tenants, "balance 1 acme"),
+            json!({ "error": "unknown tenant \"acme\": tenants are not enabled" })
+        );
+
+        let path = std::env::temp_dir().join("payment_engine_admin_snapshot.csv");
+        let reply = command(&tenants, &format!("snapshot {}", path.display()));
+        assert_eq!(reply["accounts"], 1);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "client,available,held,total,locked\n1,2.5000,0.0000,2.5000,false\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+use crate::amount::Amount;
+use crate::transactions::{Client, TransactionRecord, TxType};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+// Patterns worth a closer look by a compliance team. A flag stays on the
+// account once raised.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskFlag {
+    // repeated deposits just under the reporting threshold
+    Structuring,
+    // money withdrawn again soon after it was deposited
+    RapidCycling,
+    // more disputes than a customer normally raises
+    DisputeAbuse,
+}
+
+impl RiskFlag {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RiskFlag::Structuring => "structuring",
+            RiskFlag::RapidCycling => "rapid_cycling",
+            RiskFlag::DisputeAbuse => "dispute_abuse",
+        }
+    }
+}
+
+// The `[aml]` config section. Records carry no time, so "soon" and
+// "repeated" are measured in a client's own transactions: the last `window`
+// deposits and withdrawals applied to it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AmlConfig {
+    // deposits within 10% under it count towards structuring, e.g.
+    // `reporting_threshold = "10000.00"`; without one structuring isn't
+    // looked for
+    #[serde(deserialize_with = "crate::amount::threshold")]
+    pub reporting_threshold: Option<Amount>,
+    pub structuring_deposits: usize,
+    // a withdrawal of at least this share of a deposit in the window
+    pub cycle_percent: u64,
+    pub dispute_limit: u32,
+    pub window: usize,
+    // rejects deposits and withdrawals of flagged clients; by default flags
+    // are only reported
+    pub block: bool,
+}
+
+impl Default for AmlConfig {
+    fn default() -> AmlConfig {
+        AmlConfig {
+            reporting_threshold: None,
+            structuring_deposits: 3,
+            cycle_percent: 90,
+            dispute_limit: 3,
+            window: 10,
+            block: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClientActivity {
+    // deposits and withdrawals, most recent last
+    recent: VecDeque<(TxType, Amount)>,
+    disputes: u32,
+    flags: Vec<RiskFlag>,
+}
+
+// Watches what an engine applies for the patterns in `RiskFlag`. Each
+// engine gets its own, so state never crosses tenants.
+#[derive(Debug)]
+pub struct AmlMonitor {
+    config: AmlConfig,
+    clients: HashMap<Client, ClientActivity>,
+}
+
+impl AmlMonitor {
+    pub fn new(config: AmlConfig) -> AmlMonitor {
+        AmlMonitor {
+            config,
+            clients: HashMap::new(),
+        }
+    }
+
+    pub fn flags(&self, client: Client) -> &[RiskFlag] {
+        self.clients
+            .get(&client)
+            .map_or(&[], |activity| activity.flags.as_slice())
+    }
+
+    // Whether `record` is turned away because its client is flagged.
+    pub(crate) fn blocks(&self, record: &TransactionRecord) -> bool {
+        self.config.block
+            && matches!(record.r#type, TxType::Deposit | TxType::Withdrawal)
+            && !self.flags(record.client).is_empty()
+    }
+
+    fn structuring(&self, activity: &ClientActivity) -> bool {
+        let threshold = match self.config.reporting_threshold {
+            Some(threshold) => threshold.0,
+            None => return false,
+        };
+        let floor = threshold - threshold / 10;
+        let just_under = activity
+            .recent
+            .iter()
+            .filter(|(r#type, amount)| {
+                *r#type == TxType::Deposit && (floor..threshold).contains(&amount.0)
+            })
+            .count();
+        just_under >= self.config.structuring_deposits
+    }
+
+    fn rapid_cycling(&self, activity: &ClientActivity, withdrawn: Amount) -> bool {
+        let withdrawn = u128::from(withdrawn.0) * 100;
+        activity.recent.iter().any(|(r#type, deposited)| {
+            *r#type == TxType::Deposit
+                && withdrawn >= u128::from(deposited.0) * u128::from(self.config.cycle_percent)
+        })
+    }
+
+    // Called with an applied record and the amount it moved; returns the
+    // flags it raised.
+    pub(crate) fn observe(
+        &mut self,
+        record: &TransactionRecord,
+        amount: Option<Amount>,
+    ) -> Vec<RiskFlag> {
+        let mut activity = self.clients.remove(&record.client).unwrap_or_default();
+        let mut raised = Vec::new();
+        match (record.r#type, amount) {
+            (TxType::Deposit, Some(amount)) => {
+                activity.recent.push_back((TxType::Deposit, amount));
+                if self.structuring(&activity) {
+                    raised.push(RiskFlag::Structuring);
+                }
+            }
+            (TxType::Withdrawal, Some(amount)) => {
+                if self.rapid_cycling(&activity, amount) {
+                    raised.push(RiskFlag::RapidCycling);
+                }
+                activity.recent.push_back((TxType::Withdrawal, amount));
+            }
+            (TxType::Dispute, _) => {
+                activity.disputes += 1;
+                if activity.disputes >= self.config.dispute_limit {
+                    raised.push(RiskFlag::DisputeAbuse);
+                }
+            }
+            _ => {}
+        }
+        while activity.recent.len() > self.config.window {
+            activity.recent.pop_front();
+        }
+        raised.retain(|flag| !activity.flags.contains(flag));
+        activity.flags.extend(&raised);
+        activity.flags.sort();
+        self.clients.insert(record.client, activity);
+        raised
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AmlConfig, RiskFlag};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::output::write_extended_accounts;
+    use crate::risk::{RiskEvent, RiskMonitor};
+    use crate::tenant::Tenants;
+    use crate::transactions::{Client, RejectReason};
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_aml_flags() {
+        let config = AmlConfig {
+            reporting_threshold: Some(Amount::new(100_000)),
+            dispute_limit: 2,
+            block: true,
+            ..AmlConfig::default()
+        };
+        let (sender, events) = mpsc::channel();
+        let builder = Engine::builder()
+            .risk_monitor(RiskMonitor::new(sender, None))
+            .aml(config);
+        let mut tenants = Tenants::single(builder);
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,9.5\ndeposit,1,2,9.9\ndeposit,1,3,2.0\ndeposit,1,4,9.2\ndeposit,1,5,1.0\n\
+            deposit,2,6,50.0\nwithdrawal,2,7,48.0\ndeposit,3,8,5.0\ndeposit,3,9,5.0\ndispute,3,8,\nresolve,3,8,\ndispute,3,9,\n";
+        let engine = tenants.engine("default");
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        assert_eq!(engine.risk_flags(Client(1)), [RiskFlag::Structuring]);
+        assert_eq!(engine.risk_flags(Client(2)), [RiskFlag::RapidCycling]);
+        assert_eq!(engine.risk_flags(Client(3)), [RiskFlag::DisputeAbuse]);
+        // blocked once flagged
+        assert_eq!(engine.metrics().rejected[&RejectReason::RiskFlagged], 1);
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(306_000)
+        );
+        assert!(events.try_iter().any(|event| event
+            == RiskEvent::Flagged {
+                client: 2,
+                tx: 7,
+                flag: RiskFlag::RapidCycling
+            }));
+
+        let mut out = Vec::new();
+        write_extended_accounts(&tenants, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked,flags,risk_score\n1,30.6000,0.0000,30.6000,false,structuring,\n2,2.0000,0.0000,2.0000,false,rapid_cycling,\n3,5.0000,5.0000,10.0000,false,dispute_abuse,\n"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::stream::{default_snapshot_every, Delivery, Payload, Snapshots, StreamApplier};
+use crate::tenant::{Tenants, DEFAULT_TENANT};
+use crate::wal::Position;
+use futures_util::StreamExt;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicPublishOptions, BasicQosOptions,
+    QueueDeclareOptions,
+};
+use lapin::types::FieldTable;
+use lapin::Acker;
+use lapin::{Channel, Connection, ConnectionProperties};
+use serde::Deserialize;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// The `[amqp]` config section. `prefetch` bounds how many deliveries the
+// broker sends before the engine acks, so a slow engine pushes back on the
+// broker instead of buffering the queue in memory.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AmqpConfig {
+    pub url: String,
+    pub queue: String,
+    #[serde(default = "default_prefetch")]
+    pub prefetch: u16,
+    // unparseable messages are republished here; without one they are
+    // rejected, which dead-letters them if the queue has a DLX
+    pub reject_queue: Option<String>,
+    #[serde(default)]
+    pub payload: Payload,
+    #[serde(default)]
+    pub delivery: Delivery,
+    pub wal: Option<PathBuf>,
+    #[serde(default = "default_flush_every")]
+    pub flush_every: usize,
+    // also keep a snapshot of the ledgers here (needs `wal`), so a restart
+    // doesn't replay everything ever consumed
+    pub snapshot: Option<PathBuf>,
+    #[serde(default = "default_snapshot_every")]
+    pub snapshot_every: usize,
+    // stop once no message arrived for this long; run forever when unset
+    pub idle_timeout_ms: Option<u64>,
+}
+
+fn default_prefetch() -> u16 {
+    100
+}
+
+fn default_flush_every() -> usize {
+    1000
+}
+
+// Acks everything up to the last applied delivery in one `multiple` ack,
+// once the WAL holds those records.
+async fn ack(
+    applier: &mut StreamApplier,
+    last: &mut Option<Acker>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    applier.flush()?;
+    if let Some(acker) = last.take() {
+        acker.ack(BasicAckOptions { multiple: true }).await?;
+    }
+    Ok(())
+}
+
+async fn dead_letter(
+    channel: &Channel,
+    config: &AmqpConfig,
+    delivery: lapin::message::Delivery,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match &config.reject_queue {
+        Some(queue) => {
+            channel
+                .basic_publish(
+                    "".into(),
+                    queue.as_str().into(),
+                    BasicPublishOptions::default(),
+                    &delivery.data,
+                    delivery.properties.clone(),
+                )
+                .await?
+                .await?;
+            delivery.acker.ack(BasicAckOptions::default()).await?;
+        }
+        None => {
+            let options = BasicNackOptions {
+                multiple: false,
+                requeue: false,
+            };
+            delivery.acker.nack(options).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn run(
+    mut applier: StreamApplier,
+    config: &AmqpConfig,
+) -> Result<Tenants, Box<dyn Error + Send + Sync>> {
+    let connection = Connection::connect(&config.url, ConnectionProperties::default()).await?;
+    let channel = connection.create_channel().await?;
+    channel
+        .basic_qos(config.prefetch, BasicQosOptions::default())
+        .await?;
+    if let Some(queue) = &config.reject_queue {
+        let options = QueueDeclareOptions {
+            durable: true,
+            ..Default::default()
+        };
+        channel
+            .queue_declare(queue.as_str().into(), options, FieldTable::default())
+            .await?;
+    }
+    let mut consumer = channel
+        .basic_consume(
+            config.queue.as_str().into(),
+            "payment_engine".into(),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    let stream = format!("amqp/{}", config.queue);
+    let idle_timeout = config.idle_timeout_ms.map(Duration::from_millis);
+    let mut last_message = Instant::now();
+    let mut last = None;
+    let mut unacked = 0;
+    loop {
+        let delivery = match tokio::time::timeout(Duration::from_millis(100), consumer.next()).await
+        {
+            Ok(Some(delivery)) => delivery?,
+            Ok(None) => break,
+            Err(_) => {
+                ack(&mut applier, &mut last).await?;
+                unacked = 0;
+                if idle_timeout.is_some_and(|timeout| last_message.elapsed() >= timeout) {
+                    break;
+                }
+                continue;
+            }
+        };
+        last_message = Instant::now();
+        match config.payload.decode(&delivery.data) {
+            Ok(record) => {
+                let position = Position {
+                    stream: stream.clone(),
+                    offset: delivery.delivery_tag as i64,
+                };
+                applier.apply(DEFAULT_TENANT, position, &record)?;
+                last = Some(delivery.acker);
+                unacked += 1;
+            }
+            Err(err) => {
+                tracing::warn!(
+                    queue = %config.queue,
+                    error = %err,
+                    "rejecting message that can't be decoded"
+                );
+                dead_letter(&channel, config, delivery).await?;
+            }
+        }
+        // the broker stops delivering once `prefetch` messages are unacked
+        if applier.should_flush() || unacked >= usize::from(config.prefetch) {
+            ack(&mut applier, &mut last).await?;
+            unacked = 0;
+        }
+    }
+    ack(&mut applier, &mut last).await?;
+    connection.close(200, "OK".into()).await?;
+    Ok(applier.into_tenants())
+}
+
+// Consumes `config.queue` until it goes idle (or forever). AMQP has no
+// replayable position to log, so only at-least-once delivery is supported:
+// deliveries the engine hadn't acked before a crash are redelivered.
+pub fn consume(
+    tenants: Tenants,
+    config: &AmqpConfig,
+) -> Result<Tenants, Box<dyn Error + Send + Sync>> {
+    if config.delivery == Delivery::ExactlyOnce {
+        return Err("AMQP sources only support at-least-once delivery".into());
+    }
+    let applier = StreamApplier::open(
+        tenants,
+        config.wal.as_deref(),
+        config.snapshot.clone().map(|path| Snapshots {
+            path,
+            every: config.snapshot_every,
+        }),
+        config.delivery,
+        config.flush_every,
+    )?;
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(applier, config))
+}
@@ -0,0 +1,201 @@
+use crate::engine::Engine;
+use crate::funds::{FundingStates, Funds};
+use crate::transactions::{amount_field, Client, RowRecord, Tx, TxType};
+use arrow::array::{
+    Array, ArrayRef, AsArray, BooleanArray, Decimal128Array, RecordBatch, UInt32Array,
+};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, UInt32Type, UInt64Type};
+use arrow::ipc::reader::{FileReader, StreamReader};
+use arrow::ipc::writer::StreamWriter;
+use std::error::Error;
+use std::io::{Cursor, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub type ArrowResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+fn column(batch: &RecordBatch, name: &str, to: &DataType) -> ArrowResult<ArrayRef> {
+    let array = batch
+        .column_by_name(name)
+        .ok_or_else(|| format!("input is missing the `{}` column", name))?;
+    Ok(cast(array, to)?)
+}
+
+// Columns are cast to the engine's types, so integer widths and the amount
+// encoding (string, decimal or float) are up to the producer. Amounts go
+// through their text form so decimals keep their exact value.
+fn process_batch(engine: &mut Engine, batch: &RecordBatch) -> ArrowResult<()> {
+    let types = column(batch, "type", &DataType::Utf8)?;
+    let clients = column(batch, "client", &DataType::UInt32)?;
+    let txs = column(batch, "tx", &DataType::UInt64)?;
+    let amounts = column(batch, "amount", &DataType::Utf8)?;
+    let (types, amounts) = (types.as_string::<i32>(), amounts.as_string::<i32>());
+    let clients = clients.as_primitive::<UInt32Type>();
+    let txs = txs.as_primitive::<UInt64Type>();
+
+    for row in 0..batch.num_rows() {
+        if types.is_null(row) || clients.is_null(row) || txs.is_null(row) {
+            return Err(format!("row {} has a null type, client or tx", row).into());
+        }
+        let amount = if amounts.is_null(row) {
+            None
+        } else {
+            amount_field(amounts.value(row))?
+        };
+        let record = RowRecord::new(
+            TxType::from_str(types.value(row))?,
+            Client(clients.value(row)),
+            Tx(txs.value(row)),
+            amount,
+        );
+        let _ = engine.process_row(record);
+    }
+    Ok(())
+}
+
+const IPC_FILE_MAGIC: &[u8] = b"ARROW1";
+const AMOUNT_PRECISION: u8 = 20;
+const AMOUNT_SCALE: i8 = 4;
+
+fn amount_column<'a>(
+    accounts: &[&'a Funds],
+    amount: impl Fn(&'a Funds) -> u64,
+) -> ArrowResult<ArrayRef> {
+    let values = accounts.iter().map(|funds| amount(funds) as i128);
+    Ok(Arc::new(
+        Decimal128Array::from_iter_values(values)
+            .with_precision_and_scale(AMOUNT_PRECISION, AMOUNT_SCALE)?,
+    ))
+}
+
+// Balances use Decimal128 with four fractional digits, the same fixed point
+// representation `Amount` uses internally.
+pub fn accounts_batch<'a, I>(accounts: I) -> ArrowResult<RecordBatch>
+where
+    I: IntoIterator<Item = &'a Funds>,
+{
+    let mut accounts: Vec<&Funds> = accounts.into_iter().collect();
+    accounts.sort_by_key(|funds| funds.client.0);
+    let amount = DataType::Decimal128(AMOUNT_PRECISION, AMOUNT_SCALE);
+    let schema = Schema::new(vec![
+        Field::new("client", DataType::UInt32, false),
+        Field::new("available", amount.clone(), false),
+        Field::new("held", amount.clone(), false),
+        Field::new("total", amount, false),
+        Field::new("locked", DataType::Boolean, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt32Array::from_iter_values(
+            accounts.iter().map(|funds| funds.client.0),
+        )),
+        amount_column(&accounts, |funds| funds.available.0)?,
+        amount_column(&accounts, |funds| funds.held.0)?,
+        amount_column(&accounts, |funds| funds.total().0)?,
+        Arc::new(BooleanArray::from(
+            accounts
+                .iter()
+                .map(|funds| funds.state == FundingStates::Frozen)
+                .collect::<Vec<_>>(),
+        )),
+    ];
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+pub fn write_accounts_arrow<'a, W, I>(accounts: I, writer: W) -> ArrowResult<()>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a Funds>,
+{
+    let batch = accounts_batch(accounts)?;
+    let mut writer = StreamWriter::try_new(writer, &batch.schema())?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(())
+}
+
+impl Engine {
+    pub fn process_record_batch(&mut self, batch: &RecordBatch) -> ArrowResult<()> {
+        process_batch(self, batch)
+    }
+
+    // Takes either the IPC file format (Feather v2) or the IPC stream format.
+    pub fn process_arrow_ipc(&mut self, input: &[u8]) -> ArrowResult<()> {
+        if input.starts_with(IPC_FILE_MAGIC) {
+            for batch in FileReader::try_new(Cursor::new(input), None)? {
+                self.process_record_batch(&batch?)?;
+            }
+        } else {
+            for batch in StreamReader::try_new(input, None)? {
+                self.process_record_batch(&batch?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accounts_batch, write_accounts_arrow};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::Client;
+    use arrow::array::{AsArray, Float64Array, RecordBatch, StringArray, UInt32Array};
+    use arrow::datatypes::Decimal128Type;
+    use arrow::ipc::reader::StreamReader;
+    use arrow::ipc::writer::{FileWriter, StreamWriter};
+    use std::sync::Arc;
+
+    fn transactions() -> RecordBatch {
+        RecordBatch::try_from_iter(vec![
+            (
+                "type",
+                Arc::new(StringArray::from(vec!["deposit", "deposit", "withdrawal"])) as _,
+            ),
+            ("client", Arc::new(UInt32Array::from(vec![2, 1, 2])) as _),
+            ("tx", Arc::new(UInt32Array::from(vec![1, 2, 3])) as _),
+            (
+                "amount",
+                Arc::new(Float64Array::from(vec![Some(2.5), Some(1.0), Some(0.5)])) as _,
+            ),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_arrow_ipc_round_trip() {
+        let batch = transactions();
+        let mut stream = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut stream, &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        let mut file = Vec::new();
+        let mut writer = FileWriter::try_new(&mut file, &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        for input in [stream, file].iter() {
+            let mut engine = Engine::new();
+            engine.process_arrow_ipc(input).unwrap();
+            assert_eq!(
+                engine.account(Client(2)).unwrap().available,
+                Amount::new(20000)
+            );
+        }
+
+        let mut engine = Engine::new();
+        engine.process_record_batch(&batch).unwrap();
+        let mut out = Vec::new();
+        write_accounts_arrow(engine.accounts(), &mut out).unwrap();
+        let batches: Vec<RecordBatch> = StreamReader::try_new(out.as_slice(), None)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(batches, vec![accounts_batch(engine.accounts()).unwrap()]);
+        let available = batches[0].column(1).as_primitive::<Decimal128Type>();
+        assert_eq!(available.value_as_string(0), "1.0000");
+        assert_eq!(available.value_as_string(1), "2.0000");
+    }
+}
@@ -0,0 +1,207 @@
+use crate::amount::Amount;
+use crate::funds::{FundingStates, Funds};
+use crate::transactions::{Client, Tx, TxType};
+use serde::Serialize;
+use std::collections::HashMap;
+
+// What changed an account's balances or state.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum AuditReason {
+    Applied(TxType),
+    // a `freeze client` rule (see `policy`)
+    RuleFreeze,
+    // a record refused by a denylist in freeze mode
+    DenylistFreeze,
+    // an operator's unfreeze through the admin socket or REST API
+    Unfreeze,
+}
+
+impl AuditReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditReason::Applied(r#type) => r#type.as_str(),
+            AuditReason::RuleFreeze => "rule_freeze",
+            AuditReason::DenylistFreeze => "denylist_freeze",
+            AuditReason::Unfreeze => "unfreeze",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Balance {
+    pub available: Amount,
+    pub held: Amount,
+    pub state: FundingStates,
+}
+
+impl From<&Funds> for Balance {
+    fn from(funds: &Funds) -> Balance {
+        Balance {
+            available: funds.available,
+            held: funds.held,
+            state: funds.state,
+        }
+    }
+}
+
+impl Balance {
+    // What an account that doesn't exist yet starts from.
+    pub(crate) fn of(funds: Option<&Funds>) -> Balance {
+        funds.map_or(
+            Balance {
+                available: Amount::new(0),
+                held: Amount::new(0),
+                state: FundingStates::Valid,
+            },
+            Balance::from,
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AuditEntry {
+    // position in the engine's log, counting from 0
+    pub sequence: u64,
+    pub client: Client,
+    // the record that caused the change; None for an unfreeze
+    pub tx: Option<Tx>,
+    pub reason: AuditReason,
+    pub before: Balance,
+    pub after: Balance,
+}
+
+// Every change made to an engine's accounts, in the order they were made.
+// Entries are only ever appended; the log lives in memory and starts over
+// with the engine.
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    entries: Vec<AuditEntry>,
+    // positions in `entries` per client
+    clients: HashMap<Client, Vec<usize>>,
+}
+
+impl AuditLog {
+    // Skips changes that left the account as it was, such as a rule
+    // freezing an account that already was.
+    pub(crate) fn record(
+        &mut self,
+        client: Client,
+        tx: Option<Tx>,
+        reason: AuditReason,
+        before: Balance,
+        after: Balance,
+    ) {
+        if before == after {
+            return;
+        }
+        let sequence = self.entries.len();
+        self.entries.push(AuditEntry {
+            sequence: sequence as u64,
+            client,
+            tx,
+            reason,
+            before,
+            after,
+        });
+        self.clients.entry(client).or_default().push(sequence);
+    }
+
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    pub fn client(&self, client: Client) -> impl Iterator<Item = &AuditEntry> {
+        self.clients
+            .get(&client)
+            .into_iter()
+            .flatten()
+            .map(move |&index| &self.entries[index])
+    }
+}
+
+// An entry as the CSV report and the APIs show it.
+#[derive(Debug, Serialize)]
+pub struct AuditRow {
+    pub sequence: u64,
+    pub client: u32,
+    pub tx: Option<u64>,
+    pub reason: &'static str,
+    pub available_before: String,
+    pub held_before: String,
+    pub state_before: &'static str,
+    pub available_after: String,
+    pub held_after: String,
+    pub state_after: &'static str,
+}
+
+impl From<&AuditEntry> for AuditRow {
+    fn from(entry: &AuditEntry) -> AuditRow {
+        AuditRow {
+            sequence: entry.sequence,
+            client: entry.client.0,
+            tx: entry.tx.map(|tx| tx.0),
+            reason: entry.reason.as_str(),
+            available_before: entry.before.available.to_string(),
+            held_before: entry.before.held.to_string(),
+            state_before: entry.before.state.as_str(),
+            available_after: entry.after.available.to_string(),
+            held_after: entry.after.held.to_string(),
+            state_after: entry.after.state.as_str(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditReason;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::output::write_audit;
+    use crate::policy::PolicyRule;
+    use crate::tenant::Tenants;
+    use crate::transactions::{Client, Tx, TxType};
+
+    #[test]
+    fn test_audit_log() {
+        let rule: PolicyRule = "freeze client if disputes >= 1".parse().unwrap();
+        let builder = Engine::builder().audit(true).rule(rule);
+        let mut tenants = Tenants::single(builder);
+        let engine = tenants.engine("default");
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,2,2,1.0\nwithdrawal,1,3,5.0\ndispute,1,1,\ndeposit,1,4,1.0\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        assert_eq!(engine.unfreeze(Client(1)), Some(true));
+
+        let audit = engine.audit().unwrap();
+        // the rejected withdrawal and deposit changed nothing
+        assert_eq!(audit.entries().len(), 5);
+        let reasons: Vec<_> = audit
+            .client(Client(1))
+            .map(|entry| (entry.tx, entry.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                (Some(Tx(1)), AuditReason::Applied(TxType::Deposit)),
+                (Some(Tx(1)), AuditReason::Applied(TxType::Dispute)),
+                (Some(Tx(1)), AuditReason::RuleFreeze),
+                (None, AuditReason::Unfreeze),
+            ]
+        );
+        let freeze = audit.client(Client(1)).nth(2).unwrap();
+        assert_eq!(freeze.before.state, FundingStates::Disputed);
+        assert_eq!(freeze.after.state, FundingStates::Frozen);
+
+        let mut out = Vec::new();
+        write_audit(&tenants, &mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        assert_eq!(
+            report.lines().take(3).collect::<Vec<_>>(),
+            [
+                "sequence,client,tx,reason,available_before,held_before,state_before,available_after,held_after,state_after",
+                "0,1,1,deposit,0.0000,0.0000,valid,2.0000,0.0000,valid",
+                "1,2,2,deposit,0.0000,0.0000,valid,1.0000,0.0000,valid",
+            ]
+        );
+        assert!(report.ends_with("4,1,,unfreeze,0.0000,2.0000,frozen,0.0000,2.0000,disputed\n"));
+    }
+}
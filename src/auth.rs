@@ -0,0 +1,226 @@
+#[cfg(any(feature = "grpc", feature = "rest"))]
+pub use self::layer::{Auth, AuthLayer};
+use serde::Deserialize;
+use std::fmt;
+
+// What a key lets its holder do. Every server request needs one scope.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    // balances, account feeds, metrics and GraphQL queries
+    Read,
+    // applying transactions
+    Submit,
+    // changing accounts outside of transactions, e.g. lifting a freeze
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Submit => "submit",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKey {
+    pub key: String,
+    pub scopes: Vec<Scope>,
+}
+
+// The `[auth]` config section. When present, every REST and gRPC request
+// must carry one of `keys`, as `authorization: Bearer <key>` or
+// `x-api-key: <key>`, with the scope the request needs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthConfig {
+    pub keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Denied {
+    // no key, or one that isn't configured
+    Unauthenticated,
+    // a configured key without the scope the request needs
+    Forbidden(Scope),
+}
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Denied::Unauthenticated => write!(f, "missing or unknown API key"),
+            Denied::Forbidden(scope) => write!(f, "API key lacks the {} scope", scope.as_str()),
+        }
+    }
+}
+
+// Compares every byte, so the time taken doesn't tell a caller how much of
+// a guessed key was right.
+fn same_key(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+impl AuthConfig {
+    pub fn authorize(&self, key: Option<&str>, scope: Scope) -> Result<(), Denied> {
+        let key = key.ok_or(Denied::Unauthenticated)?;
+        let mut found = None;
+        for candidate in &self.keys {
+            if same_key(&candidate.key, key) {
+                found = Some(candidate);
+            }
+        }
+        match found {
+            Some(found) if found.scopes.contains(&scope) => Ok(()),
+            Some(_) => Err(Denied::Forbidden(scope)),
+            None => Err(Denied::Unauthenticated),
+        }
+    }
+}
+
+#[cfg(any(feature = "grpc", feature = "rest"))]
+mod layer {
+    use super::{AuthConfig, Denied, Scope};
+    use http::header::AUTHORIZATION;
+    use http::{HeaderMap, Method, Request, Response};
+    use std::future::{self, Future};
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use tower::{Layer, Service};
+
+    fn request_key(headers: &HeaderMap) -> Option<&str> {
+        match headers.get(AUTHORIZATION) {
+            Some(value) => value.to_str().ok()?.strip_prefix("Bearer "),
+            None => headers.get("x-api-key")?.to_str().ok(),
+        }
+    }
+
+    // Middleware checking each request's API key, shared by the REST and
+    // gRPC servers. `scope` says which scope a request (by method and path)
+    // needs, and `deny` answers one that is turned away in the server's own
+    // protocol. Without a config every request is let through.
+    pub struct AuthLayer<B> {
+        config: Option<Arc<AuthConfig>>,
+        scope: fn(&Method, &str) -> Scope,
+        deny: fn(Denied) -> Response<B>,
+    }
+
+    impl<B> AuthLayer<B> {
+        pub fn new(
+            config: Option<Arc<AuthConfig>>,
+            scope: fn(&Method, &str) -> Scope,
+            deny: fn(Denied) -> Response<B>,
+        ) -> AuthLayer<B> {
+            AuthLayer {
+                config,
+                scope,
+                deny,
+            }
+        }
+    }
+
+    // derived Clone would needlessly require `B: Clone`
+    impl<B> Clone for AuthLayer<B> {
+        fn clone(&self) -> AuthLayer<B> {
+            AuthLayer {
+                config: self.config.clone(),
+                scope: self.scope,
+                deny: self.deny,
+            }
+        }
+    }
+
+    impl<S, B> Layer<S> for AuthLayer<B> {
+        type Service = Auth<S, B>;
+
+        fn layer(&self, inner: S) -> Auth<S, B> {
+            Auth {
+                inner,
+                layer: self.clone(),
+            }
+        }
+    }
+
+    pub struct Auth<S, B> {
+        inner: S,
+        layer: AuthLayer<B>,
+    }
+
+    impl<S: Clone, B> Clone for Auth<S, B> {
+        fn clone(&self) -> Auth<S, B> {
+            Auth {
+                inner: self.inner.clone(),
+                layer: self.layer.clone(),
+            }
+        }
+    }
+
+    impl<S, ReqBody, B> Service<Request<ReqBody>> for Auth<S, B>
+    where
+        S: Service<Request<ReqBody>, Response = Response<B>>,
+        S::Future: Send + 'static,
+        S::Error: Send + 'static,
+        B: Send + 'static,
+    {
+        type Response = Response<B>;
+        type Error = S::Error;
+        type Future = Pin<Box<dyn Future<Output = Result<Response<B>, S::Error>> + Send>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+            if let Some(config) = &self.layer.config {
+                let path = request.uri().path();
+                let scope = (self.layer.scope)(request.method(), path);
+                if let Err(denied) = config.authorize(request_key(request.headers()), scope) {
+                    tracing::info!(path, reason = %denied, "request denied");
+                    return Box::pin(future::ready(Ok((self.layer.deny)(denied))));
+                }
+            }
+            Box::pin(self.inner.call(request))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiKey, AuthConfig, Denied, Scope};
+
+    #[test]
+    fn test_authorize() {
+        let config = AuthConfig {
+            keys: vec![
+                ApiKey {
+                    key: "ingest".to_string(),
+                    scopes: vec![Scope::Submit],
+                },
+                ApiKey {
+                    key: "ops".to_string(),
+                    scopes: vec![Scope::Read, Scope::Admin],
+                },
+            ],
+        };
+        assert_eq!(config.authorize(Some("ingest"), Scope::Submit), Ok(()));
+        assert_eq!(
+            config.authorize(Some("ingest"), Scope::Admin),
+            Err(Denied::Forbidden(Scope::Admin))
+        );
+        assert_eq!(config.authorize(Some("ops"), Scope::Admin), Ok(()));
+        assert_eq!(
+            config.authorize(Some("opz"), Scope::Read),
+            Err(Denied::Unauthenticated)
+        );
+        assert_eq!(
+            config.authorize(None, Scope::Read),
+            Err(Denied::Unauthenticated)
+        );
+    }
+}
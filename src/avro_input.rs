@@ -0,0 +1,194 @@
+use crate::engine::Engine;
+use crate::transactions::{amount_field, Client, RowRecord, TransactionRecord, Tx, TxType};
+use apache_avro::schema::{RecordSchema, Schema};
+use apache_avro::types::Value;
+use apache_avro::Reader;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::io::Read;
+use std::str::FromStr;
+
+pub type AvroResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+pub const TRANSACTION_SCHEMA: &str = r#"{
+    "type": "record",
+    "name": "Transaction",
+    "namespace": "payments_engine",
+    "fields": [
+        {"name": "type", "type": "string"},
+        {"name": "client", "type": "int"},
+        {"name": "tx", "type": "long"},
+        {"name": "amount", "type": ["null", "string"], "default": null}
+    ]
+}"#;
+
+fn field_schema_ok(name: &str, schema: &Schema) -> bool {
+    match (name, schema) {
+        ("type", Schema::String) | ("type", Schema::Enum(_)) => true,
+        ("client", Schema::Int) | ("client", Schema::Long) => true,
+        ("tx", Schema::Int) | ("tx", Schema::Long) => true,
+        ("amount", Schema::Null)
+        | ("amount", Schema::String)
+        | ("amount", Schema::Double)
+        | ("amount", Schema::Long)
+        | ("amount", Schema::Int) => true,
+        ("amount", Schema::Union(union)) => union
+            .variants()
+            .iter()
+            .all(|variant| field_schema_ok("amount", variant)),
+        _ => false,
+    }
+}
+
+// Checks a file's writer schema against the canonical transaction record
+// before any data is read, naming the missing or mistyped field (and any
+// unexpected ones, which are usually the renamed originals).
+pub fn validate_schema(schema: &Schema) -> AvroResult<()> {
+    let canonical = Schema::parse_str(TRANSACTION_SCHEMA)?;
+    let (expected, found) = match (&canonical, schema) {
+        (
+            Schema::Record(RecordSchema {
+                fields: expected, ..
+            }),
+            Schema::Record(found),
+        ) => (expected, &found.fields),
+        _ => return Err("avro schema must be a record of transactions".into()),
+    };
+    let unknown: Vec<&str> = found
+        .iter()
+        .filter(|field| expected.iter().all(|e| e.name != field.name))
+        .map(|field| field.name.as_str())
+        .collect();
+    for field in expected {
+        match found.iter().find(|f| f.name == field.name) {
+            Some(f) if field_schema_ok(&field.name, &f.schema) => {}
+            Some(f) => {
+                return Err(format!(
+                    "avro field `{}` has unsupported type {:?}",
+                    field.name, f.schema
+                )
+                .into())
+            }
+            None if field.name == "amount" => {}
+            None => {
+                return Err(format!(
+                    "avro schema is missing field `{}` (unexpected fields: {:?})",
+                    field.name, unknown
+                )
+                .into())
+            }
+        }
+    }
+    Ok(())
+}
+
+fn field<'a>(fields: &'a [(String, Value)], name: &str) -> Option<&'a Value> {
+    fields
+        .iter()
+        .find(|(field, _)| field == name)
+        .map(|(_, value)| match value {
+            Value::Union(_, inner) => inner.as_ref(),
+            value => value,
+        })
+}
+
+fn integer(value: Option<&Value>) -> Option<i64> {
+    match value {
+        Some(Value::Int(n)) => Some(i64::from(*n)),
+        Some(Value::Long(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+fn to_record(value: Value) -> AvroResult<TransactionRecord> {
+    let fields = match value {
+        Value::Record(fields) => fields,
+        _ => return Err("avro value is not a record".into()),
+    };
+    let r#type = match field(&fields, "type") {
+        Some(Value::String(s)) | Some(Value::Enum(_, s)) => TxType::from_str(s)?,
+        _ => return Err("avro record has no `type`".into()),
+    };
+    let client = integer(field(&fields, "client"))
+        .and_then(|n| u32::try_from(n).ok())
+        .ok_or("avro record has a missing or out of range `client`")?;
+    let tx = integer(field(&fields, "tx"))
+        .and_then(|n| u64::try_from(n).ok())
+        .ok_or("avro record has a missing or out of range `tx`")?;
+    let amount = match field(&fields, "amount") {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Double(n)) => n.to_string(),
+        Some(other) => integer(Some(other))
+            .ok_or("avro record has an unsupported `amount`")?
+            .to_string(),
+    };
+    let row = RowRecord::new(r#type, Client(client), Tx(tx), amount_field(&amount)?);
+    Ok(TransactionRecord::try_from(row)?)
+}
+
+impl Engine {
+    pub fn process_avro<R: Read>(&mut self, input: R) -> AvroResult<()> {
+        let reader = Reader::new(input)?;
+        validate_schema(reader.writer_schema())?;
+        for value in reader {
+            self.process(&to_record(value?)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TRANSACTION_SCHEMA;
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::Client;
+    use apache_avro::types::{Record, Value};
+    use apache_avro::{Schema, Writer};
+
+    #[test]
+    fn test_process_avro() {
+        let schema = Schema::parse_str(TRANSACTION_SCHEMA).unwrap();
+        let mut writer = Writer::new(&schema, Vec::new());
+        for (r#type, tx, amount) in
+            [("deposit", 1, Some("2.5")), ("withdrawal", 2, Some("1"))].iter()
+        {
+            let mut record = Record::new(&schema).unwrap();
+            record.put("type", *r#type);
+            record.put("client", 7);
+            record.put("tx", Value::Long(*tx));
+            record.put(
+                "amount",
+                Value::Union(1, Box::new(Value::String(amount.unwrap().into()))),
+            );
+            writer.append(record).unwrap();
+        }
+        let bytes = writer.into_inner().unwrap();
+        let mut engine = Engine::new();
+        engine.process_avro(bytes.as_slice()).unwrap();
+        assert_eq!(
+            engine.account(Client(7)).unwrap().available,
+            Amount::new(15000)
+        );
+    }
+
+    #[test]
+    fn test_renamed_field() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "Transaction", "fields": [
+                {"name": "type", "type": "string"},
+                {"name": "customer", "type": "int"},
+                {"name": "tx", "type": "long"}
+            ]}"#,
+        )
+        .unwrap();
+        let writer = Writer::new(&schema, Vec::new());
+        let bytes = writer.into_inner().unwrap();
+        let err = Engine::new().process_avro(bytes.as_slice()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "avro schema is missing field `client` (unexpected fields: [\"customer\"])"
+        );
+    }
+}
@@ -0,0 +1,631 @@
+use clap::{Parser, Subcommand};
+#[cfg(unix)]
+use payment_engine::actor::{self, Router};
+use payment_engine::admin::AdminSocket;
+use payment_engine::config::Config;
+use payment_engine::csv_dialect::UnknownTypes;
+use payment_engine::daily::write_daily_balances;
+use payment_engine::engine::{Engine, EngineBuilder};
+use payment_engine::gl::write_gl;
+use payment_engine::input::{read_input, route_input, Input, InputFormat};
+use payment_engine::logging::{self, LogFormat};
+use payment_engine::mt940::write_mt940;
+use payment_engine::ofx::write_ofx;
+use payment_engine::output::{
+    write_accounts, write_audit, write_extended_accounts, write_rejections, write_tenant_accounts,
+    OutputFormat,
+};
+use payment_engine::period::write_periods;
+use payment_engine::prometheus;
+use payment_engine::qif::write_qif;
+use payment_engine::reconcile;
+use payment_engine::serve::{self, Protocol, SharedTenants};
+use payment_engine::shadow::Shadow;
+use payment_engine::statement::{write_statements, StatementFormat};
+use payment_engine::stream::{self, Source};
+use payment_engine::summary::{write_summary, RunSummary};
+#[cfg(feature = "otlp")]
+use payment_engine::telemetry::Telemetry;
+use payment_engine::tenant::{self, Tenants, DEFAULT_TENANT};
+use payment_engine::testkit::{write_csv, Generator, GeneratorConfig};
+use payment_engine::transactions::Client;
+use payment_engine::trial_balance::write_trial_balance;
+#[cfg(feature = "webhooks")]
+use payment_engine::webhook::Webhooks;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+#[derive(Parser)]
+#[command(
+    about = "Applies a transaction file and prints the resulting accounts",
+    long_about = "Applies a transaction file and prints the resulting accounts. Subcommands \
+        take their own input files after the subcommand, e.g. `payment_engine trial-balance \
+        transactions.csv`",
+    override_usage = "payment_engine [OPTIONS] <INPUT>...\n       \
+        payment_engine [OPTIONS] <COMMAND> [ARGS]...",
+    subcommand_negates_reqs = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Transactions files, applied in order, or "-" for stdin
+    #[arg(required_unless_present = "source")]
+    input: Vec<String>,
+    /// Consume from a message bus configured in --config instead of a file
+    #[arg(long, conflicts_with = "input")]
+    source: Option<Source>,
+    /// Input format; guessed from the file extension when omitted
+    #[arg(long)]
+    input_format: Option<InputFormat>,
+    /// TOML file with input layouts (e.g. the `[fixed_width]` columns)
+    #[arg(long, global = true)]
+    config: Option<String>,
+    /// Skip CSV rows that can't be read as a transaction instead of stopping at the first
+    #[arg(long)]
+    permissive: bool,
+    /// Threads decoding CSV rows while another reads the file and the main thread applies them; 0 picks from the available cores
+    #[arg(long)]
+    parse_threads: Option<usize>,
+    /// Apply CSV input on this many threads, each owning the accounts of a share of the clients; the reports that need every transaction in one place can't be written
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "source", "tenants", "rejects", "audit", "periods", "daily_balances", "summary",
+        ]
+    )]
+    workers: Option<usize>,
+    /// Format of the account balances written to stdout
+    #[arg(long, global = true, default_value = "csv")]
+    output_format: OutputFormat,
+    /// Add each account's AML flags and risk score, debt, pending funds and registry details to csv output (implied by --track-debt or an [aml], [dormancy], [registry] or [settlement] config section)
+    #[arg(long, global = true)]
+    extended: bool,
+    /// Also write every rejected record, with its reason code, to this CSV file
+    #[arg(long, global = true)]
+    rejects: Option<PathBuf>,
+    /// Keep a log of every balance change, queryable per client when serving, and write it to this CSV file
+    #[arg(long, global = true)]
+    audit: Option<PathBuf>,
+    /// Close the period at the end of the run and write each account's opening and closing balances and volume by type to this CSV file
+    #[arg(long, global = true)]
+    periods: Option<PathBuf>,
+    /// Write each account's end-of-day balances, for the days it changed on, to this CSV file (needs a timestamp column)
+    #[arg(long, global = true)]
+    daily_balances: Option<PathBuf>,
+    /// Write a SHA-256 digest of the applied transactions and final accounts to this file
+    #[cfg(feature = "digest")]
+    #[arg(long, global = true)]
+    digest: Option<PathBuf>,
+    /// Write a JSON summary of the run (outcomes by type and reason, frozen accounts, balances, throughput) to this file, or "-" for stderr
+    #[arg(long, global = true)]
+    summary: Option<PathBuf>,
+    /// Keep what chargebacks leave clients owing as debt, repaid first by later deposits
+    #[arg(long, global = true)]
+    track_debt: bool,
+    /// Check every account after each transaction and fail the run on a broken invariant (always on in debug builds)
+    #[arg(long, global = true)]
+    check_invariants: bool,
+    /// Keep a separate ledger per input file, Kafka topic or x-tenant header, written with a tenant column
+    #[arg(long, global = true)]
+    tenants: bool,
+    /// Most detailed transaction events logged to stderr: info logs rejections, debug also applied ones
+    #[arg(long, global = true, default_value = "off")]
+    log_level: LevelFilter,
+    /// Format of the logs written to stderr: text or json
+    #[arg(long, global = true, default_value = "text")]
+    log_format: LogFormat,
+    /// Most detailed level of spans exported over OTLP (trace adds one per transaction)
+    #[cfg(feature = "otlp")]
+    #[arg(long, global = true, default_value = "info")]
+    trace_level: tracing::Level,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serves the engine until interrupted, then prints the resulting accounts
+    Serve {
+        protocol: Protocol,
+        /// Address to listen on; defaults to the protocol's usual port on localhost
+        #[arg(long)]
+        listen: Option<SocketAddr>,
+        /// Also serve Prometheus metrics at /metrics on this address
+        #[arg(long)]
+        metrics_listen: Option<SocketAddr>,
+        /// Unix socket accepting admin commands (balance, unfreeze, metrics, snapshot)
+        #[cfg(unix)]
+        #[arg(long)]
+        admin_socket: Option<PathBuf>,
+    },
+    /// Compares account output with an external ledger's balances and prints the clients that differ
+    Reconcile {
+        /// CSV accounts written by the engine
+        accounts: PathBuf,
+        /// CSV of the external ledger with client and total columns
+        external: PathBuf,
+        /// Transactions files the accounts came from, replayed to list the transactions behind each delta
+        #[arg(long)]
+        transactions: Vec<String>,
+    },
+    /// Writes a random CSV workload to stdout, for benchmarks and fuzz-style tests
+    Generate {
+        /// Number of clients [default: 100]
+        #[arg(long)]
+        clients: Option<u32>,
+        /// Number of rows, malformed ones included [default: 10000]
+        #[arg(long)]
+        transactions: Option<u32>,
+        /// Share of rows that are disputes; as many again resolve or charge them back [default: 0.02]
+        #[arg(long)]
+        dispute_ratio: Option<f64>,
+        /// Share of disputes charged back rather than resolved [default: 0.3]
+        #[arg(long)]
+        chargeback_ratio: Option<f64>,
+        /// Share of rows that are withdrawals [default: 0.3]
+        #[arg(long)]
+        withdrawal_ratio: Option<f64>,
+        /// Share of rows that aren't valid transactions [default: 0]
+        #[arg(long)]
+        malformed_ratio: Option<f64>,
+        /// Seed reproducing an earlier workload; the one used is printed to stderr
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Runs a second engine configured by another config file alongside the first and prints where their decisions and accounts differ
+    Shadow {
+        /// TOML config of the shadow engine; --config configures the primary one
+        #[arg(long)]
+        shadow_config: String,
+        /// CSV transactions files, applied in order, or "-" for stdin
+        #[arg(required = true)]
+        inputs: Vec<String>,
+    },
+    /// Writes each client's statement: opening balance, transactions with the running balance and dispute notes, and closing balance
+    Statement {
+        /// Transactions files, applied in order, or "-" for stdin
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// csv, or html laid out for printing (e.g. to PDF)
+        #[arg(long, default_value = "csv")]
+        format: StatementFormat,
+        /// Only write this client's statement
+        #[arg(long)]
+        client: Option<u32>,
+    },
+    /// Writes the double-entry journal as general ledger lines (date, account code, client, debit, credit, tx reference) for import into accounting systems
+    GlExport {
+        /// Transactions files, applied in order, or "-" for stdin
+        #[arg(required = true)]
+        inputs: Vec<String>,
+    },
+    /// Sums the debits and credits of every ledger account, checks they net to zero and match the accounts, and prints the trial balance with any imbalance
+    TrialBalance {
+        /// Transactions files, applied in order, or "-" for stdin
+        #[arg(required = true)]
+        inputs: Vec<String>,
+    },
+}
+
+// Prints where the engines disagree and fails when they do.
+fn shadow_run(
+    inputs: &[String],
+    primary: EngineBuilder,
+    shadow: EngineBuilder,
+    config: &Config,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut shadow = Shadow::new(primary.build(), shadow.build());
+    for input in inputs {
+        shadow.process_csv_with(Input::open(input)?, &config.csv)?;
+    }
+    payment_engine::shadow::write_report(&shadow, io::stdout())?;
+    let differences = shadow.divergences().len() + shadow.account_diffs().len();
+    if differences == 0 {
+        Ok(())
+    } else {
+        Err(format!("the shadow engine differs in {} places", differences).into())
+    }
+}
+
+// Prints the mismatches and fails when there are any, so scripts can
+// check the exit status.
+fn reconcile(
+    accounts: &Path,
+    external: &Path,
+    transactions: &[String],
+    builder: EngineBuilder,
+    config: &Config,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let engine_balances = reconcile::read_balances(File::open(accounts)?)?;
+    let external_balances = reconcile::read_balances(File::open(external)?)?;
+    let mismatches = reconcile::compare(&engine_balances, &external_balances);
+    let mut engine = builder.record_history(true).build();
+    for input in transactions {
+        read_input(&mut engine, input, None, config)?;
+    }
+    let history = engine.history().filter(|_| !transactions.is_empty());
+    reconcile::write_report(&mismatches, history, io::stdout())?;
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "the accounts of {} clients don't reconcile",
+            mismatches.len()
+        )
+        .into())
+    }
+}
+
+fn statement(
+    inputs: &[String],
+    format: StatementFormat,
+    client: Option<u32>,
+    builder: EngineBuilder,
+    config: &Config,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut engine = builder.record_history(true).build();
+    for input in inputs {
+        read_input(&mut engine, input, None, config)?;
+    }
+    let client = client.map(Client);
+    if let Some(client) = client.filter(|client| engine.account(*client).is_none()) {
+        return Err(format!("no account for client {}", client.0).into());
+    }
+    let writer = io::BufWriter::new(io::stdout());
+    write_statements(&engine, client, format, &config.statement, writer)?;
+    Ok(())
+}
+
+// Entries made before the clock was set are dated as statements are.
+fn gl_export(
+    inputs: &[String],
+    builder: EngineBuilder,
+    config: &Config,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut engine = builder.double_entry(true).build();
+    for input in inputs {
+        read_input(&mut engine, input, None, config)?;
+    }
+    let journal = engine.books().map_or(&[][..], |books| books.journal());
+    let writer = io::BufWriter::new(io::stdout());
+    write_gl(
+        journal,
+        &config.chart_of_accounts,
+        config.statement.date()?,
+        writer,
+    )?;
+    Ok(())
+}
+
+// Prints the trial balance and fails when the books don't balance.
+fn trial_balance(
+    inputs: &[String],
+    builder: EngineBuilder,
+    config: &Config,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut engine = builder.double_entry(true).build();
+    for input in inputs {
+        read_input(&mut engine, input, None, config)?;
+    }
+    let trial = engine.trial_balance().ok_or("the engine kept no books")?;
+    write_trial_balance(&trial, io::stdout())?;
+    if trial.is_balanced() {
+        Ok(())
+    } else {
+        Err(format!(
+            "the books don't balance: {} ledger accounts disagree with the accounts",
+            trial.imbalances.len()
+        )
+        .into())
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let started = Instant::now();
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    config.csv.permissive |= args.permissive;
+    if let Some(threads) = args.parse_threads {
+        config.csv.parse_threads = threads;
+    }
+    if args.workers.is_some() && args.command.is_some() {
+        return Err("--workers only applies to input files".into());
+    }
+    if args.workers.is_some() && args.output_format.needs_history() {
+        return Err("--workers can't write statement formats".into());
+    }
+    #[cfg(feature = "digest")]
+    if args.workers.is_some() && args.digest.is_some() {
+        return Err("--workers can't write a digest".into());
+    }
+    // statement and binary formats have no place for a tenant
+    if args.tenants && args.output_format != OutputFormat::Csv {
+        return Err("--tenants output is only written as csv".into());
+    }
+    #[cfg(feature = "otlp")]
+    let telemetry = Telemetry::init()?;
+    let subscriber =
+        tracing_subscriber::registry().with(logging::layer(args.log_level, args.log_format));
+    #[cfg(feature = "otlp")]
+    let subscriber = subscriber.with(
+        telemetry
+            .as_ref()
+            .map(|telemetry| telemetry.layer(args.trace_level)),
+    );
+    subscriber.try_init()?;
+    let serving_history = match &args.command {
+        Some(Command::Serve { protocol, .. }) => protocol.needs_history(),
+        Some(Command::Generate { .. })
+        | Some(Command::Reconcile { .. })
+        | Some(Command::Shadow { .. })
+        | Some(Command::Statement { .. })
+        | Some(Command::GlExport { .. })
+        | Some(Command::TrialBalance { .. })
+        | None => false,
+    };
+    let extended = args.extended
+        || args.track_debt
+        || config.aml.is_some()
+        || config.dormancy.is_some()
+        || config.registry.is_some()
+        || config.settlement.is_some();
+    if args.workers.is_some() && extended {
+        return Err("--workers can't write extended accounts".into());
+    }
+    // servers are scraped for apply latency, see `prometheus`, and GraphQL
+    // answers with risk scores
+    let builder = Engine::builder()
+        .record_history(args.output_format.needs_history() || serving_history)
+        .time_applies(matches!(args.command, Some(Command::Serve { .. })))
+        .record_rejections(args.rejects.is_some())
+        .audit(args.audit.is_some())
+        .track_periods(args.periods.is_some())
+        .record_daily_balances(args.daily_balances.is_some())
+        .track_debt(args.track_debt)
+        .check_invariants(args.check_invariants || cfg!(debug_assertions))
+        .score_clients(extended || serving_history);
+    #[cfg(feature = "digest")]
+    let builder = builder.digest(args.digest.is_some());
+    let builder = config.configure(builder)?;
+    match &args.command {
+        Some(Command::Generate {
+            clients,
+            transactions,
+            dispute_ratio,
+            chargeback_ratio,
+            withdrawal_ratio,
+            malformed_ratio,
+            seed,
+        }) => {
+            let defaults = GeneratorConfig::default();
+            let generator = Generator::new(GeneratorConfig {
+                clients: clients.unwrap_or(defaults.clients),
+                transactions: transactions.unwrap_or(defaults.transactions),
+                dispute_ratio: dispute_ratio.unwrap_or(defaults.dispute_ratio),
+                chargeback_ratio: chargeback_ratio.unwrap_or(defaults.chargeback_ratio),
+                withdrawal_ratio: withdrawal_ratio.unwrap_or(defaults.withdrawal_ratio),
+                malformed_ratio: malformed_ratio.unwrap_or(defaults.malformed_ratio),
+                seed: *seed,
+            });
+            eprintln!("seed: {}", generator.seed());
+            return Ok(write_csv(generator, io::BufWriter::new(io::stdout()))?);
+        }
+        Some(Command::Reconcile {
+            accounts,
+            external,
+            transactions,
+        }) => return reconcile(accounts, external, transactions, builder, &config),
+        Some(Command::Shadow {
+            shadow_config,
+            inputs,
+        }) => {
+            let shadow = Config::load(shadow_config)?.configure(Engine::builder())?;
+            return shadow_run(inputs, builder, shadow, &config);
+        }
+        Some(Command::Statement {
+            inputs,
+            format,
+            client,
+        }) => return statement(inputs, *format, *client, builder, &config),
+        Some(Command::GlExport { inputs }) => return gl_export(inputs, builder, &config),
+        Some(Command::TrialBalance { inputs }) => return trial_balance(inputs, builder, &config),
+        _ => {}
+    }
+    #[cfg(feature = "webhooks")]
+    let (webhooks, builder) = match config.webhooks.clone() {
+        Some(webhooks) => {
+            let (webhooks, monitor) = Webhooks::start(webhooks);
+            (Some(webhooks), builder.risk_monitor(monitor))
+        }
+        None => (None, builder),
+    };
+    let router = args.workers.map(|workers| Router::new(&builder, workers));
+    let mut tenants = if args.tenants {
+        Tenants::isolated(builder)
+    } else {
+        Tenants::single(builder)
+    };
+    match (args.command, args.source, &args.input) {
+        (
+            Some(Command::Serve {
+                protocol,
+                listen,
+                metrics_listen,
+                #[cfg(unix)]
+                admin_socket,
+            }),
+            _,
+            _,
+        ) => {
+            let shared = SharedTenants::new(tenants);
+            if let Some(addr) = metrics_listen {
+                prometheus::listen(addr, &shared)?;
+            }
+            #[cfg(unix)]
+            let _admin = admin_socket
+                .map(|path| AdminSocket::bind(&path, &shared))
+                .transpose()?;
+            serve::serve(
+                shared.clone(),
+                protocol,
+                listen,
+                config.auth.clone(),
+                config.server,
+            )?;
+            tenants = serve::into_tenants(shared)?;
+        }
+        (Some(Command::Generate { .. }), _, _)
+        | (Some(Command::Reconcile { .. }), _, _)
+        | (Some(Command::Shadow { .. }), _, _)
+        | (Some(Command::Statement { .. }), _, _)
+        | (Some(Command::GlExport { .. }), _, _)
+        | (Some(Command::TrialBalance { .. }), _, _) => {
+            unreachable!("handled above")
+        }
+        (None, Some(source), _) => tenants = stream::consume(tenants, source, &config)?,
+        (None, None, inputs) => {
+            match router {
+                // --workers conflicts with --tenants
+                Some(mut router) => {
+                    let engine = tenants.engine(DEFAULT_TENANT);
+                    for input in inputs {
+                        route_input(&mut router, engine, input, args.input_format, &config)?;
+                    }
+                    actor::merge(engine, router.finish());
+                }
+                None => {
+                    for input in inputs {
+                        let tenant = if tenants.is_isolated() {
+                            tenant::from_path(input)?
+                        } else {
+                            DEFAULT_TENANT.to_string()
+                        };
+                        read_input(tenants.engine(&tenant), input, args.input_format, &config)?;
+                    }
+                }
+            }
+            let malformed: u64 = tenants
+                .iter()
+                .map(|(_, engine)| engine.metrics().rows_malformed)
+                .sum();
+            if malformed > 0 {
+                eprintln!("warning: skipped {} malformed rows", malformed);
+            }
+            if config.csv.unknown_types == UnknownTypes::Skip {
+                let unknown: u64 = tenants
+                    .iter()
+                    .map(|(_, engine)| engine.metrics().rows_unknown_type)
+                    .sum();
+                if unknown > 0 {
+                    eprintln!("warning: skipped {} rows of unknown type", unknown);
+                }
+            }
+        }
+    }
+    if let Some(path) = &args.rejects {
+        write_rejections(&tenants, std::fs::File::create(path)?)?;
+    }
+    if let Some(path) = &args.audit {
+        write_audit(&tenants, std::fs::File::create(path)?)?;
+    }
+    if let Some(path) = &args.daily_balances {
+        for (_, engine) in tenants.iter_mut() {
+            engine.end_day();
+        }
+        write_daily_balances(&tenants, std::fs::File::create(path)?)?;
+    }
+    if let Some(path) = &args.periods {
+        // at the latest timestamp read, or now when the input has none
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        for (_, engine) in tenants.iter_mut() {
+            engine.close_period(engine.now().unwrap_or(now));
+        }
+        write_periods(&tenants, std::fs::File::create(path)?)?;
+    }
+    #[cfg(feature = "digest")]
+    if let Some(path) = &args.digest {
+        payment_engine::digest::write_digests(&tenants, std::fs::File::create(path)?)?;
+    }
+    if let Some(path) = &args.summary {
+        let summary = RunSummary::new(&tenants, started.elapsed());
+        if path == Path::new("-") {
+            write_summary(&summary, io::stderr())?;
+        } else {
+            write_summary(&summary, std::fs::File::create(path)?)?;
+        }
+    }
+    let mut violations = 0;
+    for (tenant, engine) in tenants.iter() {
+        for violation in engine.violations() {
+            eprintln!(
+                "invariant {} broken by {} tx {} of client {}{}: available {} -> {}, held {} -> {}",
+                violation.invariant.as_str(),
+                violation.r#type.as_str(),
+                violation.tx.0,
+                violation.client.0,
+                if tenants.is_isolated() {
+                    format!(" in tenant {}", tenant)
+                } else {
+                    String::new()
+                },
+                violation.before.available,
+                violation.after.available,
+                violation.before.held,
+                violation.after.held,
+            );
+            violations += 1;
+        }
+    }
+    if extended && args.output_format == OutputFormat::Csv {
+        write_extended_accounts(&tenants, io::stdout())?;
+    } else if tenants.is_isolated() {
+        write_tenant_accounts(&tenants, io::stdout())?;
+    } else {
+        let engine = tenants.into_default();
+        match args.output_format {
+            OutputFormat::Csv => write_accounts(&engine.rolled_up_accounts(), io::stdout())?,
+            OutputFormat::Mt940 => write_mt940(&engine, &config.statement, io::stdout())?,
+            OutputFormat::Ofx => write_ofx(&engine, &config.statement, io::stdout())?,
+            OutputFormat::Qif => write_qif(&engine, &config.statement, io::stdout())?,
+            #[cfg(feature = "arrow-io")]
+            OutputFormat::Arrow => payment_engine::arrow_io::write_accounts_arrow(
+                &engine.rolled_up_accounts(),
+                io::stdout(),
+            )?,
+            #[cfg(feature = "msgpack")]
+            OutputFormat::MessagePack => payment_engine::msgpack::write_accounts_msgpack(
+                &engine.rolled_up_accounts(),
+                io::stdout(),
+            )?,
+        }
+    }
+    #[cfg(feature = "webhooks")]
+    if let Some(webhooks) = webhooks {
+        webhooks.finish();
+    }
+    #[cfg(feature = "otlp")]
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown()?;
+    }
+    if violations > 0 {
+        return Err(format!("{} invariant violations", violations).into());
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run(Args::parse()) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::amount::Amount;
+use crate::funds::Funds;
+use crate::transactions::{TransactionRecord, TxType};
+use serde::Deserialize;
+
+// The `[bonus]` config section. A credit's bonus can't be withdrawn until
+// the client has deposited or withdrawn `turnover_multiple` times its
+// amount since; 0 releases it with the next deposit or withdrawal.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BonusConfig {
+    pub turnover_multiple: u64,
+}
+
+impl Default for BonusConfig {
+    fn default() -> BonusConfig {
+        BonusConfig {
+            turnover_multiple: 1,
+        }
+    }
+}
+
+impl BonusConfig {
+    // Called with each applied record, its client's account and the
+    // amount it moved. Returns whether it released the account's bonus.
+    pub(crate) fn observe(
+        &self,
+        record: &TransactionRecord,
+        funds: &mut Funds,
+        amount: Amount,
+    ) -> bool {
+        match record.r#type {
+            TxType::Credit => {
+                let due = amount.0.saturating_mul(self.turnover_multiple);
+                funds.turnover = Amount(funds.turnover.0.saturating_add(due));
+                false
+            }
+            TxType::Deposit | TxType::Withdrawal => funds.turn_over(amount),
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback | TxType::Settle => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BonusConfig;
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::{Client, RejectReason};
+
+    #[test]
+    fn test_bonus_turnover() {
+        let config: BonusConfig = toml::from_str("turnover_multiple = 2").unwrap();
+        let mut engine = Engine::builder().bonus(config).build();
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,10\ncredit,1,2,5\nwithdrawal,1,3,12\n\
+                       withdrawal,1,4,8\ndispute,1,2,\ndeposit,1,5,2\nwithdrawal,1,6,9\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let metrics = engine.metrics();
+        assert_eq!(metrics.rejected[&RejectReason::InsufficientFunds], 1);
+        assert_eq!(metrics.rejected[&RejectReason::NotDisputable], 1);
+        // 8 and 2 moved, 10 of turnover for the bonus of 5
+        let funds = engine.account(Client(1)).unwrap();
+        assert_eq!(funds.bonus, Amount::new(0));
+        assert_eq!(funds.available, Amount::new(0));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::postings::{LedgerAccount, Posting};
+use crate::transactions::{ClientFunds, Tx};
+use std::collections::HashMap;
+
+// One posting in the journal, with the tx that made it (None for opening
+// balances) and the engine's clock when it was made (see `Engine::now`).
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct JournalEntry {
+    pub sequence: u64,
+    pub tx: Option<Tx>,
+    pub timestamp: Option<u64>,
+    pub posting: Posting,
+}
+
+// The engine's double-entry books: the postings of every applied record
+// (see `postings`) in the order they were made, and the balance of each
+// ledger account. A client's `Available`, `Held` and `Pending` balances
+// match its account, and all balances net to zero.
+#[derive(Debug, Default, Clone)]
+pub struct Books {
+    balances: HashMap<LedgerAccount, i128>,
+    journal: Vec<JournalEntry>,
+}
+
+impl Books {
+    pub(crate) fn post<'a, I>(&mut self, tx: Option<Tx>, timestamp: Option<u64>, postings: I)
+    where
+        I: IntoIterator<Item = &'a Posting>,
+    {
+        for posting in postings {
+            let amount = i128::from(posting.amount.0);
+            *self.balances.entry(posting.debit).or_default() -= amount;
+            *self.balances.entry(posting.credit).or_default() += amount;
+            self.journal.push(JournalEntry {
+                sequence: self.journal.len() as u64,
+                tx,
+                timestamp,
+                posting: *posting,
+            });
+        }
+    }
+
+    // Posts `accounts`' balances and debts as they are against `Suspense`, for
+    // accounts that didn't get there through the engine, e.g. imported.
+    pub(crate) fn open(&mut self, accounts: &ClientFunds, timestamp: Option<u64>) {
+        for funds in accounts.values() {
+            let (available, held, pending, debt) = (
+                LedgerAccount::Available(funds.client),
+                LedgerAccount::Held(funds.client),
+                LedgerAccount::Pending(funds.client),
+                LedgerAccount::Debt(funds.client),
+            );
+            for (debit, credit, amount) in [
+                (LedgerAccount::Suspense, available, funds.available),
+                (LedgerAccount::Suspense, held, funds.held),
+                (LedgerAccount::Suspense, pending, funds.pending),
+                (debt, LedgerAccount::Suspense, funds.debt),
+                (debt, LedgerAccount::Suspense, funds.uncovered),
+            ] {
+                let posting = Posting {
+                    debit,
+                    credit,
+                    amount,
+                };
+                if amount.0 > 0 {
+                    self.post(None, timestamp, &[posting]);
+                }
+            }
+        }
+    }
+
+    // What was credited to `account` less what was debited from it.
+    pub fn balance(&self, account: LedgerAccount) -> i128 {
+        self.balances.get(&account).copied().unwrap_or_default()
+    }
+
+    pub fn balances(&self) -> impl Iterator<Item = (LedgerAccount, i128)> + '_ {
+        self.balances
+            .iter()
+            .map(|(account, balance)| (*account, *balance))
+    }
+
+    pub fn journal(&self) -> &[JournalEntry] {
+        &self.journal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::postings::LedgerAccount;
+    use crate::transactions::{Client, Ledger, TransactionRecord, Tx};
+
+    #[test]
+    fn test_books() {
+        let mut engine = Engine::builder().double_entry(true).build();
+        let client = Client(1);
+        for record in [
+            TransactionRecord::deposit(client, Tx(1), Amount::new(100)),
+            TransactionRecord::deposit(client, Tx(2), Amount::new(30)),
+            TransactionRecord::withdrawal(client, Tx(3), Amount::new(90)),
+            TransactionRecord::dispute(client, Tx(2)),
+            TransactionRecord::resolve(client, Tx(2)),
+            // more than is left available, so it is rejected and posts
+            // nothing
+            TransactionRecord::dispute(client, Tx(1)),
+            TransactionRecord::dispute(client, Tx(2)),
+            TransactionRecord::chargeback(client, Tx(2)),
+        ] {
+            engine.process(&record);
+        }
+        let books = engine.books().unwrap();
+        let funds = engine.account(client).unwrap();
+        assert_eq!(
+            books.balance(LedgerAccount::Available(client)),
+            i128::from(funds.available.0)
+        );
+        assert_eq!(books.balance(LedgerAccount::Held(client)), 0);
+        assert_eq!(books.balance(LedgerAccount::Suspense), -10);
+        assert_eq!(
+            books.balances().map(|(_, balance)| balance).sum::<i128>(),
+            0
+        );
+        assert_eq!(books.journal().len(), 7);
+        assert_eq!(books.journal()[3].tx, Some(Tx(2)));
+
+        let mut restored = Engine::builder().double_entry(true).build();
+        restored.import_ledger(Ledger {
+            accounts: engine.export_ledger().accounts,
+            records: Default::default(),
+        });
+        let books = restored.books().unwrap();
+        assert_eq!(books.balance(LedgerAccount::Available(client)), 10);
+        assert_eq!(books.balance(LedgerAccount::Suspense), -10);
+    }
+}
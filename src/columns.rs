@@ -0,0 +1,104 @@
+use csv::StringRecord;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+
+const FIELDS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtraColumns {
+    Ignore,
+    Reject,
+    // kept per transaction, see `Engine::metadata`
+    Capture,
+}
+
+// Renames upstream headers onto the engine's fields, from the
+// `[csv.columns]` config section, e.g. `aliases = { txn_id = "tx" }`.
+// Whatever is left after aliasing is handled according to `extra`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ColumnMapping {
+    pub aliases: HashMap<String, String>,
+    pub extra: ExtraColumns,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> ColumnMapping {
+        ColumnMapping {
+            aliases: HashMap::new(),
+            extra: ExtraColumns::Ignore,
+        }
+    }
+}
+
+impl ColumnMapping {
+    fn field<'a>(&'a self, header: &'a str) -> &'a str {
+        let header = header.trim();
+        self.aliases.get(header).map_or(header, String::as_str)
+    }
+
+    // Returns the headers to deserialize rows with, and the position and
+    // original name of every extra column to capture.
+    pub fn map_headers(
+        &self,
+        headers: &StringRecord,
+    ) -> io::Result<(StringRecord, Vec<(usize, String)>)> {
+        let mapped: StringRecord = headers.iter().map(|header| self.field(header)).collect();
+        let extras: Vec<(usize, String)> = headers
+            .iter()
+            .enumerate()
+            .filter(|(_, header)| !FIELDS.contains(&self.field(header)))
+            .map(|(i, header)| (i, header.to_string()))
+            .collect();
+        match (self.extra, extras.first()) {
+            (ExtraColumns::Reject, Some((_, header))) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown column `{}`", header),
+            )),
+            (ExtraColumns::Capture, _) => Ok((mapped, extras)),
+            _ => Ok((mapped, Vec::new())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv_dialect::CsvDialect;
+    use crate::engine::Engine;
+    use crate::transactions::{Client, Tx};
+
+    #[test]
+    fn test_column_mapping() {
+        let dialect: CsvDialect = toml::from_str(
+            r#"
+            [columns]
+            extra = "capture"
+            aliases = { txn_id = "tx", amt = "amount", customer = "client", kind = "type" }
+            "#,
+        )
+        .unwrap();
+        let input = "kind,customer,branch,txn_id,amt\ndeposit,1,north,7,2.5\n";
+        let mut engine = Engine::new();
+        engine.process_csv_with(input.as_bytes(), &dialect).unwrap();
+        assert!(engine.account(Client(1)).is_some());
+        assert_eq!(
+            engine.metadata(Tx(7)),
+            Some(&[("branch".to_string(), "north".to_string())][..])
+        );
+
+        let mut reject = dialect.clone();
+        reject.columns.extra = super::ExtraColumns::Reject;
+        let err = Engine::new()
+            .process_csv_with(input.as_bytes(), &reject)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unknown column `branch`");
+
+        let mut ignore = dialect;
+        ignore.columns.extra = super::ExtraColumns::Ignore;
+        let mut engine = Engine::new();
+        engine.process_csv_with(input.as_bytes(), &ignore).unwrap();
+        assert_eq!(engine.metadata(Tx(7)), None);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::funds::Funds;
+use crate::transactions::{Client, ClientFunds};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+pub const DEFAULT_SHARDS: usize = 64;
+
+// Client accounts spread over independently locked shards, so writers for
+// different clients and readers of unrelated balances don't contend on a
+// single lock. `update` and `accounts` hold every shard they touch at once
+// (always taken in shard order), so a reader sees all of an update or none
+// of it.
+pub struct ConcurrentFunds {
+    shards: Vec<RwLock<ClientFunds>>,
+}
+
+impl Default for ConcurrentFunds {
+    fn default() -> Self {
+        ConcurrentFunds::new(DEFAULT_SHARDS)
+    }
+}
+
+impl ConcurrentFunds {
+    pub fn new(shards: usize) -> ConcurrentFunds {
+        ConcurrentFunds {
+            shards: (0..shards.max(1)).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    fn index(&self, client: Client) -> usize {
+        client.0 as usize % self.shards.len()
+    }
+
+    // A writer that panicked left whole accounts behind, never half-written
+    // ones, so a poisoned shard is still fit to read and write.
+    fn read(&self, index: usize) -> RwLockReadGuard<'_, ClientFunds> {
+        self.shards[index]
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self, index: usize) -> RwLockWriteGuard<'_, ClientFunds> {
+        self.shards[index]
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get(&self, client: Client) -> Option<Funds> {
+        self.read(self.index(client)).get(&client).cloned()
+    }
+
+    // Sets several clients' accounts as one change; `None` removes one.
+    pub fn update<I>(&self, changes: I)
+    where
+        I: IntoIterator<Item = (Client, Option<Funds>)>,
+    {
+        let changes: Vec<_> = changes.into_iter().collect();
+        let mut indexes: Vec<usize> = changes
+            .iter()
+            .map(|(client, _)| self.index(*client))
+            .collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+        let mut shards: Vec<_> = indexes.iter().map(|&index| self.write(index)).collect();
+        for (client, funds) in changes {
+            let held = indexes.binary_search(&self.index(client));
+            let shard = &mut shards[held.expect("every changed client's shard is held")];
+            match funds {
+                Some(funds) => shard.insert(client, funds),
+                None => shard.remove(&client),
+            };
+        }
+    }
+
+    // Replaces every account at once.
+    pub fn replace<I>(&self, accounts: I)
+    where
+        I: IntoIterator<Item = Funds>,
+    {
+        let mut shards: Vec<_> = (0..self.shards.len()).map(|i| self.write(i)).collect();
+        for shard in shards.iter_mut() {
+            shard.clear();
+        }
+        for funds in accounts {
+            shards[self.index(funds.client)].insert(funds.client, funds);
+        }
+    }
+
+    // Every account as of one point between updates, in client order.
+    pub fn accounts(&self) -> Vec<Funds> {
+        let shards: Vec<_> = (0..self.shards.len()).map(|i| self.read(i)).collect();
+        let mut accounts: Vec<Funds> = shards
+            .iter()
+            .flat_map(|shard| shard.values().cloned())
+            .collect();
+        accounts.sort_by_key(|funds| funds.client.0);
+        accounts
+    }
+
+    pub fn len(&self) -> usize {
+        (0..self.shards.len()).map(|i| self.read(i).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConcurrentFunds;
+    use crate::amount::Amount;
+    use crate::funds::Funds;
+    use crate::transactions::Client;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn funds(client: u32, available: u64) -> Funds {
+        let mut funds = Funds::new(Client(client));
+        funds.available = Amount::new(available);
+        funds
+    }
+
+    #[test]
+    fn test_concurrent_update_and_read() {
+        let accounts = Arc::new(ConcurrentFunds::new(8));
+        accounts.update([
+            (Client(1), Some(funds(1, 100))),
+            (Client(2), Some(funds(2, 0))),
+        ]);
+        // the writer moves funds between two clients in different shards;
+        // a reader must never see one side of a move without the other
+        let writer = {
+            let accounts = Arc::clone(&accounts);
+            thread::spawn(move || {
+                for i in 0..=100 {
+                    accounts.update([
+                        (Client(1), Some(funds(1, 100 - i))),
+                        (Client(2), Some(funds(2, i))),
+                    ]);
+                }
+            })
+        };
+        for _ in 0..100 {
+            let total = accounts
+                .accounts()
+                .iter()
+                .fold(Amount::new(0), |total, funds| total + funds.available);
+            assert_eq!(total, Amount::new(100));
+        }
+        writer.join().unwrap();
+
+        assert_eq!(accounts.get(Client(2)).unwrap().available, Amount::new(100));
+        accounts.update([(Client(2), None)]);
+        assert_eq!(accounts.get(Client(2)), None);
+        accounts.replace([funds(3, 1)]);
+        let clients: Vec<u32> = accounts.accounts().iter().map(|f| f.client.0).collect();
+        assert_eq!(clients, [3]);
+        assert_eq!(accounts.len(), 1);
+    }
+}
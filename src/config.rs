@@ -0,0 +1,160 @@
+use crate::aml::AmlConfig;
+#[cfg(feature = "amqp")]
+use crate::amqp::AmqpConfig;
+use crate::auth::AuthConfig;
+use crate::bonus::BonusConfig;
+use crate::csv_dialect::CsvDialect;
+use crate::denylist::{Denylist, DenylistConfig};
+use crate::disputes::DisputeConfig;
+use crate::dormancy::DormancyConfig;
+use crate::engine::EngineBuilder;
+use crate::fix::FixConfig;
+use crate::fixed_width::FixedWidthLayout;
+use crate::gl::ChartOfAccounts;
+#[cfg(feature = "iso20022")]
+use crate::iso20022::Iso20022Config;
+#[cfg(feature = "kafka")]
+use crate::kafka::KafkaConfig;
+#[cfg(feature = "nats")]
+use crate::nats::NatsConfig;
+use crate::output::StatementOptions;
+use crate::policy::PolicyRule;
+use crate::registry::{ClientRegistry, RegistryConfig};
+use crate::serve::ServerConfig;
+use crate::settlement::SettlementConfig;
+use crate::tiers::TiersConfig;
+#[cfg(feature = "webhooks")]
+use crate::webhook::WebhookConfig;
+#[cfg(feature = "xlsx-input")]
+use crate::xlsx_input::XlsxLayout;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+
+// Settings that describe an input rather than the engine itself, read from
+// the TOML file passed with `--config`. Every section is optional so one
+// file can hold the layouts for several upstream formats.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    pub aml: Option<AmlConfig>,
+    #[cfg(feature = "amqp")]
+    pub amqp: Option<AmqpConfig>,
+    pub auth: Option<AuthConfig>,
+    pub bonus: Option<BonusConfig>,
+    #[serde(default)]
+    pub chart_of_accounts: ChartOfAccounts,
+    #[serde(default)]
+    pub csv: CsvDialect,
+    pub denylist: Option<DenylistConfig>,
+    pub disputes: Option<DisputeConfig>,
+    pub dormancy: Option<DormancyConfig>,
+    #[serde(default)]
+    pub fix: FixConfig,
+    pub fixed_width: Option<FixedWidthLayout>,
+    #[cfg(feature = "iso20022")]
+    #[serde(default)]
+    pub iso20022: Iso20022Config,
+    #[cfg(feature = "kafka")]
+    pub kafka: Option<KafkaConfig>,
+    #[cfg(feature = "nats")]
+    pub nats: Option<NatsConfig>,
+    pub registry: Option<RegistryConfig>,
+    // checks applied on top of the built-in validation, see `PolicyRule`
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+    #[serde(default)]
+    pub server: ServerConfig,
+    pub settlement: Option<SettlementConfig>,
+    #[serde(default)]
+    pub statement: StatementOptions,
+    pub tiers: Option<TiersConfig>,
+    #[cfg(feature = "webhooks")]
+    pub webhooks: Option<WebhookConfig>,
+    #[cfg(feature = "xlsx-input")]
+    pub xlsx: Option<XlsxLayout>,
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, Box<dyn Error + Send + Sync>> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    // Adds what the file asks of every engine: AML checks, the denylist,
+    // dormancy, dispute timeouts, bonus turnover, deposit settlement, the
+    // client registry, tier limits and rules.
+    pub fn configure(
+        &self,
+        builder: EngineBuilder,
+    ) -> Result<EngineBuilder, Box<dyn Error + Send + Sync>> {
+        let builder = match self.aml.clone() {
+            Some(aml) => builder.aml(aml),
+            None => builder,
+        };
+        let builder = match &self.denylist {
+            Some(denylist) => builder.denylist(Denylist::load(denylist)?),
+            None => builder,
+        };
+        let builder = match self.dormancy.clone() {
+            Some(dormancy) => builder.dormancy(dormancy),
+            None => builder,
+        };
+        let builder = match self.disputes.clone() {
+            Some(disputes) => builder.dispute_timeout(disputes),
+            None => builder,
+        };
+        let builder = match self.bonus.clone() {
+            Some(bonus) => builder.bonus(bonus),
+            None => builder,
+        };
+        let builder = match self.settlement.clone() {
+            Some(settlement) => builder.settlement(settlement),
+            None => builder,
+        };
+        let builder = match &self.registry {
+            Some(registry) => builder.registry(ClientRegistry::load(registry)?),
+            None => builder,
+        };
+        let builder = match self.tiers.clone() {
+            Some(tiers) => builder.rule(tiers),
+            None => builder,
+        };
+        Ok(self
+            .rules
+            .iter()
+            .fold(builder, |builder, rule| builder.rule(*rule)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::fixed_width::Field;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [fixed_width]
+            skip_lines = 1
+            type = { start = 0, width = 10 }
+            client = { start = 10, width = 5 }
+            tx = { start = 15, width = 8 }
+            amount = { start = 23, width = 12 }
+            "#,
+        )
+        .unwrap();
+        let layout = config.fixed_width.unwrap();
+        assert_eq!(layout.skip_lines, 1);
+        assert_eq!(
+            layout.tx,
+            Field {
+                start: 15,
+                width: 8
+            }
+        );
+        assert!(toml::from_str::<Config>("").unwrap().fixed_width.is_none());
+        assert_eq!(config.server.queue_high_water, 10_000);
+        let config: Config = toml::from_str("[server]\nqueue_high_water = 64").unwrap();
+        assert_eq!(config.server.queue_high_water, 64);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::columns::ColumnMapping;
+use crate::schema::SchemaVersion;
+use crate::transactions::TxType;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::thread;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    // `\n`, `\r` and `\r\n` all end a row
+    Auto,
+    // only `\n` ends a row; a stray `\r` is kept as data
+    Lf,
+}
+
+// What to do with a row whose type the engine doesn't know.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownTypes {
+    // a malformed row like any other: it stops the run unless `permissive`
+    Abort,
+    // skipped, and counted in `EngineMetrics::rows_unknown_type`
+    Skip,
+    // counted too, and recorded as a rejection with reason `unknown_type`
+    Reject,
+}
+
+// Reader options for CSV input, from the `[csv]` config section. The
+// defaults match the `type,client,tx,amount` files the engine was written
+// for; files without a header row are read positionally in that order.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CsvDialect {
+    pub delimiter: char,
+    pub quote: char,
+    pub quoting: bool,
+    pub double_quote: bool,
+    pub escape: Option<char>,
+    pub comment: Option<char>,
+    pub has_headers: bool,
+    pub line_ending: LineEnding,
+    pub columns: ColumnMapping,
+    pub schema_version: SchemaVersion,
+    // skip rows that can't be read as a transaction instead of stopping at
+    // the first one; see `MalformedRow`
+    pub permissive: bool,
+    // read client ids as the 16-bit values they were before ids widened to
+    // u32, so a larger id is a malformed row as it used to be
+    pub narrow_client_ids: bool,
+    // extra spellings of the `type` column, e.g. `{ credit = "deposit" }`,
+    // matched like the built-in ones (see `TxType::from_str`)
+    pub type_aliases: HashMap<String, TxType>,
+    pub unknown_types: UnknownTypes,
+    // how many threads decode rows when the CLI reads CSV through
+    // `pipeline::process`; 0 leaves a core each to the reader and applier
+    // and uses the rest
+    pub parse_threads: usize,
+}
+
+impl Default for CsvDialect {
+    fn default() -> CsvDialect {
+        CsvDialect {
+            delimiter: ',',
+            quote: '"',
+            quoting: true,
+            double_quote: true,
+            escape: None,
+            comment: None,
+            has_headers: true,
+            line_ending: LineEnding::Auto,
+            columns: ColumnMapping::default(),
+            schema_version: SchemaVersion::V1,
+            permissive: false,
+            narrow_client_ids: false,
+            type_aliases: HashMap::new(),
+            unknown_types: UnknownTypes::Abort,
+            parse_threads: 0,
+        }
+    }
+}
+
+fn ascii(c: char) -> io::Result<u8> {
+    u8::try_from(c).ok().filter(u8::is_ascii).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "CSV dialect characters must be ASCII",
+        )
+    })
+}
+
+impl CsvDialect {
+    pub fn reader_builder(&self) -> io::Result<csv::ReaderBuilder> {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(ascii(self.delimiter)?)
+            .quote(ascii(self.quote)?)
+            .quoting(self.quoting)
+            .double_quote(self.double_quote)
+            .escape(self.escape.map(ascii).transpose()?)
+            .comment(self.comment.map(ascii).transpose()?)
+            .has_headers(self.has_headers)
+            // `deposit, 1, 1, 1.0` is as common as the unspaced form
+            .trim(csv::Trim::All)
+            .terminator(match self.line_ending {
+                LineEnding::Auto => csv::Terminator::CRLF,
+                LineEnding::Lf => csv::Terminator::Any(b'\n'),
+            });
+        Ok(builder)
+    }
+
+    // `parse_threads`, with 0 resolved against the cores available.
+    pub fn parsers(&self) -> usize {
+        match self.parse_threads {
+            0 => thread::available_parallelism()
+                .map_or(1, |cores| cores.get().saturating_sub(2).max(1)),
+            threads => threads,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsvDialect;
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::Client;
+
+    #[test]
+    fn test_dialects() {
+        let dialect: CsvDialect = toml::from_str(
+            r##"
+            delimiter = ";"
+            has_headers = false
+            comment = "#"
+            "##,
+        )
+        .unwrap();
+        let input = "# exported 2026-10-16\r\ndeposit;1;1;\"1.5\"\r\ndeposit;1;2;2.5\r\n";
+        let mut engine = Engine::new();
+        engine.process_csv_with(input.as_bytes(), &dialect).unwrap();
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(40000)
+        );
+
+        let tabs = CsvDialect {
+            delimiter: '\t',
+            ..CsvDialect::default()
+        };
+        let input = "type\tclient\ttx\tamount\ndeposit\t2\t1\t3\n";
+        let mut engine = Engine::new();
+        engine.process_csv_with(input.as_bytes(), &tabs).unwrap();
+        assert_eq!(
+            engine.account(Client(2)).unwrap().available,
+            Amount::new(30000)
+        );
+
+        let bad = CsvDialect {
+            delimiter: '→',
+            ..CsvDialect::default()
+        };
+        assert!(bad.reader_builder().is_err());
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let input = "type, client, tx, amount\n deposit, 1, 1, 1.0\nwithdrawal ,1 ,2 ,\" 0.25 \"\n";
+        let mut engine = Engine::new();
+        engine.process_csv(input.as_bytes()).unwrap();
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(7500)
+        );
+        let mut engine = Engine::new();
+        crate::pipeline::process(input.as_bytes(), &CsvDialect::default(), 2, &mut engine).unwrap();
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(7500)
+        );
+    }
+
+    #[test]
+    fn test_type_aliases() {
+        let dialect: CsvDialect = toml::from_str(
+            r#"
+            type_aliases = { Credit = "deposit", DEBIT = "Withdraw" }
+            "#,
+        )
+        .unwrap();
+        let input = "type,client,tx,amount
+CREDIT,1,1,5
+Deposit,1,2,1
+debit,1,3,2
+Charge_Back,1,3,
+";
+        let mut engine = Engine::new();
+        engine.process_csv_with(input.as_bytes(), &dialect).unwrap();
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(40000)
+        );
+        let unknown = "type,client,tx,amount
+deposit,1,1,5
+debit,1,2,1
+";
+        assert!(Engine::new().process_csv(unknown.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::amount::Amount;
+use crate::dormancy::DAY;
+use crate::output::civil_date;
+use crate::tenant::Tenants;
+use crate::transactions::{Client, ClientFunds};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+
+// An account's balances at the end of a UTC day, given as days since the
+// unix epoch.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct DailyBalance {
+    pub day: u64,
+    pub available: Amount,
+    pub held: Amount,
+}
+
+// End-of-day balances per client, kept as the engine's clock (see
+// `Engine::now`) crosses midnight. Only days an account changed on are
+// stored; on the days in between it kept the balances of the last one
+// before, which `at` looks up. Changes applied before the clock is first
+// set count towards its first day.
+#[derive(Debug, Default, Clone)]
+pub struct DailyBalances {
+    // the day being recorded
+    day: Option<u64>,
+    changed: HashSet<Client>,
+    series: HashMap<Client, Vec<DailyBalance>>,
+}
+
+impl DailyBalances {
+    pub(crate) fn touch(&mut self, client: Client) {
+        self.changed.insert(client);
+    }
+
+    // Ends the day being recorded when `now` falls on a later one.
+    pub(crate) fn advance(&mut self, now: u64, accounts: &ClientFunds) {
+        let today = now / DAY;
+        match self.day {
+            Some(day) if day < today => {
+                self.end_day(accounts);
+                self.day = Some(today);
+            }
+            Some(_) => {}
+            None => self.day = Some(today),
+        }
+    }
+
+    // Records the balances of the accounts changed on the day being
+    // recorded, as they are now.
+    pub(crate) fn end_day(&mut self, accounts: &ClientFunds) {
+        let day = match self.day {
+            Some(day) => day,
+            None => return,
+        };
+        for client in self.changed.drain() {
+            let funds = match accounts.get(&client) {
+                Some(funds) => funds,
+                None => continue,
+            };
+            let balance = DailyBalance {
+                day,
+                available: funds.available,
+                held: funds.held,
+            };
+            let series = self.series.entry(client).or_default();
+            match series.last_mut() {
+                // the open day ended early, then changed again
+                Some(last) if last.day == day => *last = balance,
+                _ => series.push(balance),
+            }
+        }
+    }
+
+    // The days `client`'s account changed on, oldest first.
+    pub fn series(&self, client: Client) -> &[DailyBalance] {
+        self.series.get(&client).map_or(&[], Vec::as_slice)
+    }
+
+    // `client`'s balances at the end of the day `timestamp` falls on; None
+    // before its first recorded day.
+    pub fn at(&self, client: Client, timestamp: u64) -> Option<DailyBalance> {
+        let day = timestamp / DAY;
+        let series = self.series(client);
+        let after = series.partition_point(|balance| balance.day <= day);
+        after.checked_sub(1).map(|index| series[index])
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = Client> + '_ {
+        self.series.keys().copied()
+    }
+}
+
+#[derive(Serialize)]
+struct DailyRow {
+    date: String,
+    client: u32,
+    available: String,
+    held: String,
+    total: String,
+}
+
+// Every tenant's recorded end-of-day balances as CSV, by client then date,
+// one row per day an account changed on. The tenant is the first column
+// only when there are tenants.
+pub fn write_daily_balances<W: Write>(tenants: &Tenants, writer: W) -> csv::Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    let header = ["date", "client", "available", "held", "total"];
+    if tenants.is_isolated() {
+        writer.write_record(std::iter::once("tenant").chain(header.iter().copied()))?;
+    } else {
+        writer.write_record(header)?;
+    }
+    for (tenant, engine) in tenants.iter() {
+        let daily = match engine.daily_balances() {
+            Some(daily) => daily,
+            None => continue,
+        };
+        let clients: BTreeMap<u32, Client> =
+            daily.clients().map(|client| (client.0, client)).collect();
+        for client in clients.values() {
+            for balance in daily.series(*client) {
+                let (year, month, day) = civil_date(balance.day * DAY);
+                let total = Amount(balance.available.0.saturating_add(balance.held.0));
+                let row = DailyRow {
+                    date: format!("{:04}-{:02}-{:02}", year, month, day),
+                    client: client.0,
+                    available: balance.available.to_string(),
+                    held: balance.held.to_string(),
+                    total: total.to_string(),
+                };
+                if tenants.is_isolated() {
+                    writer.serialize((tenant, row))?;
+                } else {
+                    writer.serialize(row)?;
+                }
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_daily_balances;
+    use crate::amount::Amount;
+    use crate::dormancy::DAY;
+    use crate::engine::Engine;
+    use crate::tenant::Tenants;
+    use crate::transactions::Client;
+
+    #[test]
+    fn test_daily_balances() {
+        let mut tenants = Tenants::single(Engine::builder().record_daily_balances(true));
+        let engine = tenants.engine("default");
+        // 2026-10-16 to 2026-10-19
+        let day = |n: u64| 20_742 * DAY + n * DAY + 3600;
+        let csvfile = format!(
+            "type,client,tx,amount,timestamp,currency\ndeposit,1,1,10,{},EUR\ndeposit,1,2,5,{},EUR\n\
+             deposit,2,3,1,{},EUR\nwithdrawal,1,4,3,{},EUR\n",
+            day(0),
+            day(0),
+            day(1),
+            day(3),
+        );
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        engine.end_day();
+        let daily = engine.daily_balances().unwrap();
+        assert_eq!(daily.series(Client(1)).len(), 2);
+        // unchanged on the 18th, so as it was at the end of the 16th
+        let balance = daily.at(Client(1), day(2)).unwrap();
+        assert_eq!(balance.available, Amount::new(150_000));
+        assert_eq!(daily.at(Client(2), day(0)), None);
+
+        let mut out = Vec::new();
+        write_daily_balances(&tenants, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "date,client,available,held,total\n\
+             2026-10-16,1,15.0000,0.0000,15.0000\n\
+             2026-10-19,1,12.0000,0.0000,12.0000\n\
+             2026-10-17,2,1.0000,0.0000,1.0000\n"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use crate::amount::Amount;
+use crate::funds::Funds;
+use crate::postings::{LedgerAccount, Posting};
+use crate::transactions::{Effect, TransactionRecord, TxRecords, TxType};
+
+fn lesser(a: Amount, b: Amount) -> Amount {
+    Amount(a.0.min(b.0))
+}
+
+// What disputes of spent funds leave clients owing. A dispute holds the
+// whole disputed amount, so when the available balance doesn't cover it
+// the engine lends the account the rest first (see `lend`), kept in
+// `Funds::uncovered`. A resolve pays the loan back from the released funds;
+// a chargeback takes it from what the account has available and the rest
+// becomes the account's debt (`Funds::debt`), which later deposits repay
+// before adding to the available balance, or pending ones once they settle.
+#[derive(Debug, Default, Clone)]
+pub struct DebtTracker;
+
+impl DebtTracker {
+    // Called before a dispute is applied: lends its client's account what
+    // its available balance doesn't cover of the disputed tx. Returns the
+    // posting of the loan, which `repay` takes back should the dispute be
+    // rejected.
+    pub(crate) fn lend(
+        &self,
+        record: &TransactionRecord,
+        records: &TxRecords,
+        funds: Option<&mut Funds>,
+    ) -> Option<Posting> {
+        let disputed = match (record.r#type, records.get(&record.tx)) {
+            (TxType::Dispute, Some(logged)) if logged.client() == record.client => logged.amount(),
+            _ => return None,
+        };
+        let funds = funds?;
+        let lent = Amount(disputed.0.checked_sub(funds.available.0)?);
+        if lent.0 == 0 {
+            return None;
+        }
+        funds.available = funds.available.checked_add(lent).ok()?;
+        funds.uncovered = Amount(funds.uncovered.0.saturating_add(lent.0));
+        Some(Posting {
+            debit: LedgerAccount::Debt(record.client),
+            credit: LedgerAccount::Available(record.client),
+            amount: lent,
+        })
+    }
+
+    // Takes back a loan `lend` made for a dispute that wasn't applied.
+    pub(crate) fn repay(&self, loan: &Posting, funds: &mut Funds) {
+        funds.available = Amount(funds.available.0 - loan.amount.0);
+        funds.uncovered = Amount(funds.uncovered.0 - loan.amount.0);
+    }
+
+    // Called with each applied record, its effect and its client's account.
+    // Returns the postings of what it moved between the account and its
+    // debt.
+    pub(crate) fn observe(
+        &self,
+        record: &TransactionRecord,
+        effect: &Effect,
+        funds: &mut Funds,
+    ) -> Vec<Posting> {
+        let client = record.client;
+        let available = LedgerAccount::Available(client);
+        let debt = LedgerAccount::Debt(client);
+        let post = |amount: Amount| {
+            (amount.0 > 0).then_some(Posting {
+                debit: available,
+                credit: debt,
+                amount,
+            })
+        };
+        let repaid = match record.r#type {
+            // loans are fungible, so whichever dispute ends first pays them
+            // back
+            TxType::Resolve => {
+                let repaid = lesser(funds.uncovered, lesser(effect.amount, funds.available));
+                funds.uncovered = Amount(funds.uncovered.0 - repaid.0);
+                repaid
+            }
+            TxType::Chargeback => {
+                let shortfall = lesser(funds.uncovered, effect.amount);
+                let paid = lesser(funds.available, shortfall);
+                funds.uncovered = Amount(funds.uncovered.0 - shortfall.0);
+                funds.debt = Amount(funds.debt.0.saturating_add(shortfall.0 - paid.0));
+                paid
+            }
+            TxType::Deposit | TxType::Settle => {
+                let repaid = lesser(funds.debt, lesser(effect.amount, funds.available));
+                funds.debt = Amount(funds.debt.0 - repaid.0);
+                repaid
+            }
+            TxType::Withdrawal | TxType::Dispute | TxType::Credit => return Vec::new(),
+        };
+        funds.available = Amount(funds.available.0 - repaid.0);
+        funds.bonus = lesser(funds.bonus, funds.available);
+        post(repaid).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::output::write_extended_accounts;
+    use crate::postings::LedgerAccount;
+    use crate::tenant::Tenants;
+    use crate::transactions::{Client, TransactionRecord, Tx};
+
+    #[test]
+    fn test_debt() {
+        let mut engine = Engine::builder()
+            .track_debt(true)
+            .double_entry(true)
+            .check_invariants(true)
+            .build();
+        let client = Client(1);
+        for record in [
+            TransactionRecord::deposit(client, Tx(1), Amount::new(100)),
+            TransactionRecord::deposit(client, Tx(2), Amount::new(30)),
+            TransactionRecord::withdrawal(client, Tx(3), Amount::new(90)),
+            // 40 left, so the dispute isn't covered
+            TransactionRecord::dispute(client, Tx(1)),
+            TransactionRecord::chargeback(client, Tx(1)),
+        ] {
+            engine.process(&record);
+        }
+        let funds = engine.account(client).unwrap();
+        assert_eq!(funds.available, Amount::new(0));
+        assert_eq!(funds.debt, Amount::new(60));
+        assert_eq!(funds.uncovered, Amount::new(0));
+
+        engine.unfreeze(client);
+        engine.process(&TransactionRecord::deposit(client, Tx(4), Amount::new(25)));
+        engine.process(&TransactionRecord::deposit(client, Tx(5), Amount::new(50)));
+        let funds = engine.account(client).unwrap();
+        assert_eq!(funds.debt, Amount::new(0));
+        assert_eq!(funds.available, Amount::new(15));
+
+        let books = engine.books().unwrap();
+        assert_eq!(books.balance(LedgerAccount::Debt(client)), 0);
+        assert!(engine.trial_balance().unwrap().is_balanced());
+
+        // a resolve pays the loan back from what it releases
+        let other = Client(2);
+        for record in [
+            TransactionRecord::deposit(other, Tx(6), Amount::new(10)),
+            TransactionRecord::withdrawal(other, Tx(7), Amount::new(8)),
+            TransactionRecord::dispute(other, Tx(6)),
+        ] {
+            engine.try_process(&record).unwrap();
+        }
+        let funds = engine.account(other).unwrap();
+        assert_eq!(
+            (funds.available, funds.held, funds.uncovered),
+            (Amount::new(0), Amount::new(10), Amount::new(8))
+        );
+        assert!(engine.trial_balance().unwrap().is_balanced());
+        engine
+            .try_process(&TransactionRecord::resolve(other, Tx(6)))
+            .unwrap();
+        let funds = engine.account(other).unwrap();
+        assert_eq!(
+            (funds.available, funds.held, funds.uncovered, funds.debt),
+            (
+                Amount::new(2),
+                Amount::new(0),
+                Amount::new(0),
+                Amount::new(0)
+            )
+        );
+        assert!(engine.violations().is_empty());
+        assert!(engine.trial_balance().unwrap().is_balanced());
+
+        let mut tenants = Tenants::single(Engine::builder().track_debt(true));
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,10\nwithdrawal,1,2,8\ndispute,1,1,\nchargeback,1,1,\n";
+        tenants
+            .engine("default")
+            .process_csv(csvfile.as_bytes())
+            .unwrap();
+        let mut out = Vec::new();
+        write_extended_accounts(&tenants, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked,flags,risk_score,debt\n\
+             1,0.0000,0.0000,0.0000,true,,,8.0000\n"
+        );
+    }
+}
@@ -0,0 +1,509 @@
+use crate::amount::Amount;
+use crate::csv_dialect::{CsvDialect, UnknownTypes};
+use crate::engine::Engine;
+use crate::schema::SchemaVersion;
+use crate::transactions::{
+    amount_field, loosely_matches, Client, RowRecord, Tx, TxType, TxTypeOrUnknown,
+};
+use csv::StringRecord;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+// Column names for rows read without a header, in positional order.
+const POSITIONAL: [&str; 6] = ["type", "client", "tx", "amount", "timestamp", "currency"];
+
+// A CSV row that couldn't be turned into a transaction: where it is, the
+// column at fault when one is, and the row as read. Carried inside the
+// `csv::Error` a decoder returns (see `malformed_row`).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MalformedRow {
+    // 1-based, counting the header row
+    pub line: u64,
+    pub field: Option<String>,
+    pub raw: String,
+    pub reason: String,
+}
+
+impl fmt::Display for MalformedRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(field) = &self.field {
+            write!(f, ", field `{}`", field)?;
+        }
+        write!(f, ": {} (row: `{}`)", self.reason, self.raw)
+    }
+}
+
+impl Error for MalformedRow {}
+
+// The malformed row an error from `CsvDecoder` is about, if it is about
+// one rather than, say, failing to read the input.
+pub fn malformed_row(err: &csv::Error) -> Option<&MalformedRow> {
+    match err.kind() {
+        csv::ErrorKind::Io(err) => err.get_ref()?.downcast_ref(),
+        _ => None,
+    }
+}
+
+// A row whose type the engine doesn't know but is otherwise readable,
+// returned as an error by `CsvDecoder` when `CsvDialect::unknown_types`
+// skips or rejects such rows (see `unknown_type_row`).
+#[derive(Debug, PartialEq, Clone)]
+pub struct UnknownTypeRow {
+    pub line: u64,
+    pub r#type: String,
+    pub client: Client,
+    pub tx: Tx,
+    pub amount: Option<Amount>,
+}
+
+impl fmt::Display for UnknownTypeRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}: unknown transaction type `{}`",
+            self.line, self.r#type
+        )
+    }
+}
+
+impl Error for UnknownTypeRow {}
+
+pub fn unknown_type_row(err: &csv::Error) -> Option<&UnknownTypeRow> {
+    match err.kind() {
+        csv::ErrorKind::Io(err) => err.get_ref()?.downcast_ref(),
+        _ => None,
+    }
+}
+
+// A source of rows for the engine. Rows may borrow from the decoder's own
+// buffers, so each one has to be consumed before the next call; that is
+// what lets a decoder reuse a single line buffer without allocating per
+// row. Implement it for proprietary formats to get the same validation and
+// account handling as the built-in readers.
+pub trait RecordDecoder {
+    type Error;
+
+    fn next(&mut self) -> Option<Result<RowRecord<'_>, Self::Error>>;
+}
+
+pub struct CsvDecoder<R> {
+    reader: csv::Reader<R>,
+    rows: RowDecoder,
+    raw: StringRecord,
+}
+
+// The part of a `CsvDecoder` that turns a record already read into a row:
+// the mapped headers, schema version and per-row dialect options. It holds
+// no reader, so the CSV pipeline's parser threads each work from a copy.
+#[derive(Clone)]
+pub(crate) struct RowDecoder {
+    headers: Option<StringRecord>,
+    extras: Vec<(usize, String)>,
+    version: SchemaVersion,
+    delimiter: char,
+    narrow_client_ids: bool,
+    type_aliases: Vec<(String, TxType)>,
+    unknown_types: UnknownTypes,
+}
+
+impl<R: Read> CsvDecoder<R> {
+    pub fn new(input: R, dialect: &CsvDialect) -> csv::Result<CsvDecoder<R>> {
+        let mut reader = dialect.reader_builder()?.from_reader(input);
+        let (headers, extras, version) = if dialect.has_headers {
+            let (headers, extras) = dialect.columns.map_headers(reader.headers()?)?;
+            let version = SchemaVersion::detect(&headers)?;
+            (Some(headers), extras, version)
+        } else {
+            (None, Vec::new(), dialect.schema_version)
+        };
+        let rows = RowDecoder {
+            headers,
+            extras,
+            version,
+            delimiter: dialect.delimiter,
+            narrow_client_ids: dialect.narrow_client_ids,
+            type_aliases: dialect
+                .type_aliases
+                .iter()
+                .map(|(alias, r#type)| {
+                    let alias = alias.to_ascii_lowercase().replace(['_', '-', ' '], "");
+                    (alias, *r#type)
+                })
+                .collect(),
+            unknown_types: dialect.unknown_types,
+        };
+        Ok(CsvDecoder {
+            reader,
+            rows,
+            raw: StringRecord::new(),
+        })
+    }
+
+    pub fn captures_columns(&self) -> bool {
+        self.rows.captures_columns()
+    }
+
+    // The captured extra columns (see `ColumnMapping`) of the last row read.
+    pub fn extra_columns(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.rows.extra_columns(&self.raw)
+    }
+
+    // The reader, past the header row, and the rest of the decoder, for
+    // reading and decoding on different threads.
+    pub(crate) fn into_parts(self) -> (csv::Reader<R>, RowDecoder) {
+        (self.reader, self.rows)
+    }
+}
+
+impl RowDecoder {
+    pub(crate) fn captures_columns(&self) -> bool {
+        !self.extras.is_empty()
+    }
+
+    pub(crate) fn extra_columns<'r>(
+        &'r self,
+        raw: &'r StringRecord,
+    ) -> impl Iterator<Item = (&'r str, &'r str)> {
+        self.extras
+            .iter()
+            .map(move |(i, name)| (name.as_str(), raw.get(*i).unwrap_or("")))
+    }
+
+    fn malformed(
+        &self,
+        raw: &StringRecord,
+        line: Option<u64>,
+        field: Option<u64>,
+        reason: String,
+    ) -> csv::Error {
+        let field = field.and_then(|index| {
+            let index = index as usize;
+            match &self.headers {
+                Some(headers) => headers.get(index).map(str::to_string),
+                None => POSITIONAL.get(index).map(|name| name.to_string()),
+            }
+        });
+        let line = line
+            .or_else(|| raw.position().map(|position| position.line()))
+            .unwrap_or(0);
+        let fields: Vec<&str> = raw.iter().collect();
+        let row = MalformedRow {
+            line,
+            field,
+            raw: fields.join(&self.delimiter.to_string()),
+            reason,
+        };
+        csv::Error::from(io::Error::new(io::ErrorKind::InvalidData, row))
+    }
+
+    fn column(&self, name: &str) -> Option<usize> {
+        match &self.headers {
+            Some(headers) => headers.iter().position(|header| header == name),
+            None => POSITIONAL.iter().position(|header| *header == name),
+        }
+    }
+
+    // Errors raised by the fields' own parsing (the type, the client and
+    // its sub-account, the amount) don't say which field they came from.
+    fn invalid_field(&self, raw: &StringRecord) -> Option<u64> {
+        let invalid = |name: &str, valid: fn(&str) -> bool| {
+            let index = self.column(name)?;
+            (!valid(raw.get(index)?)).then_some(index as u64)
+        };
+        invalid("type", |s| TxType::from_str(s).is_ok())
+            .or_else(|| {
+                invalid("client", |s| {
+                    let id = s.split(':').next().unwrap_or(s);
+                    id.parse::<u32>().is_ok()
+                })
+            })
+            .or_else(|| invalid("amount", |s| amount_field(s).is_ok()))
+    }
+
+    // Swaps a configured alias in the type column for the name it stands
+    // for; rows spelling the type any other way are left alone.
+    pub(crate) fn resolve_type_alias(&self, raw: &mut StringRecord) {
+        if self.type_aliases.is_empty() {
+            return;
+        }
+        let Some(index) = self.column("type") else {
+            return;
+        };
+        let Some(field) = raw.get(index) else {
+            return;
+        };
+        let Some((_, r#type)) = self
+            .type_aliases
+            .iter()
+            .find(|(alias, _)| loosely_matches(field, alias))
+        else {
+            return;
+        };
+        let mut resolved: StringRecord = raw
+            .iter()
+            .enumerate()
+            .map(|(i, field)| if i == index { r#type.as_str() } else { field })
+            .collect();
+        resolved.set_position(raw.position().cloned());
+        *raw = resolved;
+    }
+
+    // The permissive read of the type column: a row with an unknown type
+    // but a readable client and tx. Anything else is left to `decode`,
+    // which reports it as malformed.
+    fn unknown_type(&self, raw: &StringRecord) -> Option<UnknownTypeRow> {
+        let field = |name| self.column(name).and_then(|index| raw.get(index));
+        let TxTypeOrUnknown::Unknown(r#type) = TxTypeOrUnknown::parse(field("type")?) else {
+            return None;
+        };
+        let amount = field("amount")
+            .and_then(|amount| amount_field(amount).ok().flatten())
+            .and_then(|amount| Amount::from_str(amount).ok());
+        Some(UnknownTypeRow {
+            line: raw.position().map_or(0, |position| position.line()),
+            r#type,
+            client: Client(field("client")?.trim().parse().ok()?),
+            tx: Tx(field("tx")?.trim().parse().ok()?),
+            amount,
+        })
+    }
+
+    // A record (with its type alias resolved) as a row, or the error a
+    // `CsvDecoder` returns for it.
+    pub(crate) fn decode<'r>(&'r self, raw: &'r StringRecord) -> csv::Result<RowRecord<'r>> {
+        if self.unknown_types != UnknownTypes::Abort {
+            if let Some(row) = self.unknown_type(raw) {
+                let err = io::Error::new(io::ErrorKind::InvalidData, row);
+                return Err(csv::Error::from(err));
+            }
+        }
+        let row: RowRecord =
+            raw.deserialize(self.headers.as_ref())
+                .map_err(|err| match err.kind() {
+                    csv::ErrorKind::Deserialize { pos, err: de } => self.malformed(
+                        raw,
+                        pos.as_ref().map(|pos| pos.line()),
+                        de.field().or_else(|| self.invalid_field(raw)),
+                        de.kind().to_string(),
+                    ),
+                    _ => err,
+                })?;
+        let row = self
+            .version
+            .decode(row)
+            .map_err(|reason| self.malformed(raw, None, None, reason.to_string()))?;
+        if self.narrow_client_ids && row.client().0 > u32::from(u16::MAX) {
+            // the same error a 16-bit id used to fail to parse with
+            return Err(self.malformed(
+                raw,
+                None,
+                self.column("client").map(|index| index as u64),
+                "number too large to fit in target type".to_string(),
+            ));
+        }
+        Ok(row)
+    }
+
+    // An error from reading a record into `raw`: rows of the wrong length
+    // or encoding are malformed rows, anything else is passed on.
+    pub(crate) fn read_error(&self, raw: &StringRecord, err: csv::Error) -> csv::Error {
+        let line = err.position().map(|position| position.line());
+        let malformed = match err.kind() {
+            csv::ErrorKind::UnequalLengths {
+                expected_len, len, ..
+            } => Some((
+                None,
+                format!("expected {} fields, found {}", expected_len, len),
+            )),
+            csv::ErrorKind::Utf8 { err, .. } => Some((
+                Some(err.field() as u64),
+                "field is not valid UTF-8".to_string(),
+            )),
+            _ => None,
+        };
+        match malformed {
+            Some((field, reason)) => self.malformed(raw, line, field, reason),
+            None => err,
+        }
+    }
+}
+
+impl<R: Read> RecordDecoder for CsvDecoder<R> {
+    type Error = csv::Error;
+
+    fn next(&mut self) -> Option<csv::Result<RowRecord<'_>>> {
+        match self.reader.read_record(&mut self.raw) {
+            Ok(true) => {
+                self.rows.resolve_type_alias(&mut self.raw);
+                Some(self.rows.decode(&self.raw))
+            }
+            Ok(false) => None,
+            Err(err) => Some(Err(self.rows.read_error(&self.raw, err))),
+        }
+    }
+}
+
+impl Engine {
+    pub fn process_decoder<D: RecordDecoder>(&mut self, decoder: &mut D) -> Result<(), D::Error> {
+        while let Some(row) = decoder.next() {
+            let _ = self.process_row(row?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{malformed_row, MalformedRow, RecordDecoder};
+    use crate::amount::Amount;
+    use crate::csv_dialect::{CsvDialect, UnknownTypes};
+    use crate::engine::Engine;
+    use crate::error::PaymentsError;
+    use crate::transactions::{
+        Client, RejectReason, Rejection, RowRecord, Tx, TxType, TxTypeOrUnknown,
+    };
+    use std::str::FromStr;
+
+    // A made-up "D|client|tx|amount" format, decoded into one reused line.
+    struct PipeDecoder<'a> {
+        lines: std::str::Lines<'a>,
+        line: String,
+    }
+
+    impl RecordDecoder for PipeDecoder<'_> {
+        type Error = PaymentsError;
+
+        fn next(&mut self) -> Option<Result<RowRecord<'_>, PaymentsError>> {
+            self.line.clear();
+            self.line.push_str(self.lines.next()?);
+            let fields: Vec<&str> = self.line.split('|').collect();
+            let r#type = match fields[0] {
+                "D" => TxType::Deposit,
+                "W" => TxType::Withdrawal,
+                _ => return Some(Err(PaymentsError::Parse("Unknown transaction type"))),
+            };
+            let client = Client(fields[1].parse().unwrap());
+            let tx = Tx(fields[2].parse().unwrap());
+            if let Err(err) = Amount::from_str(fields[3]) {
+                return Some(Err(err));
+            }
+            Some(Ok(RowRecord::new(r#type, client, tx, Some(fields[3]))))
+        }
+    }
+
+    #[test]
+    fn test_custom_decoder() {
+        let mut decoder = PipeDecoder {
+            lines: "D|1|1|5\nW|1|2|1.5\nX|1|3|1".lines(),
+            line: String::new(),
+        };
+        let mut engine = Engine::new();
+        assert_eq!(
+            engine.process_decoder(&mut decoder),
+            Err(PaymentsError::Parse("Unknown transaction type"))
+        );
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(35000)
+        );
+    }
+
+    #[test]
+    fn test_malformed_rows() {
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,x,2,5.0\ndeposit,1,3,1.23456\ndeposit,1,4\ndeposit,1,5,2\n";
+        let err = Engine::new().process_csv(input.as_bytes()).unwrap_err();
+        assert_eq!(
+            malformed_row(&err),
+            Some(&MalformedRow {
+                line: 3,
+                field: Some("client".to_string()),
+                raw: "withdrawal,x,2,5.0".to_string(),
+                reason: "invalid digit found in string".to_string(),
+            })
+        );
+
+        let permissive = CsvDialect {
+            permissive: true,
+            ..CsvDialect::default()
+        };
+        let mut engine = Engine::new();
+        engine
+            .process_csv_with(input.as_bytes(), &permissive)
+            .unwrap();
+        assert_eq!(engine.metrics().rows_malformed, 3);
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(30000)
+        );
+
+        let input = "type,client,tx,amount\ndeposit,70000,1,1.0\n";
+        let mut engine = Engine::new();
+        engine.process_csv(input.as_bytes()).unwrap();
+        assert!(engine.account(Client(70000)).is_some());
+        let narrow = CsvDialect {
+            narrow_client_ids: true,
+            ..CsvDialect::default()
+        };
+        let err = Engine::new()
+            .process_csv_with(input.as_bytes(), &narrow)
+            .unwrap_err();
+        assert_eq!(
+            malformed_row(&err),
+            Some(&MalformedRow {
+                line: 2,
+                field: Some("client".to_string()),
+                raw: "deposit,70000,1,1.0".to_string(),
+                reason: "number too large to fit in target type".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_types() {
+        let input = "type,client,tx,amount\ndeposit,1,1,5\ntransferx,1,2,1.5\nwithdrawal,1,3,1\n";
+        let err = Engine::new().process_csv(input.as_bytes()).unwrap_err();
+        assert_eq!(malformed_row(&err).map(|row| row.line), Some(3));
+
+        let skip = CsvDialect {
+            unknown_types: UnknownTypes::Skip,
+            ..CsvDialect::default()
+        };
+        let mut engine = Engine::builder().record_rejections(true).build();
+        engine.process_csv_with(input.as_bytes(), &skip).unwrap();
+        assert_eq!(engine.metrics().rows_unknown_type, 1);
+        assert!(engine.rejections().is_empty());
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(40000)
+        );
+
+        let reject = CsvDialect {
+            unknown_types: UnknownTypes::Reject,
+            ..CsvDialect::default()
+        };
+        let mut engine = Engine::builder().record_rejections(true).build();
+        engine.process_csv_with(input.as_bytes(), &reject).unwrap();
+        assert_eq!(
+            engine.rejections(),
+            [Rejection {
+                r#type: TxTypeOrUnknown::Unknown("transferx".to_string()),
+                client: Client(1),
+                tx: Tx(2),
+                amount: Some(Amount::new(15000)),
+                reason: RejectReason::UnknownType,
+            }]
+        );
+        // a row that is malformed besides its type is still malformed
+        let input = "type,client,tx,amount\ntransferx,x,2,1.5\n";
+        let err = Engine::new()
+            .process_csv_with(input.as_bytes(), &reject)
+            .unwrap_err();
+        assert_eq!(
+            malformed_row(&err).and_then(|row| row.field.as_deref()),
+            Some("type")
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use crate::transactions::{Client, TransactionRecord};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+// What happens to a transaction of a denied client or tx id.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DenyAction {
+    // rejected as `denied`
+    #[default]
+    Reject,
+    // rejected, and the client's account frozen, or opened frozen when
+    // the client has none
+    Freeze,
+}
+
+// The `[denylist]` config section.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DenylistConfig {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub action: DenyAction,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    clients: HashSet<Client>,
+    txs: Vec<RangeInclusive<u64>>,
+}
+
+impl Entries {
+    fn len(&self) -> usize {
+        self.clients.len() + self.txs.len()
+    }
+}
+
+fn number<T: FromStr>(field: &str) -> Result<T, String> {
+    field.parse().map_err(|_| format!("bad id {:?}", field))
+}
+
+// One entry per line: a client id, or `tx` and a tx id or inclusive range
+// such as `tx 1000-1999`. Blank lines and `#` comments are skipped.
+fn parse(text: &str) -> Result<Entries, String> {
+    let mut entries = Entries::default();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let parsed = match words.as_slice() {
+            [] => Ok(()),
+            ["tx", range] => {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                number(start)
+                    .and_then(|start| Ok(start..=number(end)?))
+                    .map(|range| entries.txs.push(range))
+            }
+            [client] => number(client).map(|client| {
+                entries.clients.insert(Client(client));
+            }),
+            _ => Err("expected a client id or `tx <id>[-<id>]`".to_string()),
+        };
+        parsed.map_err(|reason| format!("line {}: {}", index + 1, reason))?;
+    }
+    Ok(entries)
+}
+
+// Clients and tx ids whose transactions are refused, read from a file.
+// Clones share the entries, so one `reload` reaches every engine (and
+// tenant) built with the list.
+#[derive(Debug, Clone)]
+pub struct Denylist {
+    path: PathBuf,
+    action: DenyAction,
+    entries: Arc<RwLock<Entries>>,
+}
+
+impl Denylist {
+    pub fn load(config: &DenylistConfig) -> io::Result<Denylist> {
+        let list = Denylist {
+            path: config.path.clone(),
+            action: config.action,
+            entries: Arc::default(),
+        };
+        list.reload()?;
+        Ok(list)
+    }
+
+    // Rereads the file and returns how many entries it has. A file that
+    // can't be read or parsed leaves the current entries in place.
+    pub fn reload(&self) -> io::Result<usize> {
+        let text = fs::read_to_string(&self.path)?;
+        let entries = parse(&text).map_err(|reason| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", self.path.display(), reason),
+            )
+        })?;
+        let len = entries.len();
+        *self.entries.write().expect("denylist lock poisoned") = entries;
+        Ok(len)
+    }
+
+    pub fn action(&self) -> DenyAction {
+        self.action
+    }
+
+    pub(crate) fn denies(&self, record: &TransactionRecord) -> bool {
+        let entries = self.entries.read().expect("denylist lock poisoned");
+        entries.clients.contains(&record.client)
+            || entries.txs.iter().any(|range| range.contains(&record.tx.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DenyAction, Denylist, DenylistConfig};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::transactions::{Client, RejectReason};
+    use std::fs;
+
+    #[test]
+    fn test_denylist() {
+        let path = std::env::temp_dir().join("payment_engine_denylist.txt");
+        fs::write(&path, "# sanctioned\n3\ntx 100-199\n").unwrap();
+        let mut config = DenylistConfig {
+            path: path.clone(),
+            action: DenyAction::Reject,
+        };
+        let list = Denylist::load(&config).unwrap();
+        let mut engine = Engine::builder().denylist(list.clone()).build();
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,3,2,1.0\ndeposit,1,150,1.0\ndeposit,2,3,1.0\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        assert_eq!(engine.metrics().rejected[&RejectReason::Denied], 2);
+        assert!(engine.account(Client(3)).is_none());
+
+        // reloading reaches engines already built with the list
+        fs::write(&path, "2\n").unwrap();
+        assert_eq!(list.reload().unwrap(), 1);
+        engine
+            .process_csv("type,client,tx,amount\ndeposit,3,4,1.0\n".as_bytes())
+            .unwrap();
+        assert!(engine.account(Client(3)).is_some());
+        fs::write(&path, "2\nclient 4\n").unwrap();
+        assert!(list.reload().is_err());
+
+        config.action = DenyAction::Freeze;
+        fs::write(&path, "2\n").unwrap();
+        let list = Denylist::load(&config).unwrap();
+        let mut frozen = Engine::builder().denylist(list.clone()).build();
+        frozen.process_csv(csvfile.as_bytes()).unwrap();
+        fs::write(&path, "1\n").unwrap();
+        list.reload().unwrap();
+        frozen
+            .process_csv("type,client,tx,amount\ndeposit,1,5,1.0\n".as_bytes())
+            .unwrap();
+        for client in [Client(1), Client(2)] {
+            let funds = frozen.account(client).unwrap();
+            assert_eq!(funds.state, FundingStates::Frozen);
+        }
+        assert_eq!(
+            frozen.account(Client(1)).unwrap().available,
+            Amount::new(30000)
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
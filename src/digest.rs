@@ -0,0 +1,105 @@
+use crate::funds::Funds;
+use crate::tenant::Tenants;
+use crate::transactions::TransactionRecord;
+use sha2::{Digest, Sha256};
+use std::io::{self, Write};
+
+// A SHA-256 over the records an engine applied, in order, followed by the
+// accounts it ended with, so two runs over the same input can be shown to
+// agree across machines and engine versions. The hashed text is
+//
+//   <type>,<client>,<tx>,<amount>\n     per applied record, amount as given
+//   accounts\n
+//   <client>,<available>,<held>,<state>\n     per account, by client
+//
+// Changing this encoding changes every digest, so it has to stay as is.
+#[derive(Debug, Default, Clone)]
+pub struct RunDigest {
+    hasher: Sha256,
+}
+
+impl RunDigest {
+    pub(crate) fn record(&mut self, record: &TransactionRecord) {
+        let amount = record
+            .amount
+            .map(|amount| amount.to_string())
+            .unwrap_or_default();
+        let line = format!(
+            "{},{},{},{}\n",
+            record.r#type.as_str(),
+            record.client.0,
+            record.tx.0,
+            amount
+        );
+        self.hasher.update(line.as_bytes());
+    }
+
+    // The hex digest of what was recorded so far and `accounts`.
+    pub fn finish<'a, I>(&self, accounts: I) -> String
+    where
+        I: IntoIterator<Item = &'a Funds>,
+    {
+        let mut hasher = self.hasher.clone();
+        hasher.update(b"accounts\n");
+        let mut accounts: Vec<&Funds> = accounts.into_iter().collect();
+        accounts.sort_by_key(|funds| funds.client.0);
+        for funds in accounts {
+            let line = format!(
+                "{},{},{},{}\n",
+                funds.client.0,
+                funds.available,
+                funds.held,
+                funds.state.as_str()
+            );
+            hasher.update(line.as_bytes());
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+// One line per engine built with `EngineBuilder::digest`: the digest, after
+// the tenant's name and a space when there are tenants.
+pub fn write_digests<W: Write>(tenants: &Tenants, mut writer: W) -> io::Result<()> {
+    for (tenant, engine) in tenants.iter() {
+        if let Some(digest) = engine.digest() {
+            if tenants.is_isolated() {
+                writeln!(writer, "{} {}", tenant, digest)?;
+            } else {
+                writeln!(writer, "{}", digest)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Engine;
+
+    #[test]
+    fn test_run_digest() {
+        let run = |csvfile: &str| {
+            let mut engine = Engine::builder().digest(true).build();
+            engine.process_csv(csvfile.as_bytes()).unwrap();
+            engine.digest().unwrap()
+        };
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,2,2,1.0\nwithdrawal,1,3,5.0\ndispute,1,1,\n";
+        let digest = run(csvfile);
+        assert_eq!(digest.len(), 64);
+        assert_eq!(digest, run(csvfile));
+        // rejected records don't count, but what was applied and its order do
+        assert_eq!(
+            digest,
+            run("type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,2,2,1.0\ndispute,1,1,\n")
+        );
+        assert_ne!(
+            digest,
+            run("type,client,tx,amount\ndeposit,2,2,1.0\ndeposit,1,1,2.0\ndispute,1,1,\n")
+        );
+        assert!(Engine::new().digest().is_none());
+    }
+}
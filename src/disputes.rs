@@ -0,0 +1,109 @@
+use crate::dormancy::DAY;
+use crate::transactions::{Client, TransactionRecord, Tx, TxType};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// The `[disputes]` config section.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DisputeConfig {
+    // days a dispute may stay open before it is resolved automatically
+    pub timeout_days: u64,
+}
+
+// The open disputes and when they were raised, so that
+// `Engine::advance_to` can resolve the ones left open too long. Times are
+// the engine's clock (see `Engine::now`); a dispute raised before the clock
+// was first set counts from then.
+#[derive(Debug, Clone)]
+pub struct DisputeTimer {
+    timeout: u64,
+    open: HashMap<Tx, (Client, Option<u64>)>,
+}
+
+impl DisputeTimer {
+    pub fn new(config: DisputeConfig) -> DisputeTimer {
+        DisputeTimer {
+            timeout: config.timeout_days.saturating_mul(DAY),
+            open: HashMap::new(),
+        }
+    }
+
+    pub fn open(&self) -> usize {
+        self.open.len()
+    }
+
+    // Called with each applied record.
+    pub(crate) fn observe(&mut self, record: &TransactionRecord, now: Option<u64>) {
+        match record.r#type {
+            TxType::Dispute => {
+                self.open.entry(record.tx).or_insert((record.client, now));
+            }
+            TxType::Resolve | TxType::Chargeback => {
+                self.open.remove(&record.tx);
+            }
+            TxType::Deposit | TxType::Withdrawal | TxType::Credit | TxType::Settle => {}
+        }
+    }
+
+    // Takes the disputes open for at least the timeout at `now`, oldest
+    // first.
+    pub(crate) fn expire(&mut self, now: u64) -> Vec<(Client, Tx)> {
+        let mut expired = Vec::new();
+        for (tx, (client, raised)) in self.open.iter_mut() {
+            let raised = *raised.get_or_insert(now);
+            if now.saturating_sub(raised) >= self.timeout {
+                expired.push((raised, *tx, *client));
+            }
+        }
+        expired.sort_unstable_by_key(|&(raised, tx, _)| (raised, tx.0));
+        expired
+            .into_iter()
+            .map(|(_, tx, client)| {
+                self.open.remove(&tx);
+                (client, tx)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DisputeConfig;
+    use crate::amount::Amount;
+    use crate::dormancy::DAY;
+    use crate::engine::Engine;
+    use crate::transactions::{Client, TransactionRecord, Tx};
+
+    #[test]
+    fn test_dispute_timeout() {
+        let config = DisputeConfig { timeout_days: 30 };
+        let mut engine = Engine::builder().dispute_timeout(config).build();
+        for tx in 1..=3 {
+            engine.process(&TransactionRecord::deposit(
+                Client(1),
+                Tx(tx),
+                Amount::new(10),
+            ));
+        }
+        // raised before the clock was set, so counted from day 1
+        engine.process(&TransactionRecord::dispute(Client(1), Tx(1)));
+        engine.advance_to(DAY);
+        engine.advance_to(10 * DAY);
+        engine.process(&TransactionRecord::dispute(Client(1), Tx(2)));
+        engine.process(&TransactionRecord::dispute(Client(1), Tx(3)));
+        engine.process(&TransactionRecord::resolve(Client(1), Tx(3)));
+        engine.advance_to(31 * DAY);
+        let funds = engine.account(Client(1)).unwrap();
+        assert_eq!(funds.held, Amount::new(10));
+        assert_eq!(funds.available, Amount::new(20));
+        assert_eq!(engine.open_disputes(), 1);
+        // an earlier time doesn't move the clock back
+        engine.advance_to(5 * DAY);
+        assert_eq!(engine.now(), Some(31 * DAY));
+        engine.advance_to(40 * DAY);
+        let funds = engine.account(Client(1)).unwrap();
+        assert_eq!(funds.held, Amount::new(0));
+        assert_eq!(funds.available, Amount::new(30));
+        assert_eq!(engine.open_disputes(), 0);
+    }
+}
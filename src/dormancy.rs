@@ -0,0 +1,109 @@
+use crate::transactions::{Client, TransactionRecord, TxType};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub const DAY: u64 = 24 * 60 * 60;
+
+// The `[dormancy]` config section.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DormancyConfig {
+    // days without an applied transaction after which an account is dormant
+    pub after_days: u64,
+    // rejects withdrawals from dormant accounts as `dormant` until another
+    // transaction, such as a deposit, reactivates them
+    #[serde(default)]
+    pub hold_withdrawals: bool,
+}
+
+// When each account last had a transaction applied, to tell the dormant
+// ones. Times are the engine's clock (see `Engine::now`), so clients whose
+// rows carry no timestamps are never dormant.
+#[derive(Debug, Clone)]
+pub struct DormancyMonitor {
+    config: DormancyConfig,
+    last_active: HashMap<Client, u64>,
+}
+
+impl DormancyMonitor {
+    pub fn new(config: DormancyConfig) -> DormancyMonitor {
+        DormancyMonitor {
+            config,
+            last_active: HashMap::new(),
+        }
+    }
+
+    pub fn last_active(&self, client: Client) -> Option<u64> {
+        self.last_active.get(&client).copied()
+    }
+
+    pub fn is_dormant(&self, client: Client, now: Option<u64>) -> bool {
+        match (now, self.last_active(client)) {
+            (Some(now), Some(last)) => {
+                now.saturating_sub(last) >= self.config.after_days.saturating_mul(DAY)
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn holds(&self, record: &TransactionRecord, now: Option<u64>) -> bool {
+        self.config.hold_withdrawals
+            && record.r#type == TxType::Withdrawal
+            && self.is_dormant(record.client, now)
+    }
+
+    // Called with each applied record.
+    pub(crate) fn observe(&mut self, record: &TransactionRecord, now: Option<u64>) {
+        if let Some(now) = now {
+            self.last_active.insert(record.client, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DormancyConfig, DAY};
+    use crate::engine::Engine;
+    use crate::output::write_extended_accounts;
+    use crate::tenant::Tenants;
+    use crate::transactions::{Client, RejectReason};
+
+    #[test]
+    fn test_dormancy() {
+        let config = DormancyConfig {
+            after_days: 30,
+            hold_withdrawals: true,
+        };
+        let mut tenants = Tenants::single(Engine::builder().dormancy(config));
+        let engine = tenants.engine("default");
+        let day = |n: u64| n * DAY;
+        let csvfile = format!(
+            "type,client,tx,amount,timestamp,currency\ndeposit,1,1,10,{},EUR\ndeposit,2,2,10,{},EUR\n\
+             withdrawal,2,3,1,{},EUR\nwithdrawal,1,4,1,{},EUR\ndeposit,1,5,1,{},EUR\nwithdrawal,1,6,1,{},EUR\n",
+            day(1),
+            day(1),
+            day(20),
+            day(40),
+            day(41),
+            day(42),
+        );
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        // client 1 was dormant at day 40, until its deposit the day after
+        assert_eq!(engine.metrics().rejected[&RejectReason::Dormant], 1);
+        assert!(!engine.is_dormant(Client(1)));
+        assert!(!engine.is_dormant(Client(2)));
+        assert_eq!(engine.now(), Some(day(42)));
+
+        let deposit = format!(
+            "type,client,tx,amount,timestamp,currency\ndeposit,3,7,1,{},EUR\n",
+            day(50)
+        );
+        engine.process_csv(deposit.as_bytes()).unwrap();
+        assert!(engine.is_dormant(Client(2)));
+        let mut out = Vec::new();
+        write_extended_accounts(&tenants, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out
+            .lines()
+            .any(|line| line == "2,9.0000,0.0000,9.0000,false,dormant,"));
+    }
+}
@@ -0,0 +1,1366 @@
+use crate::aml::{AmlConfig, AmlMonitor, RiskFlag};
+use crate::amount::Amount;
+use crate::audit::{AuditLog, AuditReason, Balance};
+use crate::bonus::BonusConfig;
+use crate::books::Books;
+use crate::csv_dialect::{CsvDialect, UnknownTypes};
+use crate::daily::DailyBalances;
+use crate::debt::DebtTracker;
+use crate::decoder::{malformed_row, unknown_type_row, CsvDecoder, RecordDecoder, UnknownTypeRow};
+use crate::denylist::{DenyAction, Denylist};
+#[cfg(feature = "digest")]
+use crate::digest::RunDigest;
+use crate::disputes::{DisputeConfig, DisputeTimer};
+use crate::dormancy::{DormancyConfig, DormancyMonitor};
+use crate::error::PaymentResult;
+use crate::fork::Fork;
+use crate::funds::{not_frozen, FundingStates, Funds};
+use crate::history::{Activity, History};
+use crate::hooks::{Decision, TransactionHook};
+use crate::invariants::{self, Violation};
+use crate::metrics::{self, Counters, EngineMetrics};
+use crate::observer::{AccountChange, AccountEvent, AccountObserver};
+use crate::period::{PeriodSummary, Periods};
+use crate::registry::{ClientInfo, ClientRegistry};
+use crate::risk::RiskMonitor;
+use crate::rules::{ClientStats, RuleContext, ValidationRule};
+use crate::score::ClientTracker;
+use crate::settlement::{SettlementConfig, Settlements};
+use crate::subaccount::SubAccounts;
+use crate::transactions::{
+    transact, Client, ClientFunds, Ledger, RejectReason, Rejection, RowRecord, TransactionRecord,
+    Tx, TxRecords, TxType, TxTypeOrUnknown,
+};
+use crate::trial_balance::TrialBalance;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+pub struct Engine {
+    client_funds: ClientFunds,
+    records: TxRecords,
+    counters: Counters,
+    history: Option<History>,
+    metadata: HashMap<Tx, Vec<(String, String)>>,
+    risk: Option<RiskMonitor>,
+    rejections: Option<Vec<Rejection>>,
+    rules: Vec<Arc<dyn ValidationRule>>,
+    hooks: Vec<Arc<Mutex<dyn TransactionHook>>>,
+    observers: Vec<Arc<dyn AccountObserver>>,
+    score_clients: bool,
+    tracked: HashMap<Client, ClientTracker>,
+    sequence: u64,
+    aml: Option<AmlMonitor>,
+    denylist: Option<Denylist>,
+    registry: Option<ClientRegistry>,
+    dormancy: Option<DormancyMonitor>,
+    disputes: Option<DisputeTimer>,
+    bonus: BonusConfig,
+    periods: Option<Periods>,
+    daily: Option<DailyBalances>,
+    books: Option<Books>,
+    debt: Option<DebtTracker>,
+    settlement: Option<Settlements>,
+    // the latest row timestamp seen, see `now`
+    clock: Option<u64>,
+    audit: Option<AuditLog>,
+    violations: Option<Vec<Violation>>,
+    subaccounts: SubAccounts,
+    #[cfg(feature = "digest")]
+    digest: Option<RunDigest>,
+    // only kept once `track_changes` is called
+    changed: Option<ChangedAccounts>,
+}
+
+// The accounts an engine changed since they were last taken (see
+// `Engine::take_changes`), for handles that keep a copy of them.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ChangedAccounts {
+    pub(crate) clients: HashSet<Client>,
+    // the accounts were replaced wholesale, e.g. by `import_ledger`
+    pub(crate) all: bool,
+}
+
+#[derive(Default, Clone)]
+pub struct EngineBuilder {
+    expected_clients: usize,
+    expected_txs: usize,
+    record_history: bool,
+    time_applies: bool,
+    risk: Option<RiskMonitor>,
+    record_rejections: bool,
+    rules: Vec<Arc<dyn ValidationRule>>,
+    hooks: Vec<Arc<Mutex<dyn TransactionHook>>>,
+    observers: Vec<Arc<dyn AccountObserver>>,
+    score_clients: bool,
+    aml: Option<AmlConfig>,
+    denylist: Option<Denylist>,
+    registry: Option<ClientRegistry>,
+    dormancy: Option<DormancyConfig>,
+    disputes: Option<DisputeConfig>,
+    bonus: BonusConfig,
+    track_periods: bool,
+    record_daily_balances: bool,
+    double_entry: bool,
+    track_debt: bool,
+    settlement: Option<SettlementConfig>,
+    audit: bool,
+    check_invariants: bool,
+    #[cfg(feature = "digest")]
+    digest: bool,
+}
+
+impl EngineBuilder {
+    pub fn new() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    pub fn expected_clients(mut self, clients: usize) -> EngineBuilder {
+        self.expected_clients = clients;
+        self
+    }
+
+    pub fn expected_txs(mut self, txs: usize) -> EngineBuilder {
+        self.expected_txs = txs;
+        self
+    }
+
+    pub fn record_history(mut self, record_history: bool) -> EngineBuilder {
+        self.record_history = record_history;
+        self
+    }
+
+    // Keeps a histogram of how long each record takes to apply, at the cost
+    // of reading the clock twice per record.
+    pub fn time_applies(mut self, time_applies: bool) -> EngineBuilder {
+        self.time_applies = time_applies;
+        self
+    }
+
+    pub fn risk_monitor(mut self, monitor: RiskMonitor) -> EngineBuilder {
+        self.risk = Some(monitor);
+        self
+    }
+
+    // Keeps every rejected record for a rejections report; memory grows
+    // with the number of rejections.
+    pub fn record_rejections(mut self, record_rejections: bool) -> EngineBuilder {
+        self.record_rejections = record_rejections;
+        self
+    }
+
+    // Looks for suspicious patterns in what is applied, see `aml`.
+    pub fn aml(mut self, config: AmlConfig) -> EngineBuilder {
+        self.aml = Some(config);
+        self
+    }
+
+    // Refuses the transactions of the clients and tx ids on `list`.
+    pub fn denylist(mut self, list: Denylist) -> EngineBuilder {
+        self.denylist = Some(list);
+        self
+    }
+
+    // Looks up clients' KYC status and tier in `registry` for rules, and
+    // their names for extended output.
+    pub fn registry(mut self, registry: ClientRegistry) -> EngineBuilder {
+        self.registry = Some(registry);
+        self
+    }
+
+    // Tells accounts without activity for a while, and can hold their
+    // withdrawals, see `dormancy`.
+    pub fn dormancy(mut self, config: DormancyConfig) -> EngineBuilder {
+        self.dormancy = Some(config);
+        self
+    }
+
+    // Resolves disputes left open for longer than the timeout as the clock
+    // moves, see `Engine::advance_to`.
+    pub fn dispute_timeout(mut self, config: DisputeConfig) -> EngineBuilder {
+        self.disputes = Some(config);
+        self
+    }
+
+    // How much turnover releases a credit's bonus, see `bonus`; a turnover
+    // of the bonus's amount without.
+    pub fn bonus(mut self, config: BonusConfig) -> EngineBuilder {
+        self.bonus = config;
+        self
+    }
+
+    // Keeps what each account moves until `Engine::close_period` sums it
+    // up; memory grows with the number of accounts and closed periods.
+    pub fn track_periods(mut self, track_periods: bool) -> EngineBuilder {
+        self.track_periods = track_periods;
+        self
+    }
+
+    // Keeps each account's end-of-day balances (see `daily`); memory grows
+    // with the number of days accounts change on.
+    pub fn record_daily_balances(mut self, record_daily_balances: bool) -> EngineBuilder {
+        self.record_daily_balances = record_daily_balances;
+        self
+    }
+
+    // Keeps double-entry books of every applied record (see `books`);
+    // memory grows with the number of applied records.
+    pub fn double_entry(mut self, double_entry: bool) -> EngineBuilder {
+        self.double_entry = double_entry;
+        self
+    }
+
+    // Lends accounts what a dispute of spent funds needs, and turns what its
+    // chargeback leaves uncovered into debt that later deposits repay, see
+    // `debt`.
+    pub fn track_debt(mut self, track_debt: bool) -> EngineBuilder {
+        self.track_debt = track_debt;
+        self
+    }
+
+    // Holds deposits as pending until they settle, see `settlement`.
+    pub fn settlement(mut self, config: SettlementConfig) -> EngineBuilder {
+        self.settlement = Some(config);
+        self
+    }
+
+    // Keeps an audit log of every change to the accounts (see `audit`);
+    // memory grows with the number of applied records.
+    pub fn audit(mut self, audit: bool) -> EngineBuilder {
+        self.audit = audit;
+        self
+    }
+
+    // Checks each account after every record (see `invariants`) and keeps
+    // the violations found.
+    pub fn check_invariants(mut self, check_invariants: bool) -> EngineBuilder {
+        self.check_invariants = check_invariants;
+        self
+    }
+
+    // Hashes what is applied for a digest of the run (see `digest`).
+    #[cfg(feature = "digest")]
+    pub fn digest(mut self, digest: bool) -> EngineBuilder {
+        self.digest = digest;
+        self
+    }
+
+    // Keeps each client's risk score (see `score`) even without rules,
+    // for queries and extended output.
+    pub fn score_clients(mut self, score_clients: bool) -> EngineBuilder {
+        self.score_clients = score_clients;
+        self
+    }
+
+    // Adds a check records must pass, run after the built-in ones and those
+    // added before it (see `rules`).
+    pub fn rule<R: ValidationRule + 'static>(mut self, rule: R) -> EngineBuilder {
+        self.rules.push(Arc::new(rule));
+        self
+    }
+
+    // Adds a hook run around each record, after those added before it (see
+    // `hooks`). Engines built from clones of this builder, e.g. one per
+    // tenant, share the hook.
+    pub fn hook<H: TransactionHook + 'static>(mut self, hook: H) -> EngineBuilder {
+        self.hooks.push(Arc::new(Mutex::new(hook)));
+        self
+    }
+
+    // Tells `observer` of account changes such as a freeze (see `observer`).
+    pub fn observer<O: AccountObserver + 'static>(mut self, observer: O) -> EngineBuilder {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    pub fn build(self) -> Engine {
+        Engine {
+            client_funds: ClientFunds::with_capacity(self.expected_clients),
+            records: TxRecords::with_capacity(self.expected_txs),
+            counters: if self.time_applies {
+                Counters::timed()
+            } else {
+                Counters::default()
+            },
+            history: if self.record_history {
+                Some(History::default())
+            } else {
+                None
+            },
+            metadata: HashMap::new(),
+            risk: self.risk,
+            rejections: if self.record_rejections {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            rules: self.rules,
+            hooks: self.hooks,
+            observers: self.observers,
+            score_clients: self.score_clients,
+            tracked: HashMap::new(),
+            sequence: 0,
+            aml: self.aml.map(AmlMonitor::new),
+            denylist: self.denylist,
+            registry: self.registry,
+            dormancy: self.dormancy.map(DormancyMonitor::new),
+            disputes: self.disputes.map(DisputeTimer::new),
+            bonus: self.bonus,
+            periods: if self.track_periods {
+                Some(Periods::default())
+            } else {
+                None
+            },
+            daily: if self.record_daily_balances {
+                Some(DailyBalances::default())
+            } else {
+                None
+            },
+            books: if self.double_entry {
+                Some(Books::default())
+            } else {
+                None
+            },
+            debt: if self.track_debt {
+                Some(DebtTracker)
+            } else {
+                None
+            },
+            settlement: self.settlement.map(Settlements::new),
+            clock: None,
+            audit: if self.audit {
+                Some(AuditLog::default())
+            } else {
+                None
+            },
+            violations: if self.check_invariants {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            subaccounts: SubAccounts::default(),
+            #[cfg(feature = "digest")]
+            digest: if self.digest {
+                Some(RunDigest::default())
+            } else {
+                None
+            },
+            changed: None,
+        }
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::default()
+    }
+
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+
+    pub fn process(&mut self, record: &TransactionRecord) {
+        let _ = self.try_process(record);
+    }
+
+    // A row whose amount doesn't parse is rejected with `InvalidAmount` (or
+    // `AmountTooLarge`) like any other bad record, without reaching the
+    // ledger, rules or hooks. A row addressed to a sub-account applies to
+    // that sub-account's own account (see `SubAccounts`), and a row for the
+    // client id a sub-account was given is rejected as `ReservedClient`. A
+    // row's timestamp advances the engine's clock (see `advance_to`) before
+    // it is applied.
+    pub fn process_row(&mut self, row: RowRecord<'_>) -> Result<(), RejectReason> {
+        let converted = TransactionRecord::try_from(row.with_subaccount(None));
+        self.process_converted(
+            row.r#type(),
+            row.client(),
+            row.subaccount(),
+            row.tx(),
+            row.timestamp(),
+            converted,
+        )
+    }
+
+    // The rest of `process_row` once the row's record was converted, which
+    // the CSV pipeline does on its parser threads.
+    pub(crate) fn process_converted(
+        &mut self,
+        r#type: TxType,
+        client: Client,
+        subaccount: Option<&str>,
+        tx: Tx,
+        timestamp: Option<u64>,
+        converted: PaymentResult<TransactionRecord>,
+    ) -> Result<(), RejectReason> {
+        if let Some(timestamp) = timestamp {
+            self.advance_to(timestamp);
+        }
+        let account = match subaccount {
+            Some(name) => {
+                let accounts = &self.client_funds;
+                self.subaccounts
+                    .resolve(client, name, |id| accounts.contains_key(&id))
+            }
+            None => client,
+        };
+        let reserved = subaccount.is_none() && self.subaccounts.owner(account).is_some();
+        let (reason, amount) = match converted {
+            Ok(record) if reserved => (RejectReason::ReservedClient, record.amount),
+            Ok(record) => {
+                return self.try_process(&TransactionRecord {
+                    client: account,
+                    ..record
+                })
+            }
+            Err(err) => (
+                err.reject_reason().unwrap_or(RejectReason::InvalidAmount),
+                None,
+            ),
+        };
+        let record = TransactionRecord {
+            r#type,
+            client: account,
+            tx,
+            amount,
+        };
+        self.note_rejection(Rejection::new(&record, reason));
+        self.counters.record(record.r#type, Err(reason), None);
+        Err(reason)
+    }
+
+    // Moves the clock forward to `timestamp` (unix seconds), resolves the
+    // disputes open for at least the dispute timeout, releasing their held
+    // funds as a `resolve` record would, and settles the pending deposits
+    // due as a `settle` record would. An earlier timestamp leaves the
+    // clock where it is. Long-running servers whose input carries no
+    // timestamps can call this on a timer.
+    pub fn advance_to(&mut self, timestamp: u64) {
+        let now = self.clock.map_or(timestamp, |now| now.max(timestamp));
+        self.clock = Some(now);
+        if let Some(daily) = &mut self.daily {
+            daily.advance(now, &self.client_funds);
+        }
+        let expired = match &mut self.disputes {
+            Some(disputes) => disputes.expire(now),
+            None => Vec::new(),
+        };
+        for (client, tx) in expired {
+            let resolve = TransactionRecord::resolve(client, tx);
+            let (client, tx) = (client.0, tx.0);
+            match self.try_process(&resolve) {
+                Ok(()) => tracing::info!(client, tx, "dispute timed out and was resolved"),
+                Err(reason) => tracing::warn!(
+                    client,
+                    tx,
+                    reason = reason.as_str(),
+                    "timed out dispute could not be resolved"
+                ),
+            }
+        }
+        let due = match &mut self.settlement {
+            Some(settlement) => settlement.due(now),
+            None => Vec::new(),
+        };
+        for (client, tx) in due {
+            let settle = TransactionRecord::settle(client, tx);
+            let (client, tx) = (client.0, tx.0);
+            match self.try_process(&settle) {
+                Ok(()) => tracing::info!(client, tx, "pending deposit settled"),
+                Err(reason) => tracing::warn!(
+                    client,
+                    tx,
+                    reason = reason.as_str(),
+                    "due deposit could not be settled"
+                ),
+            }
+        }
+    }
+
+    // Ends the open period at `timestamp`, advancing the clock to it first,
+    // and starts the next one. Returns the closed period's summary, or None
+    // unless built with `EngineBuilder::track_periods`.
+    pub fn close_period(&mut self, timestamp: u64) -> Option<Arc<PeriodSummary>> {
+        self.advance_to(timestamp);
+        let periods = self.periods.as_mut()?;
+        let summary = periods.close(timestamp, &self.client_funds);
+        tracing::info!(
+            period = summary.number,
+            accounts = summary.accounts.len(),
+            "period closed"
+        );
+        Some(summary)
+    }
+
+    // Records the end-of-day balances of the current day as they are now,
+    // e.g. when a batch run reaches the end of its input. Changes applied
+    // later on the same day replace them when the day ends.
+    pub fn end_day(&mut self) {
+        if let Some(daily) = &mut self.daily {
+            daily.end_day(&self.client_funds);
+        }
+    }
+
+    pub fn tracks_debt(&self) -> bool {
+        self.debt.is_some()
+    }
+
+    pub fn settles_deposits(&self) -> bool {
+        self.settlement.is_some()
+    }
+
+    // None unless built with `EngineBuilder::double_entry`.
+    pub fn books(&self) -> Option<&Books> {
+        self.books.as_ref()
+    }
+
+    // The books checked against the accounts, see `TrialBalance`; None
+    // without books.
+    pub fn trial_balance(&self) -> Option<TrialBalance> {
+        let books = self.books.as_ref()?;
+        Some(TrialBalance::new(books, &self.client_funds))
+    }
+
+    // None unless built with `EngineBuilder::record_daily_balances`.
+    pub fn daily_balances(&self) -> Option<&DailyBalances> {
+        self.daily.as_ref()
+    }
+
+    // The closed periods, oldest first.
+    pub fn periods(&self) -> &[Arc<PeriodSummary>] {
+        self.periods.as_ref().map_or(&[], Periods::closed)
+    }
+
+    fn note_rejection(&mut self, rejection: Rejection) {
+        tracing::info!(
+            client = rejection.client.0,
+            tx = rejection.tx.0,
+            r#type = rejection.r#type.as_str(),
+            reason = rejection.reason.as_str(),
+            "transaction rejected"
+        );
+        if let Some(rejections) = &mut self.rejections {
+            rejections.push(rejection);
+        }
+    }
+
+    // A row of a type the engine doesn't know, which the dialect skips or
+    // rejects instead of failing on.
+    fn unknown_type(&mut self, row: &UnknownTypeRow, policy: UnknownTypes) {
+        self.counters.record_unknown_type();
+        if policy == UnknownTypes::Reject {
+            self.note_rejection(Rejection {
+                r#type: TxTypeOrUnknown::Unknown(row.r#type.clone()),
+                client: row.client,
+                tx: row.tx,
+                amount: row.amount,
+                reason: RejectReason::UnknownType,
+            });
+        } else {
+            tracing::warn!(line = row.line, r#type = %row.r#type, "row of unknown type skipped");
+        }
+    }
+
+    // Like `process`, for callers that report the outcome of each record.
+    pub fn try_process(&mut self, record: &TransactionRecord) -> Result<(), RejectReason> {
+        // even a rejected record can freeze its account (see `screen`)
+        self.mark_changed(record.client);
+        let was_frozen = self.risk.is_some()
+            && self
+                .client_funds
+                .get(&record.client)
+                .is_some_and(|funds| !not_frozen(funds));
+        // the account's state and available balance, to tell observers what
+        // changed
+        let observed = (!self.observers.is_empty()).then(|| {
+            let funds = self.client_funds.get(&record.client);
+            funds.map(|funds| (funds.state, funds.available))
+        });
+        let started = self.counters.times_applies().then(metrics::now).flatten();
+        let stats = self.stats(record.client).unwrap_or_default();
+        let before = (self.audit.is_some() || self.violations.is_some())
+            .then(|| Balance::of(self.client_funds.get(&record.client)));
+        let mut effect = None;
+        let mut loan = None;
+        let result = self
+            .screen(record)
+            .and_then(|()| self.hooks_before(record))
+            .and_then(|()| {
+                if let Some(debt) = &self.debt {
+                    let funds = self.client_funds.get_mut(&record.client);
+                    loan = debt.lend(record, &self.records, funds);
+                }
+                let outcome = transact(
+                    &mut self.client_funds,
+                    &mut self.records,
+                    record,
+                    &self.rules,
+                    stats,
+                );
+                effect = outcome.effect();
+                outcome.into_result()
+            });
+        if result.is_err() {
+            if let (Some(debt), Some(lent)) = (&self.debt, loan.take()) {
+                if let Some(funds) = self.client_funds.get_mut(&record.client) {
+                    debt.repay(&lent, funds);
+                }
+            }
+        }
+        if let (Some(books), Some(effect)) = (&mut self.books, effect) {
+            books.post(Some(record.tx), self.clock, loan.iter());
+            books.post(Some(record.tx), self.clock, effect.postings.iter());
+        }
+        if let (Some(effect), Some(funds)) = (effect, self.client_funds.get_mut(&record.client)) {
+            if self.bonus.observe(record, funds, effect.amount) {
+                tracing::info!(client = record.client.0, "bonus released");
+            }
+        }
+        if let Some(before) = before {
+            // a loan isn't part of the dispute's own effect
+            let lent = loan.map_or(0, |loan| loan.amount.0);
+            let before = Balance {
+                available: Amount(before.available.0.saturating_add(lent)),
+                ..before
+            };
+            self.check_invariants(record, result.is_ok(), before);
+        }
+        // after the invariants, which are about the record's own effect
+        let mut held_pending = false;
+        if let (Some(settlement), Some(effect)) = (&mut self.settlement, effect) {
+            if let Some(funds) = self.client_funds.get_mut(&record.client) {
+                let postings =
+                    settlement.observe(record, &effect, funds, &mut self.records, self.clock);
+                held_pending = !postings.is_empty();
+                if let Some(books) = &mut self.books {
+                    books.post(Some(record.tx), self.clock, &postings);
+                }
+            }
+        }
+        // a pending deposit repays debt once it settles
+        let effect = effect.filter(|_| !held_pending);
+        if let (Some(debt), Some(effect)) = (&self.debt, effect) {
+            if let Some(funds) = self.client_funds.get_mut(&record.client) {
+                let postings = debt.observe(record, &effect, funds);
+                if let Some(books) = &mut self.books {
+                    books.post(Some(record.tx), self.clock, &postings);
+                }
+            }
+        }
+        if let (Ok(()), Some(before)) = (result, before) {
+            let reason = AuditReason::Applied(record.r#type);
+            self.audit_change(record.client, Some(record.tx), reason, before);
+        }
+        if result.is_ok() && self.tracks_clients() {
+            self.track(record);
+        }
+        if let (Ok(()), Some(dormancy)) = (result, &mut self.dormancy) {
+            dormancy.observe(record, self.clock);
+        }
+        if let (Ok(()), Some(disputes)) = (result, &mut self.disputes) {
+            disputes.observe(record, self.clock);
+        }
+        if let (Ok(()), Some(daily)) = (result, &mut self.daily) {
+            daily.touch(record.client);
+        }
+        #[cfg(feature = "digest")]
+        if let (Ok(()), Some(digest)) = (result, &mut self.digest) {
+            digest.record(record);
+        }
+        let latency = started.map(|started| started.elapsed());
+        match result {
+            Ok(()) => tracing::debug!(
+                client = record.client.0,
+                tx = record.tx.0,
+                r#type = record.r#type.as_str(),
+                "transaction applied"
+            ),
+            Err(reason) => self.note_rejection(Rejection::new(record, reason)),
+        }
+        if result.is_ok()
+            && (self.history.is_some()
+                || self.risk.is_some()
+                || self.aml.is_some()
+                || self.periods.is_some())
+        {
+            // disputes and their outcomes refer to the amount of the logged tx
+            let records = &self.records;
+            let amount = record
+                .amount
+                .or_else(|| records.get(&record.tx).map(|logged| logged.amount()));
+            if let (Some(history), Some(amount)) = (&mut self.history, amount) {
+                let activity = Activity {
+                    r#type: record.r#type,
+                    tx: record.tx,
+                    amount,
+                };
+                history.record(record.client, activity);
+            }
+            if let (Some(periods), Some(amount)) = (&mut self.periods, amount) {
+                periods.observe(record.client, record.r#type, amount);
+            }
+            if let Some(risk) = &self.risk {
+                let funds = self.client_funds.get(&record.client);
+                risk.observe(record, amount, was_frozen, funds);
+            }
+            if let Some(aml) = &mut self.aml {
+                for flag in aml.observe(record, amount) {
+                    let (client, tx) = (record.client.0, record.tx.0);
+                    tracing::warn!(client, tx, flag = flag.as_str(), "account flagged");
+                    if let Some(risk) = &self.risk {
+                        risk.flagged(record, flag);
+                    }
+                }
+            }
+        }
+        self.counters.record(record.r#type, result, latency);
+        if let Some(before) = observed {
+            self.notify_observers(record, result.is_ok(), before);
+        }
+        for hook in &self.hooks {
+            hook.lock()
+                .expect("hook lock poisoned")
+                .after(record, &result);
+        }
+        result
+    }
+
+    fn notify_observers(
+        &self,
+        record: &TransactionRecord,
+        applied: bool,
+        before: Option<(FundingStates, Amount)>,
+    ) {
+        let funds = match self.client_funds.get(&record.client) {
+            Some(funds) => funds,
+            None => return,
+        };
+        let state = before.map(|(state, _)| state);
+        let mut changes = Vec::new();
+        if applied && record.r#type == TxType::Deposit && before.is_none() {
+            changes.push(AccountChange::Opened);
+        }
+        if funds.state == FundingStates::Disputed && state != Some(FundingStates::Disputed) {
+            changes.push(AccountChange::EnteredDispute);
+        }
+        let disputed = self.records.get(&record.tx).map(|logged| logged.amount());
+        if let (true, TxType::Dispute, Some((_, available)), Some(amount)) =
+            (applied, record.r#type, before, disputed)
+        {
+            if amount > available {
+                changes.push(AccountChange::WentNegative);
+            }
+        }
+        if funds.state == FundingStates::Frozen && state != Some(FundingStates::Frozen) {
+            changes.push(AccountChange::Frozen);
+        }
+        for change in changes {
+            let event = AccountEvent {
+                change,
+                record,
+                funds,
+            };
+            for observer in &self.observers {
+                observer.notify(&event);
+            }
+        }
+    }
+
+    fn hooks_before(&self, record: &TransactionRecord) -> Result<(), RejectReason> {
+        for hook in &self.hooks {
+            if let Decision::Veto(reason) = hook.lock().expect("hook lock poisoned").before(record)
+            {
+                return Err(reason);
+            }
+        }
+        Ok(())
+    }
+
+    // Turns away records of denied or blocked clients before validation.
+    fn screen(&mut self, record: &TransactionRecord) -> Result<(), RejectReason> {
+        // a denied client's sub-accounts are denied with it
+        let parent = self
+            .subaccounts
+            .owner(record.client)
+            .map(|(parent, _)| TransactionRecord {
+                client: parent,
+                ..record.clone()
+            });
+        let denies = |list: &&Denylist| {
+            list.denies(record) || parent.as_ref().is_some_and(|parent| list.denies(parent))
+        };
+        if let Some(list) = self.denylist.as_ref().filter(denies) {
+            if list.action() == DenyAction::Freeze {
+                let before = Balance::of(self.client_funds.get(&record.client));
+                let funds = self
+                    .client_funds
+                    .entry(record.client)
+                    .or_insert_with(|| Funds::new(record.client));
+                if funds.freeze() {
+                    let (client, tx) = (record.client.0, record.tx.0);
+                    tracing::info!(client, tx, "account frozen by denylist");
+                    let reason = AuditReason::DenylistFreeze;
+                    self.audit_change(record.client, Some(record.tx), reason, before);
+                }
+            }
+            return Err(RejectReason::Denied);
+        }
+        match (&self.aml, &self.dormancy) {
+            (Some(aml), _) if aml.blocks(record) => Err(RejectReason::RiskFlagged),
+            (_, Some(dormancy)) if dormancy.holds(record, self.clock) => Err(RejectReason::Dormant),
+            _ => Ok(()),
+        }
+    }
+
+    fn tracks_clients(&self) -> bool {
+        self.score_clients || !self.rules.is_empty()
+    }
+
+    // Counts the applied record and freezes its client's account if a rule
+    // says to, before anything reports the account's new state.
+    fn track(&mut self, record: &TransactionRecord) {
+        let tracker = self.tracked.entry(record.client).or_default();
+        tracker.record(record, self.sequence);
+        self.sequence += 1;
+        let stats = self.stats(record.client).unwrap_or_default();
+        let previous = self
+            .records
+            .get(&record.tx)
+            .map(|packed| packed.unpack(record.tx));
+        let ctx = RuleContext {
+            record,
+            funds: self.client_funds.get(&record.client),
+            previous: previous.as_ref(),
+            stats,
+        };
+        if !self.rules.iter().any(|rule| rule.freezes(&ctx)) {
+            return;
+        }
+        let before = Balance::of(ctx.funds);
+        let funds = self.client_funds.get_mut(&record.client);
+        if funds.is_some_and(Funds::freeze) {
+            let (client, tx) = (record.client.0, record.tx.0);
+            tracing::info!(client, tx, "account frozen by rule");
+            self.audit_change(
+                record.client,
+                Some(record.tx),
+                AuditReason::RuleFreeze,
+                before,
+            );
+        }
+    }
+
+    fn check_invariants(&mut self, record: &TransactionRecord, applied: bool, before: Balance) {
+        let violations = match &mut self.violations {
+            Some(violations) => violations,
+            None => return,
+        };
+        let after = Balance::of(self.client_funds.get(&record.client));
+        let records = &self.records;
+        let amount = record
+            .amount
+            .or_else(|| records.get(&record.tx).map(|logged| logged.amount()));
+        if let Some(invariant) = invariants::check(record.r#type, amount, applied, &before, &after)
+        {
+            let (client, tx) = (record.client.0, record.tx.0);
+            tracing::error!(
+                client,
+                tx,
+                invariant = invariant.as_str(),
+                "invariant violated"
+            );
+            violations.push(Violation {
+                invariant,
+                client: record.client,
+                tx: record.tx,
+                r#type: record.r#type,
+                before,
+                after,
+            });
+        }
+    }
+
+    // Logs how `client`'s account changed from `before`, when auditing.
+    fn audit_change(
+        &mut self,
+        client: Client,
+        tx: Option<Tx>,
+        reason: AuditReason,
+        before: Balance,
+    ) {
+        if let Some(audit) = &mut self.audit {
+            let after = Balance::of(self.client_funds.get(&client));
+            audit.record(client, tx, reason, before, after);
+        }
+    }
+
+    // None when the engine doesn't track clients (see
+    // `EngineBuilder::score_clients`) or nothing was applied to `client`,
+    // and the registry, if any, doesn't list it.
+    pub fn stats(&self, client: Client) -> Option<ClientStats> {
+        let tracked = self
+            .tracked
+            .get(&client)
+            .map(|tracker| tracker.stats(self.sequence));
+        let info = self.client_info(client);
+        if tracked.is_none() && info.is_none() {
+            return None;
+        }
+        let mut stats = tracked.unwrap_or_default();
+        if let Some(info) = info {
+            stats.kyc = info.kyc;
+            stats.tier = info.tier;
+        }
+        Some(stats)
+    }
+
+    pub fn risk_score(&self, client: Client) -> Option<u8> {
+        self.tracked
+            .get(&client)
+            .map(|tracker| tracker.stats(self.sequence).risk_score)
+    }
+
+    pub fn registry(&self) -> Option<&ClientRegistry> {
+        self.registry.as_ref()
+    }
+
+    // What the registry says about `client`; a sub-account's is its
+    // parent's.
+    pub fn client_info(&self, client: Client) -> Option<&ClientInfo> {
+        let client = self
+            .subaccounts
+            .owner(client)
+            .map_or(client, |(parent, _)| parent);
+        self.registry.as_ref()?.get(client)
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    // The deposits, withdrawals and dispute events applied to `client`, in
+    // the order they were applied; `None` unless built with
+    // `record_history(true)`.
+    pub fn client_history(&self, client: Client) -> Option<&[Activity]> {
+        self.history.as_ref().map(|history| history.client(client))
+    }
+
+    // The AML flags raised on `client`; always empty without `aml`.
+    pub fn risk_flags(&self, client: Client) -> &[RiskFlag] {
+        self.aml.as_ref().map_or(&[], |aml| aml.flags(client))
+    }
+
+    pub fn denylist(&self) -> Option<&Denylist> {
+        self.denylist.as_ref()
+    }
+
+    // The latest timestamp (unix seconds) given to `advance_to` or read
+    // from a row; None until there is one. Rows read out of order don't
+    // move it back.
+    pub fn now(&self) -> Option<u64> {
+        self.clock
+    }
+
+    // How many disputes are waiting to time out; always 0 without
+    // `EngineBuilder::dispute_timeout`.
+    pub fn open_disputes(&self) -> usize {
+        self.disputes.as_ref().map_or(0, DisputeTimer::open)
+    }
+
+    // How many deposits are waiting to settle; always 0 without
+    // `EngineBuilder::settlement`.
+    pub fn pending_deposits(&self) -> usize {
+        self.settlement.as_ref().map_or(0, Settlements::pending)
+    }
+
+    // Whether `client`'s account has had no activity for the dormancy
+    // period; always false without `EngineBuilder::dormancy`.
+    pub fn is_dormant(&self, client: Client) -> bool {
+        self.dormancy
+            .as_ref()
+            .is_some_and(|dormancy| dormancy.is_dormant(client, self.clock))
+    }
+
+    // Broken invariants in the order they were found; always empty without
+    // `EngineBuilder::check_invariants`.
+    pub fn violations(&self) -> &[Violation] {
+        self.violations.as_deref().unwrap_or_default()
+    }
+
+    // Takes over violations found by another engine, e.g. a client worker's.
+    pub(crate) fn note_violations(&mut self, found: &[Violation]) {
+        if let Some(violations) = &mut self.violations {
+            violations.extend_from_slice(found);
+        }
+    }
+
+    // The hex digest of the run so far, when built with
+    // `EngineBuilder::digest`.
+    #[cfg(feature = "digest")]
+    pub fn digest(&self) -> Option<String> {
+        self.digest
+            .as_ref()
+            .map(|digest| digest.finish(self.client_funds.values()))
+    }
+
+    // None unless built with `EngineBuilder::audit`.
+    pub fn audit(&self) -> Option<&AuditLog> {
+        self.audit.as_ref()
+    }
+
+    // Rejected records in the order they were read, when recorded (see
+    // `EngineBuilder::record_rejections`).
+    pub fn rejections(&self) -> &[Rejection] {
+        self.rejections.as_deref().unwrap_or_default()
+    }
+
+    pub fn metrics(&self) -> EngineMetrics {
+        self.counters
+            .sample(self.client_funds.len(), self.records.len())
+    }
+
+    // Applies each record in order through `process`, with everything the
+    // engine is set up with, and returns how many there were. The benches
+    // time it next to the bare `transact` loop.
+    #[cfg(feature = "bench")]
+    pub fn process_counted<'a, I>(&mut self, records: I) -> usize
+    where
+        I: IntoIterator<Item = &'a TransactionRecord>,
+    {
+        let mut count = 0;
+        for record in records {
+            self.process(record);
+            count += 1;
+        }
+        count
+    }
+
+    pub fn process_csv<R: Read>(&mut self, input: R) -> csv::Result<()> {
+        self.process_csv_with(input, &CsvDialect::default())
+    }
+
+    // Reads rows into a single reused record and deserializes them in place,
+    // so steady-state processing doesn't allocate per row.
+    #[tracing::instrument(level = "info", name = "process_csv", skip_all)]
+    pub fn process_csv_with<R: Read>(&mut self, input: R, dialect: &CsvDialect) -> csv::Result<()> {
+        let mut decoder = CsvDecoder::new(input, dialect)?;
+        let capture = decoder.captures_columns();
+        while let Some(row) = decoder.next() {
+            let row = match row {
+                Ok(row) => row,
+                Err(err) => {
+                    self.skip_bad_row(err, dialect)?;
+                    continue;
+                }
+            };
+            let tx = row.tx();
+            let _ = self.process_row(row);
+            if capture {
+                let columns = decoder
+                    .extra_columns()
+                    .map(|(name, value)| (name.to_string(), value.to_string()));
+                self.add_metadata(tx, columns);
+            }
+        }
+        Ok(())
+    }
+
+    // A row a CSV decoder couldn't return: a row of unknown type is handled
+    // by the dialect's `unknown_types`, and a malformed row is skipped when
+    // it is `permissive`. Anything else ends the run with the error.
+    pub(crate) fn skip_bad_row(
+        &mut self,
+        err: csv::Error,
+        dialect: &CsvDialect,
+    ) -> csv::Result<()> {
+        if let Some(unknown) = unknown_type_row(&err) {
+            self.unknown_type(unknown, dialect.unknown_types);
+            return Ok(());
+        }
+        match malformed_row(&err) {
+            Some(malformed) if dialect.permissive => {
+                tracing::warn!(
+                    line = malformed.line,
+                    field = malformed.field.as_deref(),
+                    raw = %malformed.raw,
+                    reason = %malformed.reason,
+                    "malformed row skipped"
+                );
+                self.counters.record_malformed();
+                Ok(())
+            }
+            _ => Err(err),
+        }
+    }
+
+    pub(crate) fn add_metadata<I>(&mut self, tx: Tx, columns: I)
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        self.metadata.entry(tx).or_default().extend(columns);
+    }
+
+    // Extra input columns captured for `tx` (see `ColumnMapping`), in the
+    // order the rows referring to it were read.
+    pub fn metadata(&self, tx: Tx) -> Option<&[(String, String)]> {
+        self.metadata.get(&tx).map(Vec::as_slice)
+    }
+
+    pub fn account(&self, client: Client) -> Option<&Funds> {
+        self.client_funds.get(&client)
+    }
+
+    // None for an unknown client, otherwise whether the account was frozen.
+    pub fn unfreeze(&mut self, client: Client) -> Option<bool> {
+        self.mark_changed(client);
+        let before = Balance::of(self.client_funds.get(&client));
+        let unfrozen = self.client_funds.get_mut(&client).map(Funds::unfreeze);
+        if unfrozen == Some(true) {
+            self.audit_change(client, None, AuditReason::Unfreeze, before);
+        }
+        unfrozen
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &Funds> {
+        self.client_funds.values()
+    }
+
+    // The accounts as reported: each sub-account folded into its parent
+    // (see `SubAccounts::roll_up`).
+    pub fn rolled_up_accounts(&self) -> Vec<Funds> {
+        self.subaccounts.roll_up(self.client_funds.values())
+    }
+
+    pub fn subaccount(&self, parent: Client, name: &str) -> Option<&Funds> {
+        let id = self.subaccounts.get(parent, name)?;
+        self.client_funds.get(&id)
+    }
+
+    pub fn subaccounts(&self) -> &SubAccounts {
+        &self.subaccounts
+    }
+
+    pub(crate) fn subaccounts_mut(&mut self) -> &mut SubAccounts {
+        &mut self.subaccounts
+    }
+
+    pub fn into_funds(self) -> ClientFunds {
+        self.client_funds
+    }
+
+    // A what-if copy of the ledger to apply hypothetical records to, which
+    // only copies the accounts and log entries they touch; see `Fork`.
+    pub fn fork(&self) -> Fork<'_> {
+        Fork::new(self)
+    }
+
+    pub(crate) fn rules(&self) -> &[Arc<dyn ValidationRule>] {
+        &self.rules
+    }
+
+    // A copy of the accounts and transaction log, to serialize.
+    pub fn export_ledger(&self) -> Ledger {
+        Ledger {
+            accounts: self.client_funds.clone(),
+            records: self.records.clone(),
+        }
+    }
+
+    // Replaces the accounts and transaction log with `ledger`, e.g. one
+    // deserialized from a dump; counters and history are left as they are.
+    // With double-entry books, the imported balances are posted as opening
+    // balances.
+    pub fn import_ledger(&mut self, ledger: Ledger) {
+        self.mark_all_changed();
+        self.client_funds = ledger.accounts;
+        self.records = ledger.records;
+        self.import_pending();
+        if let Some(books) = &mut self.books {
+            books.open(&self.client_funds, self.clock);
+        }
+    }
+
+    // The state a snapshot has to keep to rebuild the engine (see
+    // `snapshot`); counters and history start over on restore.
+    pub(crate) fn ledger(&self) -> (&ClientFunds, &TxRecords) {
+        (&self.client_funds, &self.records)
+    }
+
+    // Waits for the pending deposits of a log imported into the engine to
+    // settle, counting from now.
+    pub(crate) fn import_pending(&mut self) {
+        if let Some(settlement) = &mut self.settlement {
+            settlement.import(&self.records, self.clock);
+        }
+    }
+
+    pub(crate) fn ledger_mut(&mut self) -> (&mut ClientFunds, &mut TxRecords) {
+        self.mark_all_changed();
+        (&mut self.client_funds, &mut self.records)
+    }
+
+    // Starts keeping track of the accounts changed, for `take_changes`.
+    pub(crate) fn track_changes(&mut self) {
+        self.changed.get_or_insert_with(ChangedAccounts::default);
+    }
+
+    // The accounts changed since the last call. Without `track_changes`
+    // any of them may have been.
+    pub(crate) fn take_changes(&mut self) -> ChangedAccounts {
+        match &mut self.changed {
+            Some(changed) => std::mem::take(changed),
+            None => ChangedAccounts {
+                all: true,
+                ..ChangedAccounts::default()
+            },
+        }
+    }
+
+    fn mark_changed(&mut self, client: Client) {
+        if let Some(changed) = &mut self.changed {
+            changed.clients.insert(client);
+        }
+    }
+
+    fn mark_all_changed(&mut self) {
+        if let Some(changed) = &mut self.changed {
+            changed.all = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChangedAccounts, Engine};
+    use crate::amount::Amount;
+    use crate::funds::FundingStates;
+    use crate::metrics::EngineMetrics;
+    use crate::transactions::{Client, Ledger, RejectReason, RowRecord, Tx, TxType};
+
+    #[test]
+    fn test_ledger_round_trip() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,1.5\ndeposit,2,2,2.0\nwithdrawal,1,3,0.5\ndispute,2,2,\n";
+        let mut engine = Engine::new();
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let json = serde_json::to_string(&engine.export_ledger()).unwrap();
+        let ledger: Ledger = serde_json::from_str(&json).unwrap();
+        assert_eq!(ledger, engine.export_ledger());
+
+        let mut restored = Engine::new();
+        restored.import_ledger(ledger);
+        let funds = restored.account(Client(2)).unwrap();
+        assert_eq!(funds.state, FundingStates::Disputed);
+        // the logged deposit can still be charged back
+        let chargeback = "type,client,tx,amount\nchargeback,2,2,\n";
+        restored.process_csv(chargeback.as_bytes()).unwrap();
+        let funds = restored.account(Client(2)).unwrap();
+        assert_eq!(funds.state, FundingStates::Frozen);
+        assert_eq!(funds.total(), Amount::new(0));
+
+        let bad =
+            r#"{"accounts": {}, "records": {"1": {"type": "dispute", "amount": 1, "client": 1}}}"#;
+        assert!(serde_json::from_str::<Ledger>(bad).is_err());
+    }
+
+    #[test]
+    fn test_builder_capacity() {
+        let engine = Engine::builder()
+            .expected_clients(1000)
+            .expected_txs(50_000)
+            .build();
+        assert!(engine.client_funds.capacity() >= 1000);
+        assert!(engine.records.capacity() >= 50_000);
+        assert_eq!(Engine::builder().build().records.capacity(), 0);
+    }
+
+    #[test]
+    fn test_take_changes() {
+        let mut engine = Engine::new();
+        assert!(engine.take_changes().all);
+        engine.track_changes();
+        let csvfile =
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,1.0\nwithdrawal,2,3,5.0\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let changed = engine.take_changes();
+        assert_eq!(
+            changed.clients,
+            [Client(1), Client(2)].iter().copied().collect()
+        );
+        assert!(!changed.all);
+        assert_eq!(engine.take_changes(), ChangedAccounts::default());
+        engine.unfreeze(Client(1));
+        assert_eq!(engine.take_changes().clients.len(), 1);
+        engine.import_ledger(Ledger::default());
+        assert!(engine.take_changes().all);
+    }
+
+    #[cfg(feature = "bench")]
+    #[test]
+    fn test_process_counted() {
+        use crate::transactions::TransactionRecord;
+
+        let records: Vec<TransactionRecord> = (1..=3)
+            .map(|tx| TransactionRecord::deposit(Client(1), Tx(tx), Amount::new(10)))
+            .collect();
+        let mut engine = Engine::new();
+        assert_eq!(engine.process_counted(&records), 3);
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(30)
+        );
+    }
+
+    #[test]
+    fn test_process_row() {
+        let mut engine = Engine::builder().record_rejections(true).build();
+        let row = RowRecord::new(TxType::Deposit, Client(1), Tx(1), Some("2.5"));
+        assert_eq!(engine.process_row(row), Ok(()));
+        let row = RowRecord::new(TxType::Deposit, Client(1), Tx(2), Some("1.23456"));
+        assert_eq!(engine.process_row(row), Err(RejectReason::InvalidAmount));
+        let row = RowRecord::new(TxType::Deposit, Client(1), Tx(3), Some("1e400"));
+        assert!(engine.process_row(row).is_err());
+        assert_eq!(engine.rejections().len(), 2);
+        assert_eq!(engine.rejections()[0].tx, Tx(2));
+        assert_eq!(engine.metrics().records_rejected(), 2);
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(25000)
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,1.0\nwithdrawal,1,3,5.0\ndispute,2,9,\nresolve,2,2,\nwithdrawal,2,4,0.5\n";
+        let mut engine = Engine::new();
+        assert_eq!(engine.metrics(), EngineMetrics::default());
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let metrics = engine.metrics();
+        assert_eq!(metrics.records_read, 6);
+        assert_eq!(metrics.records_applied, 3);
+        assert_eq!(metrics.records_rejected(), 3);
+        assert_eq!(metrics.rejected[&RejectReason::InsufficientFunds], 1);
+        assert_eq!(metrics.rejected[&RejectReason::UnknownTx], 1);
+        assert_eq!(metrics.rejected[&RejectReason::NotDisputed], 1);
+        assert_eq!(metrics.applied_by_type[&TxType::Deposit], 2);
+        assert_eq!(metrics.rejected_by_type[&TxType::Withdrawal], 1);
+        assert_eq!(metrics.apply_latency, None);
+        assert_eq!((metrics.clients, metrics.log_size), (2, 3));
+        assert!(metrics.records_per_sec > 0.0);
+    }
+
+    #[test]
+    fn test_process_csv() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,1.5\ndeposit,2,2,2.0\nwithdrawal,1,3,0.5\ndispute,2,2,\nchargeback,2,2,null\n";
+        let mut engine = Engine::new();
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(10000)
+        );
+        let two = engine.account(Client(2)).unwrap();
+        assert_eq!(two.total(), Amount::new(0));
+        assert_eq!(two.state, FundingStates::Frozen);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::amount::Amount;
+use crate::engine::Engine;
+use crate::funds::FundingStates;
+use crate::transactions::{Client, TransactionRecord, Tx, TxType};
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::ptr;
+
+// The C API, for settlement systems embedding the engine in-process. The
+// build writes its header to `include/payment_engine.h` (see build.rs and
+// cbindgen.toml). Amounts cross it as integers in ten-thousandths, the
+// scale `Amount` keeps them in.
+
+/// Transaction types for `payment_engine_apply`.
+pub const PAYMENT_ENGINE_DEPOSIT: u8 = 0;
+pub const PAYMENT_ENGINE_WITHDRAWAL: u8 = 1;
+pub const PAYMENT_ENGINE_DISPUTE: u8 = 2;
+pub const PAYMENT_ENGINE_RESOLVE: u8 = 3;
+pub const PAYMENT_ENGINE_CHARGEBACK: u8 = 4;
+pub const PAYMENT_ENGINE_CREDIT: u8 = 5;
+pub const PAYMENT_ENGINE_SETTLE: u8 = 6;
+
+/// What `payment_engine_apply` returns.
+pub const PAYMENT_ENGINE_APPLIED: i32 = 0;
+pub const PAYMENT_ENGINE_REJECTED: i32 = 1;
+pub const PAYMENT_ENGINE_INVALID_ARGUMENT: i32 = -1;
+
+/// An engine; opaque to C, only ever handled through a pointer.
+pub struct PaymentEngine {
+    engine: Engine,
+    // the code of the last rejection, kept for `payment_engine_last_rejection`
+    rejection: CString,
+}
+
+/// A client's balances, in ten-thousandths.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct PaymentEngineAccount {
+    pub client: u32,
+    pub available: u64,
+    pub held: u64,
+    pub total: u64,
+    pub locked: bool,
+}
+
+/// A new engine with the default rules, to be released with
+/// `payment_engine_free`.
+#[no_mangle]
+pub extern "C" fn payment_engine_new() -> *mut PaymentEngine {
+    Box::into_raw(Box::new(PaymentEngine {
+        engine: Engine::new(),
+        rejection: CString::default(),
+    }))
+}
+
+/// Releases an engine; NULL is ignored.
+///
+/// # Safety
+///
+/// `engine` must be NULL or come from `payment_engine_new`, and isn't
+/// valid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn payment_engine_free(engine: *mut PaymentEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Applies a transaction. `amount` is ignored unless `has_amount` is set,
+/// which disputes, resolves, chargebacks and settles don't need. Returns
+/// PAYMENT_ENGINE_APPLIED, PAYMENT_ENGINE_REJECTED (see
+/// `payment_engine_last_rejection` for why) or, for a NULL engine or unknown
+/// type, PAYMENT_ENGINE_INVALID_ARGUMENT.
+///
+/// # Safety
+///
+/// `engine` must be NULL or come from `payment_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn payment_engine_apply(
+    engine: *mut PaymentEngine,
+    r#type: u8,
+    client: u32,
+    tx: u64,
+    amount: u64,
+    has_amount: bool,
+) -> i32 {
+    let Some(handle) = engine.as_mut() else {
+        return PAYMENT_ENGINE_INVALID_ARGUMENT;
+    };
+    let r#type = match r#type {
+        PAYMENT_ENGINE_DEPOSIT => TxType::Deposit,
+        PAYMENT_ENGINE_WITHDRAWAL => TxType::Withdrawal,
+        PAYMENT_ENGINE_DISPUTE => TxType::Dispute,
+        PAYMENT_ENGINE_RESOLVE => TxType::Resolve,
+        PAYMENT_ENGINE_CHARGEBACK => TxType::Chargeback,
+        PAYMENT_ENGINE_CREDIT => TxType::Credit,
+        PAYMENT_ENGINE_SETTLE => TxType::Settle,
+        _ => return PAYMENT_ENGINE_INVALID_ARGUMENT,
+    };
+    let record = TransactionRecord {
+        r#type,
+        client: Client(client),
+        tx: Tx(tx),
+        amount: has_amount.then_some(Amount::new(amount)),
+    };
+    match handle.engine.try_process(&record) {
+        Ok(()) => PAYMENT_ENGINE_APPLIED,
+        Err(reason) => {
+            // rejection codes are plain identifiers, never with a NUL
+            handle.rejection = CString::new(reason.as_str()).unwrap_or_default();
+            PAYMENT_ENGINE_REJECTED
+        }
+    }
+}
+
+/// The reason code of the last rejected transaction, e.g.
+/// "insufficient_funds", or an empty string before any. The string belongs
+/// to the engine and changes with the next rejection.
+///
+/// # Safety
+///
+/// `engine` must be NULL or come from `payment_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn payment_engine_last_rejection(
+    engine: *const PaymentEngine,
+) -> *const c_char {
+    match engine.as_ref() {
+        Some(handle) => handle.rejection.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Fills `account` with the client's balances and returns true, or returns
+/// false when the client has no account.
+///
+/// # Safety
+///
+/// `engine` must be NULL or come from `payment_engine_new`, and `account`
+/// must be NULL or point to a writable `PaymentEngineAccount`.
+#[no_mangle]
+pub unsafe extern "C" fn payment_engine_account(
+    engine: *const PaymentEngine,
+    client: u32,
+    account: *mut PaymentEngineAccount,
+) -> bool {
+    let (Some(handle), Some(account)) = (engine.as_ref(), account.as_mut()) else {
+        return false;
+    };
+    let Some(funds) = handle.engine.account(Client(client)) else {
+        return false;
+    };
+    *account = PaymentEngineAccount {
+        client,
+        available: funds.available.0,
+        held: funds.held.0,
+        total: funds.total().0,
+        locked: funds.state == FundingStates::Frozen,
+    };
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        payment_engine_account, payment_engine_apply, payment_engine_free,
+        payment_engine_last_rejection, payment_engine_new, PaymentEngineAccount,
+        PAYMENT_ENGINE_APPLIED, PAYMENT_ENGINE_DEPOSIT, PAYMENT_ENGINE_DISPUTE,
+        PAYMENT_ENGINE_INVALID_ARGUMENT, PAYMENT_ENGINE_REJECTED, PAYMENT_ENGINE_WITHDRAWAL,
+    };
+    use std::ffi::CStr;
+    use std::ptr;
+
+    #[test]
+    fn test_ffi() {
+        unsafe {
+            let engine = payment_engine_new();
+            let apply = |r#type, tx, amount: Option<u64>| {
+                let (amount, has_amount) = (amount.unwrap_or(0), amount.is_some());
+                payment_engine_apply(engine, r#type, 1, tx, amount, has_amount)
+            };
+            assert_eq!(
+                apply(PAYMENT_ENGINE_DEPOSIT, 1, Some(50000)),
+                PAYMENT_ENGINE_APPLIED
+            );
+            assert_eq!(
+                apply(PAYMENT_ENGINE_WITHDRAWAL, 2, Some(80000)),
+                PAYMENT_ENGINE_REJECTED
+            );
+            let rejection = CStr::from_ptr(payment_engine_last_rejection(engine));
+            assert_eq!(rejection.to_str(), Ok("insufficient_funds"));
+            assert_eq!(
+                apply(PAYMENT_ENGINE_DISPUTE, 1, None),
+                PAYMENT_ENGINE_APPLIED
+            );
+            assert_eq!(apply(9, 3, None), PAYMENT_ENGINE_INVALID_ARGUMENT);
+
+            let mut account = PaymentEngineAccount::default();
+            assert!(payment_engine_account(engine, 1, &mut account));
+            assert_eq!(
+                account,
+                PaymentEngineAccount {
+                    client: 1,
+                    available: 0,
+                    held: 50000,
+                    total: 50000,
+                    locked: false,
+                }
+            );
+            assert!(!payment_engine_account(engine, 2, &mut account));
+            payment_engine_free(engine);
+
+            let apply = payment_engine_apply(ptr::null_mut(), 0, 1, 1, 1, true);
+            assert_eq!(apply, PAYMENT_ENGINE_INVALID_ARGUMENT);
+            payment_engine_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::amount::{Amount, SCALE};
+use crate::engine::Engine;
+use crate::transactions::{Client, TransactionRecord, Tx, TxType};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::{self, FromStr};
+
+// Which FIX tags carry each field. The defaults follow FIX 4.4: Account(1)
+// is the client, ExecID(17) the transaction id, and a fill's cash amount is
+// LastQty(32) * LastPx(31) unless `amount_tag` names a tag that already
+// holds it (e.g. GrossTradeAmt(381)). Sells bring cash in, buys take it out.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FixConfig {
+    pub delimiter: String,
+    pub client_tag: u32,
+    pub tx_tag: u32,
+    pub side_tag: u32,
+    pub qty_tag: u32,
+    pub price_tag: u32,
+    pub amount_tag: Option<u32>,
+    pub deposit_sides: Vec<String>,
+    pub withdrawal_sides: Vec<String>,
+    // Message types (e.g. a venue's custom cash transfer message) booked as
+    // transfers, with their amount in `transfer_amount_tag` and their
+    // direction in the side tag.
+    pub transfer_msg_types: Vec<String>,
+    pub transfer_amount_tag: u32,
+}
+
+impl Default for FixConfig {
+    fn default() -> FixConfig {
+        FixConfig {
+            delimiter: "\u{1}".to_string(),
+            client_tag: 1,
+            tx_tag: 17,
+            side_tag: 54,
+            qty_tag: 32,
+            price_tag: 31,
+            amount_tag: None,
+            deposit_sides: vec!["2".to_string()],
+            withdrawal_sides: vec!["1".to_string()],
+            transfer_msg_types: Vec::new(),
+            transfer_amount_tag: 118,
+        }
+    }
+}
+
+const MSG_TYPE: u32 = 35;
+const EXEC_TYPE: u32 = 150;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FixError {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for FixError {}
+
+// qty and price are both 4 decimal fixed point, so their product carries 8
+// decimals; it is rounded half up back to 4.
+fn notional(qty: Amount, price: Amount) -> Result<Amount, String> {
+    let scale = u128::from(SCALE);
+    let product = (u128::from(qty.0) * u128::from(price.0) + scale / 2) / scale;
+    u64::try_from(product)
+        .map(Amount)
+        .map_err(|_| "fill amount is too large".to_string())
+}
+
+impl FixConfig {
+    fn parse_message<'a>(&self, message: &'a str) -> Result<HashMap<u32, &'a str>, String> {
+        message
+            .split(self.delimiter.as_str())
+            .filter(|field| !field.trim().is_empty())
+            .map(|field| {
+                let (tag, value) = field
+                    .split_once('=')
+                    .ok_or_else(|| format!("malformed field `{}`", field))?;
+                let tag = tag
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("malformed tag `{}`", tag))?;
+                Ok((tag, value))
+            })
+            .collect()
+    }
+
+    fn record(&self, fields: &HashMap<u32, &str>) -> Result<Option<TransactionRecord>, String> {
+        let field = |tag: u32| {
+            fields
+                .get(&tag)
+                .copied()
+                .ok_or_else(|| format!("missing tag {}", tag))
+        };
+        let amount =
+            |tag: u32| Amount::from_str(field(tag)?).map_err(|e| format!("tag {}: {}", tag, e));
+
+        let msg_type = field(MSG_TYPE)?;
+        let is_fill = msg_type == "8" && fields.get(&EXEC_TYPE) == Some(&"F");
+        let is_transfer = self.transfer_msg_types.iter().any(|t| t == msg_type);
+        if !is_fill && !is_transfer {
+            return Ok(None);
+        }
+
+        let side = field(self.side_tag)?;
+        let r#type = if self.deposit_sides.iter().any(|s| s == side) {
+            TxType::Deposit
+        } else if self.withdrawal_sides.iter().any(|s| s == side) {
+            TxType::Withdrawal
+        } else {
+            return Err(format!(
+                "side `{}` is not mapped to a deposit or withdrawal",
+                side
+            ));
+        };
+        let amount = match (is_transfer, self.amount_tag) {
+            (true, _) => amount(self.transfer_amount_tag)?,
+            (false, Some(tag)) => amount(tag)?,
+            (false, None) => notional(amount(self.qty_tag)?, amount(self.price_tag)?)?,
+        };
+        let client = field(self.client_tag)?;
+        let tx = field(self.tx_tag)?;
+        Ok(Some(TransactionRecord {
+            r#type,
+            client: Client(
+                client
+                    .parse()
+                    .map_err(|_| format!("client `{}` is not a numeric id", client))?,
+            ),
+            tx: Tx(tx
+                .parse()
+                .map_err(|_| format!("tx `{}` is not a numeric id", tx))?),
+            amount: Some(amount),
+        }))
+    }
+}
+
+impl Engine {
+    // Reads a drop-copy log with one message per line. Anything before
+    // `8=FIX` (timestamps, session ids) is ignored, as are messages other
+    // than fills and the configured transfer types.
+    pub fn process_fix(&mut self, input: &[u8], config: &FixConfig) -> Result<(), FixError> {
+        for (i, line) in input.split(|b| *b == b'\n').enumerate() {
+            let error = |reason| FixError {
+                line: i + 1,
+                reason,
+            };
+            let line =
+                str::from_utf8(line).map_err(|_| error("line is not valid UTF-8".to_string()))?;
+            let message = match line.find("8=FIX") {
+                Some(start) => line[start..].trim_end(),
+                None => continue,
+            };
+            let fields = config.parse_message(message).map_err(error)?;
+            if let Some(record) = config.record(&fields).map_err(error)? {
+                self.process(&record);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FixConfig, FixError};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::Client;
+
+    #[test]
+    fn test_fills() {
+        let log = concat!(
+            "08:00:00.001 8=FIX.4.4\u{1}35=8\u{1}1=5\u{1}17=1\u{1}150=F\u{1}54=2\u{1}32=100\u{1}31=12.3456\u{1}\n",
+            "08:00:00.002 8=FIX.4.4\u{1}35=8\u{1}1=5\u{1}17=2\u{1}150=0\u{1}54=1\u{1}\n",
+            "08:00:00.003 8=FIX.4.4\u{1}35=8\u{1}1=5\u{1}17=3\u{1}150=F\u{1}54=1\u{1}32=3\u{1}31=0.33335\u{1}\n",
+            "08:00:00.004 8=FIX.4.4\u{1}35=0\u{1}\n",
+            "heartbeat timeout\n",
+        );
+        let mut engine = Engine::new();
+        assert_eq!(
+            engine.process_fix(log.as_bytes(), &FixConfig::default()),
+            Err(FixError {
+                line: 3,
+                reason: "tag 31: A valid amount is up to 4 digits precision".to_string(),
+            })
+        );
+        assert_eq!(
+            engine.account(Client(5)).unwrap().available,
+            Amount::new(12_345_600)
+        );
+    }
+
+    #[test]
+    fn test_tag_mapping() {
+        let config: FixConfig = toml::from_str(
+            r#"
+            delimiter = "|"
+            client_tag = 448
+            amount_tag = 381
+            transfer_msg_types = ["U1"]
+            "#,
+        )
+        .unwrap();
+        let log = concat!(
+            "8=FIX.4.4|35=U1|448=2|17=1|54=2|118=50.5|\n",
+            "8=FIX.4.4|35=8|448=2|17=2|150=F|54=1|32=10|31=1|381=10.25|\n",
+        );
+        let mut engine = Engine::new();
+        engine.process_fix(log.as_bytes(), &config).unwrap();
+        assert_eq!(
+            engine.account(Client(2)).unwrap().available,
+            Amount::new(402500)
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use crate::engine::Engine;
+use crate::error::{PaymentResult, PaymentsError};
+use crate::transactions::{amount_field, Client, RowRecord, Tx, TxType};
+use serde::Deserialize;
+use std::fmt;
+use std::str::{self, FromStr};
+
+// Byte offset and width of one column; values are space padded on either
+// side, as legacy exports pad numbers left and text right.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize)]
+pub struct Field {
+    pub start: usize,
+    pub width: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FixedWidthLayout {
+    #[serde(default)]
+    pub skip_lines: usize,
+    pub r#type: Field,
+    pub client: Field,
+    pub tx: Field,
+    pub amount: Field,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FixedWidthError {
+    pub line: usize,
+    pub field: &'static str,
+    pub reason: PaymentsError,
+}
+
+impl fmt::Display for FixedWidthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}: field `{}`: {}",
+            self.line, self.field, self.reason
+        )
+    }
+}
+
+impl std::error::Error for FixedWidthError {}
+
+impl FixedWidthLayout {
+    // Rows shorter than a field's end are fine as long as the field starts
+    // inside the row: trailing blanks (e.g. an empty amount) are often
+    // trimmed by whatever produced the file.
+    fn field<'a>(&self, line: &'a [u8], field: Field) -> PaymentResult<&'a str> {
+        let start = field.start.min(line.len());
+        let end = (field.start + field.width).min(line.len());
+        str::from_utf8(&line[start..end])
+            .map(str::trim)
+            .map_err(|_| PaymentsError::Parse("Field is not valid UTF-8"))
+    }
+
+    pub fn parse_row<'a>(
+        &self,
+        line: &'a [u8],
+    ) -> Result<RowRecord<'a>, (&'static str, PaymentsError)> {
+        let text = |name, field| self.field(line, field).map_err(|reason| (name, reason));
+        let r#type =
+            TxType::from_str(text("type", self.r#type)?).map_err(|reason| ("type", reason))?;
+        let client = text("client", self.client)?
+            .parse()
+            .map_err(|_| ("client", PaymentsError::Parse("Bad input for client")))?;
+        let tx = text("tx", self.tx)?
+            .parse()
+            .map_err(|_| ("tx", PaymentsError::Parse("Bad input for tx")))?;
+        let amount =
+            amount_field(text("amount", self.amount)?).map_err(|reason| ("amount", reason))?;
+        Ok(RowRecord::new(r#type, Client(client), Tx(tx), amount))
+    }
+
+    pub fn rows<'a>(
+        &'a self,
+        input: &'a [u8],
+    ) -> impl Iterator<Item = Result<RowRecord<'a>, FixedWidthError>> + 'a {
+        input
+            .split(|b| *b == b'\n')
+            .enumerate()
+            .skip(self.skip_lines)
+            .map(|(i, line)| (i + 1, line.strip_suffix(b"\r").unwrap_or(line)))
+            .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+            .map(move |(line_no, line)| {
+                self.parse_row(line)
+                    .map_err(|(field, reason)| FixedWidthError {
+                        line: line_no,
+                        field,
+                        reason,
+                    })
+            })
+    }
+}
+
+impl Engine {
+    pub fn process_fixed_width(
+        &mut self,
+        input: &[u8],
+        layout: &FixedWidthLayout,
+    ) -> Result<(), FixedWidthError> {
+        for row in layout.rows(input) {
+            let _ = self.process_row(row?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Field, FixedWidthError, FixedWidthLayout};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::error::PaymentsError;
+    use crate::transactions::Client;
+
+    fn layout() -> FixedWidthLayout {
+        FixedWidthLayout {
+            skip_lines: 1,
+            r#type: Field {
+                start: 0,
+                width: 10,
+            },
+            client: Field {
+                start: 10,
+                width: 5,
+            },
+            tx: Field {
+                start: 15,
+                width: 8,
+            },
+            amount: Field {
+                start: 23,
+                width: 12,
+            },
+        }
+    }
+
+    #[test]
+    fn test_fixed_width_rows() {
+        let input = concat!(
+            "TYPE      CLNT TX      AMOUNT\n",
+            "deposit       1       1      10.5\r\n",
+            "deposit       1       2    2.2500\n",
+            "dispute       1       2\n",
+            "\n",
+        );
+        let mut engine = Engine::new();
+        engine
+            .process_fixed_width(input.as_bytes(), &layout())
+            .unwrap();
+        let funds = engine.account(Client(1)).unwrap();
+        assert_eq!(funds.available, Amount::new(105000));
+        assert_eq!(funds.held, Amount::new(22500));
+        assert_eq!(engine.metrics().records_read, 3);
+    }
+
+    #[test]
+    fn test_fixed_width_errors() {
+        let input =
+            "header\ndeposit       1       1       1.0\ndeposit       x       2       1.0\n";
+        let err = Engine::new()
+            .process_fixed_width(input.as_bytes(), &layout())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            FixedWidthError {
+                line: 3,
+                field: "client",
+                reason: PaymentsError::Parse("Bad input for client"),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "line 3: field `client`: Bad input for client"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::engine::Engine;
+use crate::funds::Funds;
+use crate::transactions::{
+    transact, Client, ClientFunds, RejectReason, TransactionRecord, TxRecords,
+};
+
+// An account a fork changed, as it is live and in the fork.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ForkDiff {
+    pub client: Client,
+    pub live: Option<Funds>,
+    pub forked: Option<Funds>,
+}
+
+// A what-if copy of an engine's ledger (see `Engine::fork`). A record only
+// ever touches its own client's account and the log entry of its own tx,
+// so those are copied from the engine the first time a record needs them
+// and everything else is read through: forking is free, and a batch costs
+// what it touches. The engine itself can't change while it is borrowed.
+//
+// Records go through the built-in validation and the engine's rules, with
+// the clients' stats as they are live; the denylist, AML screening, hooks
+// and observers are left out, since they act outside the ledger.
+pub struct Fork<'a> {
+    live: &'a Engine,
+    accounts: ClientFunds,
+    records: TxRecords,
+}
+
+impl<'a> Fork<'a> {
+    pub(crate) fn new(live: &'a Engine) -> Fork<'a> {
+        Fork {
+            live,
+            accounts: ClientFunds::new(),
+            records: TxRecords::new(),
+        }
+    }
+
+    pub fn try_process(&mut self, record: &TransactionRecord) -> Result<(), RejectReason> {
+        let (live_accounts, live_records) = self.live.ledger();
+        if let Some(funds) = live_accounts.get(&record.client) {
+            self.accounts
+                .entry(record.client)
+                .or_insert_with(|| funds.clone());
+        }
+        if let Some(logged) = live_records.get(&record.tx) {
+            self.records.entry(record.tx).or_insert(*logged);
+        }
+        let stats = self.live.stats(record.client).unwrap_or_default();
+        transact(
+            &mut self.accounts,
+            &mut self.records,
+            record,
+            self.live.rules(),
+            stats,
+        )
+        .into_result()
+    }
+
+    pub fn account(&self, client: Client) -> Option<&Funds> {
+        self.accounts
+            .get(&client)
+            .or_else(|| self.live.account(client))
+    }
+
+    // The accounts the fork's records changed, in client order.
+    pub fn account_diffs(&self) -> Vec<ForkDiff> {
+        let mut diffs: Vec<ForkDiff> = self
+            .accounts
+            .values()
+            .map(|funds| ForkDiff {
+                client: funds.client,
+                live: self.live.account(funds.client).cloned(),
+                forked: Some(funds.clone()),
+            })
+            .filter(|diff| diff.live != diff.forked)
+            .collect();
+        diffs.sort_by_key(|diff| diff.client.0);
+        diffs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::transactions::{Client, RejectReason, TransactionRecord, Tx};
+
+    #[test]
+    fn test_fork() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,5.0\ndeposit,2,2,3.0\ndeposit,3,3,1.0\ndispute,1,1,\ndispute,2,2,\n";
+        let mut engine = Engine::new();
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+
+        // what if both disputes are charged back?
+        let mut fork = engine.fork();
+        for (client, tx) in [(1, 1), (2, 2), (3, 3)] {
+            let chargeback = TransactionRecord::chargeback(Client(client), Tx(tx));
+            let expected = if client == 3 {
+                Err(RejectReason::NotDisputed)
+            } else {
+                Ok(())
+            };
+            assert_eq!(fork.try_process(&chargeback), expected);
+        }
+        assert_eq!(
+            fork.account(Client(1)).unwrap().state,
+            FundingStates::Frozen
+        );
+        assert_eq!(
+            fork.account(Client(3)).unwrap().available,
+            Amount::new(10000)
+        );
+        let diffs = fork.account_diffs();
+        let clients: Vec<u32> = diffs.iter().map(|diff| diff.client.0).collect();
+        assert_eq!(clients, vec![1, 2]);
+        assert_eq!(diffs[1].forked.as_ref().unwrap().total(), Amount::new(0));
+
+        // the live engine is untouched
+        let funds = engine.account(Client(2)).unwrap();
+        assert_eq!(funds.state, FundingStates::Disputed);
+        assert_eq!(funds.held, Amount::new(30000));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::books::JournalEntry;
+use crate::output::civil_date;
+use crate::postings::LedgerAccount;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+// The account codes ledger accounts are exported under, from the
+// `[chart_of_accounts]` config section. Clients' balances are liabilities
+// of the engine, so their accounts share a code and are told apart by the
+// client column.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ChartOfAccounts {
+    pub available: String,
+    pub held: String,
+    pub pending: String,
+    pub debt: String,
+    pub suspense: String,
+    pub promotion: String,
+}
+
+impl Default for ChartOfAccounts {
+    fn default() -> ChartOfAccounts {
+        ChartOfAccounts {
+            available: "2000".to_string(),
+            held: "2010".to_string(),
+            pending: "2020".to_string(),
+            debt: "1200".to_string(),
+            suspense: "1000".to_string(),
+            promotion: "6100".to_string(),
+        }
+    }
+}
+
+impl ChartOfAccounts {
+    pub fn code(&self, account: LedgerAccount) -> &str {
+        match account {
+            LedgerAccount::Available(_) => &self.available,
+            LedgerAccount::Held(_) => &self.held,
+            LedgerAccount::Pending(_) => &self.pending,
+            LedgerAccount::Debt(_) => &self.debt,
+            LedgerAccount::Suspense => &self.suspense,
+            LedgerAccount::Promotion => &self.promotion,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GlRow<'a> {
+    date: &'a str,
+    account: &'a str,
+    client: Option<u32>,
+    debit: Option<String>,
+    credit: Option<String>,
+    reference: &'a str,
+}
+
+// The journal as CSV general ledger lines, two per posting: the debited
+// account's, then the credited one's. The reference is the tx, or
+// "opening" for opening balances; entries made before the engine's clock
+// was set are dated `date`.
+pub fn write_gl<W: Write>(
+    journal: &[JournalEntry],
+    chart: &ChartOfAccounts,
+    date: (i64, u32, u32),
+    writer: W,
+) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for entry in journal {
+        let (year, month, day) = entry.timestamp.map_or(date, civil_date);
+        let date = format!("{:04}-{:02}-{:02}", year, month, day);
+        let reference = entry
+            .tx
+            .map_or_else(|| "opening".to_string(), |tx| tx.0.to_string());
+        let posting = &entry.posting;
+        let amount = posting.amount.to_string();
+        for (account, debit) in [(posting.debit, true), (posting.credit, false)] {
+            writer.serialize(GlRow {
+                date: &date,
+                account: chart.code(account),
+                client: account.client().map(|client| client.0),
+                debit: debit.then(|| amount.clone()),
+                credit: (!debit).then(|| amount.clone()),
+                reference: &reference,
+            })?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_gl, ChartOfAccounts};
+    use crate::engine::Engine;
+
+    #[test]
+    fn test_write_gl() {
+        let mut engine = Engine::builder().double_entry(true).build();
+        let csvfile = "type,client,tx,amount,timestamp,currency\ndeposit,1,1,10,1792108800,EUR\nwithdrawal,1,2,4,1792195200,EUR\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let chart: ChartOfAccounts = toml::from_str("available = \"2100\"").unwrap();
+        let mut out = Vec::new();
+        write_gl(
+            engine.books().unwrap().journal(),
+            &chart,
+            (2026, 1, 1),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "date,account,client,debit,credit,reference\n\
+             2026-10-16,1000,,10.0000,,1\n\
+             2026-10-16,2100,1,,10.0000,1\n\
+             2026-10-17,2100,1,4.0000,,2\n\
+             2026-10-17,1000,,,4.0000,2\n"
+        );
+    }
+}
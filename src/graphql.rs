@@ -0,0 +1,306 @@
+use crate::engine::Engine;
+use crate::funds::{FundingStates, Funds};
+use crate::history::Activity;
+use crate::serve::SharedTenants;
+use crate::shared::SharedEngine;
+use crate::tenant::TENANT_HEADER;
+use crate::transactions::{Client, Tx, TxType};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Enum, Object, Schema, SimpleObject,
+};
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::{Json, Router};
+use std::collections::HashMap;
+
+pub type EngineSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+#[derive(Enum, Debug, PartialEq, Eq, Copy, Clone)]
+enum AccountState {
+    Valid,
+    Disputed,
+    Frozen,
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Copy, Clone)]
+enum TransactionType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Credit,
+    Settle,
+}
+
+impl From<TxType> for TransactionType {
+    fn from(r#type: TxType) -> TransactionType {
+        match r#type {
+            TxType::Deposit => TransactionType::Deposit,
+            TxType::Withdrawal => TransactionType::Withdrawal,
+            TxType::Dispute => TransactionType::Dispute,
+            TxType::Resolve => TransactionType::Resolve,
+            TxType::Chargeback => TransactionType::Chargeback,
+            TxType::Credit => TransactionType::Credit,
+            TxType::Settle => TransactionType::Settle,
+        }
+    }
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Copy, Clone)]
+enum DisputeState {
+    Open,
+    Resolved,
+    ChargedBack,
+}
+
+#[derive(SimpleObject)]
+struct Transaction {
+    #[graphql(name = "type")]
+    r#type: TransactionType,
+    tx: u64,
+    amount: String,
+}
+
+#[derive(SimpleObject)]
+struct Dispute {
+    tx: u64,
+    amount: String,
+    state: DisputeState,
+}
+
+// Where each disputed transaction of a client ended up, in the order they
+// were first disputed. A transaction resolved and disputed again is open.
+fn disputes(activity: &[Activity]) -> Vec<Dispute> {
+    let mut disputes = Vec::new();
+    let mut index: HashMap<Tx, usize> = HashMap::new();
+    for activity in activity {
+        let state = match activity.r#type {
+            TxType::Dispute => DisputeState::Open,
+            TxType::Resolve => DisputeState::Resolved,
+            TxType::Chargeback => DisputeState::ChargedBack,
+            TxType::Deposit | TxType::Withdrawal | TxType::Credit | TxType::Settle => continue,
+        };
+        let i = *index.entry(activity.tx).or_insert_with(|| {
+            disputes.push(Dispute {
+                tx: activity.tx.0,
+                amount: activity.amount.to_string(),
+                state,
+            });
+            disputes.len() - 1
+        });
+        disputes[i].state = state;
+    }
+    disputes
+}
+
+// A copy of one account taken under the engine lock, so resolving nested
+// fields doesn't hold it.
+struct Account {
+    funds: Funds,
+    activity: Vec<Activity>,
+    risk_score: Option<u8>,
+}
+
+impl Account {
+    fn new(engine: &Engine, funds: &Funds) -> Account {
+        let activity = engine
+            .client_history(funds.client)
+            .map(<[Activity]>::to_vec)
+            .unwrap_or_default();
+        Account {
+            funds: funds.clone(),
+            activity,
+            risk_score: engine.risk_score(funds.client),
+        }
+    }
+}
+
+#[Object]
+impl Account {
+    async fn client(&self) -> u32 {
+        self.funds.client.0
+    }
+
+    async fn available(&self) -> String {
+        self.funds.available.to_string()
+    }
+
+    async fn held(&self) -> String {
+        self.funds.held.to_string()
+    }
+
+    async fn total(&self) -> String {
+        self.funds.total().to_string()
+    }
+
+    async fn locked(&self) -> bool {
+        self.funds.state == FundingStates::Frozen
+    }
+
+    async fn state(&self) -> AccountState {
+        match self.funds.state {
+            FundingStates::Valid => AccountState::Valid,
+            FundingStates::Disputed => AccountState::Disputed,
+            FundingStates::Frozen => AccountState::Frozen,
+        }
+    }
+
+    // 0 to 100, see `score::risk_score`; null when the engine doesn't
+    // score clients.
+    async fn risk_score(&self) -> Option<u8> {
+        self.risk_score
+    }
+
+    // Applied transactions in order, optionally only those of one type.
+    async fn transactions(&self, r#type: Option<TransactionType>) -> Vec<Transaction> {
+        self.activity
+            .iter()
+            .map(|activity| Transaction {
+                r#type: activity.r#type.into(),
+                tx: activity.tx.0,
+                amount: activity.amount.to_string(),
+            })
+            .filter(|transaction| r#type.is_none_or(|r#type| transaction.r#type == r#type))
+            .collect()
+    }
+
+    async fn disputes(&self, state: Option<DisputeState>) -> Vec<Dispute> {
+        disputes(&self.activity)
+            .into_iter()
+            .filter(|dispute| state.is_none_or(|state| dispute.state == state))
+            .collect()
+    }
+}
+
+// The `x-tenant` header of the request being executed, if it had one.
+struct RequestedTenant(String);
+
+// The engine of the request's tenant, or `None` for a tenant nothing was
+// submitted to yet.
+fn engine(ctx: &Context<'_>) -> async_graphql::Result<Option<SharedEngine>> {
+    let tenants = ctx.data_unchecked::<SharedTenants>();
+    let requested = ctx
+        .data_opt::<RequestedTenant>()
+        .map(|requested| requested.0.as_str());
+    let tenant = tenants.resolve(requested)?;
+    Ok(tenants.get(&tenant))
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn account(
+        &self,
+        ctx: &Context<'_>,
+        client: u32,
+    ) -> async_graphql::Result<Option<Account>> {
+        let engine = match engine(ctx)? {
+            Some(engine) => engine,
+            None => return Ok(None),
+        };
+        Ok(engine.read(|engine| {
+            engine
+                .account(Client(client))
+                .map(|funds| Account::new(engine, funds))
+        }))
+    }
+
+    async fn accounts(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Account>> {
+        let engine = match engine(ctx)? {
+            Some(engine) => engine,
+            None => return Ok(Vec::new()),
+        };
+        let mut accounts: Vec<Account> = engine.read(|engine| {
+            engine
+                .accounts()
+                .map(|funds| Account::new(engine, funds))
+                .collect()
+        });
+        accounts.sort_by_key(|account| account.funds.client.0);
+        Ok(accounts)
+    }
+}
+
+pub fn schema(tenants: SharedTenants) -> EngineSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(tenants)
+        .finish()
+}
+
+// A header that isn't valid UTF-8 is passed on as an empty name, which is
+// rejected like any other invalid one.
+async fn execute(
+    State(schema): State<EngineSchema>,
+    headers: HeaderMap,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let request = match headers.get(TENANT_HEADER) {
+        Some(value) => request.data(RequestedTenant(
+            value.to_str().unwrap_or_default().to_string(),
+        )),
+        None => request,
+    };
+    Json(schema.execute(request).await)
+}
+
+pub fn router(tenants: SharedTenants) -> Router {
+    Router::new()
+        .route("/graphql", post(execute))
+        .with_state(schema(tenants))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::schema;
+    use crate::engine::Engine;
+    use crate::serve::SharedTenants;
+    use crate::tenant::{Tenants, DEFAULT_TENANT};
+    use serde_json::json;
+
+    #[test]
+    fn test_account_query() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,5\ndeposit,1,2,2\ndeposit,2,3,1\ndispute,1,1,\nresolve,1,1,\ndispute,1,2,\nchargeback,1,2,\n";
+        let builder = Engine::builder().record_history(true).score_clients(true);
+        let mut tenants = Tenants::single(builder);
+        tenants
+            .engine(DEFAULT_TENANT)
+            .process_csv(csvfile.as_bytes())
+            .unwrap();
+        let schema = schema(SharedTenants::new(tenants));
+        let query = r#"{
+            account(client: 1) {
+                state total riskScore
+                transactions(type: DEPOSIT) { type tx amount }
+                disputes { tx state }
+            }
+            accounts { client }
+        }"#;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let response = runtime.block_on(schema.execute(query));
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            json!({
+                "account": {
+                    "state": "FROZEN",
+                    "total": "5.0000",
+                    "riskScore": 56,
+                    "transactions": [
+                        {"type": "DEPOSIT", "tx": 1, "amount": "5.0000"},
+                        {"type": "DEPOSIT", "tx": 2, "amount": "2.0000"},
+                    ],
+                    "disputes": [
+                        {"tx": 1, "state": "RESOLVED"},
+                        {"tx": 2, "state": "CHARGED_BACK"},
+                    ],
+                },
+                "accounts": [{"client": 1}, {"client": 2}],
+            })
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use crate::auth::{AuthConfig, AuthLayer, Denied, Scope};
+use crate::engine::Engine;
+use crate::funds::{FundingStates, Funds};
+use crate::protobuf::proto::payments_engine_server::{PaymentsEngine, PaymentsEngineServer};
+use crate::protobuf::proto::{
+    Account, AccountRequest, BatchResult, SubmitResult, Transaction, TransactionBatch,
+};
+use crate::serve::{Ingest, SharedTenants};
+use crate::tenant::TENANT_HEADER;
+use crate::transactions::{Client, TransactionRecord};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+// How many account changes a slow watcher may fall behind before its
+// stream is ended with `DATA_LOSS`.
+const WATCH_BUFFER: usize = 1024;
+
+impl From<&Funds> for Account {
+    fn from(funds: &Funds) -> Account {
+        Account {
+            client: funds.client.0,
+            available: funds.available.to_string(),
+            held: funds.held.to_string(),
+            total: funds.total().to_string(),
+            locked: funds.state == FundingStates::Frozen,
+        }
+    }
+}
+
+fn record(message: &Transaction) -> Result<TransactionRecord, Status> {
+    TransactionRecord::try_from(message).map_err(|err| Status::invalid_argument(err.to_string()))
+}
+
+struct Service {
+    tenants: SharedTenants,
+    ingest: Ingest,
+    changes: broadcast::Sender<(String, Account)>,
+}
+
+// Applies on the applier thread, under the engine lock, and publishes the
+// client's account when the record changed it, so watchers see changes in
+// order.
+fn apply(
+    changes: &broadcast::Sender<(String, Account)>,
+    tenant: &str,
+    engine: &mut Engine,
+    record: &TransactionRecord,
+) -> SubmitResult {
+    let result = engine.try_process(record);
+    if let (Ok(()), Some(funds)) = (result, engine.account(record.client)) {
+        // no receivers just means nobody is watching
+        let _ = changes.send((tenant.to_string(), Account::from(funds)));
+    }
+    SubmitResult {
+        tx: record.tx.0,
+        applied: result.is_ok(),
+        reason: result
+            .err()
+            .map(|reason| reason.as_str().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn shutting_down() -> Status {
+    Status::unavailable("server is shutting down")
+}
+
+impl Service {
+    // The tenant named by the request's `x-tenant` metadata, or the default
+    // one.
+    fn tenant<T>(&self, request: &Request<T>) -> Result<String, Status> {
+        let requested = match request.metadata().get(TENANT_HEADER) {
+            Some(value) => Some(
+                value
+                    .to_str()
+                    .map_err(|_| Status::invalid_argument("invalid tenant metadata"))?,
+            ),
+            None => None,
+        };
+        self.tenants
+            .resolve(requested)
+            .map_err(Status::invalid_argument)
+    }
+}
+
+#[tonic::async_trait]
+impl PaymentsEngine for Service {
+    async fn submit_transaction(
+        &self,
+        request: Request<Transaction>,
+    ) -> Result<Response<SubmitResult>, Status> {
+        let tenant = self.tenant(&request)?;
+        let record = record(request.get_ref())?;
+        let changes = self.changes.clone();
+        let result = self
+            .ingest
+            .apply(tenant.clone(), move |engine| {
+                apply(&changes, &tenant, engine, &record)
+            })
+            .await
+            .ok_or_else(shutting_down)?;
+        Ok(Response::new(result))
+    }
+
+    // The whole batch is validated before anything is applied, and applied
+    // as one submission so other submissions can't interleave with it.
+    async fn submit_batch(
+        &self,
+        request: Request<TransactionBatch>,
+    ) -> Result<Response<BatchResult>, Status> {
+        let tenant = self.tenant(&request)?;
+        let records = request
+            .get_ref()
+            .transactions
+            .iter()
+            .map(record)
+            .collect::<Result<Vec<_>, _>>()?;
+        let changes = self.changes.clone();
+        let results = self
+            .ingest
+            .apply(tenant.clone(), move |engine| {
+                records
+                    .iter()
+                    .map(|record| apply(&changes, &tenant, engine, record))
+                    .collect()
+            })
+            .await
+            .ok_or_else(shutting_down)?;
+        Ok(Response::new(BatchResult { results }))
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<AccountRequest>,
+    ) -> Result<Response<Account>, Status> {
+        let tenant = self.tenant(&request)?;
+        let client = Client(request.get_ref().client);
+        self.tenants
+            .get(&tenant)
+            .and_then(|engine| engine.account(client))
+            .map(|funds| Response::new(Account::from(&funds)))
+            .ok_or_else(|| Status::not_found(format!("no account for client {}", client.0)))
+    }
+
+    type WatchAccountStream = ReceiverStream<Result<Account, Status>>;
+
+    async fn watch_account(
+        &self,
+        request: Request<AccountRequest>,
+    ) -> Result<Response<Self::WatchAccountStream>, Status> {
+        let tenant = self.tenant(&request)?;
+        let client = Client(request.get_ref().client);
+        // subscribe before reading the current state so no change is missed
+        let mut changes = self.changes.subscribe();
+        let current = self
+            .tenants
+            .get(&tenant)
+            .and_then(|engine| engine.account(client))
+            .map(|funds| Account::from(&funds));
+        let (sender, receiver) = mpsc::channel(16);
+        tokio::spawn(async move {
+            if let Some(account) = current {
+                if sender.send(Ok(account)).await.is_err() {
+                    return;
+                }
+            }
+            loop {
+                let update = match changes.recv().await {
+                    Ok((changed, account)) if changed == tenant && account.client == client.0 => {
+                        Ok(account)
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(_)) => {
+                        Err(Status::data_loss("watcher fell behind the account changes"))
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                let lagged = update.is_err();
+                if sender.send(update).await.is_err() || lagged {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+// Submissions need `submit`; every other call only reads.
+fn required_scope(_: &http::Method, path: &str) -> Scope {
+    match path.rsplit('/').next() {
+        Some("SubmitTransaction") | Some("SubmitBatch") => Scope::Submit,
+        _ => Scope::Read,
+    }
+}
+
+fn deny(denied: Denied) -> http::Response<tonic::body::Body> {
+    let status = match denied {
+        Denied::Unauthenticated => Status::unauthenticated(denied.to_string()),
+        Denied::Forbidden(_) => Status::permission_denied(denied.to_string()),
+    };
+    status.into_http()
+}
+
+// Serves the engines on `addr` until interrupted (Ctrl-C), checking API
+// keys against `auth` when given.
+pub fn serve(
+    tenants: SharedTenants,
+    ingest: Ingest,
+    addr: SocketAddr,
+    auth: Option<Arc<AuthConfig>>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let service = Service {
+        tenants,
+        ingest,
+        changes: broadcast::channel(WATCH_BUFFER).0,
+    };
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(
+            tonic::transport::Server::builder()
+                .layer(AuthLayer::new(auth, required_scope, deny))
+                .add_service(PaymentsEngineServer::new(service))
+                .serve_with_shutdown(addr, async {
+                    let _ = tokio::signal::ctrl_c().await;
+                }),
+        )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Service;
+    use crate::engine::Engine;
+    use crate::protobuf::proto::payments_engine_server::PaymentsEngine;
+    use crate::protobuf::proto::{AccountRequest, Transaction, TransactionBatch, TransactionType};
+    use crate::serve::{Applier, ServerConfig, SharedTenants};
+    use crate::tenant::Tenants;
+    use tokio::sync::broadcast;
+    use tokio_stream::StreamExt;
+    use tonic::{Code, Request};
+
+    fn transaction(r#type: TransactionType, tx: u64, amount: Option<&str>) -> Transaction {
+        Transaction {
+            r#type: r#type as i32,
+            client: 7,
+            tx,
+            amount: amount.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_service() {
+        let tenants = SharedTenants::new(Tenants::single(Engine::builder()));
+        let applier = Applier::spawn(tenants.clone(), ServerConfig::default());
+        let service = Service {
+            tenants,
+            ingest: applier.ingest(),
+            changes: broadcast::channel(16).0,
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let deposit = transaction(TransactionType::Deposit, 1, Some("5"));
+            let result = service
+                .submit_transaction(Request::new(deposit))
+                .await
+                .unwrap();
+            assert!(result.get_ref().applied);
+
+            let request = || Request::new(AccountRequest { client: 7 });
+            let mut watch = service.watch_account(request()).await.unwrap().into_inner();
+            assert_eq!(watch.next().await.unwrap().unwrap().available, "5.0000");
+
+            let batch = TransactionBatch {
+                transactions: vec![
+                    transaction(TransactionType::Withdrawal, 2, Some("9")),
+                    transaction(TransactionType::Withdrawal, 3, Some("2")),
+                ],
+            };
+            let results = service.submit_batch(Request::new(batch)).await.unwrap();
+            let results = &results.get_ref().results;
+            assert_eq!(results[0].reason, "insufficient_funds");
+            assert!(results[1].applied);
+            let account = watch.next().await.unwrap().unwrap();
+            assert_eq!(
+                (account.available.as_str(), account.total.as_str()),
+                ("3.0000", "3.0000")
+            );
+
+            let account = service.get_account(request()).await.unwrap();
+            assert_eq!(account.get_ref().held, "0.0000");
+            let missing = Request::new(AccountRequest { client: 8 });
+            assert_eq!(
+                service.get_account(missing).await.unwrap_err().code(),
+                Code::NotFound
+            );
+        });
+    }
+}
@@ -0,0 +1,157 @@
+use crate::amount::Amount;
+use crate::engine::Engine;
+use crate::transactions::{Client, Tx, TxType};
+use std::collections::HashMap;
+use std::io;
+
+// One applied record and the amount it moved. Disputes, resolves and
+// chargebacks carry no amount of their own, so theirs is the amount of the
+// transaction they refer to.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Activity {
+    pub r#type: TxType,
+    pub tx: Tx,
+    pub amount: Amount,
+}
+
+impl Activity {
+    // Whether the activity credits (`Some(true)`) or debits the account's
+    // booked balance. Disputes, resolves and settles only move funds
+    // between available, held and pending, so they don't change it.
+    pub fn is_credit(&self) -> Option<bool> {
+        match self.r#type {
+            TxType::Deposit | TxType::Credit => Some(true),
+            TxType::Withdrawal | TxType::Chargeback => Some(false),
+            TxType::Dispute | TxType::Resolve | TxType::Settle => None,
+        }
+    }
+}
+
+// Per-client record of everything the engine applied, in order. Statement
+// exports need it; plain balance output doesn't, so engines only keep it
+// when built with `record_history(true)`.
+#[derive(Debug, Default)]
+pub struct History {
+    clients: HashMap<Client, Vec<Activity>>,
+}
+
+impl History {
+    pub fn record(&mut self, client: Client, activity: Activity) {
+        self.clients.entry(client).or_default().push(activity);
+    }
+
+    pub fn client(&self, client: Client) -> &[Activity] {
+        self.clients.get(&client).map_or(&[], Vec::as_slice)
+    }
+
+    // Up to `limit` of the client's activities matching `filter`, starting
+    // at `cursor` (0 for the first page). A client's history is only ever
+    // appended to, so a cursor stays valid and paging never skips or
+    // repeats an activity, even while new ones are recorded.
+    pub fn page(
+        &self,
+        client: Client,
+        filter: &HistoryFilter,
+        cursor: usize,
+        limit: usize,
+    ) -> HistoryPage {
+        let all = self.client(client);
+        let mut activity = Vec::new();
+        let mut position = cursor.min(all.len());
+        while position < all.len() && activity.len() < limit {
+            if filter.matches(&all[position]) {
+                activity.push(all[position]);
+            }
+            position += 1;
+        }
+        let next = all[position..]
+            .iter()
+            .position(|activity| filter.matches(activity))
+            .map(|skipped| position + skipped);
+        HistoryPage { activity, next }
+    }
+}
+
+// Narrows a history page; a field left unset matches everything. Amount
+// bounds are inclusive. Applied records keep no timestamp, so there is no
+// time range to filter on.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HistoryFilter {
+    pub r#type: Option<TxType>,
+    pub min_amount: Option<Amount>,
+    pub max_amount: Option<Amount>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, activity: &Activity) -> bool {
+        self.r#type.is_none_or(|r#type| activity.r#type == r#type)
+            && self.min_amount.is_none_or(|min| activity.amount >= min)
+            && self.max_amount.is_none_or(|max| activity.amount <= max)
+    }
+}
+
+// Activities in the order they were applied, and the cursor of the next
+// page; `None` once nothing further matches.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HistoryPage {
+    pub activity: Vec<Activity>,
+    pub next: Option<usize>,
+}
+
+pub(crate) fn statement_history(engine: &Engine) -> io::Result<&History> {
+    engine.history().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "statement export needs an engine built with record_history(true)",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Activity, HistoryFilter};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::{Client, Tx, TxType};
+
+    #[test]
+    fn test_client_history() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,2,2,1.0\nwithdrawal,1,3,5.0\ndispute,1,1,\nresolve,1,1,\n";
+        let mut engine = Engine::new();
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        assert_eq!(engine.client_history(Client(1)), None);
+
+        let mut engine = Engine::builder().record_history(true).build();
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let activity = |r#type, tx| Activity {
+            r#type,
+            tx: Tx(tx),
+            amount: Amount::new(20000),
+        };
+        // the rejected withdrawal isn't there
+        assert_eq!(
+            engine.client_history(Client(1)).unwrap(),
+            [
+                activity(TxType::Deposit, 1),
+                activity(TxType::Dispute, 1),
+                activity(TxType::Resolve, 1),
+            ]
+        );
+        assert_eq!(engine.client_history(Client(3)).unwrap(), []);
+
+        let csvfile = "type,client,tx,amount\ndeposit,4,11,1.0\ndeposit,4,12,5.0\nwithdrawal,4,13,2.0\ndeposit,4,14,3.0\ndeposit,4,15,0.5\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let history = engine.history().unwrap();
+        let deposits = HistoryFilter {
+            r#type: Some(TxType::Deposit),
+            min_amount: Some(Amount::new(10000)),
+            ..HistoryFilter::default()
+        };
+        let page = history.page(Client(4), &deposits, 0, 2);
+        let txs: Vec<u64> = page.activity.iter().map(|activity| activity.tx.0).collect();
+        assert_eq!((txs, page.next), (vec![11, 12], Some(3)));
+        let page = history.page(Client(4), &deposits, 3, 2);
+        let txs: Vec<u64> = page.activity.iter().map(|activity| activity.tx.0).collect();
+        assert_eq!((txs, page.next), (vec![14], None));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::transactions::{RejectReason, TransactionRecord};
+
+// What a hook decides about a record before the engine validates it.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Decision {
+    Proceed,
+    // turns the record away, as if a rule had rejected it; custom codes go
+    // through `RejectReason::Rule`
+    Veto(RejectReason),
+}
+
+// How a record ended up: applied, or rejected with the reason why.
+pub type Outcome = Result<(), RejectReason>;
+
+// Side effects around applying a record, for callers that log, enrich or
+// veto transactions from outside the crate. `before` runs after the
+// denylist and AML screening and before the rules, for every record that
+// gets that far; `after` runs for every record, once the engine is done
+// with it. Hooks run in the order they were added with
+// `EngineBuilder::hook`, and the first veto stops the rest. Unlike a
+// `ValidationRule`, a hook can keep state of its own.
+pub trait TransactionHook: Send {
+    fn before(&mut self, _record: &TransactionRecord) -> Decision {
+        Decision::Proceed
+    }
+
+    fn after(&mut self, _record: &TransactionRecord, _outcome: &Outcome) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decision, Outcome, TransactionHook};
+    use crate::engine::Engine;
+    use crate::transactions::{Client, RejectReason, TransactionRecord, Tx};
+    use std::sync::{Arc, Mutex};
+
+    // Vetoes a client's records and keeps every outcome it sees.
+    struct Recorder {
+        blocked: Client,
+        seen: Arc<Mutex<Vec<(Tx, Outcome)>>>,
+    }
+
+    impl TransactionHook for Recorder {
+        fn before(&mut self, record: &TransactionRecord) -> Decision {
+            if record.client == self.blocked {
+                Decision::Veto(RejectReason::Rule("blocked_by_hook"))
+            } else {
+                Decision::Proceed
+            }
+        }
+
+        fn after(&mut self, record: &TransactionRecord, outcome: &Outcome) {
+            self.seen.lock().unwrap().push((record.tx, *outcome));
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = Engine::builder()
+            .hook(Recorder {
+                blocked: Client(2),
+                seen: Arc::clone(&seen),
+            })
+            .build();
+        let csvfile =
+            "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,2,2,1.0\nwithdrawal,1,3,5.0\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (Tx(1), Ok(())),
+                (Tx(2), Err(RejectReason::Rule("blocked_by_hook"))),
+                (Tx(3), Err(RejectReason::InsufficientFunds)),
+            ]
+        );
+        assert!(engine.account(Client(2)).is_none());
+        let metrics = engine.metrics();
+        assert_eq!(metrics.rejected[&RejectReason::Rule("blocked_by_hook")], 1);
+    }
+}
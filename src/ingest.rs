@@ -0,0 +1,209 @@
+use crate::engine::Engine;
+use crate::transactions::TransactionRecord;
+use std::collections::VecDeque;
+use std::future;
+use std::sync::{Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+
+struct State<T> {
+    queue: VecDeque<T>,
+    waiting: Vec<Waker>,
+    closed: bool,
+}
+
+// A bounded hand-off between sources (network readers, consumers) and the
+// thread applying what they read. Sources call `poll_ready` before
+// `try_push` (or just `push`); once the queue reaches its high-water mark
+// they are parked until the applier drains it back below that mark.
+pub struct IngestQueue<T> {
+    state: Mutex<State<T>>,
+    // signalled when something is pushed or the queue is closed
+    pushed: Condvar,
+    high_water: usize,
+}
+
+impl<T> IngestQueue<T> {
+    pub fn new(high_water: usize) -> IngestQueue<T> {
+        IngestQueue {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                waiting: Vec::new(),
+                closed: false,
+            }),
+            pushed: Condvar::new(),
+            high_water: high_water.max(1),
+        }
+    }
+
+    pub fn high_water(&self) -> usize {
+        self.high_water
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Ready once there is room, or once the queue is closed, after which
+    // `try_push` fails for good.
+    pub fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed || state.queue.len() < self.high_water {
+            return Poll::Ready(());
+        }
+        if !state.waiting.iter().any(|w| w.will_wake(cx.waker())) {
+            state.waiting.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    pub fn try_push(&self, item: T) -> Result<(), T> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed || state.queue.len() >= self.high_water {
+            return Err(item);
+        }
+        state.queue.push_back(item);
+        self.pushed.notify_one();
+        Ok(())
+    }
+
+    // Waits for room and pushes; the item comes back if the queue was
+    // closed first.
+    pub async fn push(&self, mut item: T) -> Result<(), T> {
+        loop {
+            future::poll_fn(|cx| self.poll_ready(cx)).await;
+            match self.try_push(item) {
+                Ok(()) => return Ok(()),
+                Err(back) if self.state.lock().unwrap().closed => return Err(back),
+                // another source took the room first
+                Err(back) => item = back,
+            }
+        }
+    }
+
+    // Takes up to `max` items without waiting.
+    pub fn drain(&self, max: usize) -> Vec<T> {
+        let mut state = self.state.lock().unwrap();
+        self.take(&mut state, max)
+    }
+
+    // Takes up to `max` items, waiting for one while the queue is empty.
+    // None once the queue is closed and everything in it was taken.
+    pub fn wait_drain(&self, max: usize) -> Option<Vec<T>> {
+        let mut state = self.state.lock().unwrap();
+        while state.queue.is_empty() && !state.closed {
+            state = self.pushed.wait(state).unwrap();
+        }
+        if state.queue.is_empty() {
+            return None;
+        }
+        Some(self.take(&mut state, max))
+    }
+
+    fn take(&self, state: &mut State<T>, max: usize) -> Vec<T> {
+        let count = max.min(state.queue.len());
+        let batch: Vec<_> = state.queue.drain(..count).collect();
+        if state.queue.len() < self.high_water {
+            state.waiting.drain(..).for_each(Waker::wake);
+        }
+        batch
+    }
+
+    // Refuses anything pushed from now on and wakes everyone waiting; what
+    // is already queued can still be drained.
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.waiting.drain(..).for_each(Waker::wake);
+        self.pushed.notify_all();
+    }
+}
+
+impl IngestQueue<TransactionRecord> {
+    pub fn drain_into(&self, engine: &mut Engine, max: usize) -> usize {
+        let batch = self.drain(max);
+        batch.iter().for_each(|record| engine.process(record));
+        batch.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IngestQueue;
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::{Client, TransactionRecord, Tx};
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn deposit(tx: u64) -> TransactionRecord {
+        TransactionRecord::deposit(Client(1), Tx(tx), Amount::new(1))
+    }
+
+    #[test]
+    fn test_backpressure() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(Arc::clone(&counter));
+        let mut cx = Context::from_waker(&waker);
+        let queue = IngestQueue::new(2);
+
+        assert_eq!(queue.poll_ready(&mut cx), Poll::Ready(()));
+        queue.try_push(deposit(1)).unwrap();
+        queue.try_push(deposit(2)).unwrap();
+        assert_eq!(queue.poll_ready(&mut cx), Poll::Pending);
+        assert_eq!(queue.try_push(deposit(3)), Err(deposit(3)));
+
+        let mut engine = Engine::new();
+        assert_eq!(queue.drain_into(&mut engine, 1), 1);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(queue.poll_ready(&mut cx), Poll::Ready(()));
+        queue.try_push(deposit(3)).unwrap();
+        assert_eq!(queue.drain_into(&mut engine, 10), 2);
+        assert!(queue.is_empty());
+        assert_eq!(engine.account(Client(1)).unwrap().available, Amount::new(3));
+    }
+
+    #[test]
+    fn test_push_and_close() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(Arc::clone(&counter));
+        let mut cx = Context::from_waker(&waker);
+        let queue = Arc::new(IngestQueue::new(1));
+        let applier = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                let mut applied = Vec::new();
+                while let Some(batch) = queue.wait_drain(10) {
+                    applied.extend(batch);
+                }
+                applied
+            })
+        };
+
+        // a full queue parks the push until the applier takes the item
+        queue.try_push(1).unwrap();
+        let mut push = pin!(queue.push(2));
+        while push.as_mut().poll(&mut cx).is_pending() {
+            thread::yield_now();
+        }
+        queue.close();
+        let mut refused = pin!(queue.push(3));
+        assert_eq!(refused.as_mut().poll(&mut cx), Poll::Ready(Err(3)));
+        assert_eq!(applier.join().unwrap(), [1, 2]);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::actor::{self, Router};
+use crate::config::Config;
+use crate::engine::Engine;
+use crate::pipeline;
+use memmap2::Mmap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum InputFormat {
+    Csv,
+    Fix,
+    FixedWidth,
+    Json,
+    #[cfg(feature = "arrow-io")]
+    Arrow,
+    #[cfg(feature = "avro-input")]
+    Avro,
+    #[cfg(feature = "iso20022")]
+    Iso20022,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "parquet-input")]
+    Parquet,
+    #[cfg(feature = "protobuf")]
+    Protobuf,
+    #[cfg(feature = "xlsx-input")]
+    Xlsx,
+}
+
+impl InputFormat {
+    pub fn from_path(path: &str) -> InputFormat {
+        // a URL's query string isn't part of the object name
+        let path = match path.split_once("://") {
+            Some((_, rest)) => rest.split(['?', '#']).next().unwrap_or(rest),
+            None => path,
+        };
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("fix") => InputFormat::Fix,
+            Some("json") | Some("ndjson") | Some("jsonl") => InputFormat::Json,
+            #[cfg(feature = "arrow-io")]
+            Some("arrow") | Some("arrows") | Some("feather") => InputFormat::Arrow,
+            #[cfg(feature = "avro-input")]
+            Some("avro") => InputFormat::Avro,
+            #[cfg(feature = "iso20022")]
+            Some("xml") => InputFormat::Iso20022,
+            #[cfg(feature = "msgpack")]
+            Some("msgpack") | Some("mpk") => InputFormat::MessagePack,
+            #[cfg(feature = "parquet-input")]
+            Some("parquet") => InputFormat::Parquet,
+            #[cfg(feature = "protobuf")]
+            Some("pb") | Some("binpb") => InputFormat::Protobuf,
+            #[cfg(feature = "xlsx-input")]
+            Some("xlsx") => InputFormat::Xlsx,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<InputFormat, &'static str> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "fix" => Ok(InputFormat::Fix),
+            "fixed-width" => Ok(InputFormat::FixedWidth),
+            "json" | "ndjson" => Ok(InputFormat::Json),
+            #[cfg(feature = "arrow-io")]
+            "arrow" => Ok(InputFormat::Arrow),
+            #[cfg(feature = "avro-input")]
+            "avro" => Ok(InputFormat::Avro),
+            #[cfg(feature = "iso20022")]
+            "iso20022" => Ok(InputFormat::Iso20022),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Ok(InputFormat::MessagePack),
+            #[cfg(feature = "parquet-input")]
+            "parquet" => Ok(InputFormat::Parquet),
+            #[cfg(feature = "protobuf")]
+            "protobuf" => Ok(InputFormat::Protobuf),
+            #[cfg(feature = "xlsx-input")]
+            "xlsx" => Ok(InputFormat::Xlsx),
+            _ => Err("Unknown input format"),
+        }
+    }
+}
+
+pub enum Input {
+    Mapped(Cursor<Mmap>),
+    Stream(Box<dyn Read + Send>),
+}
+
+impl Input {
+    // Regular files are memory-mapped so the csv reader pulls straight from
+    // the page cache. Anything that can't be mapped (stdin via "-", pipes,
+    // special files) falls back to plain reads, as do URLs with the
+    // `url-input` feature.
+    pub fn open(path: &str) -> io::Result<Input> {
+        if path == "-" {
+            return Ok(Input::Stream(Box::new(io::stdin())));
+        }
+        #[cfg(feature = "url-input")]
+        if crate::url_input::is_url(path) {
+            return crate::url_input::open(path).map(Input::Stream);
+        }
+        let file = File::open(path)?;
+        if !file.metadata()?.is_file() {
+            return Ok(Input::Stream(Box::new(file)));
+        }
+        // Safety: the mapping is read-only and only lives as long as the
+        // run; the input file is not expected to be modified while processed.
+        match unsafe { Mmap::map(&file) } {
+            Ok(map) => Ok(Input::Mapped(Cursor::new(map))),
+            Err(_) => Ok(Input::Stream(Box::new(file))),
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Input::Mapped(map) => Some(map.get_ref()),
+            Input::Stream(_) => None,
+        }
+    }
+
+    // Formats that need the whole document (e.g. a JSON array) use the
+    // mapping directly and only buffer streamed input.
+    pub fn with_bytes<T>(mut self, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        if let Some(bytes) = self.as_bytes() {
+            return Ok(f(bytes));
+        }
+        let mut buf = Vec::new();
+        self.read_to_end(&mut buf)?;
+        Ok(f(&buf))
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Mapped(map) => map.read(buf),
+            Input::Stream(stream) => stream.read(buf),
+        }
+    }
+}
+
+// Applies a whole input file (or "-" for stdin, or a URL with the
+// `url-input` feature) to the engine, as the CLI does. The format comes from
+// the file extension unless given.
+pub fn read_input(
+    engine: &mut Engine,
+    input: &str,
+    format: Option<InputFormat>,
+    config: &Config,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let format = format.unwrap_or_else(|| InputFormat::from_path(input));
+    let _span = tracing::info_span!("read_input", input, format = ?format).entered();
+    match format {
+        InputFormat::Csv => {
+            let dialect = &config.csv;
+            pipeline::process(Input::open(input)?, dialect, dialect.parsers(), engine)?
+        }
+        InputFormat::Fix => {
+            Input::open(input)?.with_bytes(|bytes| engine.process_fix(bytes, &config.fix))??
+        }
+        InputFormat::FixedWidth => {
+            let layout = config
+                .fixed_width
+                .as_ref()
+                .ok_or("fixed-width input needs a [fixed_width] section in --config")?;
+            Input::open(input)?.with_bytes(|bytes| engine.process_fixed_width(bytes, layout))??
+        }
+        InputFormat::Json => {
+            Input::open(input)?.with_bytes(|bytes| engine.process_json(bytes))??
+        }
+        #[cfg(feature = "arrow-io")]
+        InputFormat::Arrow => {
+            Input::open(input)?.with_bytes(|bytes| engine.process_arrow_ipc(bytes))??
+        }
+        #[cfg(feature = "avro-input")]
+        InputFormat::Avro => engine.process_avro(Input::open(input)?)?,
+        #[cfg(feature = "iso20022")]
+        InputFormat::Iso20022 => Input::open(input)?
+            .with_bytes(|bytes| engine.process_iso20022(bytes, &config.iso20022))??,
+        #[cfg(feature = "msgpack")]
+        InputFormat::MessagePack => {
+            Input::open(input)?.with_bytes(|bytes| engine.process_msgpack(bytes))??
+        }
+        #[cfg(feature = "parquet-input")]
+        InputFormat::Parquet => engine.process_parquet(std::fs::File::open(input)?)?,
+        #[cfg(feature = "protobuf")]
+        InputFormat::Protobuf => {
+            Input::open(input)?.with_bytes(|bytes| engine.process_protobuf(bytes))??
+        }
+        #[cfg(feature = "xlsx-input")]
+        InputFormat::Xlsx => engine.process_xlsx(
+            std::fs::File::open(input)?,
+            &config.xlsx.clone().unwrap_or_default(),
+        )?,
+    }
+    Ok(())
+}
+
+// `read_input` for the client workers behind `router`: only CSV input can be
+// split by client before it's applied.
+pub fn route_input(
+    router: &mut Router,
+    engine: &mut Engine,
+    input: &str,
+    format: Option<InputFormat>,
+    config: &Config,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let format = format.unwrap_or_else(|| InputFormat::from_path(input));
+    let _span = tracing::info_span!("route_input", input, format = ?format).entered();
+    if format != InputFormat::Csv {
+        return Err(format!("--workers only reads csv input, not {}", input).into());
+    }
+    actor::route_csv(router, engine, Input::open(input)?, &config.csv)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Input, InputFormat};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::Client;
+    use std::fs;
+
+    #[test]
+    fn test_mapped_input() {
+        let path = std::env::temp_dir().join("payment_engine_mapped_input.csv");
+        fs::write(&path, "type,client,tx,amount\ndeposit,1,1,1.5\n").unwrap();
+        let input = Input::open(path.to_str().unwrap()).unwrap();
+        assert!(input.as_bytes().is_some());
+        let mut engine = Engine::new();
+        engine.process_csv(input).unwrap();
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(15000)
+        );
+
+        fs::remove_file(&path).unwrap();
+        assert!(Input::open("-").unwrap().as_bytes().is_none());
+    }
+
+    #[test]
+    fn test_input_format() {
+        assert_eq!(InputFormat::from_path("tx.csv"), InputFormat::Csv);
+        assert_eq!(InputFormat::from_path("tx.ndjson"), InputFormat::Json);
+        assert_eq!(InputFormat::from_path("-"), InputFormat::Csv);
+        assert_eq!("json".parse(), Ok(InputFormat::Json));
+        assert!("xml".parse::<InputFormat>().is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::amount::Amount;
+use crate::audit::Balance;
+use crate::funds::FundingStates;
+use crate::transactions::{Client, Tx, TxType};
+
+// What must hold for an account after every transaction. `Amount`s can't
+// go negative, but its subtraction clamps instead of failing, so a held or
+// available balance that should have gone below zero shows up as a
+// balance that moved by less than the transaction says.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Invariant {
+    // available and held moved by exactly what the transaction says, so
+    // neither went below zero and the total moved with them
+    Balances,
+    // an account frozen before the transaction kept its balances
+    FrozenUnchanged,
+    // a rejected transaction left the balances alone
+    RejectedUnchanged,
+}
+
+impl Invariant {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Invariant::Balances => "balances",
+            Invariant::FrozenUnchanged => "frozen_unchanged",
+            Invariant::RejectedUnchanged => "rejected_unchanged",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Violation {
+    pub invariant: Invariant,
+    pub client: Client,
+    pub tx: Tx,
+    pub r#type: TxType,
+    pub before: Balance,
+    pub after: Balance,
+}
+
+// How much available and held should move, in `Amount` units.
+fn expected(r#type: TxType, amount: Amount) -> (i128, i128) {
+    let amount = i128::from(amount.0);
+    match r#type {
+        TxType::Deposit | TxType::Credit | TxType::Settle => (amount, 0),
+        TxType::Withdrawal => (-amount, 0),
+        TxType::Dispute => (-amount, amount),
+        TxType::Resolve => (amount, -amount),
+        TxType::Chargeback => (0, -amount),
+    }
+}
+
+// The first invariant the transaction broke, if any. `amount` is what it
+// moved: its own, or the disputed transaction's.
+pub(crate) fn check(
+    r#type: TxType,
+    amount: Option<Amount>,
+    applied: bool,
+    before: &Balance,
+    after: &Balance,
+) -> Option<Invariant> {
+    let moved = (
+        i128::from(after.available.0) - i128::from(before.available.0),
+        i128::from(after.held.0) - i128::from(before.held.0),
+    );
+    if !applied {
+        return (moved != (0, 0)).then_some(Invariant::RejectedUnchanged);
+    }
+    if before.state == FundingStates::Frozen && moved != (0, 0) {
+        return Some(Invariant::FrozenUnchanged);
+    }
+    match amount {
+        Some(amount) if moved != expected(r#type, amount) => Some(Invariant::Balances),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, Invariant};
+    use crate::amount::Amount;
+    use crate::audit::Balance;
+    use crate::engine::Engine;
+    use crate::transactions::{Client, TxType};
+
+    #[test]
+    fn test_invariants() {
+        let mut engine = Engine::builder().check_invariants(true).build();
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,2.0\nwithdrawal,1,2,1.5\nwithdrawal,1,3,5.0\nresolve,1,1,\n\
+            deposit,2,4,1.0\ndispute,2,4,\nchargeback,2,4,\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        assert!(engine.violations().is_empty());
+
+        // disputing more than is available is turned away rather than
+        // taking it from available
+        engine
+            .process_csv("type,client,tx,amount\ndispute,1,1,\n".as_bytes())
+            .unwrap();
+        assert!(engine.violations().is_empty());
+        let account = engine.account(Client(1)).unwrap();
+        let before = Balance::from(account);
+        let clamped = Balance {
+            held: Amount::new(20000),
+            ..before
+        };
+        assert_eq!(
+            check(
+                TxType::Dispute,
+                Some(Amount::new(20000)),
+                true,
+                &before,
+                &clamped
+            ),
+            Some(Invariant::Balances)
+        );
+        assert_eq!(
+            check(TxType::Dispute, None, false, &before, &clamped),
+            Some(Invariant::RejectedUnchanged)
+        );
+    }
+}
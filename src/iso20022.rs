@@ -0,0 +1,304 @@
+use crate::amount::Amount;
+use crate::engine::Engine;
+use crate::transactions::{Client, TransactionRecord, Tx, TxType};
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+// Bank account identifiers (IBAN or proprietary `Othr/Id`) are mapped to
+// clients through the `[iso20022.accounts]` config table; identifiers that
+// are plain numbers may also be used as the client id directly.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Iso20022Config {
+    pub accounts: HashMap<String, u32>,
+}
+
+#[derive(Debug)]
+pub enum Iso20022Error {
+    Xml(quick_xml::Error),
+    UnknownMessage(String),
+    Entry { position: u64, reason: String },
+}
+
+impl fmt::Display for Iso20022Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Iso20022Error::Xml(err) => write!(f, "{}", err),
+            Iso20022Error::UnknownMessage(name) => write!(
+                f,
+                "unsupported ISO 20022 message `{}` (expected pain.001 or camt.053)",
+                name
+            ),
+            Iso20022Error::Entry { position, reason } => {
+                write!(f, "entry ending at byte {}: {}", position, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Iso20022Error {}
+
+impl From<quick_xml::Error> for Iso20022Error {
+    fn from(err: quick_xml::Error) -> Iso20022Error {
+        Iso20022Error::Xml(err)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Message {
+    // pain.001: payments initiated from the debtor account, i.e. withdrawals
+    Initiation,
+    // camt.053: booked statement entries, credits and debits
+    Statement,
+}
+
+#[derive(Default)]
+struct Entry {
+    amount: Option<String>,
+    credit: Option<bool>,
+    end_to_end_id: Option<String>,
+    pending: bool,
+}
+
+fn ends_with(path: &[String], suffix: &[&str]) -> bool {
+    path.len() >= suffix.len() && path[path.len() - suffix.len()..].iter().eq(suffix)
+}
+
+fn is_account_id(path: &[String], account: &str) -> bool {
+    ends_with(path, &[account, "Id", "IBAN"]) || ends_with(path, &[account, "Id", "Othr", "Id"])
+}
+
+impl Iso20022Config {
+    fn client(&self, account: Option<&str>) -> Result<Client, String> {
+        let account = account.ok_or("no account identifier before this entry")?;
+        self.accounts
+            .get(account)
+            .copied()
+            .or_else(|| account.parse().ok())
+            .map(Client)
+            .ok_or_else(|| format!("account `{}` is not mapped to a client", account))
+    }
+
+    fn record(
+        &self,
+        message: Message,
+        account: Option<&str>,
+        entry: Entry,
+    ) -> Result<TransactionRecord, String> {
+        let r#type = match (message, entry.credit) {
+            (Message::Initiation, _) => TxType::Withdrawal,
+            (Message::Statement, Some(true)) => TxType::Deposit,
+            (Message::Statement, Some(false)) => TxType::Withdrawal,
+            (Message::Statement, None) => return Err("entry has no CdtDbtInd".to_string()),
+        };
+        let id = entry.end_to_end_id.ok_or("entry has no EndToEndId")?;
+        let tx = id
+            .parse()
+            .map_err(|_| format!("EndToEndId `{}` is not a numeric transaction id", id))?;
+        let amount = entry.amount.ok_or("entry has no amount")?;
+        let amount =
+            Amount::from_str(&amount).map_err(|reason| format!("{}: {}", reason, amount))?;
+        Ok(TransactionRecord {
+            r#type,
+            client: self.client(account)?,
+            tx: Tx(tx),
+            amount: Some(amount),
+        })
+    }
+}
+
+impl Engine {
+    // Streams a pain.001 (customer credit transfer initiation) or camt.053
+    // (bank to customer statement) document. Each credit transfer or booked
+    // statement entry becomes one deposit/withdrawal, with its end-to-end id
+    // as the `Tx`; pending (`PDNG`) statement entries are skipped.
+    pub fn process_iso20022(
+        &mut self,
+        input: &[u8],
+        config: &Iso20022Config,
+    ) -> Result<(), Iso20022Error> {
+        let mut reader = Reader::from_reader(input);
+        reader.config_mut().trim_text(true);
+        let mut path: Vec<String> = Vec::new();
+        let mut text = String::new();
+        let mut message = None;
+        let mut account = None;
+        let mut entry = Entry::default();
+
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => {
+                    let name = start.local_name().as_ref().to_string();
+                    if path.len() == 1 {
+                        message = match name.as_str() {
+                            "CstmrCdtTrfInitn" => Some(Message::Initiation),
+                            "BkToCstmrStmt" => Some(Message::Statement),
+                            _ => return Err(Iso20022Error::UnknownMessage(name)),
+                        };
+                    }
+                    path.push(name);
+                    text.clear();
+                }
+                Event::Empty(empty) if path.len() == 1 => {
+                    let name = empty.local_name().as_ref().to_string();
+                    return Err(Iso20022Error::UnknownMessage(name));
+                }
+                Event::Text(content) => text.push_str(&content.xml10_content()),
+                Event::GeneralRef(reference) => {
+                    if let Some(c) = reference.resolve_char_ref()? {
+                        text.push(c);
+                    } else if let Some(s) = resolve_predefined_entity(&reference) {
+                        text.push_str(s);
+                    }
+                }
+                Event::End(_) => {
+                    let value = text.trim();
+                    match message {
+                        Some(Message::Initiation) if is_account_id(&path, "DbtrAcct") => {
+                            account = Some(value.to_string())
+                        }
+                        Some(Message::Statement) if is_account_id(&path, "Acct") => {
+                            account = Some(value.to_string())
+                        }
+                        _ => {}
+                    }
+                    if ends_with(&path, &["CdtTrfTxInf", "Amt", "InstdAmt"])
+                        || ends_with(&path, &["Ntry", "Amt"])
+                    {
+                        entry.amount = Some(value.to_string());
+                    } else if ends_with(&path, &["Ntry", "CdtDbtInd"]) {
+                        entry.credit = Some(value == "CRDT");
+                    } else if ends_with(&path, &["Ntry", "Sts"])
+                        || ends_with(&path, &["Ntry", "Sts", "Cd"])
+                    {
+                        entry.pending |= value == "PDNG";
+                    } else if ends_with(&path, &["EndToEndId"]) && entry.end_to_end_id.is_none() {
+                        entry.end_to_end_id = Some(value.to_string());
+                    }
+
+                    let done = match message {
+                        Some(Message::Initiation) => ends_with(&path, &["CdtTrfTxInf"]),
+                        Some(Message::Statement) => ends_with(&path, &["Ntry"]),
+                        None => false,
+                    };
+                    if done {
+                        let entry = std::mem::take(&mut entry);
+                        if !entry.pending {
+                            let record = config
+                                .record(message.unwrap(), account.as_deref(), entry)
+                                .map_err(|reason| Iso20022Error::Entry {
+                                    position: reader.buffer_position(),
+                                    reason,
+                                })?;
+                            self.process(&record);
+                        }
+                    }
+                    path.pop();
+                    text.clear();
+                }
+                Event::Eof => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Iso20022Config;
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::Client;
+
+    const STATEMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.08">
+  <BkToCstmrStmt>
+    <Stmt>
+      <Acct><Id><IBAN>DE89370400440532013000</IBAN></Id></Acct>
+      <Ntry>
+        <Amt Ccy="EUR">100.50</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+        <NtryDtls><TxDtls><Refs><EndToEndId>1</EndToEndId></Refs></TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">20.25</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+        <NtryDtls><TxDtls><Refs><EndToEndId>2</EndToEndId></Refs></TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">5.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>PDNG</Cd></Sts>
+        <NtryDtls><TxDtls><Refs><EndToEndId>3</EndToEndId></Refs></TxDtls></NtryDtls>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+
+    const INITIATION: &str = r#"<Document xmlns="urn:iso:std:iso:20022:tech:xsd:pain.001.001.09">
+  <CstmrCdtTrfInitn>
+    <GrpHdr><MsgId>MSG-1</MsgId></GrpHdr>
+    <PmtInf>
+      <DbtrAcct><Id><Othr><Id>7</Id></Othr></Id></DbtrAcct>
+      <CdtTrfTxInf>
+        <PmtId><InstrId>A</InstrId><EndToEndId>10</EndToEndId></PmtId>
+        <Amt><InstdAmt Ccy="EUR">1.5</InstdAmt></Amt>
+      </CdtTrfTxInf>
+      <CdtTrfTxInf>
+        <PmtId><EndToEndId>INV&amp;11</EndToEndId></PmtId>
+        <Amt><InstdAmt Ccy="EUR">1.0</InstdAmt></Amt>
+      </CdtTrfTxInf>
+    </PmtInf>
+  </CstmrCdtTrfInitn>
+</Document>"#;
+
+    #[test]
+    fn test_statement() {
+        let mut config = Iso20022Config::default();
+        config
+            .accounts
+            .insert("DE89370400440532013000".to_string(), 3);
+        let mut engine = Engine::new();
+        engine
+            .process_iso20022(STATEMENT.as_bytes(), &config)
+            .unwrap();
+        let funds = engine.account(Client(3)).unwrap();
+        assert_eq!(funds.available, Amount::new(802500));
+        assert_eq!(engine.metrics().records_read, 2);
+
+        let err = Engine::new()
+            .process_iso20022(STATEMENT.as_bytes(), &Iso20022Config::default())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("account `DE89370400440532013000` is not mapped to a client"));
+    }
+
+    #[test]
+    fn test_initiation() {
+        let mut engine = Engine::new();
+        engine
+            .process_csv("type,client,tx,amount\ndeposit,7,1,10\n".as_bytes())
+            .unwrap();
+        let err = engine
+            .process_iso20022(INITIATION.as_bytes(), &Iso20022Config::default())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("EndToEndId `INV&11` is not a numeric transaction id"));
+        assert_eq!(
+            engine.account(Client(7)).unwrap().available,
+            Amount::new(85000)
+        );
+        assert!(Engine::new()
+            .process_iso20022(b"<Document><Other/></Document>", &Iso20022Config::default())
+            .is_err());
+    }
+}
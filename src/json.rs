@@ -0,0 +1,101 @@
+use crate::engine::Engine;
+use crate::transactions::{amount_field, Client, RowRecord, TransactionRecord, Tx, TxType};
+use serde::de::Error;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::convert::TryFrom;
+
+// Amounts may arrive as JSON strings or numbers; keeping the raw text lets
+// both go through the same decimal parsing as CSV fields instead of
+// round-tripping numbers through f64.
+#[derive(Deserialize)]
+struct JsonRow<'a> {
+    r#type: TxType,
+    client: Client,
+    tx: Tx,
+    #[serde(borrow, default)]
+    amount: Option<&'a RawValue>,
+}
+
+impl<'a> JsonRow<'a> {
+    fn into_row(self) -> serde_json::Result<RowRecord<'a>> {
+        let raw = self.amount.map(RawValue::get).unwrap_or("null");
+        let text = raw
+            .strip_prefix('"')
+            .and_then(|raw| raw.strip_suffix('"'))
+            .unwrap_or(raw);
+        let amount = amount_field(text).map_err(serde_json::Error::custom)?;
+        Ok(RowRecord::new(self.r#type, self.client, self.tx, amount))
+    }
+}
+
+// A single JSON object, as carried by one message on a bus.
+pub fn parse_record(input: &[u8]) -> serde_json::Result<TransactionRecord> {
+    let row: JsonRow = serde_json::from_slice(input)?;
+    TransactionRecord::try_from(row.into_row()?).map_err(serde_json::Error::custom)
+}
+
+impl Engine {
+    // Accepts either a single JSON array of transactions or newline
+    // delimited objects (NDJSON), told apart by the first character.
+    pub fn process_json(&mut self, input: &[u8]) -> serde_json::Result<()> {
+        let is_array = input.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'[');
+        if is_array {
+            let rows: Vec<JsonRow> = serde_json::from_slice(input)?;
+            for row in rows {
+                let _ = self.process_row(row.into_row()?);
+            }
+        } else {
+            for line in input.split(|b| *b == b'\n') {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let row: JsonRow = serde_json::from_slice(line)?;
+                let _ = self.process_row(row.into_row()?);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::transactions::Client;
+
+    #[test]
+    fn test_json_array() {
+        let input = r#"[
+            {"type": "deposit", "client": 1, "tx": 1, "amount": 1.5},
+            {"type": "deposit", "client": 2, "tx": 2, "amount": "2.0"},
+            {"type": "dispute", "client": 2, "tx": 2, "amount": null},
+            {"type": "chargeback", "client": 2, "tx": 2}
+        ]"#;
+        let mut engine = Engine::new();
+        engine.process_json(input.as_bytes()).unwrap();
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(15000)
+        );
+        assert_eq!(
+            engine.account(Client(2)).unwrap().state,
+            FundingStates::Frozen
+        );
+    }
+
+    #[test]
+    fn test_ndjson() {
+        let input = "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":3}\n\n{\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"1.25\"}\n";
+        let mut engine = Engine::new();
+        engine.process_json(input.as_bytes()).unwrap();
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(17500)
+        );
+
+        let bad = "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":1.23456}\n";
+        assert!(Engine::new().process_json(bad.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::stream::{default_snapshot_every, Delivery, Payload, Snapshots, StreamApplier};
+use crate::tenant::{Tenants, DEFAULT_TENANT};
+use crate::wal::Position;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::message::Message;
+use rdkafka::{Offset, TopicPartitionList};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// The `[kafka]` config section. `properties` are passed to librdkafka as is
+// (e.g. `"security.protocol" = "SSL"`); auto commit is always turned off,
+// offsets are committed after the WAL is flushed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct KafkaConfig {
+    pub brokers: String,
+    // a `^`-prefixed topic is a regex subscribing to every matching topic
+    pub topic: String,
+    // apply each topic's records to a tenant named after it (needs
+    // `--tenants`) instead of the default one
+    #[serde(default)]
+    pub tenant_per_topic: bool,
+    #[serde(default = "default_group_id")]
+    pub group_id: String,
+    #[serde(default)]
+    pub payload: Payload,
+    #[serde(default)]
+    pub delivery: Delivery,
+    pub wal: Option<PathBuf>,
+    #[serde(default = "default_flush_every")]
+    pub flush_every: usize,
+    // also keep a snapshot of the ledgers here (needs `wal`), so a restart
+    // doesn't replay everything ever consumed
+    pub snapshot: Option<PathBuf>,
+    #[serde(default = "default_snapshot_every")]
+    pub snapshot_every: usize,
+    // stop once no message arrived for this long; run forever when unset
+    pub idle_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+fn default_group_id() -> String {
+    "payment_engine".to_string()
+}
+
+fn default_flush_every() -> usize {
+    1000
+}
+
+fn stream_name(topic: &str, partition: i32) -> String {
+    format!("kafka/{}/{}", topic, partition)
+}
+
+// Flushes the WAL, then commits the next offset of every partition applied
+// since the last commit.
+fn commit(
+    consumer: &BaseConsumer,
+    applier: &mut StreamApplier,
+    pending: &mut BTreeMap<(String, i32), i64>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    applier.flush()?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let mut offsets = TopicPartitionList::new();
+    for ((topic, partition), offset) in pending.iter() {
+        offsets.add_partition_offset(topic, *partition, Offset::Offset(offset + 1))?;
+    }
+    consumer.commit(&offsets, CommitMode::Sync)?;
+    pending.clear();
+    Ok(())
+}
+
+// Consumes `config.topic` until it goes idle (or forever), applying each
+// partition's records in offset order. Messages that can't be decoded are
+// logged as warnings and committed past, so one bad payload can't stall a
+// partition.
+pub fn consume(
+    tenants: Tenants,
+    config: &KafkaConfig,
+) -> Result<Tenants, Box<dyn Error + Send + Sync>> {
+    if config.tenant_per_topic && !tenants.is_isolated() {
+        return Err("tenant_per_topic needs --tenants".into());
+    }
+    let mut applier = StreamApplier::open(
+        tenants,
+        config.wal.as_deref(),
+        config.snapshot.clone().map(|path| Snapshots {
+            path,
+            every: config.snapshot_every,
+        }),
+        config.delivery,
+        config.flush_every,
+    )?;
+    let mut client = ClientConfig::new();
+    for (key, value) in &config.properties {
+        client.set(key, value);
+    }
+    let consumer: BaseConsumer = client
+        .set("bootstrap.servers", &config.brokers)
+        .set("group.id", &config.group_id)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()?;
+    consumer.subscribe(&[&config.topic])?;
+
+    let idle_timeout = config.idle_timeout_ms.map(Duration::from_millis);
+    let mut last_message = Instant::now();
+    let mut pending = BTreeMap::new();
+    loop {
+        let message = match consumer.poll(Duration::from_millis(100)) {
+            Some(message) => message?,
+            None => {
+                commit(&consumer, &mut applier, &mut pending)?;
+                if idle_timeout.is_some_and(|timeout| last_message.elapsed() >= timeout) {
+                    break;
+                }
+                continue;
+            }
+        };
+        last_message = Instant::now();
+        let (partition, offset) = (message.partition(), message.offset());
+        match config.payload.decode(message.payload().unwrap_or_default()) {
+            Ok(record) => {
+                let stream = stream_name(message.topic(), partition);
+                // topic names only use characters tenant names allow
+                let tenant = if config.tenant_per_topic {
+                    message.topic()
+                } else {
+                    DEFAULT_TENANT
+                };
+                applier.apply(tenant, Position { stream, offset }, &record)?;
+            }
+            Err(err) => tracing::warn!(
+                stream = %stream_name(message.topic(), partition),
+                offset,
+                error = %err,
+                "skipping message that can't be decoded"
+            ),
+        }
+        pending.insert((message.topic().to_string(), partition), offset);
+        if applier.should_flush() {
+            commit(&consumer, &mut applier, &mut pending)?;
+        }
+    }
+    commit(&consumer, &mut applier, &mut pending)?;
+    Ok(applier.into_tenants())
+}
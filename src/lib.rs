@@ -1,3 +1,108 @@
-mod amount;
-mod funds;
-mod transactions;
+pub mod actor;
+#[cfg(unix)]
+pub mod admin;
+pub mod aml;
+pub mod amount;
+#[cfg(feature = "amqp")]
+pub mod amqp;
+#[cfg(feature = "arrow-io")]
+pub mod arrow_io;
+pub mod audit;
+pub mod auth;
+#[cfg(feature = "avro-input")]
+pub mod avro_input;
+pub mod bonus;
+pub mod books;
+pub mod columns;
+pub mod concurrent;
+pub mod config;
+pub mod csv_dialect;
+pub mod daily;
+pub mod debt;
+pub mod decoder;
+pub mod denylist;
+#[cfg(feature = "digest")]
+pub mod digest;
+pub mod disputes;
+pub mod dormancy;
+pub mod engine;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fix;
+pub mod fixed_width;
+pub mod fork;
+pub mod funds;
+pub mod gl;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+pub mod hooks;
+pub mod ingest;
+pub mod input;
+pub mod invariants;
+#[cfg(feature = "iso20022")]
+pub mod iso20022;
+pub mod json;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod logging;
+pub mod metrics;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+pub mod mt940;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod observer;
+pub mod ofx;
+pub mod output;
+#[cfg(feature = "parquet-input")]
+pub mod parquet_input;
+pub mod period;
+pub mod pipeline;
+pub mod policy;
+pub mod postings;
+pub mod prometheus;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod qif;
+pub mod reconcile;
+pub mod reference;
+pub mod registry;
+#[cfg(feature = "rest")]
+pub mod rest;
+pub mod risk;
+pub mod rules;
+pub mod schema;
+pub mod score;
+pub mod serve;
+pub mod settlement;
+pub mod shadow;
+pub mod shared;
+pub mod simulation;
+pub mod snapshot;
+pub mod state;
+pub mod statement;
+pub mod stream;
+pub mod subaccount;
+pub mod summary;
+#[cfg(feature = "tcp")]
+pub mod tcp;
+#[cfg(feature = "otlp")]
+pub mod telemetry;
+pub mod tenant;
+pub mod testkit;
+pub mod tiers;
+pub mod transactions;
+pub mod trial_balance;
+#[cfg(feature = "url-input")]
+pub mod url_input;
+pub mod wal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "webhooks")]
+pub mod webhook;
+#[cfg(feature = "xlsx-input")]
+pub mod xlsx_input;
@@ -0,0 +1,116 @@
+use std::io;
+use std::str::FromStr;
+use tracing::Subscriber;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum LogFormat {
+    Text,
+    // one JSON object per line, for log shippers
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<LogFormat, &'static str> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err("Unknown log format"),
+        }
+    }
+}
+
+// A layer writing events at `level` and above to `writer`. The engine
+// reports each rejected transaction at `info` and each applied one at
+// `debug`, with `client`, `tx`, `type` and (when rejected) `reason` fields.
+pub fn layer_with<S, W>(
+    level: LevelFilter,
+    format: LogFormat,
+    writer: W,
+) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(false);
+    match format {
+        LogFormat::Text => layer.with_filter(level).boxed(),
+        LogFormat::Json => layer.json().with_filter(level).boxed(),
+    }
+}
+
+// Logs go to stderr so they never mix with the accounts written to stdout.
+pub fn layer<S>(level: LevelFilter, format: LogFormat) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    layer_with(level, format, io::stderr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{layer_with, LogFormat};
+    use crate::engine::Engine;
+    use serde_json::Value;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::filter::LevelFilter;
+    use tracing_subscriber::fmt::MakeWriter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'w> MakeWriter<'w> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'w self) -> Buffer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_rejections_are_logged() {
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::registry().with(layer_with(
+            LevelFilter::INFO,
+            LogFormat::Json,
+            buffer.clone(),
+        ));
+        tracing::subscriber::with_default(subscriber, || {
+            let csvfile = "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,5.0\n";
+            Engine::new().process_csv(csvfile.as_bytes()).unwrap();
+        });
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // the applied deposit is below the level
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["level"], "INFO");
+        let fields = &events[0]["fields"];
+        assert_eq!(fields["message"], "transaction rejected");
+        assert_eq!(fields["client"], 1);
+        assert_eq!(fields["tx"], 2);
+        assert_eq!(fields["type"], "withdrawal");
+        assert_eq!(fields["reason"], "insufficient_funds");
+    }
+}
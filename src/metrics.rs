@@ -0,0 +1,158 @@
+use crate::transactions::{RejectReason, TxType};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// Upper bounds, in seconds, of the apply latency histogram buckets; slower
+// applies only count towards the total.
+pub const LATENCY_BUCKETS: [f64; 8] = [1e-6, 2.5e-6, 5e-6, 1e-5, 2.5e-5, 5e-5, 1e-4, 1e-3];
+
+// The time now, on platforms with a clock: `Instant::now` panics on
+// wasm32-unknown-unknown, so there rates and latencies go unmeasured.
+pub(crate) fn now() -> Option<Instant> {
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        None
+    } else {
+        Some(Instant::now())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyHistogram {
+    // per bucket rather than cumulative
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+    pub count: u64,
+    pub sum: Duration,
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| secs <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += latency;
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EngineMetrics {
+    pub records_read: u64,
+    pub records_applied: u64,
+    pub rejected: BTreeMap<RejectReason, u64>,
+    // rows skipped by permissive CSV reading, see `CsvDialect`
+    pub rows_malformed: u64,
+    // rows of a type the engine doesn't know, skipped or rejected (see
+    // `UnknownTypes`) rather than stopping the run
+    pub rows_unknown_type: u64,
+    pub applied_by_type: BTreeMap<TxType, u64>,
+    pub rejected_by_type: BTreeMap<TxType, u64>,
+    pub clients: usize,
+    pub log_size: usize,
+    pub records_per_sec: f64,
+    // only kept when the engine was built with `time_applies`
+    pub apply_latency: Option<LatencyHistogram>,
+}
+
+impl EngineMetrics {
+    pub fn records_rejected(&self) -> u64 {
+        self.rejected.values().sum()
+    }
+}
+
+const TX_TYPES: [TxType; 7] = [
+    TxType::Deposit,
+    TxType::Withdrawal,
+    TxType::Dispute,
+    TxType::Resolve,
+    TxType::Chargeback,
+    TxType::Credit,
+    TxType::Settle,
+];
+
+#[derive(Default)]
+pub(crate) struct Counters {
+    read: u64,
+    applied: u64,
+    rejected: BTreeMap<RejectReason, u64>,
+    malformed: u64,
+    unknown_type: u64,
+    // [applied, rejected], indexed by `TxType` discriminant
+    by_type: [[u64; 2]; TX_TYPES.len()],
+    latency: Option<LatencyHistogram>,
+    started: Option<Instant>,
+}
+
+impl Counters {
+    pub(crate) fn timed() -> Counters {
+        Counters {
+            latency: Some(LatencyHistogram::default()),
+            ..Counters::default()
+        }
+    }
+
+    pub(crate) fn times_applies(&self) -> bool {
+        self.latency.is_some()
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        r#type: TxType,
+        result: Result<(), RejectReason>,
+        latency: Option<Duration>,
+    ) {
+        if self.started.is_none() {
+            self.started = now();
+        }
+        self.read += 1;
+        match result {
+            Ok(()) => self.applied += 1,
+            Err(reason) => *self.rejected.entry(reason).or_insert(0) += 1,
+        }
+        self.by_type[r#type as usize][usize::from(result.is_err())] += 1;
+        if let (Some(histogram), Some(latency)) = (&mut self.latency, latency) {
+            histogram.record(latency);
+        }
+    }
+
+    pub(crate) fn record_malformed(&mut self) {
+        self.malformed += 1;
+    }
+
+    pub(crate) fn record_unknown_type(&mut self) {
+        self.unknown_type += 1;
+    }
+
+    // The rate is measured from the first record seen rather than from
+    // construction, so an engine that sat idle doesn't report a low rate.
+    pub(crate) fn sample(&self, clients: usize, log_size: usize) -> EngineMetrics {
+        let elapsed = self
+            .started
+            .map(|started| started.elapsed().as_secs_f64())
+            .unwrap_or_default();
+        let by_type = |outcome: usize| {
+            TX_TYPES
+                .iter()
+                .map(|&r#type| (r#type, self.by_type[r#type as usize][outcome]))
+                .filter(|&(_, count)| count > 0)
+                .collect()
+        };
+        EngineMetrics {
+            records_read: self.read,
+            records_applied: self.applied,
+            rejected: self.rejected.clone(),
+            rows_malformed: self.malformed,
+            rows_unknown_type: self.unknown_type,
+            applied_by_type: by_type(0),
+            rejected_by_type: by_type(1),
+            clients,
+            log_size,
+            records_per_sec: if elapsed > 0.0 {
+                self.read as f64 / elapsed
+            } else {
+                0.0
+            },
+            apply_latency: self.latency.clone(),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::engine::Engine;
+use crate::funds::Funds;
+use crate::output::account_rows;
+use crate::transactions::RowRecord;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Write};
+
+// Values are written back to back with no extra framing; each MessagePack
+// value is self-delimiting, so a stream is just concatenated encodings.
+pub fn write_value<W: Write, T: Serialize + ?Sized>(
+    writer: &mut W,
+    value: &T,
+) -> Result<(), rmp_serde::encode::Error> {
+    value.serialize(&mut rmp_serde::Serializer::new(writer).with_struct_map())
+}
+
+fn next_value_len(input: &[u8]) -> Result<usize, rmp_serde::decode::Error> {
+    let mut skipper = rmp_serde::Deserializer::new(Cursor::new(input));
+    IgnoredAny::deserialize(&mut skipper)?;
+    Ok(skipper.position() as usize)
+}
+
+// The first pass over each value only finds where it ends, so the real
+// decode can borrow strings straight out of `input`.
+pub fn read_values<'a, T: Deserialize<'a>>(
+    mut input: &'a [u8],
+) -> impl Iterator<Item = Result<T, rmp_serde::decode::Error>> + 'a {
+    std::iter::from_fn(move || {
+        if input.is_empty() {
+            return None;
+        }
+        let value = next_value_len(input).and_then(|len| {
+            let (value, rest) = input.split_at(len);
+            input = rest;
+            rmp_serde::from_slice(value)
+        });
+        if value.is_err() {
+            input = &[];
+        }
+        Some(value)
+    })
+}
+
+pub fn write_accounts_msgpack<'a, W, I>(
+    accounts: I,
+    mut writer: W,
+) -> Result<(), rmp_serde::encode::Error>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a Funds>,
+{
+    for row in account_rows(accounts) {
+        write_value(&mut writer, &row)?;
+    }
+    Ok(())
+}
+
+impl Engine {
+    pub fn process_msgpack(&mut self, input: &[u8]) -> Result<(), rmp_serde::decode::Error> {
+        for row in read_values::<RowRecord>(input) {
+            let _ = self.process_row(row?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_values, write_accounts_msgpack, write_value};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::{Client, RowRecord, Tx, TxType};
+
+    #[test]
+    fn test_row_round_trip() {
+        let rows = [
+            RowRecord::new(TxType::Deposit, Client(1), Tx(1), Some("3.5")),
+            RowRecord::new(TxType::Withdrawal, Client(1), Tx(2), Some("1")),
+            RowRecord::new(TxType::Dispute, Client(1), Tx(2), None),
+        ];
+        let mut buf = Vec::new();
+        for row in rows.iter() {
+            write_value(&mut buf, row).unwrap();
+        }
+        let decoded: Vec<RowRecord> = read_values(&buf).map(Result::unwrap).collect();
+        assert_eq!(decoded, rows);
+
+        let mut engine = Engine::new();
+        engine.process_msgpack(&buf).unwrap();
+        let funds = engine.account(Client(1)).unwrap();
+        assert_eq!(funds.held, Amount::new(10000));
+        assert!(Engine::new()
+            .process_msgpack(&buf[..buf.len() - 1])
+            .is_err());
+
+        let mut out = Vec::new();
+        write_accounts_msgpack(engine.accounts(), &mut out).unwrap();
+        let accounts: Vec<serde_json::Value> = read_values(&out).map(Result::unwrap).collect();
+        assert_eq!(
+            accounts,
+            vec![serde_json::json!({
+                "client": 1,
+                "available": funds.available.to_string(),
+                "held": "1.0000",
+                "total": funds.total().to_string(),
+                "locked": false,
+            })]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use crate::amount::Amount;
+use crate::engine::Engine;
+use crate::history::{statement_history, Activity};
+use crate::output::StatementOptions;
+use crate::transactions::TxType;
+use std::io::{self, Write};
+
+// SWIFT amounts use a decimal comma and may drop trailing zeros ("10,5").
+fn swift_amount(amount: Amount) -> String {
+    let text = amount.to_string().replace('.', ",");
+    text.trim_end_matches('0').to_string()
+}
+
+// Disputes, resolves and settles only move funds within the account, so
+// they leave the booked balance alone and produce no statement line. A
+// chargeback is the reversal of the credit it refers to.
+fn statement_line(activity: &Activity) -> Option<(&'static str, &'static str)> {
+    match activity.r#type {
+        TxType::Deposit => Some(("C", "deposit")),
+        TxType::Withdrawal => Some(("D", "withdrawal")),
+        TxType::Credit => Some(("C", "credit")),
+        TxType::Chargeback => Some(("RC", "chargeback")),
+        TxType::Dispute | TxType::Resolve | TxType::Settle => None,
+    }
+}
+
+// Writes one statement per client. The engine starts from empty accounts,
+// so the opening balance is always zero and the closing balance is the
+// account total.
+pub fn write_mt940<W: Write>(
+    engine: &Engine,
+    options: &StatementOptions,
+    mut writer: W,
+) -> io::Result<()> {
+    let history = statement_history(engine)?;
+    let (year, month, day) = options
+        .date()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let date = format!("{:02}{:02}{:02}", year % 100, month, day);
+    let mut accounts: Vec<_> = engine.accounts().collect();
+    accounts.sort_by_key(|funds| funds.client.0);
+
+    for funds in accounts {
+        let client = funds.client.0;
+        write!(writer, ":20:PE{}\r\n:25:{}\r\n:28C:1/1\r\n", client, client)?;
+        write!(writer, ":60F:C{}{}0,\r\n", date, options.currency)?;
+        for activity in history.client(funds.client) {
+            if let Some((mark, description)) = statement_line(activity) {
+                write!(
+                    writer,
+                    ":61:{}{}{}NTRF{}//{}\r\n:86:{} {}\r\n",
+                    date,
+                    mark,
+                    swift_amount(activity.amount),
+                    activity.tx.0,
+                    activity.tx.0,
+                    description,
+                    activity.tx.0,
+                )?;
+            }
+        }
+        write!(
+            writer,
+            ":62F:C{}{}{}\r\n-\r\n",
+            date,
+            options.currency,
+            swift_amount(funds.total())
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_mt940;
+    use crate::engine::Engine;
+    use crate::output::StatementOptions;
+
+    #[test]
+    fn test_write_mt940() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,10.5\ndeposit,1,2,2\nwithdrawal,1,3,1.25\ndispute,1,2,\nchargeback,1,2,\n";
+        let mut engine = Engine::builder().record_history(true).build();
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let options = StatementOptions {
+            currency: "EUR".to_string(),
+            date: Some("2026-10-16".to_string()),
+        };
+        let mut out = Vec::new();
+        write_mt940(&engine, &options, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                ":20:PE1\r\n:25:1\r\n:28C:1/1\r\n:60F:C261016EUR0,\r\n",
+                ":61:261016C10,5NTRF1//1\r\n:86:deposit 1\r\n",
+                ":61:261016C2,NTRF2//2\r\n:86:deposit 2\r\n",
+                ":61:261016D1,25NTRF3//3\r\n:86:withdrawal 3\r\n",
+                ":61:261016RC2,NTRF2//2\r\n:86:chargeback 2\r\n",
+                ":62F:C261016EUR9,25\r\n-\r\n",
+            )
+        );
+
+        assert!(write_mt940(&Engine::new(), &options, Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::stream::{default_snapshot_every, Delivery, Payload, Snapshots, StreamApplier};
+use crate::tenant::{Tenants, DEFAULT_TENANT};
+use crate::wal::Position;
+use async_nats::jetstream::{self, consumer::pull, Message};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// The `[nats]` config section. The stream must already exist; the durable
+// consumer is created on first use and keeps its ack floor on the server, so
+// a restarted engine resumes where the previous one stopped.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NatsConfig {
+    pub url: String,
+    pub stream: String,
+    #[serde(default = "default_consumer")]
+    pub consumer: String,
+    // only consume subjects matching this filter, e.g. `transactions.>`
+    #[serde(default)]
+    pub subject: String,
+    #[serde(default)]
+    pub payload: Payload,
+    #[serde(default)]
+    pub delivery: Delivery,
+    pub wal: Option<PathBuf>,
+    #[serde(default = "default_flush_every")]
+    pub flush_every: usize,
+    // also keep a snapshot of the ledgers here (needs `wal`), so a restart
+    // doesn't replay everything ever consumed
+    pub snapshot: Option<PathBuf>,
+    #[serde(default = "default_snapshot_every")]
+    pub snapshot_every: usize,
+    // stop once no message arrived for this long; run forever when unset
+    pub idle_timeout_ms: Option<u64>,
+}
+
+fn default_consumer() -> String {
+    "payment_engine".to_string()
+}
+
+fn default_flush_every() -> usize {
+    1000
+}
+
+// Acks are held back until the records they carried are in the flushed WAL,
+// so the server redelivers anything the engine could lose in a crash.
+async fn ack(
+    applier: &mut StreamApplier,
+    pending: &mut Vec<Message>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    applier.flush()?;
+    for message in pending.drain(..) {
+        message.ack().await?;
+    }
+    Ok(())
+}
+
+async fn run(
+    mut applier: StreamApplier,
+    config: &NatsConfig,
+) -> Result<Tenants, Box<dyn Error + Send + Sync>> {
+    let client = async_nats::connect(&config.url).await?;
+    let stream = jetstream::new(client).get_stream(&config.stream).await?;
+    let consumer: jetstream::consumer::PullConsumer = stream
+        .get_or_create_consumer(
+            &config.consumer,
+            pull::Config {
+                durable_name: Some(config.consumer.clone()),
+                filter_subject: config.subject.clone(),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let idle_timeout = config.idle_timeout_ms.map(Duration::from_millis);
+    let mut last_message = Instant::now();
+    let mut pending = Vec::new();
+    loop {
+        let mut batch = consumer
+            .batch()
+            .max_messages(config.flush_every.max(1))
+            .expires(Duration::from_millis(500))
+            .messages()
+            .await?;
+        let mut received = false;
+        while let Some(message) = batch.next().await {
+            let message = message?;
+            received = true;
+            let info = message.info()?;
+            let position = Position {
+                stream: format!("nats/{}", info.stream),
+                offset: info.stream_sequence as i64,
+            };
+            match config.payload.decode(&message.payload) {
+                Ok(record) => {
+                    applier.apply(DEFAULT_TENANT, position, &record)?;
+                }
+                Err(err) => tracing::warn!(
+                    stream = %position.stream,
+                    sequence = position.offset,
+                    error = %err,
+                    "skipping message that can't be decoded"
+                ),
+            }
+            pending.push(message);
+        }
+        ack(&mut applier, &mut pending).await?;
+        if received {
+            last_message = Instant::now();
+        } else if idle_timeout.is_some_and(|timeout| last_message.elapsed() >= timeout) {
+            break;
+        } else {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+    Ok(applier.into_tenants())
+}
+
+// Consumes `config.stream` through a durable pull consumer until it goes idle
+// (or forever), acking each message once it has been applied and logged.
+// Messages that can't be decoded are logged as warnings and acked, so one bad
+// payload isn't redelivered forever.
+pub fn consume(
+    tenants: Tenants,
+    config: &NatsConfig,
+) -> Result<Tenants, Box<dyn Error + Send + Sync>> {
+    let applier = StreamApplier::open(
+        tenants,
+        config.wal.as_deref(),
+        config.snapshot.clone().map(|path| Snapshots {
+            path,
+            every: config.snapshot_every,
+        }),
+        config.delivery,
+        config.flush_every,
+    )?;
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(applier, config))
+}
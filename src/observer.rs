@@ -0,0 +1,86 @@
+use crate::funds::Funds;
+use crate::transactions::TransactionRecord;
+
+// Turns in an account's life that business workflows hang off, e.g. a
+// welcome email or opening a case.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum AccountChange {
+    // the client's first deposit opened the account
+    Opened,
+    // funds are held for a dispute where none were before
+    EnteredDispute,
+    // a dispute held more than was available, so the balance would have
+    // gone negative; only with debt tracking, which lends the account the
+    // difference (see `debt`), as otherwise such a dispute is rejected
+    WentNegative,
+    // by a chargeback, a rule or the denylist
+    Frozen,
+}
+
+impl AccountChange {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AccountChange::Opened => "opened",
+            AccountChange::EnteredDispute => "entered_dispute",
+            AccountChange::WentNegative => "went_negative",
+            AccountChange::Frozen => "frozen",
+        }
+    }
+}
+
+// A change, with the record that caused it and the account as it is now.
+#[derive(Debug)]
+pub struct AccountEvent<'a> {
+    pub change: AccountChange,
+    pub record: &'a TransactionRecord,
+    pub funds: &'a Funds,
+}
+
+// Told of account changes as the engine makes them (see
+// `EngineBuilder::observer`), in the order they're listed in
+// `AccountChange` when one record makes several. Observers run inline, so
+// slow work belongs on another thread; `risk::RiskMonitor` is the
+// channel-based sink for alerting.
+pub trait AccountObserver: Send + Sync {
+    fn notify(&self, event: &AccountEvent<'_>);
+}
+
+impl<F> AccountObserver for F
+where
+    F: Fn(&AccountEvent<'_>) + Send + Sync,
+{
+    fn notify(&self, event: &AccountEvent<'_>) {
+        self(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccountChange, AccountEvent};
+    use crate::engine::Engine;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_account_observer() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let mut engine = Engine::builder()
+            .track_debt(true)
+            .observer(move |event: &AccountEvent<'_>| {
+                let entry = (event.change, event.record.tx.0, event.funds.client.0);
+                sink.lock().unwrap().push(entry);
+            })
+            .build();
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,1,2,1.0\nwithdrawal,1,3,2.5\ndispute,1,1,\nchargeback,1,1,\ndeposit,1,4,1.0\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (AccountChange::Opened, 1, 1),
+                (AccountChange::EnteredDispute, 1, 1),
+                (AccountChange::WentNegative, 1, 1),
+                (AccountChange::Frozen, 1, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use crate::amount::Amount;
+use crate::engine::Engine;
+use crate::history::statement_history;
+use crate::output::StatementOptions;
+use crate::transactions::TxType;
+use std::io::{self, Write};
+
+fn signed(amount: Amount, credit: bool) -> String {
+    if credit {
+        amount.to_string()
+    } else {
+        format!("-{}", amount)
+    }
+}
+
+// Writes an OFX 2 bank statement response with one statement per client:
+// the booked activity as STMTTRN entries, the total as the ledger balance
+// and the available funds as the available balance. FITIDs have to be
+// unique per account, so a chargeback gets a suffix to tell it apart from
+// the deposit it reverses.
+pub fn write_ofx<W: Write>(
+    engine: &Engine,
+    options: &StatementOptions,
+    mut writer: W,
+) -> io::Result<()> {
+    let history = statement_history(engine)?;
+    let (year, month, day) = options
+        .date()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let status = "<STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>";
+    let mut accounts: Vec<_> = engine.accounts().collect();
+    accounts.sort_by_key(|funds| funds.client.0);
+
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        writer,
+        "<?OFX OFXHEADER=\"200\" VERSION=\"220\" SECURITY=\"NONE\" OLDFILEUID=\"NONE\" NEWFILEUID=\"NONE\"?>"
+    )?;
+    writeln!(writer, "<OFX>")?;
+    writeln!(
+        writer,
+        "<SIGNONMSGSRSV1><SONRS>{}<DTSERVER>{}</DTSERVER><LANGUAGE>ENG</LANGUAGE></SONRS></SIGNONMSGSRSV1>",
+        status, date
+    )?;
+    writeln!(writer, "<BANKMSGSRSV1>")?;
+    for funds in accounts {
+        let client = funds.client.0;
+        writeln!(
+            writer,
+            "<STMTTRNRS><TRNUID>{}</TRNUID>{}<STMTRS>",
+            client, status
+        )?;
+        writeln!(writer, "<CURDEF>{}</CURDEF>", options.currency)?;
+        writeln!(
+            writer,
+            "<BANKACCTFROM><BANKID>PAYMENTENGINE</BANKID><ACCTID>{}</ACCTID><ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM>",
+            client
+        )?;
+        writeln!(
+            writer,
+            "<BANKTRANLIST><DTSTART>{}</DTSTART><DTEND>{}</DTEND>",
+            date, date
+        )?;
+        for activity in history.client(funds.client) {
+            let credit = match activity.is_credit() {
+                Some(credit) => credit,
+                None => continue,
+            };
+            let (kind, fitid) = match activity.r#type {
+                TxType::Chargeback => ("DEBIT", format!("{}-CB", activity.tx.0)),
+                _ if credit => ("CREDIT", activity.tx.0.to_string()),
+                _ => ("DEBIT", activity.tx.0.to_string()),
+            };
+            writeln!(
+                writer,
+                "<STMTTRN><TRNTYPE>{}</TRNTYPE><DTPOSTED>{}</DTPOSTED><TRNAMT>{}</TRNAMT><FITID>{}</FITID><NAME>{:?}</NAME></STMTTRN>",
+                kind,
+                date,
+                signed(activity.amount, credit),
+                fitid,
+                activity.r#type
+            )?;
+        }
+        writeln!(writer, "</BANKTRANLIST>")?;
+        writeln!(
+            writer,
+            "<LEDGERBAL><BALAMT>{}</BALAMT><DTASOF>{}</DTASOF></LEDGERBAL>",
+            funds.total(),
+            date
+        )?;
+        writeln!(
+            writer,
+            "<AVAILBAL><BALAMT>{}</BALAMT><DTASOF>{}</DTASOF></AVAILBAL>",
+            funds.available, date
+        )?;
+        writeln!(writer, "</STMTRS></STMTTRNRS>")?;
+    }
+    writeln!(writer, "</BANKMSGSRSV1>")?;
+    writeln!(writer, "</OFX>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_ofx;
+    use crate::engine::Engine;
+    use crate::output::StatementOptions;
+
+    #[test]
+    fn test_write_ofx() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,10.5\ndeposit,1,2,2\ndispute,1,2,\nchargeback,1,2,\n";
+        let mut engine = Engine::builder().record_history(true).build();
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let options = StatementOptions {
+            currency: "EUR".to_string(),
+            date: Some("2026-10-16".to_string()),
+        };
+        let mut out = Vec::new();
+        write_ofx(&engine, &options, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("<CURDEF>EUR</CURDEF>"));
+        assert!(out.contains("<TRNTYPE>CREDIT</TRNTYPE><DTPOSTED>20261016</DTPOSTED><TRNAMT>2.0000</TRNAMT><FITID>2</FITID>"));
+        assert!(out.contains("<TRNTYPE>DEBIT</TRNTYPE><DTPOSTED>20261016</DTPOSTED><TRNAMT>-2.0000</TRNAMT><FITID>2-CB</FITID><NAME>Chargeback</NAME>"));
+        assert!(out.contains("<LEDGERBAL><BALAMT>10.5000</BALAMT>"));
+        assert_eq!(out.matches("<STMTTRN>").count(), 3);
+    }
+}
@@ -0,0 +1,413 @@
+use crate::audit::{AuditLog, AuditRow};
+use crate::funds::{FundingStates, Funds};
+use crate::tenant::Tenants;
+use crate::transactions::{Client, Rejection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum OutputFormat {
+    Csv,
+    Mt940,
+    Ofx,
+    Qif,
+    #[cfg(feature = "arrow-io")]
+    Arrow,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl OutputFormat {
+    // Statement formats list each client's activity, not just balances.
+    pub fn needs_history(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Mt940 | OutputFormat::Ofx | OutputFormat::Qif
+        )
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<OutputFormat, &'static str> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "mt940" => Ok(OutputFormat::Mt940),
+            "ofx" => Ok(OutputFormat::Ofx),
+            "qif" => Ok(OutputFormat::Qif),
+            #[cfg(feature = "arrow-io")]
+            "arrow" => Ok(OutputFormat::Arrow),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Ok(OutputFormat::MessagePack),
+            _ => Err("Unknown output format"),
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub(crate) struct AccountRow {
+    pub(crate) client: u32,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+}
+
+impl From<&Funds> for AccountRow {
+    fn from(funds: &Funds) -> AccountRow {
+        AccountRow {
+            client: funds.client.0,
+            available: funds.available.to_string(),
+            held: funds.held.to_string(),
+            total: funds.total().to_string(),
+            locked: funds.state == FundingStates::Frozen,
+        }
+    }
+}
+
+pub(crate) fn account_rows<'a, I>(accounts: I) -> Vec<AccountRow>
+where
+    I: IntoIterator<Item = &'a Funds>,
+{
+    let mut accounts: Vec<&Funds> = accounts.into_iter().collect();
+    accounts.sort_by_key(|funds| funds.client.0);
+    accounts.into_iter().map(AccountRow::from).collect()
+}
+
+pub fn write_accounts<'a, W, I>(accounts: I, writer: W) -> csv::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a Funds>,
+{
+    let mut writer = csv::Writer::from_writer(writer);
+    for row in account_rows(accounts) {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Every tenant's accounts in one CSV, tenant by tenant, with the tenant as
+// the first column.
+pub fn write_tenant_accounts<W: Write>(tenants: &Tenants, writer: W) -> csv::Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    writer.write_record(["tenant", "client", "available", "held", "total", "locked"])?;
+    for (tenant, engine) in tenants.iter() {
+        for row in account_rows(&engine.rolled_up_accounts()) {
+            writer.serialize((tenant, row))?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct RejectionRow<'a> {
+    r#type: &'a str,
+    client: u32,
+    tx: u64,
+    amount: Option<String>,
+    reason: &'static str,
+}
+
+impl<'a> From<&'a Rejection> for RejectionRow<'a> {
+    fn from(rejection: &'a Rejection) -> RejectionRow<'a> {
+        RejectionRow {
+            r#type: rejection.r#type.as_str(),
+            client: rejection.client.0,
+            tx: rejection.tx.0,
+            amount: rejection.amount.map(|amount| amount.to_string()),
+            reason: rejection.reason.as_str(),
+        }
+    }
+}
+
+// Every recorded rejection as CSV, in input order per tenant, with the
+// reason as the code the APIs and logs use. Like the accounts, the tenant
+// is the first column only when there are tenants.
+pub fn write_rejections<W: Write>(tenants: &Tenants, writer: W) -> csv::Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    let header = ["type", "client", "tx", "amount", "reason"];
+    if tenants.is_isolated() {
+        writer.write_record(std::iter::once("tenant").chain(header.iter().copied()))?;
+    } else {
+        writer.write_record(header)?;
+    }
+    for (tenant, engine) in tenants.iter() {
+        for rejection in engine.rejections() {
+            let row = RejectionRow::from(rejection);
+            if tenants.is_isolated() {
+                writer.serialize((tenant, row))?;
+            } else {
+                writer.serialize(row)?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Every tenant's audit log as CSV, oldest change first per tenant (see
+// `audit`). The tenant is the first column only when there are tenants.
+pub fn write_audit<W: Write>(tenants: &Tenants, writer: W) -> csv::Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    let header = [
+        "sequence",
+        "client",
+        "tx",
+        "reason",
+        "available_before",
+        "held_before",
+        "state_before",
+        "available_after",
+        "held_after",
+        "state_after",
+    ];
+    if tenants.is_isolated() {
+        writer.write_record(std::iter::once("tenant").chain(header.iter().copied()))?;
+    } else {
+        writer.write_record(header)?;
+    }
+    for (tenant, engine) in tenants.iter() {
+        let entries = engine.audit().map(AuditLog::entries).unwrap_or_default();
+        for row in entries.iter().map(AuditRow::from) {
+            if tenants.is_isolated() {
+                writer.serialize((tenant, row))?;
+            } else {
+                writer.serialize(row)?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ExtendedColumns {
+    flags: String,
+    risk_score: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    debt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pending: Option<String>,
+}
+
+// The accounts as CSV with trailing columns for each account's AML flags
+// (see `aml`), `dormant` (see `dormancy`) and `bonus` while a credit's bonus
+// is locked (see `bonus`), `;`-separated, and risk score (see `score`),
+// empty when the engine doesn't score clients. When an engine tracks debt
+// (see `debt`) each account's debt follows, when one settles deposits (see
+// `settlement`) its pending funds, and with a client registry (see
+// `registry`) each client's name, KYC status and tier. The tenant is first
+// when there are tenants.
+pub fn write_extended_accounts<W: Write>(tenants: &Tenants, writer: W) -> csv::Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    let header = [
+        "client",
+        "available",
+        "held",
+        "total",
+        "locked",
+        "flags",
+        "risk_score",
+    ];
+    let tracks_debt = tenants.iter().any(|(_, engine)| engine.tracks_debt());
+    let debt_header: &[&str] = if tracks_debt { &["debt"] } else { &[] };
+    let settles = tenants.iter().any(|(_, engine)| engine.settles_deposits());
+    let pending_header: &[&str] = if settles { &["pending"] } else { &[] };
+    let registered = tenants
+        .iter()
+        .any(|(_, engine)| engine.registry().is_some());
+    let registry_header: &[&str] = if registered {
+        &["name", "kyc", "tier"]
+    } else {
+        &[]
+    };
+    let tenant_header: &[&str] = if tenants.is_isolated() {
+        &["tenant"]
+    } else {
+        &[]
+    };
+    writer.write_record(
+        tenant_header
+            .iter()
+            .chain(header.iter())
+            .chain(debt_header.iter())
+            .chain(pending_header.iter())
+            .chain(registry_header.iter()),
+    )?;
+    for (tenant, engine) in tenants.iter() {
+        let accounts = engine.rolled_up_accounts();
+        let funds: HashMap<Client, &Funds> =
+            accounts.iter().map(|funds| (funds.client, funds)).collect();
+        for row in account_rows(&accounts) {
+            let client = Client(row.client);
+            let mut flags: Vec<&str> = engine
+                .risk_flags(client)
+                .iter()
+                .map(|flag| flag.as_str())
+                .collect();
+            if engine.is_dormant(client) {
+                flags.push("dormant");
+            }
+            let funds = funds[&client];
+            if funds.bonus.0 > 0 {
+                flags.push("bonus");
+            }
+            let extra = ExtendedColumns {
+                flags: flags.join(";"),
+                risk_score: engine.risk_score(client),
+                debt: tracks_debt.then(|| funds.debt.to_string()),
+                pending: settles.then(|| funds.pending.to_string()),
+            };
+            let info = engine.client_info(client);
+            let registry = registered.then(|| {
+                (
+                    info.map_or("", |info| info.name.as_str()),
+                    info.and_then(|info| info.kyc).map(|kyc| kyc.as_str()),
+                    info.and_then(|info| info.tier).map(|tier| tier.as_str()),
+                )
+            });
+            match (tenants.is_isolated(), registry) {
+                (true, Some(registry)) => writer.serialize((tenant, row, extra, registry))?,
+                (true, None) => writer.serialize((tenant, row, extra))?,
+                (false, Some(registry)) => writer.serialize((row, extra, registry))?,
+                (false, None) => writer.serialize((row, extra))?,
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Settings shared by the statement exports, from the `[statement]` config
+// section. Records carry no dates or currency, so every entry is booked on
+// `date` (YYYY-MM-DD, today when unset) in `currency` (ISO 4217, "XXX"
+// meaning no currency).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StatementOptions {
+    pub currency: String,
+    pub date: Option<String>,
+}
+
+impl Default for StatementOptions {
+    fn default() -> StatementOptions {
+        StatementOptions {
+            currency: "XXX".to_string(),
+            date: None,
+        }
+    }
+}
+
+impl StatementOptions {
+    pub fn date(&self) -> Result<(i64, u32, u32), &'static str> {
+        let date = match &self.date {
+            Some(date) => date,
+            None => return Ok(today()),
+        };
+        let mut parts = date.splitn(3, '-').map(str::parse::<u32>);
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(year)), Some(Ok(month)), Some(Ok(day)))
+                if (1..=12).contains(&month) && (1..=31).contains(&day) =>
+            {
+                Ok((i64::from(year), month, day))
+            }
+            _ => Err("Statement date must be YYYY-MM-DD"),
+        }
+    }
+}
+
+// Today's UTC date as (year, month, day), for exports that need a
+// statement date.
+fn today() -> (i64, u32, u32) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    civil_date(secs)
+}
+
+// The UTC date of a unix timestamp as (year, month, day). Converts days
+// since the epoch to a civil date (Howard Hinnant's algorithm) so no date
+// crate is needed.
+pub(crate) fn civil_date(secs: u64) -> (i64, u32, u32) {
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_accounts, write_rejections, write_tenant_accounts};
+    use crate::engine::Engine;
+    use crate::tenant::{Tenants, DEFAULT_TENANT};
+
+    #[test]
+    fn test_write_accounts() {
+        let csvfile = "type,client,tx,amount\ndeposit,2,1,1.5\ndeposit,1,2,2.0\ndispute,1,2,\nchargeback,1,2,\n";
+        let mut engine = Engine::new();
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        write_accounts(engine.accounts(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked\n1,0.0000,0.0000,0.0000,true\n2,1.5000,0.0000,1.5000,false\n"
+        );
+    }
+
+    #[test]
+    fn test_write_tenant_accounts() {
+        let mut tenants = Tenants::isolated(Engine::builder());
+        for (tenant, csvfile) in [
+            ("globex", "type,client,tx,amount\ndeposit,1,1,2.0\n"),
+            ("acme", "type,client,tx,amount\ndeposit,1,1,1.0\n"),
+        ] {
+            tenants
+                .engine(tenant)
+                .process_csv(csvfile.as_bytes())
+                .unwrap();
+        }
+        let mut out = Vec::new();
+        write_tenant_accounts(&tenants, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "tenant,client,available,held,total,locked\nacme,1,1.0000,0.0000,1.0000,false\nglobex,1,2.0000,0.0000,2.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn test_write_rejections() {
+        let mut tenants = Tenants::single(Engine::builder().record_rejections(true));
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,5.0\ndispute,1,7,\ndeposit,1,1,1.0\n";
+        tenants
+            .engine(DEFAULT_TENANT)
+            .process_csv(csvfile.as_bytes())
+            .unwrap();
+        let mut out = Vec::new();
+        write_rejections(&tenants, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "type,client,tx,amount,reason\nwithdrawal,1,2,5.0000,insufficient_funds\ndispute,1,7,,unknown_tx\ndeposit,1,1,1.0000,duplicate_tx\n"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use crate::arrow_io::ArrowResult;
+use crate::engine::Engine;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::fs::File;
+
+impl Engine {
+    pub fn process_parquet(&mut self, file: File) -> ArrowResult<()> {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+        for batch in reader {
+            self.process_record_batch(&batch?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::transactions::Client;
+    use arrow::array::{Decimal128Array, RecordBatch, StringArray, UInt16Array, UInt64Array};
+    use parquet::arrow::ArrowWriter;
+    use std::fs::{self, File};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_process_parquet() {
+        let amounts = Decimal128Array::from(vec![Some(15000), Some(20000), None])
+            .with_precision_and_scale(18, 4)
+            .unwrap();
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "type",
+                Arc::new(StringArray::from(vec!["deposit", "deposit", "dispute"])) as _,
+            ),
+            ("client", Arc::new(UInt16Array::from(vec![1, 2, 2])) as _),
+            ("tx", Arc::new(UInt64Array::from(vec![1, 2, 2])) as _),
+            ("amount", Arc::new(amounts) as _),
+        ])
+        .unwrap();
+        let path = std::env::temp_dir().join("payment_engine_input.parquet");
+        let mut writer =
+            ArrowWriter::try_new(File::create(&path).unwrap(), batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let mut engine = Engine::new();
+        engine.process_parquet(File::open(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(15000)
+        );
+        let two = engine.account(Client(2)).unwrap();
+        assert_eq!(two.held, Amount::new(20000));
+        assert_eq!(two.state, FundingStates::Disputed);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::amount::Amount;
+use crate::tenant::Tenants;
+use crate::transactions::{Client, ClientFunds, TxType};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::Arc;
+
+// What the records of one type applied to an account in a period moved.
+// Disputes, resolves and chargebacks move the amount of the disputed tx.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Volume {
+    pub deposits: Amount,
+    pub withdrawals: Amount,
+    pub disputes: Amount,
+    pub resolves: Amount,
+    pub chargebacks: Amount,
+    pub credits: Amount,
+    pub settles: Amount,
+}
+
+impl Volume {
+    fn add(&mut self, r#type: TxType, amount: Amount) {
+        let total = match r#type {
+            TxType::Deposit => &mut self.deposits,
+            TxType::Withdrawal => &mut self.withdrawals,
+            TxType::Dispute => &mut self.disputes,
+            TxType::Resolve => &mut self.resolves,
+            TxType::Chargeback => &mut self.chargebacks,
+            TxType::Credit => &mut self.credits,
+            TxType::Settle => &mut self.settles,
+        };
+        total.0 = total.0.saturating_add(amount.0);
+    }
+}
+
+// One account over a closed period: its balances when the period opened
+// (zero for accounts opened during it) and closed, and what moved in
+// between.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct PeriodAccount {
+    pub client: Client,
+    pub opening_available: Amount,
+    pub opening_held: Amount,
+    pub closing_available: Amount,
+    pub closing_held: Amount,
+    pub volume: Volume,
+}
+
+// A closed period. `start` is when the previous period was closed, None
+// for the first one; `end` is the timestamp it was closed at. Accounts are
+// in client order and include those with no activity in the period.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PeriodSummary {
+    pub number: u32,
+    pub start: Option<u64>,
+    pub end: u64,
+    pub accounts: Vec<PeriodAccount>,
+}
+
+// The open period's activity and the closed periods' summaries. Summaries
+// are shared rather than copied, and nothing changes one once closed.
+#[derive(Debug, Default, Clone)]
+pub struct Periods {
+    start: Option<u64>,
+    // balances as the open period started, by client
+    opening: HashMap<Client, (Amount, Amount)>,
+    volume: HashMap<Client, Volume>,
+    closed: Vec<Arc<PeriodSummary>>,
+}
+
+impl Periods {
+    // Called with each applied record and the amount it moved.
+    pub(crate) fn observe(&mut self, client: Client, r#type: TxType, amount: Amount) {
+        self.volume.entry(client).or_default().add(r#type, amount);
+    }
+
+    pub(crate) fn close(&mut self, end: u64, accounts: &ClientFunds) -> Arc<PeriodSummary> {
+        let mut rows = BTreeMap::new();
+        for funds in accounts.values() {
+            let (opening_available, opening_held) = self
+                .opening
+                .get(&funds.client)
+                .copied()
+                .unwrap_or((Amount(0), Amount(0)));
+            rows.insert(
+                funds.client.0,
+                PeriodAccount {
+                    client: funds.client,
+                    opening_available,
+                    opening_held,
+                    closing_available: funds.available,
+                    closing_held: funds.held,
+                    volume: self.volume.get(&funds.client).copied().unwrap_or_default(),
+                },
+            );
+        }
+        let summary = Arc::new(PeriodSummary {
+            number: self.closed.len() as u32 + 1,
+            start: self.start,
+            end,
+            accounts: rows.into_values().collect(),
+        });
+        self.start = Some(end);
+        self.opening = accounts
+            .values()
+            .map(|funds| (funds.client, (funds.available, funds.held)))
+            .collect();
+        self.volume.clear();
+        self.closed.push(Arc::clone(&summary));
+        summary
+    }
+
+    pub fn closed(&self) -> &[Arc<PeriodSummary>] {
+        &self.closed
+    }
+}
+
+#[derive(Serialize)]
+struct PeriodRow {
+    period: u32,
+    start: Option<u64>,
+    end: u64,
+    client: u32,
+    opening_available: String,
+    opening_held: String,
+    closing_available: String,
+    closing_held: String,
+    deposits: String,
+    withdrawals: String,
+    disputes: String,
+    resolves: String,
+    chargebacks: String,
+    credits: String,
+    settles: String,
+}
+
+impl PeriodRow {
+    fn new(summary: &PeriodSummary, account: &PeriodAccount) -> PeriodRow {
+        let volume = &account.volume;
+        PeriodRow {
+            period: summary.number,
+            start: summary.start,
+            end: summary.end,
+            client: account.client.0,
+            opening_available: account.opening_available.to_string(),
+            opening_held: account.opening_held.to_string(),
+            closing_available: account.closing_available.to_string(),
+            closing_held: account.closing_held.to_string(),
+            deposits: volume.deposits.to_string(),
+            withdrawals: volume.withdrawals.to_string(),
+            disputes: volume.disputes.to_string(),
+            resolves: volume.resolves.to_string(),
+            chargebacks: volume.chargebacks.to_string(),
+            credits: volume.credits.to_string(),
+            settles: volume.settles.to_string(),
+        }
+    }
+}
+
+// Every tenant's closed periods as CSV, one row per account and period.
+// The tenant is the first column only when there are tenants.
+pub fn write_periods<W: Write>(tenants: &Tenants, writer: W) -> csv::Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    let header = [
+        "period",
+        "start",
+        "end",
+        "client",
+        "opening_available",
+        "opening_held",
+        "closing_available",
+        "closing_held",
+        "deposits",
+        "withdrawals",
+        "disputes",
+        "resolves",
+        "chargebacks",
+        "credits",
+        "settles",
+    ];
+    if tenants.is_isolated() {
+        writer.write_record(std::iter::once("tenant").chain(header.iter().copied()))?;
+    } else {
+        writer.write_record(header)?;
+    }
+    for (tenant, engine) in tenants.iter() {
+        for summary in engine.periods() {
+            for account in &summary.accounts {
+                let row = PeriodRow::new(summary, account);
+                if tenants.is_isolated() {
+                    writer.serialize((tenant, row))?;
+                } else {
+                    writer.serialize(row)?;
+                }
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_periods;
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::tenant::Tenants;
+    use crate::transactions::{Client, TransactionRecord, Tx};
+
+    #[test]
+    fn test_close_period() {
+        let mut tenants = Tenants::single(Engine::builder().track_periods(true));
+        let engine = tenants.engine("default");
+        engine.process(&TransactionRecord::deposit(
+            Client(1),
+            Tx(1),
+            Amount::new(100_000),
+        ));
+        engine.process(&TransactionRecord::withdrawal(
+            Client(1),
+            Tx(2),
+            Amount::new(20_000),
+        ));
+        let first = engine.close_period(86_400).unwrap();
+        assert_eq!(first.accounts[0].volume.deposits, Amount::new(100_000));
+        engine.process(&TransactionRecord::deposit(
+            Client(2),
+            Tx(3),
+            Amount::new(50_000),
+        ));
+        engine.process(&TransactionRecord::dispute(Client(2), Tx(3)));
+        let second = engine.close_period(172_800).unwrap();
+        assert_eq!(second.start, Some(86_400));
+        // the first period's summary is as it was closed
+        assert_eq!(engine.periods()[0], first);
+        assert_eq!(engine.periods().len(), 2);
+
+        let mut out = Vec::new();
+        write_periods(&tenants, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "period,start,end,client,opening_available,opening_held,closing_available,closing_held,deposits,withdrawals,disputes,resolves,chargebacks,credits,settles\n\
+             1,,86400,1,0.0000,0.0000,8.0000,0.0000,10.0000,2.0000,0.0000,0.0000,0.0000,0.0000,0.0000\n\
+             2,86400,172800,1,8.0000,0.0000,8.0000,0.0000,0.0000,0.0000,0.0000,0.0000,0.0000,0.0000,0.0000\n\
+             2,86400,172800,2,0.0000,0.0000,0.0000,5.0000,5.0000,0.0000,5.0000,0.0000,0.0000,0.0000,0.0000\n"
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use crate::csv_dialect::CsvDialect;
+use crate::decoder::{malformed_row, CsvDecoder, RowDecoder};
+use crate::engine::Engine;
+use crate::error::PaymentResult;
+use crate::transactions::{Client, TransactionRecord, Tx, TxType};
+use csv::StringRecord;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::Read;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::Span;
+
+const BATCH_SIZE: usize = 1024;
+const QUEUE_DEPTH: usize = 16;
+
+// A row as a parser thread hands it to the applier: what
+// `Engine::process_converted` takes, owned, plus any captured columns.
+struct ParsedRow {
+    r#type: TxType,
+    client: Client,
+    subaccount: Option<String>,
+    tx: Tx,
+    timestamp: Option<u64>,
+    converted: PaymentResult<TransactionRecord>,
+    extras: Vec<(String, String)>,
+}
+
+type Batch<T> = (usize, Vec<T>);
+type RawBatch = Batch<csv::Result<StringRecord>>;
+type ParsedBatch = Batch<csv::Result<ParsedRow>>;
+
+// Read errors travel with the batch so the applier reports them in input
+// order. A malformed row (wrong length, bad UTF-8) can be read past; any
+// other error ends the reading.
+fn read_batches<R: Read>(
+    reader: &mut csv::Reader<R>,
+    rows: &RowDecoder,
+    send: impl Fn(RawBatch) -> bool,
+) {
+    let mut seq = 0;
+    loop {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut done = false;
+        while !done && batch.len() < BATCH_SIZE {
+            let mut raw = StringRecord::new();
+            match reader.read_record(&mut raw) {
+                Ok(true) => batch.push(Ok(raw)),
+                Ok(false) => done = true,
+                Err(err) => {
+                    let err = rows.read_error(&raw, err);
+                    done = malformed_row(&err).is_none();
+                    batch.push(Err(err));
+                }
+            }
+        }
+        if batch.is_empty() || !send((seq, batch)) || done {
+            return;
+        }
+        seq += 1;
+    }
+}
+
+fn parse(rows: &RowDecoder, raw: csv::Result<StringRecord>) -> csv::Result<ParsedRow> {
+    let mut raw = raw?;
+    rows.resolve_type_alias(&mut raw);
+    let row = rows.decode(&raw)?;
+    let extras = rows
+        .extra_columns(&raw)
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    Ok(ParsedRow {
+        r#type: row.r#type(),
+        client: row.client(),
+        subaccount: row.subaccount().map(str::to_string),
+        tx: row.tx(),
+        timestamp: row.timestamp(),
+        converted: TransactionRecord::try_from(row.with_subaccount(None)),
+        extras,
+    })
+}
+
+fn parse_batches(
+    rows: &RowDecoder,
+    raw: &Mutex<Receiver<RawBatch>>,
+    send: impl Fn(ParsedBatch) -> bool,
+) {
+    loop {
+        let next = raw.lock().unwrap().recv();
+        let (seq, batch) = match next {
+            Ok(batch) => batch,
+            Err(_) => return,
+        };
+        let _span = tracing::debug_span!("parse", seq, rows = batch.len()).entered();
+        let parsed = batch.into_iter().map(|raw| parse(rows, raw)).collect();
+        if !send((seq, parsed)) {
+            return;
+        }
+    }
+}
+
+fn apply_batches(
+    parsed: Receiver<ParsedBatch>,
+    engine: &mut Engine,
+    dialect: &CsvDialect,
+) -> csv::Result<()> {
+    let mut pending = BTreeMap::new();
+    let mut next_seq = 0;
+    for (seq, batch) in parsed {
+        pending.insert(seq, batch);
+        while let Some(batch) = pending.remove(&next_seq) {
+            let _span = tracing::debug_span!("apply", seq = next_seq, rows = batch.len()).entered();
+            for row in batch {
+                let row = match row {
+                    Ok(row) => row,
+                    Err(err) => {
+                        engine.skip_bad_row(err, dialect)?;
+                        continue;
+                    }
+                };
+                let _ = engine.process_converted(
+                    row.r#type,
+                    row.client,
+                    row.subaccount.as_deref(),
+                    row.tx,
+                    row.timestamp,
+                    row.converted,
+                );
+                if !row.extras.is_empty() {
+                    engine.add_metadata(row.tx, row.extras);
+                }
+            }
+            next_seq += 1;
+        }
+    }
+    Ok(())
+}
+
+// `Engine::process_csv_with` split into three stages connected by bounded
+// channels: one thread reads raw CSV records, `parsers` threads decode and
+// convert them, and the calling thread applies them to `engine`. Batches
+// are tagged with a sequence number and re-ordered before they are
+// applied, so the engine sees rows in input order and ends up as it would
+// have reading the input itself, errors included.
+#[tracing::instrument(level = "info", name = "pipeline", skip(input, dialect, engine))]
+pub fn process<R: Read + Send>(
+    input: R,
+    dialect: &CsvDialect,
+    parsers: usize,
+    engine: &mut Engine,
+) -> csv::Result<()> {
+    let (mut reader, rows) = CsvDecoder::new(input, dialect)?.into_parts();
+    let (raw_tx, raw_rx) = sync_channel::<RawBatch>(QUEUE_DEPTH);
+    let (parsed_tx, parsed_rx) = sync_channel::<ParsedBatch>(QUEUE_DEPTH);
+    let raw_rx = Arc::new(Mutex::new(raw_rx));
+
+    // the stage threads report their spans under this call's
+    let parent = Span::current();
+    thread::scope(|scope| {
+        {
+            let (rows, parent) = (&rows, parent.clone());
+            scope.spawn(move || {
+                let _span = tracing::info_span!(parent: &parent, "read").entered();
+                read_batches(&mut reader, rows, |batch| raw_tx.send(batch).is_ok())
+            });
+        }
+        for _ in 0..parsers.max(1) {
+            let (rows, raw_rx, parsed_tx) = (&rows, Arc::clone(&raw_rx), parsed_tx.clone());
+            let parent = parent.clone();
+            scope.spawn(move || {
+                let _guard = parent.enter();
+                parse_batches(rows, &raw_rx, |batch| parsed_tx.send(batch).is_ok())
+            });
+        }
+        drop((raw_rx, parsed_tx));
+
+        apply_batches(parsed_rx, engine, dialect)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::process;
+    use crate::amount::Amount;
+    use crate::columns::ExtraColumns;
+    use crate::csv_dialect::{CsvDialect, UnknownTypes};
+    use crate::decoder::malformed_row;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::transactions::{Client, Tx};
+
+    #[test]
+    fn test_pipeline_applies_in_order() {
+        let mut csvfile = String::from("type,client,tx,amount\n");
+        for tx in 1..=5000 {
+            csvfile.push_str(&format!("deposit,{},{},1.0\n", tx % 3, tx));
+        }
+        csvfile.push_str("withdrawal,1,5001,0.5\ndispute,2,2,\nresolve,2,2,\ndispute,0,3,\n");
+
+        let mut engine = Engine::new();
+        process(csvfile.as_bytes(), &CsvDialect::default(), 4, &mut engine).unwrap();
+        let one = engine.account(Client(1)).unwrap();
+        assert_eq!(one.available, Amount::new(1667 * 10000 - 5000));
+        let two = engine.account(Client(2)).unwrap();
+        assert_eq!(two.available, Amount::new(1667 * 10000));
+        assert_eq!(two.state, FundingStates::Valid);
+        let zero = engine.account(Client(0)).unwrap();
+        assert_eq!(zero.held, Amount::new(10000));
+        assert_eq!(zero.state, FundingStates::Disputed);
+    }
+
+    #[test]
+    fn test_pipeline_reports_bad_rows() {
+        let mut csvfile = String::from("type,client,tx,amount\ndeposit,1,1,1.0\nbogus,1,2,1.0\n");
+        for tx in 3..=100_000 {
+            csvfile.push_str(&format!("deposit,1,{},1.0\n", tx));
+        }
+        let mut engine = Engine::new();
+        let err = process(csvfile.as_bytes(), &CsvDialect::default(), 2, &mut engine).unwrap_err();
+        assert_eq!(malformed_row(&err).map(|row| row.line), Some(3));
+        // what came before the bad row is applied, nothing after it
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(10000)
+        );
+    }
+
+    // The pipeline ends up exactly where `process_csv_with` does, with the
+    // dialect's options, sub-accounts and skipped rows.
+    #[test]
+    fn test_pipeline_matches_process_csv() {
+        let input = "type,client,tx,amount,memo\ndeposit,1,1,5,a\ndeposit,1:savings,2,3,b\nbogus,1,3,1,c\ncredit,2,4,2,d\nwithdrawal,1,5,9,e\ndeposit,2,6,1,f,g\ndeposit,1,7,x,h\ndispute,1:savings,2,,i\n";
+        let mut dialect = CsvDialect {
+            permissive: true,
+            unknown_types: UnknownTypes::Reject,
+            ..CsvDialect::default()
+        };
+        dialect.columns.extra = ExtraColumns::Capture;
+        let builder = Engine::builder().record_rejections(true);
+        let mut expected = builder.clone().build();
+        expected
+            .process_csv_with(input.as_bytes(), &dialect)
+            .unwrap();
+        let mut engine = builder.build();
+        process(input.as_bytes(), &dialect, 3, &mut engine).unwrap();
+
+        assert_eq!(engine.rolled_up_accounts(), expected.rolled_up_accounts());
+        assert_eq!(engine.rejections(), expected.rejections());
+        let counts = |engine: &Engine| {
+            let metrics = engine.metrics();
+            (
+                metrics.records_read,
+                metrics.rejected,
+                metrics.rows_malformed,
+            )
+        };
+        assert_eq!(counts(&engine), counts(&expected));
+        assert_eq!(engine.metadata(Tx(2)), expected.metadata(Tx(2)));
+        assert_eq!(engine.metrics().rows_malformed, 2);
+        assert_eq!(engine.metrics().rows_unknown_type, 1);
+        let savings = engine.subaccount(Client(1), "savings").unwrap();
+        assert_eq!(savings.held, Amount::new(30000));
+    }
+}
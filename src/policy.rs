@@ -0,0 +1,298 @@
+use crate::amount::Amount;
+use crate::funds::Funds;
+use crate::rules::{KycStatus, RuleContext, Tier, ValidationRule};
+use crate::transactions::{RejectReason, TxType};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+// Reason code of a `reject` rule that doesn't name its own.
+pub const DEFAULT_CODE: &str = "policy";
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Metric {
+    // what the record moves, see `RuleContext::amount`
+    Amount,
+    Available,
+    Held,
+    Total,
+    // how many records of the type were applied to the client
+    Applied(TxType),
+    // see `score::risk_score`
+    Score,
+    // the client's KYC status and tier, see `registry`
+    Kyc,
+    Tier,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Op {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Action {
+    // None rejects records of any type
+    Reject {
+        r#type: Option<TxType>,
+        code: &'static str,
+    },
+    Freeze,
+}
+
+// A rule from the `rules` list of the config file, written as
+//
+//   reject <type|any> if <metric> <op> <value> [as <code>]
+//   freeze client if <metric> <op> <value>
+//
+// `reject` rules turn records away before they are applied; `freeze` rules
+// freeze the account once a record has been applied. Metrics are `amount`,
+// the account's `available`, `held` and `total`, counts of applied
+// `deposits`, `withdrawals`, `disputes`, `resolves` and `chargebacks`, the
+// client's risk `score`, and its `kyc` status and `tier` from the client
+// registry, compared by name (`none` for clients it doesn't list). Tiers
+// are ordered, so `tier < premium` works; KYC statuses only take `==` and
+// `!=`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PolicyRule {
+    action: Action,
+    metric: Metric,
+    op: Op,
+    // an `Amount`'s units for the money metrics, see `kyc_value` and
+    // `tier_value` for those, a plain number otherwise
+    value: u64,
+}
+
+fn kyc_value(kyc: Option<KycStatus>) -> u64 {
+    match kyc {
+        None => 0,
+        Some(KycStatus::Pending) => 1,
+        Some(KycStatus::Verified) => 2,
+        Some(KycStatus::Rejected) => 3,
+    }
+}
+
+fn tier_value(tier: Option<Tier>) -> u64 {
+    match tier {
+        None => 0,
+        Some(Tier::Basic) => 1,
+        Some(Tier::Verified) => 2,
+        Some(Tier::Premium) => 3,
+    }
+}
+
+// `none`, or a name of a `T`.
+fn named<T: FromStr>(value: &str) -> Result<Option<T>, String> {
+    match value {
+        "none" => Ok(None),
+        value => T::from_str(value)
+            .map(Some)
+            .map_err(|_| format!("unknown value {:?}", value)),
+    }
+}
+
+fn parse_metric(word: &str) -> Result<Metric, String> {
+    Ok(match word {
+        "amount" => Metric::Amount,
+        "available" => Metric::Available,
+        "held" => Metric::Held,
+        "total" => Metric::Total,
+        "deposits" => Metric::Applied(TxType::Deposit),
+        "withdrawals" => Metric::Applied(TxType::Withdrawal),
+        "disputes" => Metric::Applied(TxType::Dispute),
+        "resolves" => Metric::Applied(TxType::Resolve),
+        "chargebacks" => Metric::Applied(TxType::Chargeback),
+        "credits" => Metric::Applied(TxType::Credit),
+        "settles" => Metric::Applied(TxType::Settle),
+        "score" => Metric::Score,
+        "kyc" => Metric::Kyc,
+        "tier" => Metric::Tier,
+        _ => return Err(format!("unknown metric {:?}", word)),
+    })
+}
+
+fn parse_op(word: &str) -> Result<Op, String> {
+    Ok(match word {
+        ">" => Op::Gt,
+        ">=" => Op::Ge,
+        "<" => Op::Lt,
+        "<=" => Op::Le,
+        "==" => Op::Eq,
+        "!=" => Op::Ne,
+        _ => return Err(format!("unknown comparison {:?}", word)),
+    })
+}
+
+// Codes end up in CSV reports and metric labels, like the built-in ones.
+fn valid_code(code: &str) -> bool {
+    !code.is_empty()
+        && code
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+}
+
+impl FromStr for PolicyRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<PolicyRule, String> {
+        let words: Vec<&str> = rule.split_whitespace().collect();
+        let (action, condition) = match words.as_slice() {
+            ["freeze", "client", "if", condition @ ..] => (Action::Freeze, condition),
+            ["reject", target, "if", condition @ ..] => {
+                let r#type = match *target {
+                    "any" => None,
+                    target => Some(TxType::from_str(target).map_err(|err| err.to_string())?),
+                };
+                let (condition, code) = match condition {
+                    [condition @ .., "as", code] if valid_code(code) => (condition, *code),
+                    [.., "as", code] => return Err(format!("invalid reason code {:?}", code)),
+                    condition => (condition, DEFAULT_CODE),
+                };
+                // rules are compiled once at startup, and rejections carry
+                // their code as a `&'static str`
+                let code = match code {
+                    DEFAULT_CODE => DEFAULT_CODE,
+                    code => Box::leak(code.to_string().into_boxed_str()),
+                };
+                (Action::Reject { r#type, code }, condition)
+            }
+            _ => {
+                return Err("expected `reject <type> if ...` or `freeze client if ...`".to_string())
+            }
+        };
+        let (metric, op, value) = match condition {
+            [metric, op, value] => (parse_metric(metric)?, parse_op(op)?, *value),
+            _ => return Err("expected a condition like `amount > 100`".to_string()),
+        };
+        if metric == Metric::Kyc && !matches!(op, Op::Eq | Op::Ne) {
+            return Err("KYC statuses can only be compared with `==` or `!=`".to_string());
+        }
+        let value = match metric {
+            Metric::Applied(_) | Metric::Score => value
+                .parse()
+                .map_err(|_| format!("bad number {:?}", value))?,
+            Metric::Kyc => kyc_value(named(value)?),
+            Metric::Tier => tier_value(named(value)?),
+            _ => Amount::from_str(value).map_err(|err| err.to_string())?.0,
+        };
+        Ok(PolicyRule {
+            action,
+            metric,
+            op,
+            value,
+        })
+    }
+}
+
+impl TryFrom<String> for PolicyRule {
+    type Error = String;
+
+    fn try_from(rule: String) -> Result<PolicyRule, String> {
+        PolicyRule::from_str(&rule).map_err(|reason| format!("rule {:?}: {}", rule, reason))
+    }
+}
+
+impl PolicyRule {
+    // A record without the amount an `amount` condition needs never matches.
+    fn matches(&self, ctx: &RuleContext<'_>) -> bool {
+        let balance = |amount: fn(&Funds) -> Amount| ctx.funds.map_or(0, |funds| amount(funds).0);
+        let actual = match self.metric {
+            Metric::Amount => match ctx.amount() {
+                Some(amount) => amount.0,
+                None => return false,
+            },
+            Metric::Available => balance(|funds| funds.available),
+            Metric::Held => balance(|funds| funds.held),
+            Metric::Total => balance(|funds| funds.total()),
+            Metric::Applied(r#type) => u64::from(ctx.stats.applied.get(r#type)),
+            Metric::Score => u64::from(ctx.stats.risk_score),
+            Metric::Kyc => kyc_value(ctx.stats.kyc),
+            Metric::Tier => tier_value(ctx.stats.tier),
+        };
+        match self.op {
+            Op::Gt => actual > self.value,
+            Op::Ge => actual >= self.value,
+            Op::Lt => actual < self.value,
+            Op::Le => actual <= self.value,
+            Op::Eq => actual == self.value,
+            Op::Ne => actual != self.value,
+        }
+    }
+}
+
+impl ValidationRule for PolicyRule {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        match self.action {
+            Action::Reject { r#type, code }
+                if r#type.is_none_or(|r#type| r#type == ctx.record.r#type) && self.matches(ctx) =>
+            {
+                Err(RejectReason::Rule(code))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn freezes(&self, ctx: &RuleContext<'_>) -> bool {
+        self.action == Action::Freeze && self.matches(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PolicyRule;
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::transactions::{Client, RejectReason};
+
+    #[test]
+    fn test_policy_rules() {
+        let rules = [
+            "reject withdrawal if amount > 100",
+            "reject any if amount >= 5000 as too_large",
+            "freeze client if withdrawals >= 2",
+        ];
+        let mut builder = Engine::builder();
+        for rule in rules {
+            builder = builder.rule(rule.parse::<PolicyRule>().unwrap());
+        }
+        let mut engine = builder.build();
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,500.0\nwithdrawal,1,2,150.0\nwithdrawal,1,3,50.0\ndeposit,1,4,6000.0\nwithdrawal,1,5,10.0\nwithdrawal,1,6,10.0\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let metrics = engine.metrics();
+        assert_eq!(metrics.rejected[&RejectReason::Rule("policy")], 1);
+        assert_eq!(metrics.rejected[&RejectReason::Rule("too_large")], 1);
+        // the second withdrawal froze the account
+        assert_eq!(metrics.rejected[&RejectReason::AccountFrozen], 1);
+        let funds = engine.account(Client(1)).unwrap();
+        assert_eq!(funds.available, Amount::new(4_400_000));
+        assert_eq!(funds.state, FundingStates::Frozen);
+
+        for good in [
+            "freeze client if chargebacks >= 2",
+            "reject withdrawal if score > 60 as risky",
+            "reject any if kyc != verified",
+            "reject withdrawal if tier < verified as tier_too_low",
+        ] {
+            assert!(good.parse::<PolicyRule>().is_ok(), "{}", good);
+        }
+        for bad in [
+            "reject refund if amount > 1",
+            "reject withdrawal if amount >> 1",
+            "reject withdrawal if balance > 1",
+            "freeze client if chargebacks >= 1.5",
+            "reject any if kyc > pending",
+            "reject any if tier == gold",
+            "reject any if amount > 1 as Too-Large",
+            "allow deposit",
+        ] {
+            assert!(bad.parse::<PolicyRule>().is_err(), "{}", bad);
+        }
+    }
+}
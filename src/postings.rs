@@ -0,0 +1,2 @@
+// Part of the no_std core, see `payment_engine_core`.
+pub use payment_engine_core::postings::*;
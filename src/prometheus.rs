@@ -0,0 +1,251 @@
+use crate::amount::Amount;
+use crate::funds::{not_frozen, Funds};
+use crate::metrics::{EngineMetrics, LATENCY_BUCKETS};
+use crate::serve::{SharedTenants, WeakTenants};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// Every tenant's metrics in the Prometheus text exposition format, each
+// series labelled with its tenant. Balances are summed over the tenant's
+// published accounts when scraped, without waiting for the engine.
+pub fn render(tenants: &SharedTenants) -> String {
+    let sampled: Vec<(String, Vec<Funds>, EngineMetrics)> = tenants
+        .engines()
+        .into_iter()
+        .map(|(tenant, engine)| {
+            let metrics = engine.read(|engine| engine.metrics());
+            (tenant, engine.accounts(), metrics)
+        })
+        .collect();
+    let mut out = String::new();
+
+    let name = "payment_engine_transactions_total";
+    header(
+        &mut out,
+        name,
+        "counter",
+        "Transactions processed, by type and outcome.",
+    );
+    for (tenant, _, metrics) in &sampled {
+        for (outcome, counts) in [
+            ("applied", &metrics.applied_by_type),
+            ("rejected", &metrics.rejected_by_type),
+        ] {
+            for (r#type, count) in counts {
+                let _ = writeln!(
+                    out,
+                    "{}{{tenant=\"{}\",type=\"{}\",outcome=\"{}\"}} {}",
+                    name,
+                    tenant,
+                    r#type.as_str(),
+                    outcome,
+                    count
+                );
+            }
+        }
+    }
+
+    let name = "payment_engine_rejections_total";
+    header(
+        &mut out,
+        name,
+        "counter",
+        "Rejected transactions, by reason.",
+    );
+    for (tenant, _, metrics) in &sampled {
+        for (reason, count) in &metrics.rejected {
+            let _ = writeln!(
+                out,
+                "{}{{tenant=\"{}\",reason=\"{}\"}} {}",
+                name,
+                tenant,
+                reason.as_str(),
+                count
+            );
+        }
+    }
+
+    let name = "payment_engine_malformed_rows_total";
+    header(
+        &mut out,
+        name,
+        "counter",
+        "Input rows skipped as malformed.",
+    );
+    for (tenant, _, metrics) in &sampled {
+        let _ = writeln!(
+            out,
+            "{}{{tenant=\"{}\"}} {}",
+            name, tenant, metrics.rows_malformed
+        );
+    }
+
+    let gauges: Vec<[String; 4]> = sampled
+        .iter()
+        .map(|(_, accounts, metrics)| {
+            let (mut available, mut held, mut frozen) = (Amount::new(0), Amount::new(0), 0);
+            for funds in accounts {
+                available = available + funds.available;
+                held = held + funds.held;
+                frozen += usize::from(!not_frozen(funds));
+            }
+            [
+                metrics.clients.to_string(),
+                frozen.to_string(),
+                available.to_string(),
+                held.to_string(),
+            ]
+        })
+        .collect();
+    for (gauge, &(name, help)) in [
+        ("payment_engine_accounts", "Client accounts."),
+        (
+            "payment_engine_frozen_accounts",
+            "Accounts frozen by a chargeback.",
+        ),
+        (
+            "payment_engine_available_funds",
+            "Available funds across all accounts.",
+        ),
+        (
+            "payment_engine_held_funds",
+            "Funds held by open disputes across all accounts.",
+        ),
+    ]
+    .iter()
+    .enumerate()
+    {
+        header(&mut out, name, "gauge", help);
+        for ((tenant, _, _), values) in sampled.iter().zip(&gauges) {
+            let _ = writeln!(out, "{}{{tenant=\"{}\"}} {}", name, tenant, values[gauge]);
+        }
+    }
+
+    let name = "payment_engine_apply_duration_seconds";
+    let timed = sampled
+        .iter()
+        .any(|(_, _, metrics)| metrics.apply_latency.is_some());
+    if timed {
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "Time taken to apply one transaction.",
+        );
+    }
+    for (tenant, _, metrics) in &sampled {
+        let latency = match &metrics.apply_latency {
+            Some(latency) => latency,
+            None => continue,
+        };
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&latency.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{tenant=\"{}\",le=\"{}\"}} {}",
+                name, tenant, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{tenant=\"{}\",le=\"+Inf\"}} {}",
+            name, tenant, latency.count
+        );
+        let _ = writeln!(
+            out,
+            "{}_sum{{tenant=\"{}\"}} {}",
+            name,
+            tenant,
+            latency.sum.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "{}_count{{tenant=\"{}\"}} {}",
+            name, tenant, latency.count
+        );
+    }
+    out
+}
+
+fn respond(tenants: &WeakTenants, mut stream: TcpStream) -> io::Result<()> {
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let body = match (request.split_whitespace().nth(1), tenants.upgrade()) {
+        (Some("/metrics"), Some(tenants)) => render(&tenants),
+        _ => {
+            return stream.write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n");
+        }
+    };
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )
+}
+
+// Answers `GET /metrics` on `addr` from a background thread, for servers
+// whose own protocol can't carry a scrape (the REST server serves
+// `/metrics` itself). Like the admin socket it only holds a weak reference
+// to the engines.
+pub fn listen(addr: SocketAddr, tenants: &SharedTenants) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let tenants = tenants.downgrade();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(err) = stream.and_then(|stream| respond(&tenants, stream)) {
+                tracing::warn!(error = %err, "metrics scrape failed");
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use crate::engine::Engine;
+    use crate::serve::SharedTenants;
+    use crate::tenant::Tenants;
+
+    #[test]
+    fn test_render() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,2,2,1.5\nwithdrawal,1,3,5.0\ndispute,2,2,\n";
+        let mut tenants = Tenants::isolated(Engine::builder().time_applies(true));
+        tenants
+            .engine("acme")
+            .process_csv(csvfile.as_bytes())
+            .unwrap();
+        tenants.engine("globex");
+        let text = render(&SharedTenants::new(tenants));
+        for line in [
+            "payment_engine_transactions_total{tenant=\"acme\",type=\"deposit\",outcome=\"applied\"} 2",
+            "payment_engine_transactions_total{tenant=\"acme\",type=\"withdrawal\",outcome=\"rejected\"} 1",
+            "payment_engine_rejections_total{tenant=\"acme\",reason=\"insufficient_funds\"} 1",
+            "payment_engine_accounts{tenant=\"acme\"} 2",
+            "payment_engine_accounts{tenant=\"globex\"} 0",
+            "payment_engine_available_funds{tenant=\"acme\"} 2.0000",
+            "payment_engine_held_funds{tenant=\"acme\"} 1.5000",
+            "payment_engine_apply_duration_seconds_bucket{tenant=\"acme\",le=\"+Inf\"} 4",
+            "payment_engine_apply_duration_seconds_count{tenant=\"acme\"} 4",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {:?} in\n{}",
+                line,
+                text
+            );
+        }
+    }
+}
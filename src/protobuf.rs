@@ -0,0 +1,115 @@
+use crate::engine::Engine;
+use crate::transactions::{amount_field, Client, RowRecord, TransactionRecord, Tx, TxType};
+use prost::Message;
+use std::convert::TryFrom;
+use std::error::Error;
+
+#[allow(clippy::all)]
+pub mod proto {
+    include!(concat!(env!("OUT_DIR"), "/payments_engine.rs"));
+}
+
+use proto::{Transaction, TransactionType};
+
+pub type ProtoResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+impl From<TransactionType> for TxType {
+    fn from(r#type: TransactionType) -> TxType {
+        match r#type {
+            TransactionType::Deposit => TxType::Deposit,
+            TransactionType::Withdrawal => TxType::Withdrawal,
+            TransactionType::Dispute => TxType::Dispute,
+            TransactionType::Resolve => TxType::Resolve,
+            TransactionType::Chargeback => TxType::Chargeback,
+            TransactionType::Credit => TxType::Credit,
+            TransactionType::Settle => TxType::Settle,
+        }
+    }
+}
+
+impl TryFrom<&Transaction> for TransactionRecord {
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn try_from(message: &Transaction) -> ProtoResult<TransactionRecord> {
+        let r#type = TransactionType::try_from(message.r#type)
+            .map_err(|_| format!("unknown transaction type {}", message.r#type))?;
+        let amount = amount_field(message.amount.as_deref().unwrap_or(""))?;
+        let row = RowRecord::new(
+            r#type.into(),
+            Client(message.client),
+            Tx(message.tx),
+            amount,
+        );
+        Ok(TransactionRecord::try_from(row)?)
+    }
+}
+
+// A single, not length-delimited, `Transaction` message.
+pub fn parse_record(input: &[u8]) -> ProtoResult<TransactionRecord> {
+    TransactionRecord::try_from(&Transaction::decode(input)?)
+}
+
+impl Engine {
+    // Input is a stream of length-delimited `Transaction` messages, the
+    // framing gRPC producers and `writeDelimitedTo` use.
+    pub fn process_protobuf(&mut self, mut input: &[u8]) -> ProtoResult<()> {
+        while !input.is_empty() {
+            let message = Transaction::decode_length_delimited(&mut input)?;
+            self.process(&TransactionRecord::try_from(&message)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::{Transaction, TransactionType};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::transactions::Client;
+    use prost::Message;
+
+    fn encode(messages: &[Transaction]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for message in messages {
+            message.encode_length_delimited(&mut buf).unwrap();
+        }
+        buf
+    }
+
+    #[test]
+    fn test_process_protobuf() {
+        let input = encode(&[
+            Transaction {
+                r#type: TransactionType::Deposit as i32,
+                client: 3,
+                tx: 1,
+                amount: Some("4.25".into()),
+            },
+            Transaction {
+                r#type: TransactionType::Dispute as i32,
+                client: 3,
+                tx: 1,
+                amount: None,
+            },
+        ]);
+        let mut engine = Engine::new();
+        engine.process_protobuf(&input).unwrap();
+        let funds = engine.account(Client(3)).unwrap();
+        assert_eq!(funds.held, Amount::new(42500));
+        assert_eq!(funds.state, FundingStates::Disputed);
+    }
+
+    #[test]
+    fn test_rejects_bad_messages() {
+        let unknown_type = encode(&[Transaction {
+            r#type: 99,
+            client: 70000,
+            tx: 1,
+            amount: Some("1".into()),
+        }]);
+        assert!(Engine::new().process_protobuf(&unknown_type).is_err());
+        assert!(Engine::new().process_protobuf(&[0x05, 0x08]).is_err());
+    }
+}
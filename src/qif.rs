@@ -0,0 +1,72 @@
+use crate::engine::Engine;
+use crate::history::statement_history;
+use crate::output::StatementOptions;
+use std::io::{self, Write};
+
+// Writes each client as a QIF bank account: an `!Account` header carrying
+// the closing balance (`$`) and its date (`/`), followed by the booked
+// activity. QIF has no currency field, so `options.currency` is unused.
+pub fn write_qif<W: Write>(
+    engine: &Engine,
+    options: &StatementOptions,
+    mut writer: W,
+) -> io::Result<()> {
+    let history = statement_history(engine)?;
+    let (year, month, day) = options
+        .date()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let date = format!("{:02}/{:02}/{:04}", month, day, year);
+    let mut accounts: Vec<_> = engine.accounts().collect();
+    accounts.sort_by_key(|funds| funds.client.0);
+
+    for funds in accounts {
+        writeln!(writer, "!Account")?;
+        writeln!(writer, "NClient {}", funds.client.0)?;
+        writeln!(writer, "TBank")?;
+        writeln!(writer, "${}", funds.total())?;
+        writeln!(writer, "/{}", date)?;
+        writeln!(writer, "^")?;
+        writeln!(writer, "!Type:Bank")?;
+        for activity in history.client(funds.client) {
+            let sign = match activity.is_credit() {
+                Some(true) => "",
+                Some(false) => "-",
+                None => continue,
+            };
+            writeln!(writer, "D{}", date)?;
+            writeln!(writer, "T{}{}", sign, activity.amount)?;
+            writeln!(writer, "N{}", activity.tx.0)?;
+            writeln!(writer, "P{:?}", activity.r#type)?;
+            writeln!(writer, "^")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_qif;
+    use crate::engine::Engine;
+    use crate::output::StatementOptions;
+
+    #[test]
+    fn test_write_qif() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,10.5\nwithdrawal,1,2,0.5\n";
+        let mut engine = Engine::builder().record_history(true).build();
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let options = StatementOptions {
+            currency: "EUR".to_string(),
+            date: Some("2026-10-16".to_string()),
+        };
+        let mut out = Vec::new();
+        write_qif(&engine, &options, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "!Account\nNClient 1\nTBank\n$10.0000\n/10/16/2026\n^\n!Type:Bank\n",
+                "D10/16/2026\nT10.5000\nN1\nPDeposit\n^\n",
+                "D10/16/2026\nT-0.5000\nN2\nPWithdrawal\n^\n",
+            )
+        );
+    }
+}
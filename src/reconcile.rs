@@ -0,0 +1,204 @@
+use crate::amount::Amount;
+use crate::history::{Activity, History};
+use crate::transactions::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+// Each client's total.
+pub type Balances = HashMap<Client, Amount>;
+
+// The columns read from a balance file; others, such as the engine's
+// `available` and `held`, are ignored.
+#[derive(Deserialize)]
+struct BalanceRow {
+    client: u32,
+    total: String,
+}
+
+// Reads a CSV with `client` and `total` columns, such as the engine's own
+// account output, into each client's total.
+pub fn read_balances<R: Read>(reader: R) -> Result<Balances, Box<dyn Error + Send + Sync>> {
+    let mut balances = HashMap::new();
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    for row in reader.deserialize() {
+        let row: BalanceRow = row?;
+        let total = Amount::from_str(&row.total)?;
+        if balances.insert(Client(row.client), total).is_some() {
+            return Err(format!("client {} is listed twice", row.client).into());
+        }
+    }
+    Ok(balances)
+}
+
+// A client whose totals differ, or who is only on one side.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Mismatch {
+    pub client: Client,
+    pub engine: Option<Amount>,
+    pub external: Option<Amount>,
+}
+
+impl Mismatch {
+    // Engine minus external, in `Amount` units; a missing side counts as 0.
+    pub fn delta(&self) -> i128 {
+        let units = |amount: Option<Amount>| i128::from(amount.map_or(0, |amount| amount.0));
+        units(self.engine) - units(self.external)
+    }
+}
+
+// Mismatches in client order.
+pub fn compare(engine: &Balances, external: &Balances) -> Vec<Mismatch> {
+    let mut clients: Vec<Client> = engine.keys().chain(external.keys()).copied().collect();
+    clients.sort_by_key(|client| client.0);
+    clients.dedup();
+    clients
+        .into_iter()
+        .map(|client| Mismatch {
+            client,
+            engine: engine.get(&client).copied(),
+            external: external.get(&client).copied(),
+        })
+        .filter(|mismatch| mismatch.engine != mismatch.external)
+        .collect()
+}
+
+// How much `activity` changed the client's total, in `Amount` units.
+fn effect(activity: &Activity) -> i128 {
+    match activity.is_credit() {
+        Some(true) => i128::from(activity.amount.0),
+        Some(false) => -i128::from(activity.amount.0),
+        None => 0,
+    }
+}
+
+// The client's transactions that changed its total. When some of them
+// alone account for the delta, e.g. a deposit the other ledger never
+// booked, only those are returned.
+pub fn contributing<'a>(history: &'a History, mismatch: &Mismatch) -> Vec<&'a Activity> {
+    let moved: Vec<&Activity> = history
+        .client(mismatch.client)
+        .iter()
+        .filter(|activity| effect(activity) != 0)
+        .collect();
+    let explaining: Vec<&Activity> = moved
+        .iter()
+        .copied()
+        .filter(|activity| effect(activity) == mismatch.delta())
+        .collect();
+    if explaining.is_empty() {
+        moved
+    } else {
+        explaining
+    }
+}
+
+pub(crate) fn signed(units: i128) -> String {
+    let amount = Amount(units.unsigned_abs() as u64);
+    if units < 0 {
+        format!("-{}", amount)
+    } else {
+        amount.to_string()
+    }
+}
+
+#[derive(Serialize)]
+struct ReportRow {
+    client: u32,
+    engine_total: Option<String>,
+    external_total: Option<String>,
+    delta: Option<String>,
+    tx: Option<u64>,
+    r#type: Option<&'static str>,
+    amount: Option<String>,
+}
+
+// One row per mismatch with both totals and the delta, each followed by
+// the rows of its contributing transactions (see `contributing`) when the
+// history they were applied with is given. Amounts are signed by their
+// effect on the total.
+pub fn write_report<W: Write>(
+    mismatches: &[Mismatch],
+    history: Option<&History>,
+    writer: W,
+) -> csv::Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    writer.write_record([
+        "client",
+        "engine_total",
+        "external_total",
+        "delta",
+        "tx",
+        "type",
+        "amount",
+    ])?;
+    for mismatch in mismatches {
+        writer.serialize(ReportRow {
+            client: mismatch.client.0,
+            engine_total: mismatch.engine.map(|total| total.to_string()),
+            external_total: mismatch.external.map(|total| total.to_string()),
+            delta: Some(signed(mismatch.delta())),
+            tx: None,
+            r#type: None,
+            amount: None,
+        })?;
+        let activities = history.map_or_else(Vec::new, |history| contributing(history, mismatch));
+        for activity in activities {
+            writer.serialize(ReportRow {
+                client: mismatch.client.0,
+                engine_total: None,
+                external_total: None,
+                delta: None,
+                tx: Some(activity.tx.0),
+                r#type: Some(activity.r#type.as_str()),
+                amount: Some(signed(effect(activity))),
+            })?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, read_balances, write_report};
+    use crate::engine::Engine;
+    use crate::output::write_accounts;
+
+    #[test]
+    fn test_reconcile() {
+        let mut engine = Engine::builder().record_history(true).build();
+        let csvfile =
+            "type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,1,2,2.5\nwithdrawal,1,3,1.0\n\
+            deposit,2,4,4.0\ndeposit,2,5,1.0\nwithdrawal,2,6,3.0\ndeposit,3,7,1.0\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let mut accounts = Vec::new();
+        write_accounts(engine.accounts(), &mut accounts).unwrap();
+
+        // the bank never booked tx 2, has client 2 short and knows client 4
+        let external = "client,total\n1,9.0\n2, 1.5\n3,1\n4,0.25\n";
+        let ours = read_balances(accounts.as_slice()).unwrap();
+        let theirs = read_balances(external.as_bytes()).unwrap();
+        let mismatches = compare(&ours, &theirs);
+        assert_eq!(mismatches.len(), 3);
+        assert_eq!(mismatches[1].delta(), 5000);
+
+        let mut out = Vec::new();
+        write_report(&mismatches, engine.history(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,engine_total,external_total,delta,tx,type,amount\n\
+             1,11.5000,9.0000,2.5000,,,\n1,,,,2,deposit,2.5000\n\
+             2,2.0000,1.5000,0.5000,,,\n\
+             2,,,,4,deposit,4.0000\n2,,,,5,deposit,1.0000\n2,,,,6,withdrawal,-3.0000\n\
+             4,,0.2500,-0.2500,,,\n"
+        );
+        assert!(read_balances("client,total\n1,1\n1,2\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,402 @@
+use crate::engine::Engine;
+use crate::funds::{FundingStates, Funds};
+use crate::transactions::{Client, TransactionRecord, TxType};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+// A deliberately simple and slow model of the engine's built-in rules, for
+// differential tests: balances are decimal strings added and subtracted
+// digit by digit, and all state lives in `BTreeMap`s, so it shares nothing
+// with the engine's `Amount`, packed tx log or rule chain. It is written
+// from the rules as the README states them rather than from the engine's
+// code: a subtraction that would go below zero rejects the record instead
+// of stopping at zero, and each logged tx carries its own dispute. It only
+// tells whether a record applies, not the reason it was rejected.
+#[derive(Debug, Default, Clone)]
+pub struct ReferenceModel {
+    accounts: BTreeMap<u32, ReferenceAccount>,
+    // deposits, withdrawals and credits, which later records refer to
+    logged: BTreeMap<u64, Logged>,
+}
+
+#[derive(Debug, Clone)]
+struct Logged {
+    client: u32,
+    amount: String,
+    r#type: TxType,
+    dispute: Dispute,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Dispute {
+    None,
+    Open,
+    ChargedBack,
+}
+
+// Balances written like `Amount` displays them, e.g. `12.3400`. A credit's
+// bonus is released after as much again has been deposited or withdrawn,
+// as with the engine's default `BonusConfig`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ReferenceAccount {
+    pub available: String,
+    pub held: String,
+    pub locked: bool,
+    pub bonus: String,
+    pub turnover: String,
+}
+
+impl Default for ReferenceAccount {
+    fn default() -> ReferenceAccount {
+        ReferenceAccount {
+            available: "0.0000".to_string(),
+            held: "0.0000".to_string(),
+            locked: false,
+            bonus: "0.0000".to_string(),
+            turnover: "0.0000".to_string(),
+        }
+    }
+}
+
+impl From<&Funds> for ReferenceAccount {
+    fn from(funds: &Funds) -> ReferenceAccount {
+        ReferenceAccount {
+            available: funds.available.to_string(),
+            held: funds.held.to_string(),
+            locked: funds.state == FundingStates::Frozen,
+            bonus: funds.bonus.to_string(),
+            turnover: funds.turnover.to_string(),
+        }
+    }
+}
+
+impl ReferenceModel {
+    pub fn new() -> ReferenceModel {
+        ReferenceModel::default()
+    }
+
+    // Applies the record if the rules allow it, returning whether they did.
+    // A rejected record leaves the model as it was.
+    pub fn apply(&mut self, record: &TransactionRecord) -> bool {
+        self.try_apply(record).is_some()
+    }
+
+    fn try_apply(&mut self, record: &TransactionRecord) -> Option<()> {
+        let (client, tx) = (record.client.0, record.tx.0);
+        if self
+            .accounts
+            .get(&client)
+            .is_some_and(|account| account.locked)
+        {
+            return None;
+        }
+        match record.r#type {
+            TxType::Deposit | TxType::Withdrawal | TxType::Credit => {
+                let amount = record.amount?.to_string();
+                if self.logged.contains_key(&tx) {
+                    return None;
+                }
+                let account = match record.r#type {
+                    TxType::Deposit => self.accounts.entry(client).or_default(),
+                    _ => self.accounts.get_mut(&client)?,
+                };
+                match record.r#type {
+                    TxType::Deposit => {
+                        account.available = add(&account.available, &amount);
+                        turn_over(account, &amount);
+                    }
+                    TxType::Withdrawal => {
+                        // the bonus can't be withdrawn
+                        let withdrawable = sub(&account.available, &account.bonus)?;
+                        sub(&withdrawable, &amount)?;
+                        account.available = sub(&account.available, &amount)?;
+                        turn_over(account, &amount);
+                    }
+                    _ => {
+                        account.available = add(&account.available, &amount);
+                        account.bonus = add(&account.bonus, &amount);
+                        account.turnover = add(&account.turnover, &amount);
+                    }
+                }
+                let logged = Logged {
+                    client,
+                    amount,
+                    r#type: record.r#type,
+                    dispute: Dispute::None,
+                };
+                self.logged.insert(tx, logged);
+                Some(())
+            }
+            // without a `[settlement]` section no deposit is pending, so
+            // there is nothing to settle
+            TxType::Settle => None,
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback => {
+                let account = self.accounts.get_mut(&client)?;
+                let logged = self.logged.get_mut(&tx)?;
+                if logged.client != client || logged.r#type == TxType::Credit {
+                    return None;
+                }
+                let amount = &logged.amount;
+                match (record.r#type, logged.dispute) {
+                    // disputing nothing changes nothing, the tx included
+                    (TxType::Dispute, Dispute::None) if compare(amount, "0") == Ordering::Equal => {
+                    }
+                    (TxType::Dispute, Dispute::None) => {
+                        account.available = sub(&account.available, amount)?;
+                        account.held = add(&account.held, amount);
+                        // a disputed deposit may have been spent down to the
+                        // bonus
+                        if compare(&account.bonus, &account.available) == Ordering::Greater {
+                            account.bonus = account.available.clone();
+                        }
+                        logged.dispute = Dispute::Open;
+                    }
+                    (TxType::Resolve, Dispute::Open) => {
+                        account.held = sub(&account.held, amount)?;
+                        account.available = add(&account.available, amount);
+                        logged.dispute = Dispute::None;
+                    }
+                    (TxType::Chargeback, Dispute::Open) => {
+                        account.held = sub(&account.held, amount)?;
+                        account.locked = true;
+                        logged.dispute = Dispute::ChargedBack;
+                    }
+                    _ => return None,
+                }
+                Some(())
+            }
+        }
+    }
+
+    pub fn accounts(&self) -> &BTreeMap<u32, ReferenceAccount> {
+        &self.accounts
+    }
+}
+
+// Counts a deposit or withdrawal towards a locked bonus's turnover.
+fn turn_over(account: &mut ReferenceAccount, amount: &str) {
+    if compare(&account.bonus, "0") == Ordering::Equal {
+        return;
+    }
+    match sub(&account.turnover, amount) {
+        Some(left) if compare(&left, "0") == Ordering::Greater => account.turnover = left,
+        _ => {
+            account.turnover = "0.0000".to_string();
+            account.bonus = "0.0000".to_string();
+        }
+    }
+}
+
+// Decimal digits with the 4 fraction digits implied, leading zeros dropped
+// so that the longer number is the larger.
+fn digits(amount: &str) -> Vec<u8> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let mut digits: Vec<u8> = whole.bytes().map(|b| b - b'0').collect();
+    digits.extend((0..4).map(|i| fraction.as_bytes().get(i).map_or(0, |b| b - b'0')));
+    let zeros = digits.iter().take_while(|digit| **digit == 0).count();
+    digits.split_off(zeros)
+}
+
+fn format(digits: &[u8]) -> String {
+    let zeros = digits.iter().take_while(|digit| **digit == 0).count();
+    let digits = &digits[zeros..];
+    let mut padded = vec![0; 5usize.saturating_sub(digits.len())];
+    padded.extend_from_slice(digits);
+    let text: String = padded
+        .iter()
+        .map(|digit| char::from(b'0' + digit))
+        .collect();
+    let point = text.len() - 4;
+    format!("{}.{}", &text[..point], &text[point..])
+}
+
+fn compare(a: &str, b: &str) -> Ordering {
+    let (a, b) = (digits(a), digits(b));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(&b))
+}
+
+fn add(a: &str, b: &str) -> String {
+    let (a, b) = (digits(a), digits(b));
+    let mut sum = Vec::new();
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let digit = |digits: &[u8]| digits.len().checked_sub(i + 1).map_or(0, |at| digits[at]);
+        let total = digit(&a) + digit(&b) + carry;
+        sum.push(total % 10);
+        carry = total / 10;
+    }
+    if carry > 0 {
+        sum.push(carry);
+    }
+    sum.reverse();
+    format(&sum)
+}
+
+// `a - b`, or None when `b` is more than `a`.
+fn sub(a: &str, b: &str) -> Option<String> {
+    if compare(a, b) == Ordering::Less {
+        return None;
+    }
+    let (a, b) = (digits(a), digits(b));
+    let mut difference = Vec::new();
+    let mut borrow = 0;
+    for i in 0..a.len() {
+        let b = b.len().checked_sub(i + 1).map_or(0, |at| b[at]);
+        let mut digit = a[a.len() - 1 - i] as i8 - b as i8 - borrow;
+        borrow = 0;
+        if digit < 0 {
+            digit += 10;
+            borrow = 1;
+        }
+        difference.push(digit as u8);
+    }
+    difference.reverse();
+    Some(format(&difference))
+}
+
+// Where the engine and the model first disagree.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Difference {
+    // 1-based position of a record only one of them applied
+    Decision {
+        position: usize,
+        record: TransactionRecord,
+        engine_applied: bool,
+    },
+    // an account that ended up different, None when only one has it
+    Account {
+        client: Client,
+        engine: Option<Box<ReferenceAccount>>,
+        model: Option<Box<ReferenceAccount>>,
+    },
+}
+
+// Runs the records through the engine and a fresh model side by side.
+pub fn differential<'a, I>(engine: &mut Engine, records: I) -> Result<(), Difference>
+where
+    I: IntoIterator<Item = &'a TransactionRecord>,
+{
+    let mut model = ReferenceModel::new();
+    for (index, record) in records.into_iter().enumerate() {
+        let engine_applied = engine.try_process(record).is_ok();
+        if engine_applied != model.apply(record) {
+            return Err(Difference::Decision {
+                position: index + 1,
+                record: record.clone(),
+                engine_applied,
+            });
+        }
+    }
+    let engine_accounts: BTreeMap<u32, ReferenceAccount> = engine
+        .accounts()
+        .map(|funds| (funds.client.0, ReferenceAccount::from(funds)))
+        .collect();
+    let clients = engine_accounts.keys().chain(model.accounts().keys());
+    for client in clients {
+        let (engine, model) = (engine_accounts.get(client), model.accounts().get(client));
+        if engine != model {
+            return Err(Difference::Account {
+                client: Client(*client),
+                engine: engine.cloned().map(Box::new),
+                model: model.cloned().map(Box::new),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add, compare, differential, sub, Difference, ReferenceModel};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::testkit::{Generator, GeneratorConfig, Row};
+    use crate::transactions::{Client, TransactionRecord, Tx, TxType};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn test_reference_model() {
+        assert_eq!(add("999.9999", "0.0001"), "1000.0000");
+        assert_eq!(sub("1000.0000", "0.0001").unwrap(), "999.9999");
+        assert_eq!(sub("1.0000", "2.0000"), None);
+        assert_eq!(compare("10.0000", "9.9999"), Ordering::Greater);
+
+        for seed in 0..5 {
+            let config = GeneratorConfig {
+                clients: 20,
+                transactions: 5000,
+                dispute_ratio: 0.05,
+                seed: Some(seed),
+                ..GeneratorConfig::default()
+            };
+            let records: Vec<TransactionRecord> = Generator::new(config)
+                .filter_map(|row| match row {
+                    Row::Record(record) => Some(record),
+                    Row::Malformed(_) => None,
+                })
+                .collect();
+            assert_eq!(differential(&mut Engine::new(), &records), Ok(()));
+        }
+
+        // credits lock their bonus until as much again has moved
+        let client = Client(2);
+        let credits = [
+            TransactionRecord::deposit(client, Tx(10), Amount::new(100)),
+            TransactionRecord::credit(client, Tx(11), Amount::new(50)),
+            TransactionRecord::withdrawal(client, Tx(12), Amount::new(120)),
+            TransactionRecord::dispute(client, Tx(11)),
+            TransactionRecord::withdrawal(client, Tx(13), Amount::new(30)),
+            TransactionRecord::deposit(client, Tx(14), Amount::new(20)),
+            TransactionRecord::withdrawal(client, Tx(15), Amount::new(120)),
+        ];
+        assert_eq!(differential(&mut Engine::new(), &credits), Ok(()));
+
+        // disputes are per tx, and can't hold more than is available
+        let client = Client(3);
+        let disputes = [
+            TransactionRecord::deposit(client, Tx(20), Amount::new(1)),
+            TransactionRecord::deposit(client, Tx(21), Amount::new(100)),
+            TransactionRecord::dispute(client, Tx(20)),
+            TransactionRecord::resolve(client, Tx(21)),
+            TransactionRecord::dispute(client, Tx(20)),
+            TransactionRecord::withdrawal(client, Tx(22), Amount::new(95)),
+            TransactionRecord::dispute(client, Tx(21)),
+            TransactionRecord::resolve(client, Tx(21)),
+            TransactionRecord::chargeback(client, Tx(20)),
+        ];
+        let mut model = ReferenceModel::new();
+        let applied: Vec<bool> = disputes.iter().map(|record| model.apply(record)).collect();
+        assert_eq!(
+            applied,
+            [true, true, true, false, false, true, false, false, true]
+        );
+        let account = &model.accounts()[&3];
+        assert_eq!(
+            (
+                account.available.as_str(),
+                account.held.as_str(),
+                account.locked
+            ),
+            ("0.0005", "0.0000", true)
+        );
+        assert_eq!(differential(&mut Engine::new(), &disputes), Ok(()));
+
+        // an engine that already saw a deposit disagrees from the start
+        let deposit = TransactionRecord {
+            r#type: TxType::Deposit,
+            client: Client(1),
+            tx: Tx(1),
+            amount: Some(Amount::new(10000)),
+        };
+        let mut engine = Engine::new();
+        engine.process(&deposit);
+        assert_eq!(
+            differential(&mut engine, std::slice::from_ref(&deposit)),
+            Err(Difference::Decision {
+                position: 1,
+                record: deposit,
+                engine_applied: false,
+            })
+        );
+    }
+}
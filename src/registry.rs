@@ -0,0 +1,152 @@
+use crate::rules::{KycStatus, Tier};
+use crate::transactions::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// The `[registry]` config section.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegistryConfig {
+    pub path: PathBuf,
+}
+
+// What the registry says about one client. Every field but the id may be
+// left empty.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ClientInfo {
+    pub client: Client,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub kyc: Option<KycStatus>,
+    #[serde(default)]
+    pub tier: Option<Tier>,
+}
+
+// Names, KYC statuses and tiers of clients, read from a side file: CSV
+// with a `client,name,kyc,tier` header, or, for a `.json` file, an array
+// of objects with those keys. Rules see a client's KYC status and tier
+// through `ClientStats`; clients the file doesn't list have neither.
+// Clones share the entries, e.g. between tenants.
+#[derive(Debug, Clone, Default)]
+pub struct ClientRegistry {
+    clients: Arc<HashMap<Client, ClientInfo>>,
+}
+
+impl ClientRegistry {
+    pub fn load(config: &RegistryConfig) -> io::Result<ClientRegistry> {
+        let invalid = |reason: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", config.path.display(), reason),
+            )
+        };
+        let text = fs::read_to_string(&config.path)?;
+        let entries = if is_json(&config.path) {
+            serde_json::from_str(&text).map_err(|err| invalid(err.to_string()))?
+        } else {
+            parse_csv(&text).map_err(|err| invalid(err.to_string()))?
+        };
+        Ok(ClientRegistry::new(entries))
+    }
+
+    // A later entry for the same client replaces an earlier one.
+    pub fn new<I: IntoIterator<Item = ClientInfo>>(entries: I) -> ClientRegistry {
+        let clients = entries
+            .into_iter()
+            .map(|info| (info.client, info))
+            .collect();
+        ClientRegistry {
+            clients: Arc::new(clients),
+        }
+    }
+
+    pub fn get(&self, client: Client) -> Option<&ClientInfo> {
+        self.clients.get(&client)
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+fn parse_csv(text: &str) -> csv::Result<Vec<ClientInfo>> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes())
+        .deserialize()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_csv, ClientInfo, ClientRegistry};
+    use crate::engine::Engine;
+    use crate::output::write_extended_accounts;
+    use crate::policy::PolicyRule;
+    use crate::rules::{KycStatus, Tier};
+    use crate::tenant::Tenants;
+    use crate::transactions::{Client, RejectReason};
+
+    #[test]
+    fn test_client_registry() {
+        let csvfile = "client,name,kyc,tier\n1,Ada,verified,premium\n2,Bob,pending,\n3,,,\n";
+        let registry = ClientRegistry::new(parse_csv(csvfile).unwrap());
+        assert_eq!(
+            registry.get(Client(1)),
+            Some(&ClientInfo {
+                client: Client(1),
+                name: "Ada".to_string(),
+                kyc: Some(KycStatus::Verified),
+                tier: Some(Tier::Premium),
+            })
+        );
+        assert_eq!(registry.get(Client(3)).unwrap().kyc, None);
+        let json: Vec<ClientInfo> =
+            serde_json::from_str(r#"[{"client":2,"name":"Bob","kyc":"pending"}]"#).unwrap();
+        assert_eq!(json[0], *registry.get(Client(2)).unwrap());
+        assert!(parse_csv("client,name,kyc,tier\n1,Ada,unknown,basic\n").is_err());
+
+        let rule: PolicyRule = "reject withdrawal if kyc == pending as kyc_pending"
+            .parse()
+            .unwrap();
+        let builder = Engine::builder().registry(registry).rule(rule);
+        let mut tenants = Tenants::single(builder);
+        let engine = tenants.engine("default");
+        let input = "type,client,tx,amount\ndeposit,1,1,10\ndeposit,2,2,10\ndeposit,4,3,10\nwithdrawal,1,4,1\nwithdrawal,2,5,1\nwithdrawal,4,6,1\n";
+        engine.process_csv(input.as_bytes()).unwrap();
+        let metrics = engine.metrics();
+        assert_eq!(metrics.rejected[&RejectReason::Rule("kyc_pending")], 1);
+        assert_eq!(
+            engine.stats(Client(2)).unwrap().kyc,
+            Some(KycStatus::Pending)
+        );
+
+        let mut out = Vec::new();
+        write_extended_accounts(&tenants, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines: Vec<&str> = out.lines().collect();
+        lines.sort_unstable();
+        assert_eq!(
+            lines,
+            [
+                "1,9.0000,0.0000,9.0000,false,,0,Ada,verified,premium",
+                "2,10.0000,0.0000,10.0000,false,,0,Bob,pending,",
+                "4,9.0000,0.0000,9.0000,false,,0,,,",
+                "client,available,held,total,locked,flags,risk_score,name,kyc,tier",
+            ]
+        );
+    }
+}
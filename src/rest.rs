@@ -0,0 +1,581 @@
+use crate::amount::Amount;
+use crate::audit::AuditRow;
+use crate::auth::{AuthConfig, AuthLayer, Denied, Scope};
+use crate::funds::Funds;
+use crate::history::HistoryFilter;
+use crate::json::parse_record;
+use crate::output::{account_rows, AccountRow};
+use crate::serve::{Ingest, SharedTenants};
+use crate::tenant::TENANT_HEADER;
+use crate::transactions::{Client, TxType};
+use axum::body::{Body, Bytes};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::{header::CONTENT_TYPE, HeaderMap, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// How many account changes a slow feed subscriber may fall behind before it
+// starts missing them.
+const FEED_BUFFER: usize = 1024;
+
+// History page sizes, when `limit` isn't given and at most.
+const DEFAULT_PAGE: usize = 100;
+const MAX_PAGE: usize = 1000;
+
+// The tenants' engines, the queue submissions go through and the feed of
+// accounts changed through them, shared by all handlers.
+#[derive(Clone)]
+pub struct AppState {
+    tenants: SharedTenants,
+    ingest: Ingest,
+    changes: broadcast::Sender<(String, AccountRow)>,
+}
+
+impl AppState {
+    pub fn new(tenants: SharedTenants, ingest: Ingest) -> AppState {
+        AppState {
+            tenants,
+            ingest,
+            changes: broadcast::channel(FEED_BUFFER).0,
+        }
+    }
+
+    // The tenant named by the `x-tenant` header, or the default one.
+    fn tenant(&self, headers: &HeaderMap) -> Result<String, String> {
+        let requested = match headers.get(TENANT_HEADER) {
+            Some(value) => Some(
+                value
+                    .to_str()
+                    .map_err(|_| "invalid tenant header".to_string())?,
+            ),
+            None => None,
+        };
+        self.tenants.resolve(requested)
+    }
+
+    fn publish(&self, tenant: &str, funds: &Funds) {
+        // no receivers just means nobody is watching
+        let _ = self
+            .changes
+            .send((tenant.to_string(), AccountRow::from(funds)));
+    }
+}
+
+fn error(status: StatusCode, message: impl ToString) -> Response {
+    (status, Json(json!({ "error": message.to_string() }))).into_response()
+}
+
+fn no_account(client: u32) -> Response {
+    error(
+        StatusCode::NOT_FOUND,
+        format!("no account for client {}", client),
+    )
+}
+
+fn no_tenant(tenant: &str) -> Response {
+    error(StatusCode::NOT_FOUND, format!("no tenant `{}`", tenant))
+}
+
+// Takes the same JSON object as a line of NDJSON input. A rejected
+// transaction is answered with 422 and the reason code the gRPC service
+// reports, e.g. `insufficient_funds`.
+async fn submit(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let tenant = match state.tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    let record = match parse_record(&body) {
+        Ok(record) => record,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    // published by the applier under the engine's lock, so the feed sees
+    // changes in the order they were made
+    let tx = record.tx.0;
+    let publisher = state.clone();
+    let result = state
+        .ingest
+        .apply(tenant.clone(), move |engine| {
+            let result = engine.try_process(&record);
+            if let (Ok(()), Some(funds)) = (result, engine.account(record.client)) {
+                publisher.publish(&tenant, funds);
+            }
+            result
+        })
+        .await;
+    match result {
+        Some(Ok(())) => Json(json!({ "tx": tx, "applied": true })).into_response(),
+        Some(Err(reason)) => {
+            let body = json!({ "tx": tx, "applied": false, "reason": reason.as_str() });
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response()
+        }
+        None => error(StatusCode::SERVICE_UNAVAILABLE, "server is shutting down"),
+    }
+}
+
+async fn metrics(State(state): State<AppState>) -> Response {
+    let body = crate::prometheus::render(&state.tenants);
+    ([(CONTENT_TYPE, crate::prometheus::CONTENT_TYPE)], body).into_response()
+}
+
+// A tenant nothing was submitted to yet has no accounts. Only the engine
+// knows its sub-accounts, so rolling them up waits for it.
+async fn accounts(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let tenant = match state.tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    let rows = match state.tenants.get(&tenant) {
+        Some(engine) => {
+            let rolled_up = engine.read(|engine| {
+                (!engine.subaccounts().is_empty()).then(|| engine.rolled_up_accounts())
+            });
+            account_rows(&rolled_up.unwrap_or_else(|| engine.accounts()))
+        }
+        None => Vec::new(),
+    };
+    Json(rows).into_response()
+}
+
+async fn account(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(client): Path<u32>,
+) -> Response {
+    let tenant = match state.tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    match state
+        .tenants
+        .get(&tenant)
+        .and_then(|engine| engine.account(Client(client)))
+    {
+        Some(funds) => Json(AccountRow::from(&funds)).into_response(),
+        None => no_account(client),
+    }
+}
+
+// The changes made to the client's account, oldest first; empty for a
+// client without one. An unknown tenant is 404 rather than created.
+async fn audit(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(client): Path<u32>,
+) -> Response {
+    let tenant = match state.tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    let engine = match state.tenants.get(&tenant) {
+        Some(engine) => engine,
+        None => return no_tenant(&tenant),
+    };
+    let rows: Option<Vec<AuditRow>> = engine.read(|engine| {
+        let log = engine.audit()?;
+        Some(log.client(Client(client)).map(AuditRow::from).collect())
+    });
+    match rows {
+        Some(rows) => Json(rows).into_response(),
+        None => error(StatusCode::NOT_FOUND, "no audit log is kept"),
+    }
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    r#type: Option<TxType>,
+    min_amount: Option<String>,
+    max_amount: Option<String>,
+    #[serde(default)]
+    cursor: usize,
+    limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    fn filter(&self) -> Result<HistoryFilter, String> {
+        let amount = |bound: &Option<String>| {
+            bound
+                .as_deref()
+                .map(Amount::from_str)
+                .transpose()
+                .map_err(|err| err.to_string())
+        };
+        Ok(HistoryFilter {
+            r#type: self.r#type,
+            min_amount: amount(&self.min_amount)?,
+            max_amount: amount(&self.max_amount)?,
+        })
+    }
+}
+
+// A page of the client's applied transactions, oldest first, filtered by
+// `type`, `min_amount` and `max_amount`. `next` in the answer is the
+// `cursor` of the page after it.
+async fn history(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(client): Path<u32>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    let tenant = match state.tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    let filter = match query.filter() {
+        Ok(filter) => filter,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE).min(MAX_PAGE);
+    let engine = match state.tenants.get(&tenant) {
+        Some(engine) => engine,
+        None => return no_account(client),
+    };
+    engine.read(|engine| {
+        let page = match engine.history() {
+            Some(history) => history.page(Client(client), &filter, query.cursor, limit),
+            None => return error(StatusCode::NOT_FOUND, "no history is kept"),
+        };
+        let activity: Vec<_> = page
+            .activity
+            .iter()
+            .map(|activity| {
+                json!({
+                    "type": activity.r#type,
+                    "tx": activity.tx.0,
+                    "amount": activity.amount.to_string(),
+                })
+            })
+            .collect();
+        Json(json!({ "activity": activity, "next": page.next })).into_response()
+    })
+}
+
+async fn unfreeze(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(client): Path<u32>,
+) -> Response {
+    let tenant = match state.tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    let engine = match state.tenants.get(&tenant) {
+        Some(engine) => engine,
+        None => return no_account(client),
+    };
+    engine.with_engine(|engine| match engine.unfreeze(Client(client)) {
+        Some(true) => {
+            let funds = engine
+                .account(Client(client))
+                .expect("account was just unfrozen");
+            state.publish(&tenant, funds);
+            Json(AccountRow::from(funds)).into_response()
+        }
+        Some(false) => error(StatusCode::CONFLICT, "account is not frozen"),
+        None => no_account(client),
+    })
+}
+
+// Rereads the denylist file, which every tenant shares. Naming a tenant
+// nothing was submitted to yet is an error rather than a way to create it.
+async fn reload_denylist(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let tenant = match state.tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    let engine = match state.tenants.get(&tenant) {
+        Some(engine) => engine,
+        None => return no_tenant(&tenant),
+    };
+    match engine.read(|engine| engine.denylist().map(|list| list.reload())) {
+        Some(Ok(entries)) => Json(json!({ "denylist_entries": entries })).into_response(),
+        Some(Err(err)) => error(StatusCode::UNPROCESSABLE_ENTITY, err),
+        None => error(StatusCode::NOT_FOUND, "no denylist is configured"),
+    }
+}
+
+#[derive(Deserialize)]
+struct FeedFilter {
+    client: Option<u32>,
+}
+
+// Upgrades to a WebSocket that receives every changed account of the
+// tenant as a JSON text message, or only one client's with `?client=`.
+async fn feed(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(filter): Query<FeedFilter>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let tenant = match state.tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    let changes = state.changes.subscribe();
+    upgrade.on_upgrade(move |socket| push_changes(socket, changes, tenant, filter.client))
+}
+
+async fn push_changes(
+    mut socket: WebSocket,
+    mut changes: broadcast::Receiver<(String, AccountRow)>,
+    tenant: String,
+    client: Option<u32>,
+) {
+    loop {
+        let (changed, account) = match changes.recv().await {
+            Ok(change) => change,
+            // a lagging front-end only misses intermediate balances; the
+            // next change for an account carries its current state
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        if changed != tenant || client.is_some_and(|client| account.client != client) {
+            continue;
+        }
+        let text = serde_json::to_string(&account).expect("account rows serialize");
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            return;
+        }
+    }
+}
+
+// GraphQL only has queries, so it needs no more than `read`.
+fn required_scope(method: &Method, path: &str) -> Scope {
+    match (method, path) {
+        (&Method::POST, "/transactions") => Scope::Submit,
+        (&Method::POST, path) if path.ends_with("/unfreeze") => Scope::Admin,
+        (&Method::POST, "/denylist/reload") => Scope::Admin,
+        _ => Scope::Read,
+    }
+}
+
+fn deny(denied: Denied) -> Response {
+    let status = match denied {
+        Denied::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Denied::Forbidden(_) => StatusCode::FORBIDDEN,
+    };
+    error(status, denied)
+}
+
+pub fn auth_layer(config: Option<Arc<AuthConfig>>) -> AuthLayer<Body> {
+    AuthLayer::new(config, required_scope, deny)
+}
+
+pub fn router(state: AppState) -> Router {
+    #[cfg(feature = "graphql")]
+    let graphql = crate::graphql::router(state.tenants.clone());
+    let router = Router::new()
+        .route("/transactions", post(submit))
+        .route("/accounts", get(accounts))
+        .route("/accounts/{client}", get(account))
+        .route("/accounts/{client}/audit", get(audit))
+        .route("/accounts/{client}/history", get(history))
+        .route("/accounts/{client}/unfreeze", post(unfreeze))
+        .route("/denylist/reload", post(reload_denylist))
+        .route("/feed", get(feed))
+        .route("/metrics", get(metrics))
+        .with_state(state);
+    #[cfg(feature = "graphql")]
+    let router = router.merge(graphql);
+    router
+}
+
+// Serves the engines on `addr` until interrupted (Ctrl-C), checking API
+// keys against `auth` when given.
+pub fn serve(
+    tenants: SharedTenants,
+    ingest: Ingest,
+    addr: SocketAddr,
+    auth: Option<Arc<AuthConfig>>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let app = router(AppState::new(tenants, ingest)).layer(auth_layer(auth));
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            axum::serve(listener, app)
+                .with_graceful_shutdown(async {
+                    let _ = tokio::signal::ctrl_c().await;
+                })
+                .await
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{auth_layer, router, AppState};
+    use crate::auth::{ApiKey, AuthConfig, Scope};
+    use crate::engine::Engine;
+    use crate::serve::{Applier, ServerConfig, SharedTenants};
+    use crate::tenant::Tenants;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    // The applier stops (after what is queued) once it is dropped.
+    fn state(tenants: Tenants) -> (AppState, Applier) {
+        let tenants = SharedTenants::new(tenants);
+        let applier = Applier::spawn(tenants.clone(), ServerConfig::default());
+        (AppState::new(tenants, applier.ingest()), applier)
+    }
+
+    async fn call(state: &AppState, method: &str, uri: &str, body: &str) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router(state.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_rest_api() {
+        let builder = Engine::builder().record_history(true);
+        let (state, _applier) = state(Tenants::single(builder));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let submit = |body| call(&state, "POST", "/transactions", body);
+            submit(r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "3"}"#).await;
+            submit(r#"{"type": "dispute", "client": 1, "tx": 1}"#).await;
+            submit(r#"{"type": "chargeback", "client": 1, "tx": 1}"#).await;
+            let (status, body) =
+                submit(r#"{"type": "deposit", "client": 1, "tx": 2, "amount": 1}"#).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(body["reason"], "account_frozen");
+            let (status, _) = submit(r#"{"type": "deposit"}"#).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+
+            let (status, body) = call(&state, "POST", "/accounts/1/unfreeze", "").await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body["locked"], false);
+            let (status, _) = call(&state, "POST", "/accounts/1/unfreeze", "").await;
+            assert_eq!(status, StatusCode::CONFLICT);
+
+            let (status, body) =
+                submit(r#"{"type": "deposit", "client": 1, "tx": 2, "amount": 1}"#).await;
+            assert_eq!(
+                (status, body),
+                (StatusCode::OK, json!({"tx": 2, "applied": true}))
+            );
+            let (_, body) = call(&state, "GET", "/accounts", "").await;
+            assert_eq!(body[0]["available"], "1.0000");
+            let (status, _) = call(&state, "GET", "/accounts/2", "").await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+
+            let (_, body) = call(&state, "GET", "/accounts/1/history?limit=2", "").await;
+            assert_eq!(body["next"], 2);
+            assert_eq!(
+                body["activity"][1],
+                json!({"type": "dispute", "tx": 1, "amount": "3.0000"})
+            );
+            let uri = "/accounts/1/history?type=deposit&max_amount=2&cursor=2";
+            let (_, body) = call(&state, "GET", uri, "").await;
+            assert_eq!(
+                body,
+                json!({"activity": [{"type": "deposit", "tx": 2, "amount": "1.0000"}], "next": null})
+            );
+            let uri = "/accounts/1/history?min_amount=x";
+            let (status, _) = call(&state, "GET", uri, "").await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        });
+    }
+
+    #[test]
+    fn test_feed_publishes_changes() {
+        let (state, _applier) = state(Tenants::single(Engine::builder()));
+        let mut changes = state.changes.subscribe();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let deposit = r#"{"type": "deposit", "client": 4, "tx": 1, "amount": "2.5"}"#;
+            call(&state, "POST", "/transactions", deposit).await;
+            let withdrawal = r#"{"type": "withdrawal", "client": 4, "tx": 2, "amount": "9"}"#;
+            call(&state, "POST", "/transactions", withdrawal).await;
+        });
+        let (tenant, account) = changes.try_recv().unwrap();
+        assert_eq!(tenant, "default");
+        let account = serde_json::to_value(account).unwrap();
+        assert_eq!(account["client"], 4);
+        assert_eq!(account["available"], "2.5000");
+        // rejected transactions change nothing, so they aren't published
+        assert!(changes.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_auth() {
+        let config = AuthConfig {
+            keys: vec![ApiKey {
+                key: "ingest".to_string(),
+                scopes: vec![Scope::Submit],
+            }],
+        };
+        let (state, _applier) = state(Tenants::single(Engine::builder()));
+        let app = router(state).layer(auth_layer(Some(Arc::new(config))));
+        let status = |uri: &str, key: Option<&str>| {
+            let mut request = Request::builder().method("POST").uri(uri);
+            if let Some(key) = key {
+                request = request.header("authorization", format!("Bearer {}", key));
+            }
+            let body = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "1"}"#;
+            let request = request.body(Body::from(body)).unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            assert_eq!(
+                status("/transactions", None).await,
+                StatusCode::UNAUTHORIZED
+            );
+            assert_eq!(
+                status("/transactions", Some("ingest")).await,
+                StatusCode::OK
+            );
+            assert_eq!(
+                status("/accounts/1/unfreeze", Some("ingest")).await,
+                StatusCode::FORBIDDEN
+            );
+        });
+    }
+
+    #[test]
+    fn test_unknown_tenant() {
+        let (state, _applier) = state(Tenants::isolated(Engine::builder()));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            for (method, uri) in [("POST", "/denylist/reload"), ("GET", "/accounts/1/audit")] {
+                let request = Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("x-tenant", "acme")
+                    .body(Body::empty())
+                    .unwrap();
+                let response = router(state.clone()).oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            }
+        });
+        assert!(state.tenants.get("acme").is_none());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::aml::RiskFlag;
+use crate::amount::Amount;
+use crate::funds::{FundingStates, Funds};
+use crate::transactions::{TransactionRecord, TxType};
+use serde::Serialize;
+use std::sync::mpsc::Sender;
+
+// Account events that fraud and support systems want to hear about as soon
+// as they are applied. Serialized as e.g.
+// `{"event":"chargeback","client":2,"tx":7,"amount":"10.0000"}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RiskEvent {
+    AccountFrozen {
+        client: u32,
+        tx: u64,
+    },
+    Chargeback {
+        client: u32,
+        tx: u64,
+        amount: String,
+    },
+    LargeWithdrawal {
+        client: u32,
+        tx: u64,
+        amount: String,
+    },
+    // raised by the AML heuristics, see `aml`
+    Flagged {
+        client: u32,
+        tx: u64,
+        flag: RiskFlag,
+    },
+}
+
+// Reports risk events from an engine (see `EngineBuilder::risk_monitor`)
+// over a channel, so whoever delivers them never holds up processing.
+#[derive(Clone)]
+pub struct RiskMonitor {
+    events: Sender<RiskEvent>,
+    large_withdrawal: Option<Amount>,
+}
+
+impl RiskMonitor {
+    // Withdrawals of at least `large_withdrawal` are reported; without a
+    // threshold none are.
+    pub fn new(events: Sender<RiskEvent>, large_withdrawal: Option<Amount>) -> RiskMonitor {
+        RiskMonitor {
+            events,
+            large_withdrawal,
+        }
+    }
+
+    // Called with an applied record, the amount it moved and whether the
+    // account was frozen before it.
+    pub(crate) fn observe(
+        &self,
+        record: &TransactionRecord,
+        amount: Option<Amount>,
+        was_frozen: bool,
+        funds: Option<&Funds>,
+    ) {
+        let (client, tx) = (record.client.0, record.tx.0);
+        let amount = amount.map(|amount| amount.to_string());
+        let mut events = Vec::new();
+        match (record.r#type, amount) {
+            (TxType::Chargeback, Some(amount)) => {
+                events.push(RiskEvent::Chargeback { client, tx, amount })
+            }
+            (TxType::Withdrawal, Some(amount))
+                if self
+                    .large_withdrawal
+                    .is_some_and(|threshold| record.amount >= Some(threshold)) =>
+            {
+                events.push(RiskEvent::LargeWithdrawal { client, tx, amount })
+            }
+            _ => {}
+        }
+        if !was_frozen && funds.is_some_and(|funds| funds.state == FundingStates::Frozen) {
+            events.push(RiskEvent::AccountFrozen { client, tx });
+        }
+        for event in events {
+            // a monitor whose receiver is gone just stops reporting
+            let _ = self.events.send(event);
+        }
+    }
+
+    pub(crate) fn flagged(&self, record: &TransactionRecord, flag: RiskFlag) {
+        let _ = self.events.send(RiskEvent::Flagged {
+            client: record.client.0,
+            tx: record.tx.0,
+            flag,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RiskEvent, RiskMonitor};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_risk_events() {
+        let (sender, events) = mpsc::channel();
+        let mut engine = Engine::builder()
+            .risk_monitor(RiskMonitor::new(sender, Some(Amount::new(50_000))))
+            .build();
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,20.0\nwithdrawal,1,2,4.0\nwithdrawal,1,3,5.0\ndeposit,2,4,1.5\ndispute,2,4,\nchargeback,2,4,\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                RiskEvent::LargeWithdrawal {
+                    client: 1,
+                    tx: 3,
+                    amount: "5.0000".to_string()
+                },
+                RiskEvent::Chargeback {
+                    client: 2,
+                    tx: 4,
+                    amount: "1.5000".to_string()
+                },
+                RiskEvent::AccountFrozen { client: 2, tx: 4 },
+            ]
+        );
+    }
+}
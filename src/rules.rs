@@ -0,0 +1,41 @@
+// Part of the no_std core, see `payment_engine_core`.
+pub use payment_engine_core::rules::*;
+
+#[cfg(test)]
+mod tests {
+    use super::{RuleContext, ValidationRule};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::{Client, RejectReason};
+
+    #[test]
+    fn test_custom_rule() {
+        struct Limit(Amount);
+
+        impl ValidationRule for Limit {
+            fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+                match ctx.record.amount {
+                    Some(amount) if amount > self.0 => Err(RejectReason::Rule("over_limit")),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let no_client_seven = |ctx: &RuleContext<'_>| match ctx.record.client {
+            Client(7) => Err(RejectReason::Rule("blocked_client")),
+            _ => Ok(()),
+        };
+        let mut engine = Engine::builder()
+            .rule(Limit(Amount::new(50000)))
+            .rule(no_client_seven)
+            .build();
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,1,2,9.0\ndeposit,7,3,1.0\nwithdrawal,1,4,9.0\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let metrics = engine.metrics();
+        assert_eq!(metrics.rejected[&RejectReason::Rule("over_limit")], 1);
+        assert_eq!(metrics.rejected[&RejectReason::Rule("blocked_client")], 1);
+        // built-in rules come first
+        assert_eq!(metrics.rejected[&RejectReason::InsufficientFunds], 1);
+        assert!(engine.account(Client(7)).is_none());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::transactions::RowRecord;
+use csv::StringRecord;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::io;
+
+// Input layouts the CSV reader understands. v1 is the original
+// `type,client,tx,amount`; v2 adds a `timestamp` (unix seconds) and an ISO
+// 4217 `currency` to every row. Files with headers are told apart by their
+// columns, headerless files use the `schema_version` from `[csv]`.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(try_from = "u8")]
+pub enum SchemaVersion {
+    #[default]
+    V1,
+    V2,
+}
+
+impl TryFrom<u8> for SchemaVersion {
+    type Error = &'static str;
+
+    fn try_from(version: u8) -> Result<SchemaVersion, &'static str> {
+        match version {
+            1 => Ok(SchemaVersion::V1),
+            2 => Ok(SchemaVersion::V2),
+            _ => Err("Unknown schema version"),
+        }
+    }
+}
+
+impl SchemaVersion {
+    pub fn detect(headers: &StringRecord) -> io::Result<SchemaVersion> {
+        let has = |column: &str| headers.iter().any(|header| header == column);
+        match (has("timestamp"), has("currency")) {
+            (false, false) => Ok(SchemaVersion::V1),
+            (true, true) => Ok(SchemaVersion::V2),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "schema v2 needs both `timestamp` and `currency` columns",
+            )),
+        }
+    }
+
+    pub fn decode(self, row: RowRecord<'_>) -> Result<RowRecord<'_>, &'static str> {
+        match self {
+            SchemaVersion::V1 => Ok(row),
+            SchemaVersion::V2 => {
+                row.timestamp().ok_or("Missing timestamp")?;
+                let currency = row.currency().ok_or("Missing currency")?;
+                if currency.len() != 3 || !currency.bytes().all(|b| b.is_ascii_uppercase()) {
+                    return Err("Currency must be a three letter ISO 4217 code");
+                }
+                Ok(row)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SchemaVersion;
+    use crate::csv_dialect::CsvDialect;
+    use crate::engine::Engine;
+    use crate::transactions::Client;
+    use csv::StringRecord;
+
+    #[test]
+    fn test_detect_version() {
+        let v1 = StringRecord::from(vec!["type", "client", "tx", "amount"]);
+        assert_eq!(SchemaVersion::detect(&v1).unwrap(), SchemaVersion::V1);
+        let v2 = StringRecord::from(vec![
+            "type",
+            "client",
+            "tx",
+            "amount",
+            "timestamp",
+            "currency",
+        ]);
+        assert_eq!(SchemaVersion::detect(&v2).unwrap(), SchemaVersion::V2);
+        let partial = StringRecord::from(vec!["type", "client", "tx", "amount", "currency"]);
+        assert!(SchemaVersion::detect(&partial).is_err());
+    }
+
+    #[test]
+    fn test_v2_rows() {
+        let input = "type,client,tx,amount,timestamp,currency\ndeposit,1,1,2.5,1792108800,EUR\n";
+        let mut engine = Engine::new();
+        engine.process_csv(input.as_bytes()).unwrap();
+        assert!(engine.account(Client(1)).is_some());
+
+        let input = "type,client,tx,amount,timestamp,currency\ndeposit,1,1,2.5,1792108800,euro\n";
+        let err = Engine::new().process_csv(input.as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: Currency must be a three letter ISO 4217 code (row: `deposit,1,1,2.5,1792108800,euro`)"
+        );
+
+        let headerless: CsvDialect =
+            toml::from_str("has_headers = false\nschema_version = 2").unwrap();
+        let input = "deposit,1,1,2.5,1792108800,EUR\ndeposit,1,2,1,,EUR\n";
+        let err = Engine::new()
+            .process_csv_with(input.as_bytes(), &headerless)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: Missing timestamp (row: `deposit,1,2,1,,EUR`)"
+        );
+    }
+}
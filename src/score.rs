@@ -0,0 +1,91 @@
+use crate::amount::Amount;
+use crate::rules::{AppliedCounts, ClientStats};
+use crate::transactions::{TransactionRecord, TxType};
+use std::collections::VecDeque;
+
+// Velocity is the client's share of the engine's last `VELOCITY_WINDOW`
+// applied records; records carry no time to measure a rate with.
+pub const VELOCITY_WINDOW: u64 = 100;
+
+// What an engine keeps per client to work out its `ClientStats`.
+#[derive(Debug, Default)]
+pub(crate) struct ClientTracker {
+    applied: AppliedCounts,
+    deposited: Amount,
+    withdrawn: Amount,
+    // engine sequence numbers of the client's latest applied records
+    recent: VecDeque<u64>,
+}
+
+impl ClientTracker {
+    pub(crate) fn record(&mut self, record: &TransactionRecord, sequence: u64) {
+        self.applied.record(record.r#type);
+        let amount = record.amount.unwrap_or_default().0;
+        match record.r#type {
+            TxType::Deposit => self.deposited.0 = self.deposited.0.saturating_add(amount),
+            TxType::Withdrawal => self.withdrawn.0 = self.withdrawn.0.saturating_add(amount),
+            _ => {}
+        }
+        self.recent.push_back(sequence);
+        if self.recent.len() as u64 > VELOCITY_WINDOW {
+            self.recent.pop_front();
+        }
+    }
+
+    // `sequence` is the number of records the engine has applied so far.
+    pub(crate) fn stats(&self, sequence: u64) -> ClientStats {
+        let since = sequence.saturating_sub(VELOCITY_WINDOW);
+        let in_window = self.recent.iter().filter(|&&seq| seq >= since).count();
+        ClientStats {
+            applied: self.applied,
+            risk_score: risk_score(&self.applied, in_window as u64),
+            deposited: self.deposited,
+            withdrawn: self.withdrawn,
+            ..ClientStats::default()
+        }
+    }
+}
+
+fn ratio(count: u32, of: u32) -> f64 {
+    (f64::from(count) / f64::from(of.max(1))).min(1.0)
+}
+
+// 0 to 100: half from the share of deposits charged back, 30 points from
+// the share of deposits and withdrawals disputed and 20 from velocity.
+pub fn risk_score(applied: &AppliedCounts, in_window: u64) -> u8 {
+    let deposits = applied.get(TxType::Deposit);
+    let moves = deposits.saturating_add(applied.get(TxType::Withdrawal));
+    let chargebacks = ratio(applied.get(TxType::Chargeback), deposits);
+    let disputes = ratio(applied.get(TxType::Dispute), moves);
+    let velocity = in_window.min(VELOCITY_WINDOW) as f64 / VELOCITY_WINDOW as f64;
+    (50.0 * chargebacks + 30.0 * disputes + 20.0 * velocity).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientTracker, VELOCITY_WINDOW};
+    use crate::amount::Amount;
+    use crate::transactions::{Client, TransactionRecord, Tx};
+
+    #[test]
+    fn test_risk_score() {
+        let mut tracker = ClientTracker::default();
+        assert_eq!(tracker.stats(0).risk_score, 0);
+        tracker.record(
+            &TransactionRecord::deposit(Client(1), Tx(1), Amount::new(10)),
+            0,
+        );
+        tracker.record(
+            &TransactionRecord::deposit(Client(1), Tx(2), Amount::new(5)),
+            1,
+        );
+        tracker.record(&TransactionRecord::dispute(Client(1), Tx(2)), 2);
+        assert_eq!(tracker.stats(3).deposited, Amount::new(15));
+        // half the moves disputed, 3 of the last 100 records
+        assert_eq!(tracker.stats(3).risk_score, 16);
+        tracker.record(&TransactionRecord::chargeback(Client(1), Tx(2)), 3);
+        assert_eq!(tracker.stats(4).risk_score, 41);
+        // long after, velocity no longer counts
+        assert_eq!(tracker.stats(4 + VELOCITY_WINDOW).risk_score, 40);
+    }
+}
@@ -0,0 +1,365 @@
+use crate::auth::AuthConfig;
+use crate::engine::{Engine, EngineBuilder};
+use crate::ingest::IngestQueue;
+use crate::shared::SharedEngine;
+use crate::tenant::{self, Tenants};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock, Weak};
+use std::thread::{self, JoinHandle};
+
+// How many queued submissions the applier takes off the queue at once.
+const APPLY_BATCH: usize = 64;
+
+fn default_queue_high_water() -> usize {
+    10_000
+}
+
+// The `[server]` config section. `queue_high_water` is how many submissions
+// may wait for the applier before connections stop reading new ones.
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub struct ServerConfig {
+    #[serde(default = "default_queue_high_water")]
+    pub queue_high_water: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            queue_high_water: default_queue_high_water(),
+        }
+    }
+}
+
+// The tenants' engines as shared between a server's handlers, the admin
+// socket and the metrics listener. Each tenant is a `SharedEngine`, so
+// requests for different tenants never wait on each other, and balance
+// reads don't wait on writes to the same tenant either; the map itself is
+// only written when a tenant is created.
+#[derive(Clone)]
+pub struct SharedTenants {
+    inner: Arc<TenantsInner>,
+}
+
+struct TenantsInner {
+    builder: EngineBuilder,
+    isolated: bool,
+    engines: RwLock<BTreeMap<String, SharedEngine>>,
+}
+
+// A handle that doesn't keep the engines alive, for listeners that outlive
+// the server.
+pub struct WeakTenants(Weak<TenantsInner>);
+
+impl WeakTenants {
+    pub fn upgrade(&self) -> Option<SharedTenants> {
+        self.0.upgrade().map(|inner| SharedTenants { inner })
+    }
+}
+
+impl SharedTenants {
+    pub fn new(tenants: Tenants) -> SharedTenants {
+        let (builder, engines, isolated) = tenants.into_parts();
+        let engines = engines
+            .into_iter()
+            .map(|(tenant, engine)| (tenant, SharedEngine::new(engine)))
+            .collect();
+        SharedTenants {
+            inner: Arc::new(TenantsInner {
+                builder,
+                isolated,
+                engines: RwLock::new(engines),
+            }),
+        }
+    }
+
+    // See `Tenants::resolve`.
+    pub fn resolve(&self, requested: Option<&str>) -> Result<String, String> {
+        tenant::resolve(self.inner.isolated, requested)
+    }
+
+    // The engine of a resolved tenant, created on first use.
+    pub fn engine(&self, tenant: &str) -> SharedEngine {
+        if let Some(engine) = self.get(tenant) {
+            return engine;
+        }
+        let mut engines = self
+            .inner
+            .engines
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        engines
+            .entry(tenant.to_string())
+            .or_insert_with(|| SharedEngine::new(self.inner.builder.clone().build()))
+            .clone()
+    }
+
+    pub fn get(&self, tenant: &str) -> Option<SharedEngine> {
+        let engines = self
+            .inner
+            .engines
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        engines.get(tenant).cloned()
+    }
+
+    // Tenants in name order, as of the call.
+    pub fn engines(&self) -> Vec<(String, SharedEngine)> {
+        let engines = self
+            .inner
+            .engines
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        engines
+            .iter()
+            .map(|(tenant, engine)| (tenant.clone(), engine.clone()))
+            .collect()
+    }
+
+    pub fn downgrade(&self) -> WeakTenants {
+        WeakTenants(Arc::downgrade(&self.inner))
+    }
+}
+
+// Takes the engines back once the server has stopped, so the caller can
+// write out the final accounts.
+pub fn into_tenants(tenants: SharedTenants) -> Result<Tenants, Box<dyn Error + Send + Sync>> {
+    let inner = Arc::try_unwrap(tenants.inner).map_err(|_| "engine is still in use")?;
+    let engines = inner
+        .engines
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner)
+        .into_iter()
+        .map(|(tenant, engine)| {
+            let engine = engine.into_engine().map_err(|_| "engine is still in use")?;
+            Ok((tenant, engine))
+        })
+        .collect::<Result<_, Box<dyn Error + Send + Sync>>>()?;
+    Ok(Tenants::from_parts(inner.builder, engines, inner.isolated))
+}
+
+// A submission waiting for the applier: what to run against which tenant's
+// engine.
+struct Job {
+    tenant: String,
+    run: Box<dyn FnOnce(&mut Engine) + Send>,
+}
+
+// The one thread applying the servers' submissions. Connections queue them
+// on a bounded `IngestQueue` through an `Ingest` handle and wait for their
+// turn, so a burst of submissions backs up into the connections (and from
+// there into the clients' sockets) rather than into the engine locks, and
+// every tenant sees its submissions in the order they were queued.
+pub struct Applier {
+    queue: Arc<IngestQueue<Job>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+// Closes the queue when the applier stops, however it stops, and drops what
+// it didn't get to so nobody waits on a reply that won't come.
+struct Closing(Arc<IngestQueue<Job>>);
+
+impl Drop for Closing {
+    fn drop(&mut self) {
+        self.0.close();
+        self.0.drain(usize::MAX);
+    }
+}
+
+impl Applier {
+    pub fn spawn(tenants: SharedTenants, config: ServerConfig) -> Applier {
+        let queue = Arc::new(IngestQueue::new(config.queue_high_water));
+        let closing = Closing(Arc::clone(&queue));
+        let thread = thread::spawn(move || {
+            while let Some(jobs) = closing.0.wait_drain(APPLY_BATCH) {
+                for job in jobs {
+                    tenants.engine(&job.tenant).with_engine(job.run);
+                }
+            }
+        });
+        Applier {
+            queue,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn ingest(&self) -> Ingest {
+        Ingest {
+            queue: Arc::clone(&self.queue),
+        }
+    }
+
+    // Applies what is already queued, then stops; submissions made after
+    // this are refused.
+    pub fn stop(mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.queue.close();
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|_| "the applier panicked".into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Applier {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+// A server's handle for queueing submissions to the `Applier`.
+#[derive(Clone)]
+pub struct Ingest {
+    #[cfg_attr(
+        not(any(feature = "grpc", feature = "rest", feature = "tcp")),
+        allow(dead_code)
+    )]
+    queue: Arc<IngestQueue<Job>>,
+}
+
+impl Ingest {
+    // Runs `f` against the tenant's engine (created on first use) once the
+    // applier gets to it, and returns what it returned. Waits while the
+    // queue is at its high-water mark; `None` once the applier has stopped.
+    #[cfg(any(feature = "grpc", feature = "rest", feature = "tcp"))]
+    pub async fn apply<T, F>(&self, tenant: String, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut Engine) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (reply, result) = tokio::sync::oneshot::channel();
+        let run = Box::new(move |engine: &mut Engine| {
+            // the connection may have gone away in the meantime
+            let _ = reply.send(f(engine));
+        });
+        self.queue.push(Job { tenant, run }).await.ok()?;
+        result.await.ok()
+    }
+}
+
+// Protocols `serve` can expose the engine over.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Protocol {
+    #[cfg(feature = "grpc")]
+    Grpc,
+    #[cfg(feature = "rest")]
+    Rest,
+    #[cfg(feature = "tcp")]
+    Tcp,
+}
+
+impl Protocol {
+    pub fn default_port(self) -> u16 {
+        match self {
+            #[cfg(feature = "grpc")]
+            Protocol::Grpc => 50051,
+            #[cfg(feature = "rest")]
+            Protocol::Rest => 8080,
+            #[cfg(feature = "tcp")]
+            Protocol::Tcp => 7070,
+        }
+    }
+
+    // The REST API's history pages and GraphQL's transaction lists come
+    // from the engine's history.
+    pub fn needs_history(self) -> bool {
+        match self {
+            #[cfg(feature = "grpc")]
+            Protocol::Grpc => false,
+            #[cfg(feature = "rest")]
+            Protocol::Rest => true,
+            #[cfg(feature = "tcp")]
+            Protocol::Tcp => false,
+        }
+    }
+
+    pub fn default_addr(self) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], self.default_port()))
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Protocol, &'static str> {
+        match s {
+            #[cfg(feature = "grpc")]
+            "grpc" => Ok(Protocol::Grpc),
+            #[cfg(feature = "rest")]
+            "rest" | "http" => Ok(Protocol::Rest),
+            #[cfg(feature = "tcp")]
+            "tcp" => Ok(Protocol::Tcp),
+            _ => Err("Unknown or disabled protocol"),
+        }
+    }
+}
+
+// Serves the tenants over the protocol until the server shuts down, then
+// applies whatever submissions are still queued.
+#[cfg_attr(
+    not(any(feature = "grpc", feature = "rest", feature = "tcp")),
+    allow(unused_variables, unreachable_code)
+)]
+pub fn serve(
+    tenants: SharedTenants,
+    protocol: Protocol,
+    listen: Option<SocketAddr>,
+    auth: Option<AuthConfig>,
+    config: ServerConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let addr = listen.unwrap_or_else(|| protocol.default_addr());
+    let auth = auth.map(Arc::new);
+    let applier = Applier::spawn(tenants.clone(), config);
+    let ingest = applier.ingest();
+    let result = match protocol {
+        #[cfg(feature = "grpc")]
+        Protocol::Grpc => crate::grpc::serve(tenants, ingest, addr, auth),
+        #[cfg(feature = "rest")]
+        Protocol::Rest => crate::rest::serve(tenants, ingest, addr, auth),
+        // lines have nowhere to carry a key, so refuse rather than serve
+        // unauthenticated
+        #[cfg(feature = "tcp")]
+        Protocol::Tcp if auth.is_some() => Err("the tcp server can't check [auth] API keys".into()),
+        #[cfg(feature = "tcp")]
+        Protocol::Tcp => crate::tcp::serve(ingest, addr),
+    };
+    applier.stop()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{into_tenants, SharedTenants};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::tenant::Tenants;
+    use crate::transactions::{Client, TransactionRecord, Tx};
+
+    #[test]
+    fn test_shared_tenants() {
+        let tenants = SharedTenants::new(Tenants::isolated(Engine::builder()));
+        assert!(tenants.get("acme").is_none());
+        let deposit = TransactionRecord::deposit(Client(1), Tx(1), Amount::new(5));
+        tenants.engine("acme").try_process(&deposit).unwrap();
+        tenants.engine("globex");
+        let acme = tenants.get("acme").unwrap();
+        assert_eq!(acme.account(Client(1)).unwrap().available, Amount::new(5));
+        let names: Vec<String> = tenants
+            .engines()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["acme", "globex"]);
+
+        // the engines only come back once no handle is left
+        let weak = tenants.downgrade();
+        assert!(into_tenants(weak.upgrade().unwrap()).is_err());
+        drop(acme);
+        let tenants = into_tenants(tenants).unwrap();
+        assert!(weak.upgrade().is_none());
+        let funds = tenants.get("acme").unwrap().account(Client(1)).unwrap();
+        assert_eq!(funds.available, Amount::new(5));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::dormancy::DAY;
+use crate::funds::Funds;
+use crate::postings::{LedgerAccount, Posting};
+use crate::transactions::{Client, Effect, TransactionRecord, Tx, TxRecords, TxType};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// The `[settlement]` config section. With it, deposits are pending until
+// they settle: counted in the account's total, but not available to
+// withdraw or dispute.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SettlementConfig {
+    // days after which a pending deposit settles on its own; unset, only a
+    // `settle` record settles it
+    pub after_days: Option<u64>,
+}
+
+// The pending deposits and when they were made, so that
+// `Engine::advance_to` can settle the ones due. Times are the engine's
+// clock (see `Engine::now`); a deposit made before the clock was first set
+// counts from then.
+#[derive(Debug, Clone)]
+pub struct Settlements {
+    delay: Option<u64>,
+    pending: HashMap<Tx, (Client, Option<u64>)>,
+}
+
+impl Settlements {
+    pub fn new(config: SettlementConfig) -> Settlements {
+        Settlements {
+            delay: config.after_days.map(|days| days.saturating_mul(DAY)),
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    // Called with each applied record, its effect and its client's
+    // account: a deposit's amount moves from available to pending. Returns
+    // the postings of the move.
+    pub(crate) fn observe(
+        &mut self,
+        record: &TransactionRecord,
+        effect: &Effect,
+        funds: &mut Funds,
+        records: &mut TxRecords,
+        now: Option<u64>,
+    ) -> Vec<Posting> {
+        match record.r#type {
+            TxType::Deposit => {
+                let logged = match records.get_mut(&record.tx) {
+                    Some(logged) => logged,
+                    None => return Vec::new(),
+                };
+                if funds.hold_pending(effect.amount).is_err() {
+                    return Vec::new();
+                }
+                *logged = logged.into_pending();
+                self.pending.insert(record.tx, (record.client, now));
+                let posting = Posting {
+                    debit: LedgerAccount::Available(record.client),
+                    credit: LedgerAccount::Pending(record.client),
+                    amount: effect.amount,
+                };
+                (effect.amount.0 > 0)
+                    .then_some(posting)
+                    .into_iter()
+                    .collect()
+            }
+            TxType::Settle => {
+                self.pending.remove(&record.tx);
+                Vec::new()
+            }
+            TxType::Withdrawal
+            | TxType::Dispute
+            | TxType::Resolve
+            | TxType::Chargeback
+            | TxType::Credit => Vec::new(),
+        }
+    }
+
+    // Picks up the pending deposits of an imported log, counted from `now`.
+    pub(crate) fn import(&mut self, records: &TxRecords, now: Option<u64>) {
+        self.pending = records
+            .iter()
+            .filter(|(_, logged)| logged.is_pending())
+            .map(|(tx, logged)| (*tx, (logged.client(), now)))
+            .collect();
+    }
+
+    // Takes the pending deposits made at least the delay before `now`,
+    // oldest first; none without a delay.
+    pub(crate) fn due(&mut self, now: u64) -> Vec<(Client, Tx)> {
+        let delay = match self.delay {
+            Some(delay) => delay,
+            None => return Vec::new(),
+        };
+        let mut due = Vec::new();
+        for (tx, (client, made)) in self.pending.iter_mut() {
+            let made = *made.get_or_insert(now);
+            if now.saturating_sub(made) >= delay {
+                due.push((made, *tx, *client));
+            }
+        }
+        due.sort_unstable_by_key(|&(made, tx, _)| (made, tx.0));
+        due.into_iter()
+            .map(|(_, tx, client)| {
+                self.pending.remove(&tx);
+                (client, tx)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SettlementConfig;
+    use crate::amount::Amount;
+    use crate::dormancy::DAY;
+    use crate::engine::Engine;
+    use crate::postings::LedgerAccount;
+    use crate::transactions::{Client, RejectReason, TransactionRecord, Tx};
+
+    #[test]
+    fn test_settle_records() {
+        let mut engine = Engine::builder()
+            .settlement(SettlementConfig::default())
+            .double_entry(true)
+            .build();
+        let client = Client(1);
+        engine.process(&TransactionRecord::deposit(client, Tx(1), Amount::new(10)));
+        let funds = engine.account(client).unwrap();
+        assert_eq!(funds.available, Amount::new(0));
+        assert_eq!(funds.total(), Amount::new(10));
+        for (record, reason) in [
+            (
+                TransactionRecord::withdrawal(client, Tx(2), Amount::new(1)),
+                RejectReason::InsufficientFunds,
+            ),
+            (
+                TransactionRecord::dispute(client, Tx(1)),
+                RejectReason::NotDisputable,
+            ),
+            (
+                TransactionRecord::settle(client, Tx(3)),
+                RejectReason::UnknownTx,
+            ),
+        ] {
+            assert_eq!(engine.try_process(&record), Err(reason));
+        }
+        engine
+            .try_process(&TransactionRecord::settle(client, Tx(1)))
+            .unwrap();
+        assert_eq!(
+            engine.try_process(&TransactionRecord::settle(client, Tx(1))),
+            Err(RejectReason::NotPending)
+        );
+        let funds = engine.account(client).unwrap();
+        assert_eq!(funds.available, Amount::new(10));
+        assert_eq!(funds.pending, Amount::new(0));
+        engine
+            .try_process(&TransactionRecord::dispute(client, Tx(1)))
+            .unwrap();
+
+        let books = engine.books().unwrap();
+        assert_eq!(books.balance(LedgerAccount::Pending(client)), 0);
+        assert!(engine.trial_balance().unwrap().is_balanced());
+    }
+
+    #[test]
+    fn test_settlement_delay() {
+        let config = SettlementConfig {
+            after_days: Some(2),
+        };
+        let mut engine = Engine::builder().settlement(config).build();
+        let client = Client(1);
+        engine.advance_to(DAY);
+        engine.process(&TransactionRecord::deposit(client, Tx(1), Amount::new(10)));
+        engine.advance_to(2 * DAY);
+        engine.process(&TransactionRecord::deposit(client, Tx(2), Amount::new(5)));
+        engine.advance_to(3 * DAY);
+        let funds = engine.account(client).unwrap();
+        assert_eq!(funds.available, Amount::new(10));
+        assert_eq!(funds.pending, Amount::new(5));
+        assert_eq!(engine.pending_deposits(), 1);
+        engine.advance_to(4 * DAY);
+        let funds = engine.account(client).unwrap();
+        assert_eq!(funds.available, Amount::new(15));
+        assert_eq!(engine.pending_deposits(), 0);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::csv_dialect::CsvDialect;
+use crate::decoder::{malformed_row, unknown_type_row, CsvDecoder, RecordDecoder};
+use crate::engine::Engine;
+use crate::funds::Funds;
+use crate::transactions::{Client, RejectReason, TransactionRecord};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+// A record the two engines decided differently.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Divergence {
+    // 1-based position in everything fed to the harness
+    pub position: u64,
+    pub record: TransactionRecord,
+    pub primary: Result<(), RejectReason>,
+    pub shadow: Result<(), RejectReason>,
+}
+
+// A client whose account differs between the engines at the end.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AccountDiff {
+    pub client: Client,
+    pub primary: Option<Funds>,
+    pub shadow: Option<Funds>,
+}
+
+// Feeds every record to two engines, typically built from the current and
+// a candidate configuration, and keeps where they disagree. The primary's
+// decisions are the ones reported back; the shadow's are only compared.
+pub struct Shadow {
+    primary: Engine,
+    shadow: Engine,
+    position: u64,
+    divergences: Vec<Divergence>,
+}
+
+impl Shadow {
+    pub fn new(primary: Engine, shadow: Engine) -> Shadow {
+        Shadow {
+            primary,
+            shadow,
+            position: 0,
+            divergences: Vec::new(),
+        }
+    }
+
+    pub fn process(&mut self, record: &TransactionRecord) -> Result<(), RejectReason> {
+        self.position += 1;
+        let primary = self.primary.try_process(record);
+        let shadow = self.shadow.try_process(record);
+        if primary != shadow {
+            let (client, tx) = (record.client.0, record.tx.0);
+            tracing::info!(client, tx, "shadow engine decided differently");
+            self.divergences.push(Divergence {
+                position: self.position,
+                record: record.clone(),
+                primary,
+                shadow,
+            });
+        }
+        primary
+    }
+
+    // Like `Engine::process_csv_with`, decoding each row once for both
+    // engines.
+    pub fn process_csv_with<R: Read>(&mut self, input: R, dialect: &CsvDialect) -> csv::Result<()> {
+        let mut decoder = CsvDecoder::new(input, dialect)?;
+        while let Some(row) = decoder.next() {
+            let row = match row {
+                Ok(row) => row,
+                // neither engine sees rows of unknown type
+                Err(err) if unknown_type_row(&err).is_some() => continue,
+                Err(err) => match malformed_row(&err) {
+                    Some(malformed) if dialect.permissive => {
+                        tracing::warn!(line = malformed.line, "malformed row skipped");
+                        continue;
+                    }
+                    _ => return Err(err),
+                },
+            };
+            // the decoder has already checked the amount
+            if let Ok(record) = TransactionRecord::try_from(row) {
+                let _ = self.process(&record);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn divergences(&self) -> &[Divergence] {
+        &self.divergences
+    }
+
+    // Accounts that differ, or exist in one engine only, by client.
+    pub fn account_diffs(&self) -> Vec<AccountDiff> {
+        let mut clients: Vec<Client> = self
+            .primary
+            .accounts()
+            .chain(self.shadow.accounts())
+            .map(|funds| funds.client)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        clients.sort_by_key(|client| client.0);
+        clients
+            .into_iter()
+            .map(|client| AccountDiff {
+                client,
+                primary: self.primary.account(client).cloned(),
+                shadow: self.shadow.account(client).cloned(),
+            })
+            .filter(|diff| diff.primary != diff.shadow)
+            .collect()
+    }
+
+    pub fn into_engines(self) -> (Engine, Engine) {
+        (self.primary, self.shadow)
+    }
+}
+
+fn outcome(result: Result<(), RejectReason>) -> &'static str {
+    match result {
+        Ok(()) => "applied",
+        Err(reason) => reason.as_str(),
+    }
+}
+
+fn balances(funds: Option<&Funds>) -> String {
+    match funds {
+        Some(funds) => format!(
+            "{}/{}/{}",
+            funds.available,
+            funds.held,
+            funds.state.as_str()
+        ),
+        None => "none".to_string(),
+    }
+}
+
+// The divergences as CSV, one `decision` row per record with both
+// outcomes (`applied` or the reason code), then one `account` row per
+// differing account with each side's `available/held/state`.
+pub fn write_report<W: Write>(shadow: &Shadow, writer: W) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        "kind", "position", "client", "tx", "type", "primary", "shadow",
+    ])?;
+    for divergence in shadow.divergences() {
+        let record = &divergence.record;
+        writer.write_record([
+            "decision",
+            &divergence.position.to_string(),
+            &record.client.0.to_string(),
+            &record.tx.0.to_string(),
+            record.r#type.as_str(),
+            outcome(divergence.primary),
+            outcome(divergence.shadow),
+        ])?;
+    }
+    for diff in shadow.account_diffs() {
+        writer.write_record([
+            "account",
+            "",
+            &diff.client.0.to_string(),
+            "",
+            "",
+            &balances(diff.primary.as_ref()),
+            &balances(diff.shadow.as_ref()),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_report, Shadow};
+    use crate::csv_dialect::CsvDialect;
+    use crate::engine::Engine;
+    use crate::policy::PolicyRule;
+
+    #[test]
+    fn test_shadow() {
+        let rule: PolicyRule = "reject withdrawal if amount > 5".parse().unwrap();
+        let mut shadow = Shadow::new(Engine::new(), Engine::builder().rule(rule).build());
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,10.0\nwithdrawal,1,2,6.0\ndeposit,2,3,1.0\nwithdrawal,2,4,2.0\n";
+        shadow
+            .process_csv_with(csvfile.as_bytes(), &CsvDialect::default())
+            .unwrap();
+        assert_eq!(shadow.divergences().len(), 1);
+
+        let mut out = Vec::new();
+        write_report(&shadow, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "kind,position,client,tx,type,primary,shadow\n\
+             decision,2,1,2,withdrawal,applied,policy\n\
+             account,,1,,,4.0000/0.0000/valid,10.0000/0.0000/valid\n"
+        );
+    }
+}
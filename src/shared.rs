@@ -0,0 +1,170 @@
+use crate::concurrent::{ConcurrentFunds, DEFAULT_SHARDS};
+use crate::engine::Engine;
+use crate::funds::Funds;
+use crate::transactions::{Client, RejectReason, TransactionRecord};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// An engine that can be shared between threads, e.g. a server's handlers.
+// Records are applied one at a time under the engine's lock, as they have
+// to be for the rules and the transaction log to see them in order, but
+// balances are read from a copy of the accounts in a `ConcurrentFunds`, so
+// reads never wait for a write other than to the shard being read. The
+// engine keeps track of the accounts it changed, and the writer publishes
+// just those, all at once, before it lets go of the engine, so `accounts`
+// can take a consistent cut by holding every shard at once.
+#[derive(Clone)]
+pub struct SharedEngine {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    engine: Mutex<Engine>,
+    accounts: ConcurrentFunds,
+}
+
+impl SharedEngine {
+    pub fn new(engine: Engine) -> SharedEngine {
+        SharedEngine::with_shards(engine, DEFAULT_SHARDS)
+    }
+
+    pub fn with_shards(engine: Engine, shards: usize) -> SharedEngine {
+        let mut engine = engine;
+        engine.track_changes();
+        let accounts = ConcurrentFunds::new(shards);
+        accounts.replace(engine.accounts().cloned());
+        SharedEngine {
+            inner: Arc::new(Inner {
+                engine: Mutex::new(engine),
+                accounts,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Engine> {
+        self.inner.engine.lock().expect("engine lock poisoned")
+    }
+
+    fn publish(&self, engine: &mut Engine) {
+        let changed = engine.take_changes();
+        if changed.all {
+            self.inner.accounts.replace(engine.accounts().cloned());
+        } else if !changed.clients.is_empty() {
+            let accounts = changed
+                .clients
+                .into_iter()
+                .map(|client| (client, engine.account(client).cloned()));
+            self.inner.accounts.update(accounts);
+        }
+    }
+
+    pub fn try_process(&self, record: &TransactionRecord) -> Result<(), RejectReason> {
+        self.with_engine(|engine| engine.try_process(record))
+    }
+
+    // Anything else the engine offers, with the lock held. Only the accounts
+    // it changed are published again.
+    pub fn with_engine<T>(&self, f: impl FnOnce(&mut Engine) -> T) -> T {
+        let mut engine = self.lock();
+        let result = f(&mut engine);
+        self.publish(&mut engine);
+        result
+    }
+
+    // Reads the engine itself, for what the published accounts don't carry
+    // (metrics, history, the audit log). Waits for writes like they do.
+    pub fn read<T>(&self, f: impl FnOnce(&Engine) -> T) -> T {
+        f(&self.lock())
+    }
+
+    pub fn account(&self, client: Client) -> Option<Funds> {
+        self.inner.accounts.get(client)
+    }
+
+    // Every account as of one point between writes, in client order.
+    pub fn accounts(&self) -> Vec<Funds> {
+        self.inner.accounts.accounts()
+    }
+
+    // Takes the engine back once no other handle is left.
+    pub fn into_engine(self) -> Result<Engine, SharedEngine> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(inner) => Ok(inner.engine.into_inner().expect("engine lock poisoned")),
+            Err(inner) => Err(SharedEngine { inner }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedEngine;
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::{Client, Ledger, TransactionRecord, Tx, TxType};
+    use std::thread;
+
+    #[test]
+    fn test_shared_engine() {
+        let shared = SharedEngine::with_shards(Engine::new(), 4);
+        // each writer deposits and withdraws in turn, so no read ever sees
+        // more than one deposit on hand
+        let writers: Vec<_> = (0..4u32)
+            .map(|n| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    for i in 0..100u64 {
+                        let tx = u64::from(n) * 1000 + i * 2;
+                        for (r#type, client, tx) in [
+                            (TxType::Deposit, n * 2, tx),
+                            (TxType::Withdrawal, n * 2, tx + 1),
+                        ] {
+                            let record = TransactionRecord {
+                                r#type,
+                                client: Client(client),
+                                tx: Tx(tx),
+                                amount: Some(Amount::new(10)),
+                            };
+                            shared.try_process(&record).unwrap();
+                        }
+                        shared.account(Client(n * 2));
+                    }
+                })
+            })
+            .collect();
+        let reader = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    for funds in shared.accounts() {
+                        assert!(funds.available <= Amount::new(10));
+                    }
+                }
+            })
+        };
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        reader.join().unwrap();
+
+        let accounts = shared.accounts();
+        let clients: Vec<u32> = accounts.iter().map(|funds| funds.client.0).collect();
+        assert_eq!(clients, vec![0, 2, 4, 6]);
+        assert_eq!(shared.account(Client(2)).unwrap().available, Amount::new(0));
+
+        // changes made through the engine itself are published too
+        let csvfile = "type,client,tx,amount\ndeposit,9,9000,1.5\n";
+        shared
+            .with_engine(|engine| engine.process_csv(csvfile.as_bytes()))
+            .unwrap();
+        assert_eq!(
+            shared.account(Client(9)).unwrap().available,
+            Amount::new(15000)
+        );
+        // and so is a ledger replaced wholesale
+        let ledger = shared.read(Engine::export_ledger);
+        shared.with_engine(|engine| engine.import_ledger(Ledger::default()));
+        assert!(shared.accounts().is_empty());
+        shared.with_engine(|engine| engine.import_ledger(ledger));
+        let engine = shared.into_engine().ok().unwrap();
+        assert_eq!(engine.accounts().count(), 5);
+    }
+}
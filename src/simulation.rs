@@ -0,0 +1,274 @@
+use crate::engine::{Engine, EngineBuilder};
+use crate::funds::Funds;
+use crate::stream::{Delivery, Snapshots, StreamApplier};
+use crate::tenant::{Tenants, DEFAULT_TENANT};
+use crate::transactions::{Client, TransactionRecord};
+use crate::wal::Position;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::mem;
+use std::path::PathBuf;
+
+const STREAM: &str = "simulation";
+
+// Where taking a snapshot fails, see `StreamApplier::fail_next_snapshot`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum SnapshotFault {
+    // the snapshot isn't written, so the previous one stays in place
+    Write,
+    // the snapshot is in place but the WAL isn't restarted after it
+    WalRestart,
+}
+
+// A failure injected when the simulation gets to the record at the given
+// (0-based) index.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Fault {
+    // the disk fills up while logging the record: its WAL line is only
+    // written in part and the write fails
+    WalWrite { record: usize },
+    // writing the next snapshot fails
+    SnapshotWrite { record: usize },
+    // the process dies between writing the next snapshot and restarting the
+    // WAL after it
+    SnapshotCrash { record: usize },
+    // the process dies right after applying the record, losing whatever the
+    // WAL hadn't flushed
+    Crash { record: usize },
+    // as `Crash`, and the WAL loses up to `bytes` more of what it wrote
+    // since the last sync
+    TornWal { record: usize, bytes: u64 },
+}
+
+impl Fault {
+    fn record(self) -> usize {
+        match self {
+            Fault::WalWrite { record }
+            | Fault::SnapshotWrite { record }
+            | Fault::SnapshotCrash { record }
+            | Fault::Crash { record }
+            | Fault::TornWal { record, .. } => record,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    // holds the WAL and the snapshot; those of an earlier run are removed
+    pub dir: PathBuf,
+    pub flush_every: usize,
+    pub snapshot_every: usize,
+    pub faults: Vec<Fault>,
+}
+
+// An account that recovery got wrong: after restart number `restart`, the
+// engine should have held the first `records` records and nothing else.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Inconsistency {
+    pub restart: usize,
+    pub records: usize,
+    pub client: Client,
+    pub recovered: Option<Funds>,
+    pub expected: Option<Funds>,
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct SimulationReport {
+    // recoveries from disk, the clean one at the end included
+    pub restarts: usize,
+    pub inconsistencies: Vec<Inconsistency>,
+}
+
+// Feeds the records to a `StreamApplier` with exactly-once delivery and a
+// WAL and snapshots in `config.dir`, injecting the faults. Any failure
+// kills the process: the applier is leaked, so nothing it buffered reaches
+// the disk. A new applier then recovers from the disk, and the source
+// redelivers everything after the recovered position, as a message bus
+// would. After every recovery, and a last clean one at the end, the state
+// must be that of a fresh engine that applied exactly the records up to
+// that position.
+pub fn run(
+    builder: &EngineBuilder,
+    records: &[TransactionRecord],
+    config: &SimulationConfig,
+) -> io::Result<SimulationReport> {
+    let wal_path = config.dir.join("wal.log");
+    let snapshots = Snapshots {
+        path: config.dir.join("snapshot.snap"),
+        every: config.snapshot_every,
+    };
+    for path in [&wal_path, &snapshots.path] {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    let open = || {
+        StreamApplier::open(
+            Tenants::single(builder.clone()),
+            Some(&wal_path),
+            Some(snapshots.clone()),
+            Delivery::ExactlyOnce,
+            config.flush_every,
+        )
+    };
+    let mut report = SimulationReport::default();
+    let mut pending = config.faults.clone();
+    let mut applier = open()?;
+    let mut next = 0;
+    // the WAL up to here was synced, so a crash can't tear it
+    let mut synced = 0;
+    loop {
+        let (mut crash, mut torn) = (false, 0);
+        for fault in pending.iter().filter(|fault| fault.record() == next) {
+            match *fault {
+                Fault::WalWrite { .. } => {
+                    if let Some(wal) = applier.wal_mut() {
+                        wal.fill_disk(8);
+                    }
+                }
+                Fault::SnapshotWrite { .. } => applier.fail_next_snapshot(SnapshotFault::Write),
+                Fault::SnapshotCrash { .. } => {
+                    applier.fail_next_snapshot(SnapshotFault::WalRestart)
+                }
+                Fault::Crash { .. } => crash = true,
+                Fault::TornWal { bytes, .. } => (crash, torn) = (true, bytes),
+            }
+        }
+        pending.retain(|fault| fault.record() != next);
+
+        let Some(record) = records.get(next) else {
+            break;
+        };
+        let position = Position {
+            stream: STREAM.to_string(),
+            offset: next as i64,
+        };
+        let mut result = applier.apply(DEFAULT_TENANT, position, record).map(drop);
+        if result.is_ok() && applier.should_flush() {
+            result = applier.flush();
+            if result.is_ok() {
+                synced = fs::metadata(&wal_path)?.len();
+            }
+        }
+        if result.is_ok() {
+            next += 1;
+            if !crash {
+                continue;
+            }
+        }
+
+        mem::forget(applier);
+        if torn > 0 {
+            let len = fs::metadata(&wal_path)?.len();
+            let file = OpenOptions::new().write(true).open(&wal_path)?;
+            file.set_len(len.saturating_sub(torn).max(synced))?;
+        }
+        report.restarts += 1;
+        applier = open()?;
+        next = applier
+            .position(STREAM)
+            .map_or(0, |offset| offset as usize + 1);
+        synced = fs::metadata(&wal_path)?.len();
+        check(&mut report, builder, &records[..next], applier.tenants());
+    }
+    applier.flush()?;
+    drop(applier);
+    report.restarts += 1;
+    let applier = open()?;
+    check(&mut report, builder, records, applier.tenants());
+    Ok(report)
+}
+
+fn accounts(engine: Option<&Engine>) -> HashMap<Client, Funds> {
+    engine
+        .into_iter()
+        .flat_map(Engine::accounts)
+        .map(|funds| (funds.client, funds.clone()))
+        .collect()
+}
+
+fn check(
+    report: &mut SimulationReport,
+    builder: &EngineBuilder,
+    records: &[TransactionRecord],
+    tenants: &Tenants,
+) {
+    let mut engine = builder.clone().build();
+    for record in records {
+        engine.process(record);
+    }
+    let expected = accounts(Some(&engine));
+    let recovered = accounts(tenants.get(DEFAULT_TENANT));
+    let mut clients: Vec<Client> = expected.keys().chain(recovered.keys()).copied().collect();
+    clients.sort_by_key(|client| client.0);
+    clients.dedup();
+    for client in clients {
+        let (recovered, expected) = (recovered.get(&client), expected.get(&client));
+        if recovered != expected {
+            report.inconsistencies.push(Inconsistency {
+                restart: report.restarts,
+                records: records.len(),
+                client,
+                recovered: recovered.cloned(),
+                expected: expected.cloned(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, Fault, SimulationConfig};
+    use crate::engine::Engine;
+    use crate::testkit::{Generator, GeneratorConfig, Row};
+    use crate::transactions::TransactionRecord;
+    use std::fs;
+
+    #[test]
+    fn test_simulation() {
+        let generator = Generator::new(GeneratorConfig {
+            clients: 30,
+            transactions: 3000,
+            dispute_ratio: 0.05,
+            seed: Some(11),
+            ..GeneratorConfig::default()
+        });
+        let records: Vec<TransactionRecord> = generator
+            .filter_map(|row| match row {
+                Row::Record(record) => Some(record),
+                Row::Malformed(_) => None,
+            })
+            .collect();
+        let dir = std::env::temp_dir().join("payment_engine_simulation");
+        fs::create_dir_all(&dir).unwrap();
+        // batches big enough that the WAL spills unsynced lines to disk
+        let config = SimulationConfig {
+            dir: dir.clone(),
+            flush_every: 300,
+            snapshot_every: 600,
+            faults: vec![
+                Fault::Crash { record: 150 },
+                Fault::TornWal {
+                    record: 580,
+                    bytes: 37,
+                },
+                // replays lines appended after the torn one
+                Fault::Crash { record: 1000 },
+                Fault::WalWrite { record: 1200 },
+                Fault::SnapshotCrash { record: 1300 },
+                Fault::TornWal {
+                    record: 1900,
+                    bytes: 5,
+                },
+                Fault::SnapshotWrite { record: 2200 },
+                Fault::Crash { record: 2999 },
+            ],
+        };
+        let report = run(&Engine::builder(), &records, &config).unwrap();
+        assert_eq!(report.inconsistencies, vec![]);
+        assert_eq!(report.restarts, 9);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
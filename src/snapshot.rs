@@ -0,0 +1,259 @@
+use crate::amount::Amount;
+use crate::funds::{FundingStates, Funds};
+use crate::tenant::Tenants;
+use crate::transactions::{Client, DisputeStatus, PackedRecord, Tx, TxType};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// What a snapshot covers besides the ledgers: which one it is, how many
+// bytes of the WAL it already contains and the stream positions it was
+// taken at. The positions are what keep exactly-once delivery working once
+// the WAL lines that recorded them are gone.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct SnapshotInfo {
+    pub id: u64,
+    pub wal_len: u64,
+    pub positions: HashMap<String, i64>,
+}
+
+fn parse_state(state: &str) -> Result<FundingStates, &'static str> {
+    match state {
+        "valid" => Ok(FundingStates::Valid),
+        "disputed" => Ok(FundingStates::Disputed),
+        "frozen" => Ok(FundingStates::Frozen),
+        _ => Err("Bad snapshot account state"),
+    }
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = OsString::from(path.as_os_str());
+    partial.push(".partial");
+    PathBuf::from(partial)
+}
+
+// Writes every tenant's accounts, sub-account names and transaction log,
+// one CSV-like line each, next to `path` and then renames it into place, so
+// a crash leaves either the previous snapshot or this one.
+pub fn write(path: &Path, tenants: &Tenants, info: &SnapshotInfo) -> io::Result<()> {
+    let partial = partial_path(path);
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    writeln!(writer, "snapshot,{},{}", info.id, info.wal_len)?;
+    for (stream, offset) in &info.positions {
+        writeln!(writer, "position,{},{}", stream, offset)?;
+    }
+    for (tenant, engine) in tenants.iter() {
+        let (funds, records) = engine.ledger();
+        for funds in funds.values() {
+            writeln!(
+                writer,
+                "account,{},{},{},{},{},{},{},{},{},{}",
+                tenant,
+                funds.client.0,
+                funds.available,
+                funds.held,
+                funds.state.as_str(),
+                funds.bonus,
+                funds.turnover,
+                funds.debt,
+                funds.pending,
+                funds.uncovered
+            )?;
+        }
+        // the name goes last, as it may hold commas
+        for (id, parent, name) in engine.subaccounts().iter() {
+            writeln!(
+                writer,
+                "subaccount,{},{},{},{}",
+                tenant, id.0, parent.0, name
+            )?;
+        }
+        for (tx, record) in records {
+            writeln!(
+                writer,
+                "tx,{},{},{},{},{}{}{}",
+                tenant,
+                tx.0,
+                record.client().0,
+                record.r#type().as_str(),
+                record.amount(),
+                if record.is_pending() { ",pending" } else { "" },
+                match record.dispute() {
+                    DisputeStatus::Undisputed => "",
+                    DisputeStatus::Disputed => ",disputed",
+                    DisputeStatus::ChargedBack => ",charged_back",
+                }
+            )?;
+        }
+    }
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()?;
+    fs::rename(&partial, path)
+}
+
+fn number<T: FromStr>(field: &str) -> Result<T, String> {
+    field.parse().map_err(|_| format!("bad number {:?}", field))
+}
+
+fn amount(field: &str) -> Result<Amount, String> {
+    Amount::from_str(field).map_err(|err| err.to_string())
+}
+
+fn parse_line(line: &str, tenants: &mut Tenants, info: &mut SnapshotInfo) -> Result<(), String> {
+    let fields: Vec<&str> = line.split(',').collect();
+    match fields.as_slice() {
+        ["snapshot", id, wal_len] => {
+            info.id = number(id)?;
+            info.wal_len = number(wal_len)?;
+        }
+        ["position", stream, offset] => {
+            info.positions.insert(stream.to_string(), number(offset)?);
+        }
+        // older snapshots have no bonus, debt, pending or uncovered columns
+        ["account", tenant, client, available, held, state, rest @ ..] => {
+            let tenant = tenants.resolve(Some(tenant))?;
+            let mut extra = [Amount::new(0); 5];
+            if rest.len() > extra.len() || rest.len() == 1 {
+                return Err("Bad snapshot line".to_string());
+            }
+            for (field, value) in rest.iter().zip(extra.iter_mut()) {
+                *value = amount(field)?;
+            }
+            let [bonus, turnover, debt, pending, uncovered] = extra;
+            let funds = Funds {
+                client: Client(number(client)?),
+                available: amount(available)?,
+                held: amount(held)?,
+                state: parse_state(state)?,
+                bonus,
+                turnover,
+                debt,
+                pending,
+                uncovered,
+            };
+            let (accounts, _) = tenants.engine(&tenant).ledger_mut();
+            accounts.insert(funds.client, funds);
+        }
+        ["tx", tenant, tx, client, r#type, logged, rest @ ..] => {
+            let tenant = tenants.resolve(Some(tenant))?;
+            let mut record = PackedRecord::pack(
+                TxType::from_str(r#type).map_err(|err| err.to_string())?,
+                amount(logged)?,
+                Client(number(client)?),
+            )
+            .ok_or("Bad snapshot tx amount")?;
+            for flag in rest {
+                record = match *flag {
+                    "pending" => record.into_pending(),
+                    "disputed" => record.with_dispute(DisputeStatus::Disputed),
+                    "charged_back" => record.with_dispute(DisputeStatus::ChargedBack),
+                    _ => return Err("Bad snapshot line".to_string()),
+                };
+            }
+            let (_, records) = tenants.engine(&tenant).ledger_mut();
+            records.insert(Tx(number(tx)?), record);
+        }
+        ["subaccount", tenant, id, parent, name @ ..] if !name.is_empty() => {
+            let tenant = tenants.resolve(Some(tenant))?;
+            tenants.engine(&tenant).subaccounts_mut().insert(
+                Client(number(parent)?),
+                &name.join(","),
+                Client(number(id)?),
+            );
+        }
+        _ => return Err("Bad snapshot line".to_string()),
+    }
+    Ok(())
+}
+
+// Loads a snapshot into `tenants`, which should be empty. A missing file is
+// no snapshot.
+pub fn read(path: &Path, tenants: &mut Tenants) -> io::Result<Option<SnapshotInfo>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let mut info = SnapshotInfo::default();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        parse_line(&line?, tenants, &mut info).map_err(|reason| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("snapshot line {}: {}", number + 1, reason),
+            )
+        })?;
+    }
+    for (_, engine) in tenants.iter_mut() {
+        engine.import_pending();
+    }
+    Ok(Some(info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write, SnapshotInfo};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::tenant::{Tenants, DEFAULT_TENANT};
+    use crate::transactions::Client;
+    use std::fs;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let path = std::env::temp_dir().join("payment_engine_snapshot.snap");
+        // tx ids past u32::MAX survive the round trip
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,1.5\ndeposit,2,5000000000,2.0\ndispute,2,5000000000,\n\
+            deposit,1:savings,3,1.0\n";
+        let mut tenants = Tenants::isolated(Engine::builder());
+        for tenant in ["acme", DEFAULT_TENANT] {
+            tenants
+                .engine(tenant)
+                .process_csv(csvfile.as_bytes())
+                .unwrap();
+        }
+        let mut info = SnapshotInfo {
+            id: 3,
+            wal_len: 120,
+            ..SnapshotInfo::default()
+        };
+        info.positions.insert("kafka/tx/0".to_string(), 41);
+        write(&path, &tenants, &info).unwrap();
+
+        let mut restored = Tenants::isolated(Engine::builder());
+        assert_eq!(read(&path, &mut restored).unwrap(), Some(info));
+        let engine = restored.get("acme").unwrap();
+        assert_eq!(
+            engine.account(Client(2)).unwrap().state,
+            FundingStates::Disputed
+        );
+        // the logged deposit can still be resolved
+        let resolve = "type,client,tx,amount\nresolve,2,5000000000,\n";
+        restored
+            .engine("acme")
+            .process_csv(resolve.as_bytes())
+            .unwrap();
+        let funds = restored.get("acme").unwrap().account(Client(2)).unwrap();
+        assert_eq!(funds.available, Amount::new(20000));
+
+        // sub-accounts keep their ids, and new ones don't take them
+        let engine = restored.engine("acme");
+        let savings = engine.subaccounts().get(Client(1), "savings").unwrap();
+        assert_eq!(
+            engine.subaccount(Client(1), "savings").unwrap().available,
+            Amount::new(10000)
+        );
+        let wallet = "type,client,tx,amount\ndeposit,2:savings,6,1.0\n";
+        engine.process_csv(wallet.as_bytes()).unwrap();
+        assert_ne!(
+            engine.subaccounts().get(Client(2), "savings"),
+            Some(savings)
+        );
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read(&path, &mut restored).unwrap(), None);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::amount::Amount;
+use crate::engine::Engine;
+use crate::history::{statement_history, Activity};
+use crate::output::StatementOptions;
+use crate::transactions::{Client, Tx, TxType};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum StatementFormat {
+    Csv,
+    // a standalone page laid out for printing, e.g. to PDF
+    Html,
+}
+
+impl FromStr for StatementFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<StatementFormat, &'static str> {
+        match s {
+            "csv" => Ok(StatementFormat::Csv),
+            "html" => Ok(StatementFormat::Html),
+            _ => Err("Unknown statement format"),
+        }
+    }
+}
+
+// One applied transaction and the booked balance (the account total) after
+// it. Disputes and resolves move funds between available and held, so they
+// leave the balance as it was.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StatementLine {
+    pub activity: Activity,
+    pub balance: Amount,
+    pub note: &'static str,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Statement {
+    pub client: Client,
+    pub opening: Amount,
+    pub lines: Vec<StatementLine>,
+    pub closing: Amount,
+}
+
+// Deposits and withdrawals are noted with how a dispute of them ended up;
+// dispute events with what they did to the funds.
+fn note(activity: &Activity, outcomes: &HashMap<Tx, TxType>) -> &'static str {
+    match activity.r#type {
+        TxType::Deposit | TxType::Withdrawal => match outcomes.get(&activity.tx) {
+            Some(TxType::Dispute) => "disputed",
+            Some(TxType::Resolve) => "dispute resolved",
+            Some(TxType::Chargeback) => "charged back",
+            _ => "",
+        },
+        TxType::Dispute => "funds held",
+        TxType::Resolve => "funds released",
+        TxType::Chargeback => "reversed, account frozen",
+        TxType::Credit => "bonus",
+        TxType::Settle => "funds settled",
+    }
+}
+
+// Each client's statement in client order, or only `client`'s. History
+// starts over when an engine is restored from a snapshot, so the opening
+// balance is worked back from the closing one rather than assumed zero.
+pub fn statements(engine: &Engine, client: Option<Client>) -> io::Result<Vec<Statement>> {
+    let history = statement_history(engine)?;
+    let mut accounts: Vec<_> = engine
+        .accounts()
+        .filter(|funds| client.is_none_or(|client| funds.client == client))
+        .collect();
+    accounts.sort_by_key(|funds| funds.client.0);
+
+    let mut statements = Vec::new();
+    for funds in accounts {
+        let activity = history.client(funds.client);
+        let mut outcomes = HashMap::new();
+        let (mut credits, mut debits) = (0, 0);
+        for activity in activity {
+            match activity.is_credit() {
+                Some(true) => credits += activity.amount.0,
+                Some(false) => debits += activity.amount.0,
+                None => {}
+            }
+            if !matches!(activity.r#type, TxType::Deposit | TxType::Withdrawal) {
+                outcomes.insert(activity.tx, activity.r#type);
+            }
+        }
+        let closing = funds.total();
+        let opening = Amount((closing.0 + debits).saturating_sub(credits));
+        let mut balance = opening;
+        let lines = activity
+            .iter()
+            .map(|activity| {
+                balance = match activity.is_credit() {
+                    Some(true) => Amount(balance.0 + activity.amount.0),
+                    Some(false) => Amount(balance.0.saturating_sub(activity.amount.0)),
+                    None => balance,
+                };
+                StatementLine {
+                    activity: *activity,
+                    balance,
+                    note: note(activity, &outcomes),
+                }
+            })
+            .collect();
+        statements.push(Statement {
+            client: funds.client,
+            opening,
+            lines,
+            closing,
+        });
+    }
+    Ok(statements)
+}
+
+// Debits are written with a minus sign; disputes and resolves unsigned.
+fn signed(activity: &Activity) -> String {
+    match activity.is_credit() {
+        Some(false) => format!("-{}", activity.amount),
+        _ => activity.amount.to_string(),
+    }
+}
+
+#[derive(Serialize)]
+struct StatementRow<'a> {
+    client: u32,
+    entry: &'a str,
+    tx: Option<u64>,
+    amount: String,
+    balance: String,
+    note: &'a str,
+}
+
+// One row per entry, between an `opening` and a `closing` row per client.
+fn write_csv<W: Write>(statements: &[Statement], writer: W) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for statement in statements {
+        let client = statement.client.0;
+        let balance = |entry, amount: Amount| StatementRow {
+            client,
+            entry,
+            tx: None,
+            amount: String::new(),
+            balance: amount.to_string(),
+            note: "",
+        };
+        writer.serialize(balance("opening", statement.opening))?;
+        for line in &statement.lines {
+            writer.serialize(StatementRow {
+                client,
+                entry: line.activity.r#type.as_str(),
+                tx: Some(line.activity.tx.0),
+                amount: signed(&line.activity),
+                balance: line.balance.to_string(),
+                note: line.note,
+            })?;
+        }
+        writer.serialize(balance("closing", statement.closing))?;
+    }
+    writer.flush()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; font-size: 10pt; }
+section { page-break-after: always; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #ccc; padding: 2pt 4pt; text-align: left; }
+.amount { text-align: right; }";
+
+// A page per client when printed.
+fn write_html<W: Write>(
+    statements: &[Statement],
+    options: &StatementOptions,
+    date: (i64, u32, u32),
+    mut writer: W,
+) -> io::Result<()> {
+    let (year, month, day) = date;
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(
+        writer,
+        "<html><head><meta charset=\"utf-8\"><title>Account statements</title>"
+    )?;
+    writeln!(writer, "<style>\n{}\n</style></head><body>", HTML_STYLE)?;
+    for statement in statements {
+        writeln!(writer, "<section><h1>Client {}</h1>", statement.client.0)?;
+        writeln!(
+            writer,
+            "<p>Statement of {:04}-{:02}-{:02}, in {}</p>",
+            year,
+            month,
+            day,
+            escape(&options.currency)
+        )?;
+        writeln!(writer, "<table><thead><tr><th>Tx</th><th>Entry</th><th class=\"amount\">Amount</th><th class=\"amount\">Balance</th><th>Note</th></tr></thead><tbody>")?;
+        let balance_row = |writer: &mut W, entry, amount: Amount| {
+            writeln!(
+                writer,
+                "<tr><td></td><th>{}</th><td></td><td class=\"amount\">{}</td><td></td></tr>",
+                entry, amount
+            )
+        };
+        balance_row(&mut writer, "Opening balance", statement.opening)?;
+        for line in &statement.lines {
+            writeln!(
+                writer,
+                "<tr><td>{}</td><td>{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td><td>{}</td></tr>",
+                line.activity.tx.0,
+                line.activity.r#type.as_str(),
+                signed(&line.activity),
+                line.balance,
+                line.note,
+            )?;
+        }
+        balance_row(&mut writer, "Closing balance", statement.closing)?;
+        writeln!(writer, "</tbody></table></section>")?;
+    }
+    writeln!(writer, "</body></html>")
+}
+
+// Writes the statements of every client, or only `client`'s, from an
+// engine built with `record_history(true)`.
+pub fn write_statements<W: Write>(
+    engine: &Engine,
+    client: Option<Client>,
+    format: StatementFormat,
+    options: &StatementOptions,
+    writer: W,
+) -> io::Result<()> {
+    let statements = statements(engine, client)?;
+    match format {
+        StatementFormat::Csv => write_csv(&statements, writer),
+        StatementFormat::Html => {
+            let date = options
+                .date()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            write_html(&statements, options, date, writer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_statements, StatementFormat};
+    use crate::engine::Engine;
+    use crate::output::StatementOptions;
+    use crate::transactions::Client;
+
+    #[test]
+    fn test_write_statements() {
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,10.5\ndeposit,2,2,1\nwithdrawal,1,3,0.5\ndeposit,1,4,2\ndispute,1,4,\nchargeback,1,4,\n";
+        let mut engine = Engine::builder().record_history(true).build();
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let options = StatementOptions {
+            currency: "EUR".to_string(),
+            date: Some("2026-10-16".to_string()),
+        };
+        let mut out = Vec::new();
+        write_statements(
+            &engine,
+            Some(Client(1)),
+            StatementFormat::Csv,
+            &options,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "client,entry,tx,amount,balance,note\n",
+                "1,opening,,,0.0000,\n",
+                "1,deposit,1,10.5000,10.5000,\n",
+                "1,withdrawal,3,-0.5000,10.0000,\n",
+                "1,deposit,4,2.0000,12.0000,charged back\n",
+                "1,dispute,4,2.0000,12.0000,funds held\n",
+                "1,chargeback,4,-2.0000,10.0000,\"reversed, account frozen\"\n",
+                "1,closing,,,10.0000,\n",
+            )
+        );
+
+        let mut out = Vec::new();
+        write_statements(&engine, None, StatementFormat::Html, &options, &mut out).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert_eq!(html.matches("<section>").count(), 2);
+        assert!(html.contains("<p>Statement of 2026-10-16, in EUR</p>"));
+        assert!(html.contains("<tr><td>3</td><td>withdrawal</td><td class=\"amount\">-0.5000</td><td class=\"amount\">10.0000</td><td></td></tr>"));
+
+        let engine = Engine::new();
+        assert!(
+            write_statements(&engine, None, StatementFormat::Csv, &options, Vec::new()).is_err()
+        );
+    }
+}
@@ -0,0 +1,422 @@
+use crate::config::Config;
+use crate::simulation::SnapshotFault;
+use crate::snapshot::{self, SnapshotInfo};
+use crate::tenant::Tenants;
+use crate::transactions::TransactionRecord;
+use crate::wal::{self, Position, Wal};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Message buses `--source` can consume from instead of reading a file.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Source {
+    #[cfg(feature = "amqp")]
+    Amqp,
+    #[cfg(feature = "kafka")]
+    Kafka,
+    #[cfg(feature = "nats")]
+    Nats,
+}
+
+impl FromStr for Source {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Source, &'static str> {
+        match s {
+            #[cfg(feature = "amqp")]
+            "amqp" => Ok(Source::Amqp),
+            #[cfg(feature = "kafka")]
+            "kafka" => Ok(Source::Kafka),
+            #[cfg(feature = "nats")]
+            "nats" => Ok(Source::Nats),
+            _ => Err("Unknown or disabled source"),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Delivery {
+    // Input is acknowledged once its records are durable in the WAL; after a
+    // crash, records between the last flush and the acknowledgement are
+    // delivered (and applied) again.
+    #[default]
+    AtLeastOnce,
+    // Every WAL line also records the position it was read at, and after a
+    // restart records at or before the replayed position are skipped.
+    ExactlyOnce,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Payload {
+    // one `{type, client, tx, amount}` object per message
+    #[default]
+    Json,
+    // one (not length-delimited) `Transaction` message per message
+    #[cfg(feature = "protobuf")]
+    Protobuf,
+}
+
+impl Payload {
+    pub fn decode(self, bytes: &[u8]) -> Result<TransactionRecord, Box<dyn Error + Send + Sync>> {
+        match self {
+            Payload::Json => Ok(crate::json::parse_record(bytes)?),
+            #[cfg(feature = "protobuf")]
+            Payload::Protobuf => crate::protobuf::parse_record(bytes),
+        }
+    }
+}
+
+pub fn default_snapshot_every() -> usize {
+    100_000
+}
+
+// Where `StreamApplier` keeps a snapshot of the ledgers and stream
+// positions, retaken (and the WAL restarted) at the first flush after
+// `every` more records, so a restart replays a short WAL.
+#[derive(Debug, Clone)]
+pub struct Snapshots {
+    pub path: PathBuf,
+    pub every: usize,
+}
+
+// The part of a message-bus source that doesn't depend on the bus: apply
+// records, log them to the WAL and tell the source when it may acknowledge
+// what it has consumed.
+pub struct StreamApplier {
+    tenants: Tenants,
+    wal: Option<Wal>,
+    delivery: Delivery,
+    positions: HashMap<String, i64>,
+    flush_every: usize,
+    unflushed: usize,
+    snapshots: Option<Snapshots>,
+    snapshot_id: u64,
+    since_snapshot: usize,
+    // a failure `simulation` injects into the next snapshot
+    snapshot_fault: Option<SnapshotFault>,
+}
+
+impl StreamApplier {
+    // Rebuilds the engines from the snapshot and WAL, if there are any,
+    // before consuming.
+    pub fn open(
+        mut tenants: Tenants,
+        wal_path: Option<&Path>,
+        snapshots: Option<Snapshots>,
+        delivery: Delivery,
+        flush_every: usize,
+    ) -> io::Result<StreamApplier> {
+        let invalid = |reason| io::Error::new(io::ErrorKind::InvalidInput, reason);
+        if delivery == Delivery::ExactlyOnce && wal_path.is_none() {
+            return Err(invalid("exactly-once delivery needs a WAL"));
+        }
+        // records acknowledged since the last snapshot are only in the WAL
+        if snapshots.is_some() && wal_path.is_none() {
+            return Err(invalid("snapshots need a WAL"));
+        }
+        let snapshot = match &snapshots {
+            Some(snapshots) => snapshot::read(&snapshots.path, &mut tenants)?,
+            None => None,
+        };
+        let mut positions = snapshot
+            .as_ref()
+            .map(|snapshot| snapshot.positions.clone())
+            .unwrap_or_default();
+        let wal = match wal_path {
+            Some(path) => {
+                positions.extend(wal::replay(path, &mut tenants, snapshot.as_ref())?);
+                Some(Wal::open(path)?)
+            }
+            None => None,
+        };
+        Ok(StreamApplier {
+            tenants,
+            wal,
+            delivery,
+            positions,
+            flush_every: flush_every.max(1),
+            unflushed: 0,
+            snapshots,
+            snapshot_id: snapshot.map_or(0, |snapshot| snapshot.id),
+            since_snapshot: 0,
+            snapshot_fault: None,
+        })
+    }
+
+    pub fn position(&self, stream: &str) -> Option<i64> {
+        self.positions.get(stream).copied()
+    }
+
+    // Applies a record to a resolved tenant (see `Tenants::resolve`) and
+    // returns false when it was already applied before a restart.
+    #[tracing::instrument(level = "debug", skip_all, fields(stream = %position.stream, offset = position.offset))]
+    pub fn apply(
+        &mut self,
+        tenant: &str,
+        position: Position,
+        record: &TransactionRecord,
+    ) -> io::Result<bool> {
+        let seen = self.position(&position.stream);
+        if self.delivery == Delivery::ExactlyOnce
+            && seen.is_some_and(|seen| position.offset <= seen)
+        {
+            return Ok(false);
+        }
+        self.tenants.engine(tenant).process(record);
+        if let Some(wal) = &mut self.wal {
+            let logged = match self.delivery {
+                Delivery::ExactlyOnce => Some(&position),
+                Delivery::AtLeastOnce => None,
+            };
+            wal.append(tenant, record, logged)?;
+        }
+        self.positions.insert(position.stream, position.offset);
+        self.unflushed += 1;
+        self.since_snapshot += 1;
+        Ok(true)
+    }
+
+    pub fn should_flush(&self) -> bool {
+        self.unflushed >= self.flush_every
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(records = self.unflushed))]
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(wal) = &mut self.wal {
+            wal.flush()?;
+        }
+        self.unflushed = 0;
+        match &self.snapshots {
+            Some(snapshots) if self.since_snapshot >= snapshots.every => self.snapshot(),
+            _ => Ok(()),
+        }
+    }
+
+    // Only called right after a flush, so the snapshot covers the whole WAL
+    // and the positions of everything in it.
+    #[tracing::instrument(level = "info", skip_all, fields(id = self.snapshot_id + 1))]
+    fn snapshot(&mut self) -> io::Result<()> {
+        let fault = self.snapshot_fault.take();
+        let (Some(snapshots), Some(wal)) = (&self.snapshots, &mut self.wal) else {
+            return Ok(());
+        };
+        let info = SnapshotInfo {
+            id: self.snapshot_id + 1,
+            wal_len: wal.logged_bytes()?,
+            positions: self.positions.clone(),
+        };
+        if fault == Some(SnapshotFault::Write) {
+            return Err(io::Error::other("injected failure writing the snapshot"));
+        }
+        snapshot::write(&snapshots.path, &self.tenants, &info)?;
+        if fault == Some(SnapshotFault::WalRestart) {
+            return Err(io::Error::other("injected failure restarting the WAL"));
+        }
+        wal.restart(info.id)?;
+        self.snapshot_id = info.id;
+        self.since_snapshot = 0;
+        Ok(())
+    }
+
+    pub fn tenants(&self) -> &Tenants {
+        &self.tenants
+    }
+
+    pub(crate) fn wal_mut(&mut self) -> Option<&mut Wal> {
+        self.wal.as_mut()
+    }
+
+    pub(crate) fn fail_next_snapshot(&mut self, fault: SnapshotFault) {
+        self.snapshot_fault = Some(fault);
+    }
+
+    pub fn into_tenants(self) -> Tenants {
+        self.tenants
+    }
+}
+
+// Feeds the source's messages to the tenants until it stops. Without any
+// bus feature `Source` has no variants and this never runs.
+#[cfg_attr(
+    not(any(feature = "amqp", feature = "kafka", feature = "nats")),
+    allow(unused_variables)
+)]
+pub fn consume(
+    tenants: Tenants,
+    source: Source,
+    config: &Config,
+) -> Result<Tenants, Box<dyn Error + Send + Sync>> {
+    match source {
+        #[cfg(feature = "amqp")]
+        Source::Amqp => {
+            let amqp = config
+                .amqp
+                .as_ref()
+                .ok_or("--source amqp needs an [amqp] section in --config")?;
+            crate::amqp::consume(tenants, amqp)
+        }
+        #[cfg(feature = "kafka")]
+        Source::Kafka => {
+            let kafka = config
+                .kafka
+                .as_ref()
+                .ok_or("--source kafka needs a [kafka] section in --config")?;
+            crate::kafka::consume(tenants, kafka)
+        }
+        #[cfg(feature = "nats")]
+        Source::Nats => {
+            let nats = config
+                .nats
+                .as_ref()
+                .ok_or("--source nats needs a [nats] section in --config")?;
+            crate::nats::consume(tenants, nats)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Delivery, Payload, Snapshots, StreamApplier};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::snapshot::{self, SnapshotInfo};
+    use crate::tenant::{Tenants, DEFAULT_TENANT};
+    use crate::transactions::Client;
+    use crate::wal::Position;
+    use std::fs;
+
+    #[test]
+    fn test_exactly_once_restart() {
+        let path = std::env::temp_dir().join("payment_engine_stream_restart.log");
+        let _ = fs::remove_file(&path);
+        let messages = [
+            r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "5"}"#,
+            r#"{"type": "dispute", "client": 1, "tx": 1}"#,
+            r#"{"type": "resolve", "client": 1, "tx": 1}"#,
+        ];
+        let position = |offset| Position {
+            stream: "kafka/tx/0".to_string(),
+            offset,
+        };
+
+        let tenants = || Tenants::single(Engine::builder());
+        let mut applier =
+            StreamApplier::open(tenants(), Some(&path), None, Delivery::ExactlyOnce, 2).unwrap();
+        for (offset, message) in messages.iter().enumerate() {
+            let record = Payload::Json.decode(message.as_bytes()).unwrap();
+            let position = position(offset as i64);
+            assert!(applier.apply(DEFAULT_TENANT, position, &record).unwrap());
+        }
+        assert!(applier.should_flush());
+        applier.flush().unwrap();
+        drop(applier);
+
+        // the bus redelivers everything after a crash before its commit; the
+        // dispute must not be applied a second time
+        let mut applier =
+            StreamApplier::open(tenants(), Some(&path), None, Delivery::ExactlyOnce, 2).unwrap();
+        assert_eq!(applier.position("kafka/tx/0"), Some(2));
+        for (offset, message) in messages.iter().enumerate().skip(1) {
+            let record = Payload::Json.decode(message.as_bytes()).unwrap();
+            let position = position(offset as i64);
+            assert!(!applier.apply(DEFAULT_TENANT, position, &record).unwrap());
+        }
+        let engine = applier.tenants().get(DEFAULT_TENANT).unwrap();
+        let funds = engine.account(Client(1)).unwrap();
+        assert_eq!(funds.available, Amount::new(50000));
+        assert_eq!(funds.held, Amount::new(0));
+
+        fs::remove_file(&path).unwrap();
+        assert!(StreamApplier::open(tenants(), None, None, Delivery::ExactlyOnce, 1).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_restart() {
+        let dir = std::env::temp_dir();
+        let wal_path = dir.join("payment_engine_snapshot_restart.log");
+        let snapshot_path = dir.join("payment_engine_snapshot_restart.snap");
+        let _ = fs::remove_file(&wal_path);
+        let _ = fs::remove_file(&snapshot_path);
+        let messages = [
+            r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "5"}"#,
+            r#"{"type": "dispute", "client": 1, "tx": 1}"#,
+            r#"{"type": "deposit", "client": 1, "tx": 2, "amount": "1"}"#,
+            r#"{"type": "resolve", "client": 1, "tx": 1}"#,
+        ];
+        let open = || {
+            let snapshots = Snapshots {
+                path: snapshot_path.clone(),
+                every: 2,
+            };
+            let tenants = Tenants::single(Engine::builder());
+            StreamApplier::open(
+                tenants,
+                Some(&wal_path),
+                Some(snapshots),
+                Delivery::ExactlyOnce,
+                2,
+            )
+            .unwrap()
+        };
+        // redelivers everything, as a bus would without any commits
+        let consume = |applier: &mut StreamApplier| {
+            for (offset, message) in messages.iter().enumerate() {
+                let record = Payload::Json.decode(message.as_bytes()).unwrap();
+                let position = Position {
+                    stream: "kafka/tx/0".to_string(),
+                    offset: offset as i64,
+                };
+                applier.apply(DEFAULT_TENANT, position, &record).unwrap();
+                if applier.should_flush() {
+                    applier.flush().unwrap();
+                }
+            }
+        };
+        let available = |applier: &StreamApplier| {
+            let engine = applier.tenants().get(DEFAULT_TENANT).unwrap();
+            engine.account(Client(1)).unwrap().available
+        };
+
+        let mut applier = open();
+        consume(&mut applier);
+        assert_eq!(available(&applier), Amount::new(60000));
+        drop(applier);
+        // two snapshots were taken and the WAL restarted after the second
+        assert_eq!(fs::read_to_string(&wal_path).unwrap(), "snapshot,2\n");
+
+        let mut applier = open();
+        assert_eq!(applier.position("kafka/tx/0"), Some(3));
+        consume(&mut applier);
+        assert_eq!(available(&applier), Amount::new(60000));
+
+        // a crash after writing a snapshot but before restarting the WAL
+        // leaves lines in the WAL that the snapshot already contains
+        fs::write(&wal_path, "snapshot,2\ndeposit,1,3,1.0000,kafka/tx/0,4\n").unwrap();
+        let mut tenants = Tenants::single(Engine::builder());
+        snapshot::read(&snapshot_path, &mut tenants).unwrap();
+        let mut info = SnapshotInfo {
+            id: 3,
+            wal_len: fs::metadata(&wal_path).unwrap().len(),
+            ..SnapshotInfo::default()
+        };
+        info.positions.insert("kafka/tx/0".to_string(), 4);
+        tenants.engine(DEFAULT_TENANT).process(
+            &Payload::Json
+                .decode(br#"{"type": "deposit", "client": 1, "tx": 3, "amount": "1"}"#)
+                .unwrap(),
+        );
+        snapshot::write(&snapshot_path, &tenants, &info).unwrap();
+        let applier = open();
+        assert_eq!(applier.position("kafka/tx/0"), Some(4));
+        assert_eq!(available(&applier), Amount::new(70000));
+
+        fs::remove_file(&wal_path).unwrap();
+        fs::remove_file(&snapshot_path).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use crate::amount::Amount;
+use crate::funds::{FundingStates, Funds};
+use crate::transactions::{Client, ClientFunds};
+use std::collections::HashMap;
+
+// Sub-accounts under a parent client, e.g. a wallet per purpose, addressed
+// as `client:name` in the client column. Each one is an account of its own,
+// under an id taken from the top of the client id range down, so its
+// balances, dispute holds and logged transactions stay apart from its
+// parent's and its siblings'. Ids that already have an account are skipped,
+// and once an id is a sub-account's, rows for that client id are rejected
+// (see `Engine::process_row`). Snapshots keep the names along with the ids.
+#[derive(Debug, Default, Clone)]
+pub struct SubAccounts {
+    by_parent: HashMap<Client, HashMap<String, Client>>,
+    parents: HashMap<Client, (Client, String)>,
+}
+
+impl SubAccounts {
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+
+    pub fn get(&self, parent: Client, name: &str) -> Option<Client> {
+        self.by_parent.get(&parent)?.get(name).copied()
+    }
+
+    // The id of `parent`'s sub-account `name`, given one on first use that
+    // is neither a sub-account's nor `taken`, e.g. by a client's account.
+    pub fn resolve<F>(&mut self, parent: Client, name: &str, taken: F) -> Client
+    where
+        F: Fn(Client) -> bool,
+    {
+        if let Some(id) = self.get(parent, name) {
+            return id;
+        }
+        let mut id = Client(u32::MAX - self.parents.len() as u32);
+        while taken(id) || self.parents.contains_key(&id) {
+            id = Client(id.0 - 1);
+        }
+        self.insert(parent, name, id);
+        id
+    }
+
+    // Records `id` as `parent`'s sub-account `name`, as restored from a
+    // snapshot.
+    pub fn insert(&mut self, parent: Client, name: &str, id: Client) {
+        self.by_parent
+            .entry(parent)
+            .or_default()
+            .insert(name.to_string(), id);
+        self.parents.insert(id, (parent, name.to_string()));
+    }
+
+    // Each sub-account's id, parent and name.
+    pub fn iter(&self) -> impl Iterator<Item = (Client, Client, &str)> {
+        self.parents
+            .iter()
+            .map(|(id, (parent, name))| (*id, *parent, name.as_str()))
+    }
+
+    // The parent and name of a sub-account's id; None for other clients.
+    pub fn owner(&self, id: Client) -> Option<(Client, &str)> {
+        self.parents
+            .get(&id)
+            .map(|(parent, name)| (*parent, name.as_str()))
+    }
+
+    // The accounts with each sub-account folded into its parent: balances
+    // add up, and the parent takes the most severe state among them (frozen,
+    // then disputed). A parent that only holds sub-accounts gets a row too.
+    pub fn roll_up<'a, I>(&self, accounts: I) -> Vec<Funds>
+    where
+        I: IntoIterator<Item = &'a Funds>,
+    {
+        let mut rolled = ClientFunds::default();
+        for funds in accounts {
+            let client = self
+                .owner(funds.client)
+                .map_or(funds.client, |(parent, _)| parent);
+            let total = rolled.entry(client).or_insert_with(|| Funds::new(client));
+            total.available = add(total.available, funds.available);
+            total.held = add(total.held, funds.held);
+            total.bonus = add(total.bonus, funds.bonus);
+            total.turnover = add(total.turnover, funds.turnover);
+            total.debt = add(total.debt, funds.debt);
+            total.uncovered = add(total.uncovered, funds.uncovered);
+            total.pending = add(total.pending, funds.pending);
+            total.state = severest(total.state, funds.state);
+        }
+        rolled.into_values().collect()
+    }
+}
+
+fn add(a: Amount, b: Amount) -> Amount {
+    Amount(a.0.saturating_add(b.0))
+}
+
+fn severest(a: FundingStates, b: FundingStates) -> FundingStates {
+    let rank = |state| match state {
+        FundingStates::Valid => 0,
+        FundingStates::Disputed => 1,
+        FundingStates::Frozen => 2,
+    };
+    if rank(b) > rank(a) {
+        b
+    } else {
+        a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::funds::FundingStates;
+    use crate::output::write_accounts;
+    use crate::transactions::{Client, RejectReason, RowRecord, Tx, TxType};
+
+    #[test]
+    fn test_subaccounts() {
+        let input = "type,client,tx,amount\ndeposit,7,1,10\ndeposit,7:savings,2,5\ndeposit,7:travel,3,2\ndispute,7:savings,2,\ndispute,7,3,\nwithdrawal,7:travel,4,1\n";
+        let mut engine = Engine::new();
+        engine.process_csv(input.as_bytes()).unwrap();
+
+        let savings = engine.subaccount(Client(7), "savings").unwrap();
+        assert_eq!(
+            (savings.available, savings.held),
+            (Amount::new(0), Amount::new(50000))
+        );
+        assert_eq!(savings.state, FundingStates::Disputed);
+        // tx 3 belongs to the travel wallet, so the parent can't dispute it
+        let travel = engine.subaccount(Client(7), "travel").unwrap();
+        assert_eq!(travel.available, Amount::new(10000));
+        assert_eq!(engine.account(Client(7)).unwrap().held, Amount::new(0));
+
+        let mut out = Vec::new();
+        write_accounts(&engine.rolled_up_accounts(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked\n7,11.0000,5.0000,16.0000,false\n"
+        );
+
+        let row = RowRecord::new(TxType::Deposit, Client(8), Tx(5), Some("1"));
+        engine
+            .process_row(row.with_subaccount(Some("savings")))
+            .unwrap();
+        assert_ne!(
+            engine.subaccount(Client(8), "savings"),
+            engine.subaccount(Client(7), "savings")
+        );
+        assert!(engine.account(Client(8)).is_none());
+    }
+
+    #[test]
+    fn test_subaccount_ids_stay_apart() {
+        let input = "type,client,tx,amount\ndeposit,4294967295,1,50\ndeposit,1:savings,2,10\n";
+        let mut engine = Engine::new();
+        engine.process_csv(input.as_bytes()).unwrap();
+        let id = engine.subaccounts().get(Client(1), "savings").unwrap();
+        assert_eq!(id, Client(u32::MAX - 1));
+        assert_eq!(
+            engine.account(Client(u32::MAX)).unwrap().available,
+            Amount::new(500000)
+        );
+
+        // the sub-account's id is no longer a client's to use
+        let row = RowRecord::new(TxType::Deposit, id, Tx(3), Some("5"));
+        assert_eq!(engine.process_row(row), Err(RejectReason::ReservedClient));
+        let mut out = Vec::new();
+        write_accounts(&engine.rolled_up_accounts(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked\n1,10.0000,0.0000,10.0000,false\n4294967295,50.0000,0.0000,50.0000,false\n"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::amount::Amount;
+use crate::funds::FundingStates;
+use crate::tenant::Tenants;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+// Totals for a whole run across every tenant, written once it is done so
+// batch jobs can check the outcome without parsing the account output.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub records: u64,
+    pub applied: u64,
+    pub rejected: u64,
+    pub malformed: u64,
+    pub applied_by_type: BTreeMap<&'static str, u64>,
+    pub rejected_by_type: BTreeMap<&'static str, u64>,
+    pub rejected_by_reason: BTreeMap<&'static str, u64>,
+    pub accounts: usize,
+    pub frozen_accounts: usize,
+    pub available: String,
+    pub held: String,
+    pub elapsed_secs: f64,
+    pub records_per_sec: f64,
+}
+
+impl RunSummary {
+    // `elapsed` is the wall-clock time of the run, reading included.
+    pub fn new(tenants: &Tenants, elapsed: Duration) -> RunSummary {
+        let mut summary = RunSummary::default();
+        let (mut available, mut held) = (0u64, 0u64);
+        for (_, engine) in tenants.iter() {
+            let metrics = engine.metrics();
+            summary.records += metrics.records_read;
+            summary.applied += metrics.records_applied;
+            summary.rejected += metrics.records_rejected();
+            summary.malformed += metrics.rows_malformed;
+            for (r#type, count) in metrics.applied_by_type {
+                *summary.applied_by_type.entry(r#type.as_str()).or_default() += count;
+            }
+            for (r#type, count) in metrics.rejected_by_type {
+                *summary.rejected_by_type.entry(r#type.as_str()).or_default() += count;
+            }
+            for (reason, count) in metrics.rejected {
+                *summary
+                    .rejected_by_reason
+                    .entry(reason.as_str())
+                    .or_default() += count;
+            }
+            for funds in engine.accounts() {
+                summary.accounts += 1;
+                if funds.state == FundingStates::Frozen {
+                    summary.frozen_accounts += 1;
+                }
+                available = available.saturating_add(funds.available.0);
+                held = held.saturating_add(funds.held.0);
+            }
+        }
+        summary.available = Amount(available).to_string();
+        summary.held = Amount(held).to_string();
+        summary.elapsed_secs = elapsed.as_secs_f64();
+        if summary.elapsed_secs > 0.0 {
+            summary.records_per_sec = summary.records as f64 / summary.elapsed_secs;
+        }
+        summary
+    }
+}
+
+// As a pretty-printed JSON object.
+pub fn write_summary<W: Write>(summary: &RunSummary, mut writer: W) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut writer, summary)?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_summary, RunSummary};
+    use crate::engine::Engine;
+    use crate::tenant::Tenants;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    #[test]
+    fn test_run_summary() {
+        let mut tenants = Tenants::isolated(Engine::builder());
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,2,2,1.5\nwithdrawal,1,3,5\ndispute,2,2,\nchargeback,2,2,\n";
+        tenants
+            .engine("acme")
+            .process_csv(csvfile.as_bytes())
+            .unwrap();
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,1.0\ndispute,1,1,\n";
+        tenants
+            .engine("globex")
+            .process_csv(csvfile.as_bytes())
+            .unwrap();
+        let summary = RunSummary::new(&tenants, Duration::from_secs(2));
+        assert_eq!(summary.records_per_sec, 3.5);
+
+        let mut out = Vec::new();
+        write_summary(&summary, &mut out).unwrap();
+        let summary: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            summary,
+            json!({
+                "records": 7,
+                "applied": 6,
+                "rejected": 1,
+                "malformed": 0,
+                "applied_by_type": {"chargeback": 1, "deposit": 3, "dispute": 2},
+                "rejected_by_type": {"withdrawal": 1},
+                "rejected_by_reason": {"insufficient_funds": 1},
+                "accounts": 3,
+                "frozen_accounts": 1,
+                "available": "2.0000",
+                "held": "1.0000",
+                "elapsed_secs": 2.0,
+                "records_per_sec": 3.5,
+            })
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use crate::engine::Engine;
+use crate::json::parse_record;
+use crate::serve::Ingest;
+use crate::tenant::DEFAULT_TENANT;
+use crate::transactions::{RowRecord, TransactionRecord};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+// A line that didn't parse has no transaction id to report.
+const MALFORMED: &str = "ERR - malformed";
+
+// A CSV line is read positionally as `type,client,tx,amount`, like a file
+// without a header row.
+fn parse_csv(line: &str) -> Option<TransactionRecord> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(line.as_bytes());
+    let raw = reader.records().next()?.ok()?;
+    let row: RowRecord = raw.deserialize(None).ok()?;
+    TransactionRecord::try_from(row).ok()
+}
+
+// Applies one line and returns the reply to send back, or `None` for lines
+// that get no reply: blank lines and a CSV header row.
+pub fn respond(engine: &mut Engine, line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("type") {
+        return None;
+    }
+    let record = if line.starts_with('{') {
+        parse_record(line.as_bytes()).ok()
+    } else {
+        parse_csv(line)
+    };
+    let reply = match record {
+        Some(record) => match engine.try_process(&record) {
+            Ok(()) => format!("OK {}", record.tx.0),
+            Err(reason) => format!("ERR {} {}", record.tx.0, reason.as_str()),
+        },
+        None => MALFORMED.to_string(),
+    };
+    Some(reply)
+}
+
+// The line protocol has nowhere to name a tenant, so it always applies to
+// the default one. The next line is only read once the applier has answered
+// this one, so a full queue stops the connection reading.
+async fn handle(ingest: Ingest, stream: TcpStream) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let reply = ingest
+            .apply(DEFAULT_TENANT.to_string(), move |engine| {
+                respond(engine, &line)
+            })
+            .await
+            .ok_or_else(|| std::io::Error::other("server is shutting down"))?;
+        if let Some(mut reply) = reply {
+            reply.push('\n');
+            writer.write_all(reply.as_bytes()).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn accept(ingest: Ingest, listener: TcpListener) -> std::io::Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let ingest = ingest.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle(ingest, stream).await {
+                tracing::warn!(peer = %peer, error = %err, "connection failed");
+            }
+        });
+    }
+}
+
+// Serves newline-delimited CSV or JSON transactions on `addr` until
+// interrupted (Ctrl-C), answering each line with `OK <tx>` or
+// `ERR <tx> <reason>` using the same reason codes as the REST API.
+pub fn serve(ingest: Ingest, addr: SocketAddr) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let listener = TcpListener::bind(addr).await?;
+        tokio::select! {
+            result = accept(ingest, listener) => result,
+            _ = tokio::signal::ctrl_c() => Ok(()),
+        }
+    })?;
+    // open connections hold the queue until their tasks go away
+    drop(runtime);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::respond;
+    use crate::engine::Engine;
+
+    #[test]
+    fn test_respond() {
+        let mut engine = Engine::new();
+        let replies: Vec<_> = [
+            "type,client,tx,amount",
+            "deposit, 1, 1, 5.0",
+            r#"{"type":"withdrawal","client":1,"tx":2,"amount":"9.0"}"#,
+            "",
+            "withdrawal,1,3,2.5",
+            "deposit,1,not-a-tx,1.0",
+        ]
+        .iter()
+        .filter_map(|line| respond(&mut engine, line))
+        .collect();
+        assert_eq!(
+            replies,
+            [
+                "OK 1",
+                "ERR 2 insufficient_funds",
+                "OK 3",
+                "ERR - malformed"
+            ]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::env;
+use std::error::Error;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+// Exports the engine's tracing spans over OTLP/HTTP until shut down.
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+}
+
+impl Telemetry {
+    // Connects to the collector configured with the standard
+    // `OTEL_EXPORTER_OTLP_ENDPOINT` (or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`)
+    // variable; without one there is nothing to export to.
+    pub fn init() -> Result<Option<Telemetry>, Box<dyn Error + Send + Sync>> {
+        let configured = [
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+            "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+        ]
+        .iter()
+        .any(|name| env::var_os(name).is_some());
+        if !configured {
+            return Ok(None);
+        }
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()?;
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(env!("CARGO_PKG_NAME"))
+                    .build(),
+            )
+            .build();
+        Ok(Some(Telemetry { provider }))
+    }
+
+    // A layer exporting spans at `level` and above, to install next to the
+    // log layer (see `logging`).
+    pub fn layer<S>(&self, level: Level) -> Box<dyn Layer<S> + Send + Sync>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+    {
+        tracing_opentelemetry::layer()
+            .with_tracer(self.provider.tracer("payment_engine"))
+            .with_filter(LevelFilter::from_level(level))
+            .boxed()
+    }
+
+    // Sends the spans still buffered before the process exits.
+    pub fn shutdown(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.provider.shutdown()?;
+        Ok(())
+    }
+}
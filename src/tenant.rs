@@ -0,0 +1,182 @@
+use crate::engine::{Engine, EngineBuilder};
+use std::collections::BTreeMap;
+
+// The tenant records belong to when nothing names one, and the only tenant
+// unless `--tenants` is given.
+pub const DEFAULT_TENANT: &str = "default";
+
+// Request header (REST) or metadata key (gRPC) naming a request's tenant.
+pub const TENANT_HEADER: &str = "x-tenant";
+
+// Tenant names end up in CSV output, metric labels and WAL lines, so they
+// are kept to the characters Kafka allows in topic names.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 255
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'_' || b == b'-')
+}
+
+// The tenant an input file belongs to: its name up to the first dot, so
+// `in/acme.csv.gz` and `https://host/acme.csv?sig=..` are both `acme`.
+pub fn from_path(path: &str) -> Result<String, String> {
+    if path == "-" {
+        return Err("stdin has no file name to name a tenant after".to_string());
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let name = name.split(['.', '?']).next().unwrap_or(name);
+    if valid_name(name) {
+        Ok(name.to_string())
+    } else {
+        Err(format!("can't name a tenant after {:?}", path))
+    }
+}
+
+// See `Tenants::resolve`; shared with `serve::SharedTenants`.
+pub(crate) fn resolve(isolated: bool, requested: Option<&str>) -> Result<String, String> {
+    match requested {
+        None => Ok(DEFAULT_TENANT.to_string()),
+        Some(name) if !valid_name(name) => Err(format!("invalid tenant {:?}", name)),
+        Some(name) if !isolated && name != DEFAULT_TENANT => Err(format!(
+            "unknown tenant {:?}: tenants are not enabled",
+            name
+        )),
+        Some(name) => Ok(name.to_string()),
+    }
+}
+
+// The ledgers hosted by one process, each a separate engine with its own
+// accounts, transaction log and metrics.
+pub struct Tenants {
+    builder: EngineBuilder,
+    engines: BTreeMap<String, Engine>,
+    isolated: bool,
+}
+
+impl Tenants {
+    // Everything is applied to `DEFAULT_TENANT`; naming another tenant is
+    // an error.
+    pub fn single(builder: EngineBuilder) -> Tenants {
+        let mut engines = BTreeMap::new();
+        engines.insert(DEFAULT_TENANT.to_string(), builder.clone().build());
+        Tenants {
+            builder,
+            engines,
+            isolated: false,
+        }
+    }
+
+    // Each tenant gets an engine from `builder` the first time it is named.
+    pub fn isolated(builder: EngineBuilder) -> Tenants {
+        Tenants {
+            builder,
+            engines: BTreeMap::new(),
+            isolated: true,
+        }
+    }
+
+    pub fn is_isolated(&self) -> bool {
+        self.isolated
+    }
+
+    // The tenant a request, file or topic names, or the default one when it
+    // names none.
+    pub fn resolve(&self, requested: Option<&str>) -> Result<String, String> {
+        resolve(self.isolated, requested)
+    }
+
+    // The engine of a resolved tenant, created on first use.
+    pub fn engine(&mut self, tenant: &str) -> &mut Engine {
+        if !self.engines.contains_key(tenant) {
+            let engine = self.builder.clone().build();
+            self.engines.insert(tenant.to_string(), engine);
+        }
+        self.engines.get_mut(tenant).expect("tenant was just added")
+    }
+
+    pub fn get(&self, tenant: &str) -> Option<&Engine> {
+        self.engines.get(tenant)
+    }
+
+    pub fn get_mut(&mut self, tenant: &str) -> Option<&mut Engine> {
+        self.engines.get_mut(tenant)
+    }
+
+    // Tenants in name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Engine)> {
+        self.engines
+            .iter()
+            .map(|(tenant, engine)| (tenant.as_str(), engine))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut Engine)> {
+        self.engines
+            .iter_mut()
+            .map(|(tenant, engine)| (tenant.as_str(), engine))
+    }
+
+    pub(crate) fn into_parts(self) -> (EngineBuilder, BTreeMap<String, Engine>, bool) {
+        (self.builder, self.engines, self.isolated)
+    }
+
+    pub(crate) fn from_parts(
+        builder: EngineBuilder,
+        engines: BTreeMap<String, Engine>,
+        isolated: bool,
+    ) -> Tenants {
+        Tenants {
+            builder,
+            engines,
+            isolated,
+        }
+    }
+
+    // The default tenant's engine, for output that has no tenant dimension.
+    pub fn into_default(mut self) -> Engine {
+        self.engines
+            .remove(DEFAULT_TENANT)
+            .unwrap_or_else(|| self.builder.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_path, Tenants, DEFAULT_TENANT};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::Client;
+
+    #[test]
+    fn test_tenants_are_isolated() {
+        let mut tenants = Tenants::isolated(Engine::builder());
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,2.0\n";
+        for tenant in ["acme", "globex"] {
+            let tenant = tenants.resolve(Some(tenant)).unwrap();
+            tenants
+                .engine(&tenant)
+                .process_csv(csvfile.as_bytes())
+                .unwrap();
+        }
+        // the same tx id is a duplicate only within a tenant
+        let funds = |tenants: &Tenants, tenant| {
+            tenants
+                .get(tenant)
+                .unwrap()
+                .account(Client(1))
+                .unwrap()
+                .available
+        };
+        assert_eq!(funds(&tenants, "acme"), Amount::new(20000));
+        assert_eq!(funds(&tenants, "globex"), Amount::new(20000));
+        let names: Vec<&str> = tenants.iter().map(|(tenant, _)| tenant).collect();
+        assert_eq!(names, ["acme", "globex"]);
+        assert!(tenants.resolve(Some("a/b")).is_err());
+
+        let single = Tenants::single(Engine::builder());
+        assert_eq!(single.resolve(None).unwrap(), DEFAULT_TENANT);
+        assert!(single.resolve(Some("acme")).is_err());
+        assert_eq!(from_path("in/acme.csv.gz").unwrap(), "acme");
+        assert_eq!(from_path("https://host/acme.csv?sig=x").unwrap(), "acme");
+    }
+}
@@ -0,0 +1,352 @@
+use crate::amount::Amount;
+#[cfg(feature = "testkit")]
+use crate::amount::SCALE;
+use crate::transactions::{Client, TransactionRecord, Tx, TxType};
+#[cfg(feature = "testkit")]
+use arbitrary::{Arbitrary, Unstructured};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// How many of the latest deposits can still be disputed.
+const DISPUTABLE: usize = 1000;
+
+// Shape of a generated workload. Ratios are between 0 and 1.
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    pub clients: u32,
+    // rows, malformed ones included
+    pub transactions: u32,
+    // share of rows that are disputes; as many again settle them later
+    pub dispute_ratio: f64,
+    // share of disputes settled by a chargeback rather than a resolve
+    pub chargeback_ratio: f64,
+    pub withdrawal_ratio: f64,
+    pub malformed_ratio: f64,
+    // the same seed always gives the same rows; None picks one from the clock
+    pub seed: Option<u64>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> GeneratorConfig {
+        GeneratorConfig {
+            clients: 100,
+            transactions: 10_000,
+            dispute_ratio: 0.02,
+            chargeback_ratio: 0.3,
+            withdrawal_ratio: 0.3,
+            malformed_ratio: 0.0,
+            seed: None,
+        }
+    }
+}
+
+// SplitMix64: tiny, and unlike a library generator its output for a seed
+// never changes, so a seed keeps naming the same workload.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+
+    // Uniform in [0, 1).
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, ratio: f64) -> bool {
+        self.unit() < ratio
+    }
+}
+
+// One generated row.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Row {
+    Record(TransactionRecord),
+    // a CSV line that shouldn't parse as a transaction
+    Malformed(String),
+}
+
+// Produces a random but plausible workload: deposits and withdrawals
+// spread over the clients, with withdrawals that sometimes overdraw,
+// disputes of earlier deposits by the client that made them, and each
+// dispute later resolved or charged back.
+pub struct Generator {
+    config: GeneratorConfig,
+    seed: u64,
+    rng: Rng,
+    rows: u32,
+    next_tx: u64,
+    disputable: VecDeque<(Client, Tx)>,
+    open: VecDeque<(Client, Tx)>,
+}
+
+impl Generator {
+    pub fn new(config: GeneratorConfig) -> Generator {
+        let seed = config.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+        });
+        Generator {
+            config,
+            seed,
+            rng: Rng(seed),
+            rows: 0,
+            next_tx: 1,
+            disputable: VecDeque::new(),
+            open: VecDeque::new(),
+        }
+    }
+
+    // The seed that reproduces this workload.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn client(&mut self) -> Client {
+        Client(self.rng.below(u64::from(self.config.clients)) as u32 + 1)
+    }
+
+    // Up to 1000.0000, never zero.
+    fn amount(&mut self) -> Amount {
+        Amount::new(self.rng.below(10_000_000) + 1)
+    }
+
+    fn malformed(&mut self) -> String {
+        let (client, tx) = (self.client().0, self.next_tx);
+        match self.rng.below(4) {
+            0 => format!("deposit,x{},{},1.0", client, tx),
+            1 => format!("deposit,{},{},1.23456", client, tx),
+            2 => format!("withdrawal,{},{}", client, tx),
+            _ => format!("refund,{},{},1.0", client, tx),
+        }
+    }
+
+    fn record(&mut self) -> TransactionRecord {
+        let ratio = self.config.dispute_ratio;
+        let roll = self.rng.unit();
+        if roll < ratio && !self.open.is_empty() {
+            let (client, tx) = self.open.pop_front().expect("checked not empty");
+            let r#type = if self.rng.chance(self.config.chargeback_ratio) {
+                TxType::Chargeback
+            } else {
+                TxType::Resolve
+            };
+            return record(r#type, client, tx, None);
+        }
+        if roll < ratio * 2.0 && !self.disputable.is_empty() {
+            let index = self.rng.below(self.disputable.len() as u64) as usize;
+            let (client, tx) = self.disputable.remove(index).expect("index in range");
+            self.open.push_back((client, tx));
+            return record(TxType::Dispute, client, tx, None);
+        }
+        let (client, tx, amount) = (self.client(), Tx(self.next_tx), self.amount());
+        self.next_tx += 1;
+        if roll < ratio * 2.0 + self.config.withdrawal_ratio {
+            return record(TxType::Withdrawal, client, tx, Some(amount));
+        }
+        self.disputable.push_back((client, tx));
+        if self.disputable.len() > DISPUTABLE {
+            self.disputable.pop_front();
+        }
+        record(TxType::Deposit, client, tx, Some(amount))
+    }
+}
+
+fn record(r#type: TxType, client: Client, tx: Tx, amount: Option<Amount>) -> TransactionRecord {
+    TransactionRecord {
+        r#type,
+        client,
+        tx,
+        amount,
+    }
+}
+
+impl Iterator for Generator {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Row> {
+        if self.rows >= self.config.transactions {
+            return None;
+        }
+        self.rows += 1;
+        if self.rng.chance(self.config.malformed_ratio) {
+            return Some(Row::Malformed(self.malformed()));
+        }
+        Some(Row::Record(self.record()))
+    }
+}
+
+// Writes the generator's rows as CSV input with a header.
+pub fn write_csv<W: Write>(generator: Generator, mut writer: W) -> io::Result<()> {
+    writeln!(writer, "type,client,tx,amount")?;
+    for row in generator {
+        match row {
+            Row::Record(record) => {
+                let amount = record
+                    .amount
+                    .map(|amount| amount.to_string())
+                    .unwrap_or_default();
+                writeln!(
+                    writer,
+                    "{},{},{},{}",
+                    record.r#type.as_str(),
+                    record.client.0,
+                    record.tx.0,
+                    amount
+                )?;
+            }
+            Row::Malformed(line) => writeln!(writer, "{}", line)?,
+        }
+    }
+    writer.flush()
+}
+
+// `arbitrary::Arbitrary` for the ledger's own types is implemented in
+// `payment_engine_core`, which this feature turns on there as well.
+
+// Transactions are spread over up to this many clients, so that they
+// land on the same accounts.
+#[cfg(feature = "testkit")]
+const MAX_CLIENTS: u32 = 8;
+
+// A plausible sequence of transactions, where single arbitrary records
+// would mostly be rejected as unknown: deposits and withdrawals of up to
+// 10,000.0000 with fresh tx ids, disputes of earlier deposits by the client
+// that made them, and resolves and chargebacks of open disputes.
+#[cfg(feature = "testkit")]
+#[derive(Debug, PartialEq, Clone)]
+pub struct Transactions(pub Vec<TransactionRecord>);
+
+#[cfg(feature = "testkit")]
+impl<'a> Arbitrary<'a> for Transactions {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Transactions> {
+        let clients = u.int_in_range(1..=MAX_CLIENTS)?;
+        let (mut deposits, mut open) = (Vec::new(), Vec::new());
+        let mut records = Vec::new();
+        let mut next_tx = 0;
+        while !u.is_empty() {
+            let kind = u.int_in_range(0..=9)?;
+            let (client, tx) = match kind {
+                0 if !deposits.is_empty() => {
+                    let (client, tx) = deposits.swap_remove(u.choose_index(deposits.len())?);
+                    open.push((client, tx));
+                    records.push(record(TxType::Dispute, client, tx, None));
+                    continue;
+                }
+                1 if !open.is_empty() => {
+                    let (client, tx) = open.swap_remove(u.choose_index(open.len())?);
+                    let r#type = if u.arbitrary()? {
+                        TxType::Chargeback
+                    } else {
+                        TxType::Resolve
+                    };
+                    records.push(record(r#type, client, tx, None));
+                    continue;
+                }
+                _ => {
+                    next_tx += 1;
+                    (Client(u.int_in_range(1..=clients)?), Tx(next_tx))
+                }
+            };
+            let amount = Amount(u.int_in_range(1..=10_000 * SCALE)?);
+            let r#type = if kind < 4 {
+                TxType::Withdrawal
+            } else {
+                deposits.push((client, tx));
+                TxType::Deposit
+            };
+            records.push(record(r#type, client, tx, Some(amount)));
+        }
+        Ok(Transactions(records))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_csv, Generator, GeneratorConfig, Row};
+    use crate::csv_dialect::CsvDialect;
+    use crate::engine::Engine;
+    use crate::transactions::TxType;
+
+    #[test]
+    fn test_generator() {
+        let config = GeneratorConfig {
+            clients: 200,
+            transactions: 2000,
+            dispute_ratio: 0.05,
+            malformed_ratio: 0.01,
+            seed: Some(7),
+            ..GeneratorConfig::default()
+        };
+        let rows: Vec<Row> = Generator::new(config.clone()).collect();
+        assert_eq!(rows.len(), 2000);
+        assert_eq!(rows, Generator::new(config.clone()).collect::<Vec<_>>());
+        let count = |r#type| {
+            rows.iter()
+                .filter(|row| matches!(row, Row::Record(record) if record.r#type == r#type))
+                .count()
+        };
+        assert!((50..150).contains(&count(TxType::Dispute)));
+        assert!(count(TxType::Chargeback) > 0 && count(TxType::Resolve) > 0);
+
+        let mut csv = Vec::new();
+        write_csv(Generator::new(config), &mut csv).unwrap();
+        let mut engine = Engine::new();
+        let dialect = CsvDialect {
+            permissive: true,
+            ..CsvDialect::default()
+        };
+        engine.process_csv_with(csv.as_slice(), &dialect).unwrap();
+        let metrics = engine.metrics();
+        assert!(metrics.rows_malformed > 0);
+        // chargebacks freeze accounts, so not everything applies
+        assert!(metrics.records_applied > 1000);
+    }
+
+    #[cfg(feature = "testkit")]
+    #[test]
+    fn test_arbitrary_transactions() {
+        use super::{Rng, Transactions};
+        use crate::transactions::RowRecord;
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let mut rng = Rng(3);
+        let bytes: Vec<u8> = (0..4096).map(|_| rng.next() as u8).collect();
+        let Transactions(records) =
+            Transactions::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+        assert!(records.len() > 100);
+        for (i, record) in records.iter().enumerate() {
+            if record.r#type == TxType::Dispute {
+                // an earlier deposit by the same client
+                assert!(records[..i]
+                    .iter()
+                    .any(|deposit| deposit.r#type == TxType::Deposit
+                        && deposit.tx == record.tx
+                        && deposit.client == record.client));
+            }
+        }
+        let mut engine = Engine::new();
+        for record in &records {
+            engine.process(record);
+        }
+        assert!(engine.metrics().records_applied > 0);
+
+        // rows borrow from the input; unparseable amounts are rejected
+        let mut u = Unstructured::new(&bytes);
+        for _ in 0..100 {
+            let row = RowRecord::arbitrary(&mut u).unwrap();
+            let _ = engine.process_row(row);
+        }
+    }
+}
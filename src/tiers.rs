@@ -0,0 +1,155 @@
+use crate::amount::Amount;
+use crate::rules::{RuleContext, Tier, ValidationRule};
+use crate::transactions::{RejectReason, TxType};
+use serde::Deserialize;
+
+// Reason codes by tier, then limit: the deposit and withdrawal limits, then
+// the deposit and withdrawal caps.
+const CODES: [[&str; 4]; 3] = [
+    [
+        "basic_deposit_limit",
+        "basic_withdrawal_limit",
+        "basic_deposit_cap",
+        "basic_withdrawal_cap",
+    ],
+    [
+        "verified_deposit_limit",
+        "verified_withdrawal_limit",
+        "verified_deposit_cap",
+        "verified_withdrawal_cap",
+    ],
+    [
+        "premium_deposit_limit",
+        "premium_withdrawal_limit",
+        "premium_deposit_cap",
+        "premium_withdrawal_cap",
+    ],
+];
+
+// What one tier's clients may move. Limits apply to each deposit or
+// withdrawal, caps to everything deposited or withdrawn by the client so
+// far, the record included. Unset ones don't apply.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TierLimits {
+    #[serde(deserialize_with = "crate::amount::threshold")]
+    pub deposit_limit: Option<Amount>,
+    #[serde(deserialize_with = "crate::amount::threshold")]
+    pub withdrawal_limit: Option<Amount>,
+    #[serde(deserialize_with = "crate::amount::threshold")]
+    pub deposit_cap: Option<Amount>,
+    #[serde(deserialize_with = "crate::amount::threshold")]
+    pub withdrawal_cap: Option<Amount>,
+}
+
+// The `[tiers]` config section, checked as a rule after the built-in ones.
+// A client's tier comes from the client registry (see `registry`); clients
+// without one are held to `default`'s limits, or to none when it is unset.
+// Rejections carry the tier and the limit, e.g. `basic_withdrawal_cap`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TiersConfig {
+    pub default: Option<Tier>,
+    pub basic: TierLimits,
+    pub verified: TierLimits,
+    pub premium: TierLimits,
+}
+
+impl TiersConfig {
+    pub fn limits(&self, tier: Tier) -> &TierLimits {
+        match tier {
+            Tier::Basic => &self.basic,
+            Tier::Verified => &self.verified,
+            Tier::Premium => &self.premium,
+        }
+    }
+}
+
+fn exceeds(amount: u64, limit: Option<Amount>) -> bool {
+    limit.is_some_and(|limit| amount > limit.0)
+}
+
+impl ValidationRule for TiersConfig {
+    fn check(&self, ctx: &RuleContext<'_>) -> Result<(), RejectReason> {
+        let tier = match ctx.stats.tier.or(self.default) {
+            Some(tier) => tier,
+            None => return Ok(()),
+        };
+        let limits = self.limits(tier);
+        let amount = ctx.record.amount.unwrap_or_default().0;
+        let (limit, cap, so_far, index) = match ctx.record.r#type {
+            TxType::Deposit => (
+                limits.deposit_limit,
+                limits.deposit_cap,
+                ctx.stats.deposited,
+                0,
+            ),
+            TxType::Withdrawal => (
+                limits.withdrawal_limit,
+                limits.withdrawal_cap,
+                ctx.stats.withdrawn,
+                1,
+            ),
+            _ => return Ok(()),
+        };
+        let codes = &CODES[tier as usize];
+        if exceeds(amount, limit) {
+            Err(RejectReason::Rule(codes[index]))
+        } else if exceeds(so_far.0.saturating_add(amount), cap) {
+            Err(RejectReason::Rule(codes[index + 2]))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TiersConfig;
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::registry::{ClientInfo, ClientRegistry};
+    use crate::rules::Tier;
+    use crate::transactions::{Client, RejectReason};
+
+    #[test]
+    fn test_tier_limits() {
+        let tiers: TiersConfig = toml::from_str(
+            r#"
+            default = "basic"
+            [basic]
+            deposit_limit = "100"
+            withdrawal_cap = "50"
+            [premium]
+            deposit_limit = "10000"
+            "#,
+        )
+        .unwrap();
+        let registry = ClientRegistry::new([ClientInfo {
+            client: Client(2),
+            name: String::new(),
+            kyc: None,
+            tier: Some(Tier::Premium),
+        }]);
+        let mut engine = Engine::builder().registry(registry).rule(tiers).build();
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,100\ndeposit,1,2,100.01\nwithdrawal,1,3,30\nwithdrawal,1,4,30\nwithdrawal,1,5,20\ndeposit,2,6,5000\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let metrics = engine.metrics();
+        assert_eq!(
+            metrics.rejected[&RejectReason::Rule("basic_deposit_limit")],
+            1
+        );
+        assert_eq!(
+            metrics.rejected[&RejectReason::Rule("basic_withdrawal_cap")],
+            1
+        );
+        assert_eq!(
+            engine.account(Client(1)).unwrap().available,
+            Amount::new(500_000)
+        );
+        assert_eq!(
+            engine.account(Client(2)).unwrap().available,
+            Amount::new(50_000_000)
+        );
+    }
+}
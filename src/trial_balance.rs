@@ -0,0 +1,262 @@
+use crate::books::Books;
+use crate::postings::LedgerAccount;
+use crate::reconcile::signed;
+use crate::transactions::{ClientFunds, Tx};
+use std::collections::HashMap;
+use std::io::Write;
+
+// What was debited from and credited to one ledger account.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct TrialBalanceLine {
+    pub account: LedgerAccount,
+    pub debits: i128,
+    pub credits: i128,
+}
+
+impl TrialBalanceLine {
+    pub fn balance(&self) -> i128 {
+        self.credits - self.debits
+    }
+}
+
+// A client's ledger account whose balance in the books isn't the one on
+// its account, with the txs posted to it, oldest first.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Imbalance {
+    pub account: LedgerAccount,
+    pub books: i128,
+    pub expected: i128,
+    pub txs: Vec<Tx>,
+}
+
+// The books summed up per ledger account. Every posting debits and
+// credits the same amount, so the debits and credits of all accounts
+// should be equal; that alone says nothing about the accounts, so each
+// client's `Available`, `Held`, `Pending` and `Debt` balances should also
+// be its account's. Anything else is a bug in the engine.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TrialBalance {
+    // by kind of account, then client
+    pub lines: Vec<TrialBalanceLine>,
+    pub imbalances: Vec<Imbalance>,
+}
+
+fn order(account: &LedgerAccount) -> (u8, u32) {
+    match account {
+        LedgerAccount::Available(client) => (0, client.0),
+        LedgerAccount::Held(client) => (1, client.0),
+        LedgerAccount::Pending(client) => (2, client.0),
+        LedgerAccount::Debt(client) => (3, client.0),
+        LedgerAccount::Suspense => (4, 0),
+        LedgerAccount::Promotion => (5, 0),
+    }
+}
+
+impl TrialBalance {
+    pub fn new(books: &Books, accounts: &ClientFunds) -> TrialBalance {
+        let mut lines: HashMap<LedgerAccount, TrialBalanceLine> = HashMap::new();
+        let mut txs: HashMap<LedgerAccount, Vec<Tx>> = HashMap::new();
+        for entry in books.journal() {
+            let posting = &entry.posting;
+            let amount = i128::from(posting.amount.0);
+            for (account, debit) in [(posting.debit, true), (posting.credit, false)] {
+                let line = lines.entry(account).or_insert(TrialBalanceLine {
+                    account,
+                    debits: 0,
+                    credits: 0,
+                });
+                if debit {
+                    line.debits += amount;
+                } else {
+                    line.credits += amount;
+                }
+                if let Some(tx) = entry.tx {
+                    txs.entry(account).or_default().push(tx);
+                }
+            }
+        }
+
+        // client accounts on either side, whether posted to or not
+        let mut expected: HashMap<LedgerAccount, i128> = lines
+            .keys()
+            .filter(|account| account.client().is_some())
+            .map(|account| (*account, 0))
+            .collect();
+        for funds in accounts.values() {
+            expected.insert(
+                LedgerAccount::Available(funds.client),
+                i128::from(funds.available.0),
+            );
+            expected.insert(LedgerAccount::Held(funds.client), i128::from(funds.held.0));
+            expected.insert(
+                LedgerAccount::Pending(funds.client),
+                i128::from(funds.pending.0),
+            );
+            // what the client owes is debited, lent for open disputes or not
+            let owed = i128::from(funds.debt.0) + i128::from(funds.uncovered.0);
+            expected.insert(LedgerAccount::Debt(funds.client), -owed);
+        }
+        let mut imbalances: Vec<_> = expected
+            .into_iter()
+            .filter_map(|(account, expected)| {
+                let books = lines.get(&account).map_or(0, TrialBalanceLine::balance);
+                (books != expected).then(|| Imbalance {
+                    account,
+                    books,
+                    expected,
+                    txs: txs.remove(&account).unwrap_or_default(),
+                })
+            })
+            .collect();
+        imbalances.sort_by_key(|imbalance| order(&imbalance.account));
+        let mut lines: Vec<_> = lines.into_values().collect();
+        lines.sort_by_key(|line| order(&line.account));
+        TrialBalance { lines, imbalances }
+    }
+
+    pub fn debits(&self) -> i128 {
+        self.lines.iter().map(|line| line.debits).sum()
+    }
+
+    pub fn credits(&self) -> i128 {
+        self.lines.iter().map(|line| line.credits).sum()
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.debits() == self.credits() && self.imbalances.is_empty()
+    }
+}
+
+fn client(account: &LedgerAccount) -> String {
+    account
+        .client()
+        .map_or_else(String::new, |client| client.0.to_string())
+}
+
+// The trial balance as CSV: a line per ledger account, the totals, then
+// an imbalance per client account that disagrees with the engine, with
+// the expected balance and the txs posted to the account.
+pub fn write_trial_balance<W: Write>(trial: &TrialBalance, writer: W) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        "kind", "account", "client", "debits", "credits", "balance", "expected", "txs",
+    ])?;
+    for line in &trial.lines {
+        writer.write_record([
+            "account",
+            line.account.kind(),
+            &client(&line.account),
+            &signed(line.debits),
+            &signed(line.credits),
+            &signed(line.balance()),
+            "",
+            "",
+        ])?;
+    }
+    writer.write_record([
+        "total",
+        "",
+        "",
+        &signed(trial.debits()),
+        &signed(trial.credits()),
+        &signed(trial.credits() - trial.debits()),
+        &signed(0),
+        "",
+    ])?;
+    for imbalance in &trial.imbalances {
+        let txs: Vec<_> = imbalance.txs.iter().map(|tx| tx.0.to_string()).collect();
+        writer.write_record([
+            "imbalance",
+            imbalance.account.kind(),
+            &client(&imbalance.account),
+            "",
+            "",
+            &signed(imbalance.books),
+            &signed(imbalance.expected),
+            &txs.join(" "),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_trial_balance, TrialBalance};
+    use crate::amount::Amount;
+    use crate::books::Books;
+    use crate::engine::Engine;
+    use crate::funds::{FundingStates, Funds};
+    use crate::postings::{LedgerAccount, Postings};
+    use crate::transactions::{Client, ClientFunds, Tx, TxType};
+
+    #[test]
+    fn test_trial_balance() {
+        let mut engine = Engine::builder().double_entry(true).build();
+        let csvfile = "type,client,tx,amount\ndeposit,1,1,10\ndeposit,2,2,3\ndispute,2,2,\n";
+        engine.process_csv(csvfile.as_bytes()).unwrap();
+        let trial = engine.trial_balance().unwrap();
+        assert!(trial.is_balanced());
+        assert_eq!(trial.debits(), 160_000);
+
+        let mut out = Vec::new();
+        write_trial_balance(&trial, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "kind,account,client,debits,credits,balance,expected,txs\n\
+             account,available,1,0.0000,10.0000,10.0000,,\n\
+             account,available,2,3.0000,3.0000,0.0000,,\n\
+             account,held,2,0.0000,3.0000,3.0000,,\n\
+             account,suspense,,13.0000,0.0000,-13.0000,,\n\
+             total,,,16.0000,16.0000,0.0000,0.0000,\n"
+        );
+
+        // an account changed behind the books' back
+        let (accounts, _) = engine.ledger_mut();
+        accounts.get_mut(&Client(1)).unwrap().available = Amount::new(90_000);
+        let trial = engine.trial_balance().unwrap();
+        assert!(!trial.is_balanced());
+        assert_eq!(trial.imbalances.len(), 1);
+        assert_eq!(
+            trial.imbalances[0].account,
+            LedgerAccount::Available(Client(1))
+        );
+        assert_eq!(trial.imbalances[0].txs, [Tx(1)]);
+    }
+
+    #[test]
+    fn test_clamped_dispute() {
+        // the books of a dispute of a spent deposit, against the account an
+        // engine that clamped the dispute left: 2 available and 10 held
+        let client = Client(1);
+        let mut books = Books::default();
+        for (tx, r#type, amount) in [
+            (1, TxType::Deposit, 10),
+            (2, TxType::Withdrawal, 8),
+            (1, TxType::Dispute, 10),
+        ] {
+            let postings = Postings::of(r#type, client, Amount::new(amount));
+            books.post(Some(Tx(tx)), None, postings.iter());
+        }
+        let mut accounts = ClientFunds::new();
+        accounts.insert(
+            client,
+            Funds {
+                available: Amount::new(2),
+                held: Amount::new(10),
+                state: FundingStates::Disputed,
+                ..Funds::new(client)
+            },
+        );
+        let trial = TrialBalance::new(&books, &accounts);
+        // debits and credits still net to zero
+        assert_eq!(trial.debits(), trial.credits());
+        assert!(!trial.is_balanced());
+        let imbalances: Vec<_> = trial
+            .imbalances
+            .iter()
+            .map(|imbalance| (imbalance.account, imbalance.books, imbalance.expected))
+            .collect();
+        assert_eq!(imbalances, [(LedgerAccount::Available(client), -8, 2)]);
+    }
+}
@@ -0,0 +1,323 @@
+use crate::amount::Amount;
+use crate::error::{PaymentResult, PaymentsError};
+use crate::snapshot::SnapshotInfo;
+use crate::tenant::{Tenants, DEFAULT_TENANT};
+use crate::transactions::{Client, TransactionRecord, Tx, TxType};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// The position a record was read at in its source, e.g. a Kafka partition
+// offset. Streams are named by the source (`kafka/<topic>/<partition>`) and
+// must not contain commas.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Position {
+    pub stream: String,
+    pub offset: i64,
+}
+
+// Append-only log of every record handed to the engine, one CSV-like line
+// each: `type,client,tx,amount[,stream,offset][,tenant]`, where records of
+// the default tenant leave the tenant out (and one without a position
+// leaves those fields empty). Replaying it through fresh engines rebuilds
+// the same state, rejections included. A record's position lives on its own
+// line, so a line torn by a crash loses both or neither; such an
+// unterminated last line is ignored on replay.
+//
+// After a snapshot (see `snapshot`) the log is restarted with a
+// `snapshot,<id>` first line, saying it only holds what came after it.
+pub struct Wal {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    // the line being appended, kept to reuse its allocation
+    line: Vec<u8>,
+    // bytes left on a simulated disk, see `simulation`
+    space: Option<u64>,
+}
+
+impl Wal {
+    // A line torn by a crash is cut off, so that appends don't run on from
+    // it and make it look like a corrupt whole line.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Wal> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+        let complete = complete_len(&mut file)?;
+        if complete < file.metadata()?.len() {
+            file.set_len(complete)?;
+        }
+        Ok(Wal {
+            writer: BufWriter::new(file),
+            path,
+            line: Vec::new(),
+            space: None,
+        })
+    }
+
+    // Makes appends fail once `space` more bytes have been written, the line
+    // that doesn't fit only written in part, as on a full disk.
+    pub(crate) fn fill_disk(&mut self, space: u64) {
+        self.space = Some(space);
+    }
+
+    // Bytes logged so far, for a snapshot taken right after a `flush`.
+    pub fn logged_bytes(&self) -> io::Result<u64> {
+        Ok(self.writer.get_ref().metadata()?.len())
+    }
+
+    // Swaps in an empty log following snapshot `id`. Until the rename, the
+    // old log is still in place and replay skips the part `id` covers.
+    pub fn restart(&mut self, id: u64) -> io::Result<()> {
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".partial");
+        let mut file = File::create(&partial)?;
+        writeln!(file, "snapshot,{}", id)?;
+        file.sync_all()?;
+        fs::rename(&partial, &self.path)?;
+        let space = self.space;
+        *self = Wal::open(&self.path)?;
+        self.space = space;
+        Ok(())
+    }
+
+    pub fn append(
+        &mut self,
+        tenant: &str,
+        record: &TransactionRecord,
+        position: Option<&Position>,
+    ) -> io::Result<()> {
+        let line = &mut self.line;
+        line.clear();
+        write!(
+            line,
+            "{},{},{},",
+            record.r#type.as_str(),
+            record.client.0,
+            record.tx.0
+        )?;
+        if let Some(amount) = record.amount {
+            write!(line, "{}", amount)?;
+        }
+        match (position, tenant) {
+            (Some(position), _) => write!(line, ",{},{}", position.stream, position.offset)?,
+            (None, DEFAULT_TENANT) => {}
+            (None, _) => write!(line, ",,")?,
+        }
+        if tenant != DEFAULT_TENANT {
+            write!(line, ",{}", tenant)?;
+        }
+        writeln!(line)?;
+        if let Some(space) = &mut self.space {
+            if line.len() as u64 > *space {
+                self.writer.write_all(&line[..*space as usize])?;
+                *space = 0;
+                return Err(io::Error::new(
+                    io::ErrorKind::StorageFull,
+                    "no space left for the WAL",
+                ));
+            }
+            *space -= line.len() as u64;
+        }
+        self.writer.write_all(line)
+    }
+
+    // Makes everything appended so far durable; sources only acknowledge
+    // their input after this returns.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+}
+
+// The length of the file up to the end of its last complete line.
+fn complete_len(file: &mut File) -> io::Result<u64> {
+    let mut end = file.metadata()?.len();
+    let mut chunk = [0; 256];
+    while end > 0 {
+        let start = end.saturating_sub(chunk.len() as u64);
+        let chunk = &mut chunk[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        if let Some(newline) = chunk.iter().rposition(|byte| *byte == b'\n') {
+            return Ok(start + newline as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+type Line<'a> = (&'a str, TransactionRecord, Option<Position>);
+
+fn parse_line(line: &str) -> PaymentResult<Line<'_>> {
+    let fields: Vec<&str> = line.split(',').collect();
+    if ![4, 6, 7].contains(&fields.len()) {
+        return Err(PaymentsError::Parse("Bad WAL line"));
+    }
+    let record = TransactionRecord {
+        r#type: TxType::from_str(fields[0])?,
+        client: Client(
+            fields[1]
+                .parse()
+                .map_err(|_| PaymentsError::Parse("Bad WAL client"))?,
+        ),
+        tx: Tx(fields[2]
+            .parse()
+            .map_err(|_| PaymentsError::Parse("Bad WAL tx"))?),
+        amount: match fields[3] {
+            "" => None,
+            amount => Some(Amount::from_str(amount)?),
+        },
+    };
+    let position = match fields.get(4..6) {
+        Some(["", ""]) | None => None,
+        Some([stream, offset]) => Some(Position {
+            stream: stream.to_string(),
+            offset: offset
+                .parse()
+                .map_err(|_| PaymentsError::Parse("Bad WAL offset"))?,
+        }),
+        _ => None,
+    };
+    let tenant = fields.get(6).copied().unwrap_or(DEFAULT_TENANT);
+    Ok((tenant, record, position))
+}
+
+// The id on the first line of a log restarted after a snapshot.
+fn restarted_after(line: &str) -> Option<u64> {
+    line.trim_end().strip_prefix("snapshot,")?.parse().ok()
+}
+
+// Applies a WAL to each record's tenant and returns the last position seen
+// for each stream. A missing file is an empty log. With the `snapshot`
+// already loaded into `tenants`, lines it contains are skipped.
+pub fn replay<P: AsRef<Path>>(
+    path: P,
+    tenants: &mut Tenants,
+    snapshot: Option<&SnapshotInfo>,
+) -> io::Result<HashMap<String, i64>> {
+    let mut positions = HashMap::new();
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(positions),
+        Err(err) => return Err(err),
+    };
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    let mut number = 0;
+    let (mut read, mut covered) = (0, 0);
+    loop {
+        let start = read;
+        let len = reader.read_line(&mut line)?;
+        if len == 0 || !line.ends_with('\n') {
+            break;
+        }
+        read += len as u64;
+        number += 1;
+        let invalid = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("WAL line {}: {}", number, reason),
+            )
+        };
+        if number == 1 {
+            let after = restarted_after(&line);
+            covered = match (after, snapshot) {
+                (Some(after), Some(snapshot)) if after == snapshot.id => 0,
+                // a crash between taking the snapshot and restarting the log
+                (Some(after), Some(snapshot)) if after < snapshot.id => snapshot.wal_len,
+                (None, Some(snapshot)) => snapshot.wal_len,
+                (None, None) => 0,
+                (Some(after), _) => {
+                    return Err(invalid(&format!(
+                        "the log follows snapshot {}, which wasn't loaded",
+                        after
+                    )))
+                }
+            };
+            if after.is_some() {
+                line.clear();
+                continue;
+            }
+        }
+        if start < covered {
+            line.clear();
+            continue;
+        }
+        let (tenant, record, position) =
+            parse_line(line.trim_end()).map_err(|err| invalid(&err.to_string()))?;
+        let tenant = tenants.resolve(Some(tenant)).map_err(|err| invalid(&err))?;
+        tenants.engine(&tenant).process(&record);
+        if let Some(position) = position {
+            positions.insert(position.stream, position.offset);
+        }
+        line.clear();
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{replay, Position, Wal};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::tenant::{Tenants, DEFAULT_TENANT};
+    use crate::transactions::{Client, TransactionRecord, Tx};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    #[test]
+    fn test_wal_replay() {
+        let path = std::env::temp_dir().join("payment_engine_wal_replay.log");
+        let _ = fs::remove_file(&path);
+        let mut wal = Wal::open(&path).unwrap();
+        let deposit = TransactionRecord::deposit(Client(1), Tx(1), Amount::new(15000));
+        let dispute = TransactionRecord::dispute(Client(1), Tx(1));
+        let position = |offset| Position {
+            stream: "kafka/tx/0".to_string(),
+            offset,
+        };
+        wal.append(DEFAULT_TENANT, &deposit, Some(&position(7)))
+            .unwrap();
+        wal.append(DEFAULT_TENANT, &dispute, Some(&position(8)))
+            .unwrap();
+        // the same tx id in another tenant's ledger, without a position
+        wal.append("acme", &deposit, None).unwrap();
+        wal.flush().unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .ends_with("\ndeposit,1,1,1.5000,,,acme\n"));
+        // a torn write from a crash
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"deposit,1,2,10,kafka/tx/0,9")
+            .unwrap();
+
+        let mut tenants = Tenants::isolated(Engine::builder());
+        let positions = replay(&path, &mut tenants, None).unwrap();
+        assert_eq!(positions["kafka/tx/0"], 8);
+        let funds = tenants.get(DEFAULT_TENANT).unwrap().account(Client(1));
+        assert_eq!(funds.unwrap().held, Amount::new(15000));
+        let funds = tenants.get("acme").unwrap().account(Client(1));
+        assert_eq!(funds.unwrap().available, Amount::new(15000));
+        // reopening cuts the torn line off before appending
+        let mut wal = Wal::open(&path).unwrap();
+        wal.append(DEFAULT_TENANT, &dispute, None).unwrap();
+        wal.flush().unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .ends_with(",acme\ndispute,1,1,\n"));
+        // a ledger without tenants can't take the other tenant's records
+        assert!(replay(&path, &mut Tenants::single(Engine::builder()), None).is_err());
+
+        fs::remove_file(&path).unwrap();
+        let mut tenants = Tenants::single(Engine::builder());
+        assert!(replay(&path, &mut tenants, None).unwrap().is_empty());
+    }
+}
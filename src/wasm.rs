@@ -0,0 +1,140 @@
+use crate::amount::Amount;
+use crate::engine::Engine;
+use crate::funds::FundingStates;
+use crate::output::write_accounts;
+use crate::transactions::{Client, TransactionRecord, Tx, TxType};
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+
+// JavaScript bindings, for running the engine in a browser or in Node
+// without a native build. Everything goes in and out as strings and
+// numbers, so nothing here touches a filesystem.
+
+#[wasm_bindgen(js_name = Engine)]
+pub struct WasmEngine {
+    engine: Engine,
+}
+
+// A client's balances, as the CLI prints them.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, PartialEq, Clone)]
+pub struct Account {
+    pub client: u32,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+}
+
+impl Default for WasmEngine {
+    fn default() -> WasmEngine {
+        WasmEngine {
+            engine: Engine::new(),
+        }
+    }
+}
+
+#[wasm_bindgen(js_class = Engine)]
+impl WasmEngine {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmEngine {
+        WasmEngine::default()
+    }
+
+    // Applies a transaction, with the amount written as in CSV input and
+    // left out for disputes, resolves and chargebacks. Returns "applied",
+    // the rejection code, or "bad_type" or "bad_amount" for input that
+    // isn't a transaction.
+    pub fn apply(&mut self, kind: &str, client: u32, tx: u64, amount: Option<String>) -> String {
+        let Ok(r#type) = TxType::from_str(kind) else {
+            return "bad_type".to_string();
+        };
+        let amount = match amount.as_deref().map(Amount::from_str) {
+            Some(Ok(amount)) => Some(amount),
+            Some(Err(_)) => return "bad_amount".to_string(),
+            None => None,
+        };
+        let record = TransactionRecord {
+            r#type,
+            client: Client(client),
+            tx: Tx(tx),
+            amount,
+        };
+        match self.engine.try_process(&record) {
+            Ok(()) => "applied".to_string(),
+            Err(reason) => reason.as_str().to_string(),
+        }
+    }
+
+    // Applies the contents of a CSV file, as the CLI would; fails on the
+    // first row that can't be read.
+    #[wasm_bindgen(js_name = applyCsv)]
+    pub fn apply_csv(&mut self, csv: &str) -> Result<(), String> {
+        self.engine
+            .process_csv(csv.as_bytes())
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn account(&self, client: u32) -> Option<Account> {
+        let funds = self.engine.account(Client(client))?;
+        Some(Account {
+            client,
+            available: funds.available.to_string(),
+            held: funds.held.to_string(),
+            total: funds.total().to_string(),
+            locked: funds.state == FundingStates::Frozen,
+        })
+    }
+
+    // Every account, in the CLI's CSV output format.
+    #[wasm_bindgen(js_name = accountsCsv)]
+    pub fn accounts_csv(&self) -> Result<String, String> {
+        let mut csv = Vec::new();
+        write_accounts(self.engine.accounts(), &mut csv).map_err(|err| err.to_string())?;
+        String::from_utf8(csv).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Account, WasmEngine};
+
+    #[test]
+    fn test_wasm_engine() {
+        let mut engine = WasmEngine::new();
+        assert_eq!(
+            engine.apply("deposit", 1, 1, Some("2.5".to_string())),
+            "applied"
+        );
+        assert_eq!(
+            engine.apply("withdrawal", 1, 2, Some("3".to_string())),
+            "insufficient_funds"
+        );
+        assert_eq!(engine.apply("refund", 1, 3, None), "bad_type");
+        assert_eq!(
+            engine.apply("deposit", 1, 3, Some("1.23456".to_string())),
+            "bad_amount"
+        );
+        engine
+            .apply_csv("type,client,tx,amount\ndeposit,2,4,1.0\ndispute,1,1,\n")
+            .unwrap();
+        assert!(engine
+            .apply_csv("type,client,tx,amount\ndeposit,x,5,1.0\n")
+            .is_err());
+        assert_eq!(
+            engine.account(1),
+            Some(Account {
+                client: 1,
+                available: "0.0000".to_string(),
+                held: "2.5000".to_string(),
+                total: "2.5000".to_string(),
+                locked: false,
+            })
+        );
+        assert_eq!(engine.account(3), None);
+        assert_eq!(
+            engine.accounts_csv().unwrap(),
+            "client,available,held,total,locked\n1,0.0000,2.5000,2.5000,false\n2,1.0000,0.0000,1.0000,false\n"
+        );
+    }
+}
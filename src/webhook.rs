@@ -0,0 +1,154 @@
+use crate::amount::Amount;
+use crate::risk::{RiskEvent, RiskMonitor};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// The `[webhooks]` config section. Every event is POSTed as JSON to each
+// of `urls`; with a `secret` the body is signed so receivers can check it
+// came from the engine.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    pub urls: Vec<String>,
+    pub secret: Option<String>,
+    // e.g. `large_withdrawal = "10000.00"`
+    #[serde(default, deserialize_with = "crate::amount::threshold")]
+    pub large_withdrawal: Option<Amount>,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    // the delay before the first retry, doubled for each one after
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+// `sha256=<hex HMAC-SHA256 of the body>`, sent as `X-Signature-256`.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+struct Sender {
+    agent: ureq::Agent,
+    config: WebhookConfig,
+}
+
+impl Sender {
+    // Retries connection failures and 429/5xx answers with exponential
+    // backoff; any other status means the receiver rejected the event.
+    fn post(&self, url: &str, body: &str) -> Result<(), ureq::Error> {
+        let mut backoff = Duration::from_millis(self.config.backoff_ms);
+        let mut attempt = 1;
+        loop {
+            let mut request = self
+                .agent
+                .post(url)
+                .header("content-type", "application/json");
+            if let Some(secret) = &self.config.secret {
+                request = request.header("x-signature-256", signature(secret, body.as_bytes()));
+            }
+            let err = match request.send(body) {
+                Ok(_) => return Ok(()),
+                Err(err) => err,
+            };
+            let retryable = match err {
+                ureq::Error::StatusCode(status) => status == 429 || status >= 500,
+                _ => true,
+            };
+            if !retryable || attempt >= self.config.max_attempts {
+                return Err(err);
+            }
+            thread::sleep(backoff);
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+
+    fn deliver(&self, event: &RiskEvent) {
+        let body = serde_json::to_string(event).expect("risk events serialize");
+        for url in &self.config.urls {
+            if let Err(err) = self.post(url, &body) {
+                // the body names clients and amounts, so it stays out of the log
+                tracing::warn!(url = %url, error = %err, "webhook delivery failed");
+            }
+        }
+    }
+}
+
+// Delivers risk events on a background thread, in the order the engine
+// raised them.
+pub struct Webhooks {
+    worker: JoinHandle<()>,
+    closing: Arc<AtomicBool>,
+}
+
+impl Webhooks {
+    // Returns the monitor to hand to `EngineBuilder::risk_monitor`.
+    pub fn start(config: WebhookConfig) -> (Webhooks, RiskMonitor) {
+        let (events, received) = mpsc::channel();
+        let monitor = RiskMonitor::new(events, config.large_withdrawal);
+        let closing = Arc::new(AtomicBool::new(false));
+        let sender = Sender {
+            agent: ureq::Agent::config_builder()
+                .timeout_global(Some(Duration::from_secs(10)))
+                .build()
+                .into(),
+            config,
+        };
+        let worker = {
+            let closing = Arc::clone(&closing);
+            thread::spawn(move || run(sender, received, &closing))
+        };
+        (Webhooks { worker, closing }, monitor)
+    }
+
+    // Delivers the events raised so far, then stops.
+    pub fn finish(self) {
+        self.closing.store(true, Ordering::Release);
+        let _ = self.worker.join();
+    }
+}
+
+fn run(sender: Sender, events: Receiver<RiskEvent>, closing: &AtomicBool) {
+    loop {
+        match events.recv_timeout(Duration::from_millis(100)) {
+            Ok(event) => sender.deliver(&event),
+            Err(RecvTimeoutError::Timeout) if !closing.load(Ordering::Acquire) => {}
+            Err(_) => break,
+        }
+    }
+    // the engine may still hold the monitor; drain what it has sent
+    for event in events.try_iter() {
+        sender.deliver(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::signature;
+
+    // RFC 4231 test case 2
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use crate::engine::Engine;
+use crate::transactions::{amount_field, Client, RowRecord, Tx, TxType};
+use calamine::{Data, Range, Reader, Xlsx, XlsxError};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Read, Seek};
+use std::str::FromStr;
+
+// A spreadsheet column given by its letters ("A", "AB"), stored zero based.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Column(pub u32);
+
+impl TryFrom<String> for Column {
+    type Error = &'static str;
+
+    fn try_from(letters: String) -> Result<Column, &'static str> {
+        if letters.is_empty() || !letters.bytes().all(|b| b.is_ascii_alphabetic()) {
+            return Err("A column is given by its letters, e.g. \"A\" or \"AB\"");
+        }
+        let number = letters.bytes().try_fold(0u32, |acc, b| {
+            acc.checked_mul(26)?
+                .checked_add(u32::from(b.to_ascii_uppercase() - b'A') + 1)
+        });
+        number
+            .map(|n| Column(n - 1))
+            .ok_or("Column is out of range")
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (mut n, mut letters) = (self.0 + 1, Vec::new());
+        while n > 0 {
+            letters.push(b'A' + ((n - 1) % 26) as u8);
+            n = (n - 1) / 26;
+        }
+        letters.reverse();
+        f.write_str(std::str::from_utf8(&letters).unwrap())
+    }
+}
+
+// Which sheet to read and where each field lives. Without a `[xlsx]`
+// section the first sheet is read with a header row and the columns in CSV
+// order (A..D).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct XlsxLayout {
+    pub sheet: Option<String>,
+    pub header_rows: u32,
+    pub r#type: Column,
+    pub client: Column,
+    pub tx: Column,
+    pub amount: Column,
+}
+
+impl Default for XlsxLayout {
+    fn default() -> XlsxLayout {
+        XlsxLayout {
+            sheet: None,
+            header_rows: 1,
+            r#type: Column(0),
+            client: Column(1),
+            tx: Column(2),
+            amount: Column(3),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum XlsxInputError {
+    Workbook(XlsxError),
+    NoSheets,
+    Cell {
+        sheet: String,
+        cell: String,
+        field: &'static str,
+        reason: String,
+    },
+}
+
+impl fmt::Display for XlsxInputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XlsxInputError::Workbook(err) => write!(f, "{}", err),
+            XlsxInputError::NoSheets => write!(f, "workbook has no sheets"),
+            XlsxInputError::Cell {
+                sheet,
+                cell,
+                field,
+                reason,
+            } => write!(f, "{}!{} (`{}`): {}", sheet, cell, field, reason),
+        }
+    }
+}
+
+impl std::error::Error for XlsxInputError {}
+
+impl From<XlsxError> for XlsxInputError {
+    fn from(err: XlsxError) -> XlsxInputError {
+        XlsxInputError::Workbook(err)
+    }
+}
+
+fn whole_number(cell: &Data) -> Result<u64, String> {
+    match cell {
+        Data::Int(n) => u64::try_from(*n).map_err(|_| format!("{} is negative", n)),
+        Data::Float(f) if f.fract() == 0.0 && *f >= 0.0 && *f <= u64::MAX as f64 => Ok(*f as u64),
+        Data::String(s) => s
+            .trim()
+            .parse()
+            .map_err(|_| format!("expected a whole number, found {:?}", s)),
+        Data::Empty => Err("cell is empty".to_string()),
+        other => Err(format!("expected a whole number, found {}", other)),
+    }
+}
+
+fn id<T: TryFrom<u64>>(cell: &Data) -> Result<T, String> {
+    let n = whole_number(cell)?;
+    T::try_from(n).map_err(|_| format!("{} is out of range", n))
+}
+
+// Excel stores numbers as doubles; their shortest round-trip form is what
+// the user typed, so it goes through the same decimal parser as CSV text.
+fn amount_text(cell: &Data) -> Result<Option<String>, String> {
+    let text = match cell {
+        Data::Empty => return Ok(None),
+        Data::Int(n) => n.to_string(),
+        Data::Float(f) => f.to_string(),
+        Data::String(s) => s.trim().to_string(),
+        other => return Err(format!("expected an amount, found {}", other)),
+    };
+    amount_field(&text)
+        .map(|amount| amount.map(str::to_string))
+        .map_err(|err| err.to_string())
+}
+
+impl Engine {
+    pub fn process_xlsx<RS: Read + Seek>(
+        &mut self,
+        input: RS,
+        layout: &XlsxLayout,
+    ) -> Result<(), XlsxInputError> {
+        let mut workbook: Xlsx<RS> = Xlsx::new(input)?;
+        let sheet = match &layout.sheet {
+            Some(sheet) => sheet.clone(),
+            None => workbook
+                .sheet_names()
+                .into_iter()
+                .next()
+                .ok_or(XlsxInputError::NoSheets)?,
+        };
+        let range = workbook.worksheet_range(&sheet)?;
+        self.process_sheet(&sheet, &range, layout)
+    }
+
+    pub fn process_sheet(
+        &mut self,
+        sheet: &str,
+        range: &Range<Data>,
+        layout: &XlsxLayout,
+    ) -> Result<(), XlsxInputError> {
+        let last_row = match range.end() {
+            Some((row, _)) => row,
+            None => return Ok(()),
+        };
+        for row in layout.header_rows..=last_row {
+            let cell = |column: Column| range.get_value((row, column.0)).unwrap_or(&Data::Empty);
+            let columns = [layout.r#type, layout.client, layout.tx, layout.amount];
+            if columns.iter().all(|&column| cell(column) == &Data::Empty) {
+                continue;
+            }
+            let error = |field, column: Column, reason| XlsxInputError::Cell {
+                sheet: sheet.to_string(),
+                cell: format!("{}{}", column, row + 1),
+                field,
+                reason,
+            };
+
+            let r#type = match cell(layout.r#type) {
+                Data::String(s) => TxType::from_str(s.trim()).map_err(|err| err.to_string()),
+                other => Err(format!("expected a transaction type, found {}", other)),
+            }
+            .map_err(|reason| error("type", layout.r#type, reason))?;
+            let client =
+                id(cell(layout.client)).map_err(|reason| error("client", layout.client, reason))?;
+            let tx = id(cell(layout.tx)).map_err(|reason| error("tx", layout.tx, reason))?;
+            let amount = amount_text(cell(layout.amount))
+                .map_err(|reason| error("amount", layout.amount, reason))?;
+
+            let row = RowRecord::new(r#type, Client(client), Tx(tx), amount.as_deref());
+            let _ = self.process_row(row);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Column, XlsxLayout};
+    use crate::amount::Amount;
+    use crate::engine::Engine;
+    use crate::transactions::Client;
+    use calamine::{Data, Range};
+    use std::convert::TryFrom;
+
+    fn sheet(rows: &[[Data; 4]]) -> Range<Data> {
+        let mut range = Range::new((0, 0), (rows.len() as u32, 3));
+        for (row, cells) in rows.iter().enumerate() {
+            for (column, cell) in cells.iter().enumerate() {
+                range.set_value((row as u32 + 1, column as u32), cell.clone());
+            }
+        }
+        range
+    }
+
+    #[test]
+    fn test_columns() {
+        assert_eq!(Column::try_from("A".to_string()), Ok(Column(0)));
+        assert_eq!(Column::try_from("ab".to_string()), Ok(Column(27)));
+        assert_eq!(Column(27).to_string(), "AB");
+        assert!(Column::try_from("1".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_process_sheet() {
+        let s = |s: &str| Data::String(s.to_string());
+        let range = sheet(&[
+            [
+                s("deposit"),
+                Data::Float(1.0),
+                Data::Int(1),
+                Data::Float(10.5),
+            ],
+            [s("deposit"), s(" 1 "), Data::Int(2), s("2.25")],
+            [Data::Empty, Data::Empty, Data::Empty, Data::Empty],
+            [s("dispute"), Data::Int(1), Data::Int(2), Data::Empty],
+        ]);
+        let mut engine = Engine::new();
+        engine
+            .process_sheet("Sheet1", &range, &XlsxLayout::default())
+            .unwrap();
+        let funds = engine.account(Client(1)).unwrap();
+        assert_eq!(funds.available, Amount::new(105000));
+        assert_eq!(funds.held, Amount::new(22500));
+
+        let range = sheet(&[
+            [s("deposit"), Data::Int(1), Data::Int(1), Data::Float(1.0)],
+            [
+                s("deposit"),
+                Data::Float(1.5),
+                Data::Int(2),
+                Data::Float(1.0),
+            ],
+        ]);
+        let err = Engine::new()
+            .process_sheet("Sheet1", &range, &XlsxLayout::default())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Sheet1!B3 (`client`): expected a whole number, found 1.5"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+// Golden-file tests: every `tests/fixtures/<name>.csv` is run through the
+// binary and its output compared with `<name>.expected.csv`. A fixture may
+// come with `<name>.args`, extra command-line arguments one per line. Every
+// run has to exit successfully, so an invariant violation fails it too.
+// Running with `UPDATE_FIXTURES=1` (re)writes the expected files instead.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const EXPECTED: &str = ".expected.csv";
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut inputs: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("tests/fixtures exists")
+        .map(|entry| entry.expect("fixture entry").path())
+        .filter(|path| {
+            let name = path.to_string_lossy();
+            name.ends_with(".csv") && !name.ends_with(EXPECTED)
+        })
+        .collect();
+    inputs.sort();
+    inputs
+}
+
+fn run(input: &Path) -> String {
+    let args = fs::read_to_string(input.with_extension("args")).unwrap_or_default();
+    let output = Command::new(env!("CARGO_BIN_EXE_payment_engine"))
+        .arg(input)
+        .args(args.lines().map(str::trim).filter(|arg| !arg.is_empty()))
+        .output()
+        .expect("payment_engine runs");
+    assert!(
+        output.status.success(),
+        "{} exited with {}: {}",
+        input.display(),
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).expect("output is UTF-8")
+}
+
+// The lines that differ, marked `-` for expected and `+` for actual.
+fn diff(expected: &str, actual: &str) -> String {
+    let (expected, actual): (Vec<&str>, Vec<&str>) =
+        (expected.lines().collect(), actual.lines().collect());
+    let mut out = String::new();
+    for line in 0..expected.len().max(actual.len()) {
+        match (expected.get(line), actual.get(line)) {
+            (Some(want), Some(got)) if want == got => {}
+            (want, got) => {
+                if let Some(want) = want {
+                    out.push_str(&format!("  line {}: - {}\n", line + 1, want));
+                }
+                if let Some(got) = got {
+                    out.push_str(&format!("  line {}: + {}\n", line + 1, got));
+                }
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn test_fixtures() {
+    let update = std::env::var_os("UPDATE_FIXTURES").is_some();
+    let mut failures = Vec::new();
+    for input in fixtures() {
+        let expected_path = input.with_extension("expected.csv");
+        let actual = run(&input);
+        if update {
+            fs::write(&expected_path, &actual).expect("expected file written");
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_path)
+            .unwrap_or_else(|_| panic!("{} is missing", expected_path.display()));
+        if expected != actual {
+            failures.push(format!(
+                "{}:\n{}",
+                input.display(),
+                diff(&expected, &actual)
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "fixtures differ:\n{}",
+        failures.join("\n")
+    );
+}